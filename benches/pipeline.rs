@@ -0,0 +1,119 @@
+//! End-to-end benchmarks of the formatting pipeline over synthetic exports
+//!
+//! Each benchmark runs the built binary over a generated CSV with output
+//! discarded, so the numbers include CSV parsing, JSON parsing, decoding and
+//! formatting of every row.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+const ROWS: usize = 20_000;
+
+/// Generator for the message of a row
+type RowFn = fn(usize) -> String;
+
+/// Quote a message as a CSV field
+fn csv_field(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "\"\""))
+}
+
+fn record(target: &str, message: &str, fields: &str) -> String {
+    format!(
+        r#"{{"timestamp":"2024-05-01T10:00:00.000000Z","level":"INFO","target":"{}","fields":{{"message":"{}"{}}}}}"#,
+        target, message, fields
+    )
+}
+
+/// Rows with many plain numeric and string fields
+fn wide_row(i: usize) -> String {
+    let fields: String = (0..24)
+        .map(|n| format!(r#","field_{}":{}"#, n, i * 31 + n))
+        .chain((0..8).map(|n| format!(r#","name_{}":"value {}""#, n, i)))
+        .collect();
+    record("openhcl::wide", "wide row", &fields)
+}
+
+/// Rows exercising the structure and payload decoders
+fn transform_row(i: usize) -> String {
+    let fields = match i % 4 {
+        0 => format!(
+            r#","raw_exit":"tdx_tdg_vp_enter_exit_info {{ rax: {}, rcx: {}, rdx: {}, r8: {} }}""#,
+            i,
+            i * 2,
+            i * 3,
+            i * 4
+        ),
+        1 => format!(
+            r#","cs":"SegmentRegister {{ base: {}, limit: 4294967295, selector: 8, attributes: 41115 }}""#,
+            i * 4096
+        ),
+        2 => {
+            let bytes: Vec<String> = (0..64).map(|n| ((i + n) % 256).to_string()).collect();
+            format!(r#","payload":[{}]"#, bytes.join(","))
+        }
+        _ => r#","instruction_bytes":[15,1,217],"rip":18446744071562067968"#.to_string(),
+    };
+    record("openhcl::transform", "heavy transform", &fields)
+}
+
+/// Rows that fail to parse as tracing JSON and are passed through
+fn failure_row(i: usize) -> String {
+    match i % 3 {
+        0 => format!("plain console line {}", i),
+        1 => format!(
+            r#"{{"timestamp":"t","level":"WARN","fields":{{"message":"{}""#,
+            i
+        ),
+        _ => format!(
+            r#"{{"level":"INFO","target":"x","fields":{{"message":"no timestamp {}"}}}}"#,
+            i
+        ),
+    }
+}
+
+fn write_csv(dir: &Path, name: &str, row: RowFn) -> PathBuf {
+    let path = dir.join(format!("{}.csv", name));
+    let mut content = String::from("Timestamp,ExtractedMessage\n");
+    for i in 0..ROWS {
+        content.push_str(&format!("2024-05-01,{}\n", csv_field(&row(i))));
+    }
+    std::fs::write(&path, content).unwrap();
+    path
+}
+
+fn run(path: &Path) {
+    let status = Command::new(env!("CARGO_BIN_EXE_kusto-kmsg-extract"))
+        .arg(path)
+        .stdout(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+fn pipeline(c: &mut Criterion) {
+    let dir = std::env::temp_dir().join(format!("kusto-kmsg-extract-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let inputs: [(&str, RowFn); 3] = [
+        ("wide", wide_row),
+        ("transforms", transform_row),
+        ("failures", failure_row),
+    ];
+
+    let mut group = c.benchmark_group("pipeline");
+    group.sample_size(10);
+
+    for (name, row) in inputs {
+        let path = write_csv(&dir, name, row);
+        let bytes = std::fs::metadata(&path).unwrap().len();
+        group.throughput(Throughput::Bytes(bytes));
+        group.bench_function(name, |b| b.iter(|| run(&path)));
+    }
+
+    group.finish();
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+criterion_group!(benches, pipeline);
+criterion_main!(benches);
@@ -0,0 +1,49 @@
+/*
+ * C API of kusto-kmsg-extract, formatting kernel message records like the
+ * tool given no option.
+ *
+ * Link against libkke, built with `cargo build --release -p
+ * kusto-kmsg-extract-capi`. All functions may be called from any thread.
+ */
+
+#ifndef KKE_H
+#define KKE_H
+
+#include <stdbool.h>
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/*
+ * Format the message of a record, usually its tracing JSON.
+ *
+ * Returns the formatted line, to be freed with kke_string_free, or NULL
+ * when the record shows nothing or on failure, told apart by
+ * kke_last_error.
+ */
+char *kke_decode_message(const char *message);
+
+/*
+ * Format `count` messages into `lines`, each as kke_decode_message would.
+ *
+ * Returns false on failure, with every line NULL. Each line is freed with
+ * kke_string_free.
+ */
+bool kke_decode_messages(const char *const *messages, size_t count, char **lines);
+
+/* Free a line returned by this library. NULL is ignored. */
+void kke_string_free(char *line);
+
+/*
+ * The error of the last call on this thread that failed, or NULL after a
+ * call that didn't. Valid until the next call on the thread.
+ */
+const char *kke_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* KKE_H */
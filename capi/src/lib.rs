@@ -0,0 +1,140 @@
+//! C API of the decoders, `libkke`, for triage tooling linking them
+//! directly, declared in `include/kke.h`
+//!
+//! Records are formatted like the tool given no option. Failures return
+//! NULL or false and leave their message for `kke_last_error`.
+
+use kusto_kmsg_extract::format::{self, FormatOptions};
+use kusto_kmsg_extract::sink::RecordInfo;
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::ptr;
+use std::sync::OnceLock;
+
+/// Options of every call, loaded by the first
+static OPTIONS: OnceLock<Result<FormatOptions, String>> = OnceLock::new();
+
+thread_local! {
+    /// Message of the last failure on the thread
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(error: Option<String>) {
+    let error = error.map(|text| CString::new(text.replace('\0', "")).unwrap_or_default());
+    LAST_ERROR.with(|last| *last.borrow_mut() = error);
+}
+
+fn options() -> Result<&'static FormatOptions, String> {
+    let options = OPTIONS.get_or_init(|| {
+        let decoders = format::default_decoders().map_err(|err| err.to_string())?;
+        Ok(FormatOptions::new(decoders))
+    });
+    options.as_ref().map_err(Clone::clone)
+}
+
+/// Format a message, giving None when it shows nothing
+///
+/// # Safety
+///
+/// `message` must be a valid NUL-terminated string.
+unsafe fn decode(message: *const c_char) -> Result<Option<CString>, String> {
+    if message.is_null() {
+        return Err("Message is NULL".to_string());
+    }
+    let message = CStr::from_ptr(message)
+        .to_str()
+        .map_err(|err| format!("Message isn't UTF-8: {}", err))?;
+
+    let mut output = String::new();
+    let mut info = RecordInfo::default();
+    format::process_message(message, None, options()?, &mut output, &mut info);
+    if output.is_empty() {
+        return Ok(None);
+    }
+    // Control characters, NUL among them, are escaped in the output
+    CString::new(output)
+        .map(Some)
+        .map_err(|err| err.to_string())
+}
+
+/// Format the message of a record, see `kke.h`
+///
+/// # Safety
+///
+/// `message` must be NULL or a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn kke_decode_message(message: *const c_char) -> *mut c_char {
+    match decode(message) {
+        Ok(line) => {
+            set_last_error(None);
+            line.map_or(ptr::null_mut(), CString::into_raw)
+        }
+        Err(err) => {
+            set_last_error(Some(err));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Format several messages, see `kke.h`
+///
+/// # Safety
+///
+/// `messages` must point to `count` valid NUL-terminated strings, and
+/// `lines` to room for `count` pointers.
+#[no_mangle]
+pub unsafe extern "C" fn kke_decode_messages(
+    messages: *const *const c_char,
+    count: usize,
+    lines: *mut *mut c_char,
+) -> bool {
+    if count == 0 {
+        set_last_error(None);
+        return true;
+    }
+    if messages.is_null() || lines.is_null() {
+        set_last_error(Some("Messages or lines is NULL".to_string()));
+        return false;
+    }
+    let messages = std::slice::from_raw_parts(messages, count);
+    let lines = std::slice::from_raw_parts_mut(lines, count);
+    lines.fill(ptr::null_mut());
+
+    for (index, message) in messages.iter().enumerate() {
+        match decode(*message) {
+            Ok(line) => lines[index] = line.map_or(ptr::null_mut(), CString::into_raw),
+            Err(err) => {
+                for line in &mut lines[..index] {
+                    kke_string_free(*line);
+                    *line = ptr::null_mut();
+                }
+                set_last_error(Some(format!("Message {}: {}", index, err)));
+                return false;
+            }
+        }
+    }
+    set_last_error(None);
+    true
+}
+
+/// Free a line returned by the library
+///
+/// # Safety
+///
+/// `line` must be NULL or a line returned by this library, not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn kke_string_free(line: *mut c_char) {
+    if !line.is_null() {
+        drop(CString::from_raw(line));
+    }
+}
+
+/// The error of the last failed call on the thread, see `kke.h`
+#[no_mangle]
+pub extern "C" fn kke_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |error| error.as_ptr())
+    })
+}
@@ -0,0 +1,174 @@
+//! Python bindings, the `kusto_kmsg_extract` module, for post-processing
+//! exports in pandas without running the tool per file
+//!
+//! ```python
+//! import kusto_kmsg_extract as kke
+//! import pandas as pd
+//!
+//! frame = pd.DataFrame(kke.read("export.csv"))
+//! print(kke.process_message(message))
+//! formatter = kke.Formatter(disable_decoders=["byte-payload"], signed_hex=True)
+//! ```
+
+use clap::ValueEnum;
+use kusto_kmsg_extract::ansi::AnsiStripper;
+use kusto_kmsg_extract::format::{self, FormatOptions};
+use kusto_kmsg_extract::input::{self, InputFormat, InputOptions, Records};
+use kusto_kmsg_extract::sink::RecordInfo;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::sync::PyOnceLock;
+use pyo3::types::PyDict;
+use std::fmt::Display;
+use std::path::PathBuf;
+
+/// Raise an error of the tool as a Python exception
+fn error(err: impl Display) -> PyErr {
+    PyValueError::new_err(err.to_string())
+}
+
+/// Format a message, giving None when it shows nothing
+fn format_message(options: &FormatOptions, message: &str) -> Option<String> {
+    let mut output = String::new();
+    let mut info = RecordInfo::default();
+    format::process_message(message, None, options, &mut output, &mut info);
+    (!output.is_empty()).then_some(output)
+}
+
+/// Options for formatting records, with the decoders applied to them
+#[pyclass(frozen)]
+struct Formatter {
+    options: FormatOptions,
+}
+
+#[pymethods]
+impl Formatter {
+    #[new]
+    #[pyo3(signature = (*, disable_decoders = Vec::new(), signed_hex = false, strip_ansi = false, truncate = None))]
+    fn new(
+        disable_decoders: Vec<String>,
+        signed_hex: bool,
+        strip_ansi: bool,
+        truncate: Option<usize>,
+    ) -> PyResult<Self> {
+        let mut decoders = format::default_decoders().map_err(error)?;
+        for name in &disable_decoders {
+            decoders.disable(name).map_err(error)?;
+        }
+        let mut options = FormatOptions::new(decoders);
+        options.signed_hex = signed_hex;
+        options.ansi = strip_ansi.then(AnsiStripper::new);
+        options.truncate = truncate;
+        Ok(Formatter { options })
+    }
+
+    /// Format the message of a record, or give None when it shows nothing
+    fn format(&self, message: &str) -> Option<String> {
+        format_message(&self.options, message)
+    }
+
+    /// Names of the decoders, in the order they are tried
+    fn decoders(&self) -> Vec<String> {
+        let decoders = self.options.decoders.iter();
+        decoders.map(|decoder| decoder.name().to_string()).collect()
+    }
+}
+
+/// Formatter with no option, used when none is given
+static DEFAULT: PyOnceLock<Py<Formatter>> = PyOnceLock::new();
+
+fn default_formatter(py: Python<'_>) -> PyResult<&Py<Formatter>> {
+    DEFAULT.get_or_try_init(py, || {
+        Py::new(py, Formatter::new(Vec::new(), false, false, None)?)
+    })
+}
+
+/// Format the message of a record like the tool given no option, or give
+/// None when it shows nothing
+#[pyfunction]
+fn process_message(py: Python<'_>, message: &str) -> PyResult<Option<String>> {
+    Ok(default_formatter(py)?.get().format(message))
+}
+
+/// Names of the decoders applied given no option
+#[pyfunction]
+fn decoders(py: Python<'_>) -> PyResult<Vec<String>> {
+    Ok(default_formatter(py)?.get().decoders())
+}
+
+/// The records of an export, formatted as they are read, as dicts of
+/// timestamp, level, target, vp and text
+#[pyclass(unsendable)]
+struct Reader {
+    records: Records,
+    formatter: Py<Formatter>,
+    output: String,
+    info: RecordInfo,
+}
+
+#[pymethods]
+impl Reader {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__<'py>(&mut self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyDict>>> {
+        let options = &self.formatter.get().options;
+        for record in self.records.by_ref() {
+            let record = record.map_err(error)?;
+            format::format_record(record.borrowed(), options, &mut self.output, &mut self.info);
+            if self.output.is_empty() {
+                continue;
+            }
+
+            let row = PyDict::new(py);
+            row.set_item("timestamp", &self.info.timestamp)?;
+            row.set_item("level", &self.info.level)?;
+            row.set_item("target", &self.info.target)?;
+            row.set_item("vp", self.info.vp)?;
+            row.set_item("text", &self.output)?;
+            return Ok(Some(row));
+        }
+        Ok(None)
+    }
+}
+
+/// Read the records of an export, as `--format` reads them
+#[pyfunction]
+#[pyo3(signature = (path, format = "csv", formatter = None))]
+fn read(
+    py: Python<'_>,
+    path: PathBuf,
+    format: &str,
+    formatter: Option<Py<Formatter>>,
+) -> PyResult<Reader> {
+    let input_options = InputOptions {
+        format: InputFormat::from_str(format, true)
+            .map_err(|_| error(format!("Unknown input format '{}'", format)))?,
+        strict: false,
+        follow: false,
+        mmap: true,
+    };
+    let records = input::open(&path, &input_options).map_err(error)?;
+    let formatter = match formatter {
+        Some(formatter) => formatter,
+        None => default_formatter(py)?.clone_ref(py),
+    };
+    Ok(Reader {
+        records,
+        formatter,
+        output: String::new(),
+        info: RecordInfo::default(),
+    })
+}
+
+#[pymodule]
+#[pyo3(name = "kusto_kmsg_extract")]
+fn bindings(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Formatter>()?;
+    m.add_class::<Reader>()?;
+    m.add_function(wrap_pyfunction!(process_message, m)?)?;
+    m.add_function(wrap_pyfunction!(decoders, m)?)?;
+    m.add_function(wrap_pyfunction!(read, m)?)?;
+    Ok(())
+}
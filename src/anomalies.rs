@@ -0,0 +1,270 @@
+//! Detection of message templates whose rate changes shortly before a
+//! failure
+//!
+//! Messages are reduced to templates by replacing their numbers, and the
+//! rate of each template in a window before the failure is compared with
+//! its rate over the rest of the log. Templates that first appear in the
+//! window, or that are logged much more often there, are likely related to
+//! the failure.
+
+use regex::Regex;
+use std::collections::HashMap;
+use std::error::Error;
+
+#[cfg(feature = "arrow")]
+use crate::columnar;
+use crate::first_error::FATAL_PATTERN;
+use crate::input::Records;
+use crate::report::{for_each_event, print_table, Event};
+use crate::time::{format_timestamp, parse_timestamp};
+use crate::units::format_duration;
+
+/// Fewest records of a template in the window for a rate spike to count
+const MIN_SPIKE_COUNT: u64 = 3;
+
+/// Records of one template
+#[derive(Default)]
+struct TemplateStats {
+    /// Records before the window
+    baseline: u64,
+    /// Records in the window
+    window: u64,
+    /// First time the template appears in the window
+    first_in_window: Option<i64>,
+}
+
+/// Template counts around the failure
+struct Summary {
+    /// Time of the first timestamped record
+    start: i64,
+    /// Time of the failure and what it is
+    failure: i64,
+    cause: &'static str,
+    window_start: i64,
+    templates: HashMap<String, TemplateStats>,
+}
+
+/// Finds the time of the first fatal event and reduces messages to
+/// templates
+struct Templates {
+    number: Regex,
+    fatal: Regex,
+    first_fatal: Option<i64>,
+}
+
+impl Templates {
+    fn new() -> Self {
+        Templates {
+            number: Regex::new(r"0x[0-9a-fA-F]+|\d+").unwrap(),
+            fatal: Regex::new(FATAL_PATTERN).unwrap(),
+            first_fatal: None,
+        }
+    }
+
+    /// The time and template of a record, if it has a time
+    fn read(&mut self, event: &Event) -> Option<(i64, String)> {
+        let time = parse_timestamp(event.timestamp)?;
+        if self.fatal.is_match(event.message) {
+            self.first_fatal = Some(self.first_fatal.map_or(time, |first| first.min(time)));
+        }
+        let template = self.number.replace_all(event.message, "#");
+        Some((time, format!("{}: {}", event.target, template)))
+    }
+
+    /// The failure, the first fatal event or else the end of the log
+    fn failure(&self, end: i64) -> (i64, &'static str) {
+        match self.first_fatal {
+            Some(time) => (time, "first fatal event"),
+            None => (end, "end of log"),
+        }
+    }
+}
+
+/// Count each template before and in the window before the failure
+#[cfg(not(feature = "arrow"))]
+fn collect(records: Records, window: u64) -> Result<Option<Summary>, Box<dyn Error>> {
+    let mut reader = Templates::new();
+    let mut events: Vec<(i64, String)> = Vec::new();
+    for_each_event(records, |event| events.extend(reader.read(event)))?;
+
+    let (Some(start), Some(end)) = (
+        events.iter().map(|(time, _)| *time).min(),
+        events.iter().map(|(time, _)| *time).max(),
+    ) else {
+        return Ok(None);
+    };
+    let (failure, cause) = reader.failure(end);
+    let window_start = failure.saturating_sub(window as i64);
+
+    let mut templates: HashMap<String, TemplateStats> = HashMap::new();
+    for (time, template) in events {
+        if time > failure {
+            continue;
+        }
+        let stats = templates.entry(template).or_default();
+        if time < window_start {
+            stats.baseline += 1;
+        } else {
+            stats.window += 1;
+            stats.first_in_window = Some(stats.first_in_window.map_or(time, |t| t.min(time)));
+        }
+    }
+
+    Ok(Some(Summary {
+        start,
+        failure,
+        cause,
+        window_start,
+        templates,
+    }))
+}
+
+/// Count each template before and in the window before the failure, from
+/// columns of the times and templates of the records
+#[cfg(feature = "arrow")]
+fn collect(records: Records, window: u64) -> Result<Option<Summary>, Box<dyn Error>> {
+    use arrow_arith::aggregate;
+    use arrow_array::builder::{Int64Builder, StringBuilder};
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::Int64Type;
+    use arrow_array::{ArrayRef, Int64Array};
+    use arrow_ord::cmp;
+    use arrow_select::filter::filter;
+    use std::sync::Arc;
+
+    let mut reader = Templates::new();
+    let mut times = Int64Builder::new();
+    let mut templates = StringBuilder::new();
+    for_each_event(records, |event| {
+        if let Some((time, template)) = reader.read(event) {
+            times.append_value(time);
+            templates.append_value(template);
+        }
+    })?;
+    let times = times.finish();
+    let templates = templates.finish();
+
+    let (Some(start), Some(end)) = (aggregate::min(&times), aggregate::max(&times)) else {
+        return Ok(None);
+    };
+    let (failure, cause) = reader.failure(end);
+    let window_start = failure.saturating_sub(window as i64);
+
+    // Records after the failure are left out
+    let before = cmp::lt_eq(&times, &Int64Array::new_scalar(failure))?;
+    let times: ArrayRef = Arc::new(filter(&times, &before)?);
+    let templates: ArrayRef = filter(&templates, &before)?;
+
+    let mut counts = HashMap::new();
+    let groups = columnar::group_by(std::slice::from_ref(&templates), &[])?;
+    for group in &groups.ranges {
+        let template = templates.as_string::<i32>().value(groups.first(group));
+        let times = groups.take(&times, group)?;
+        let in_window = cmp::gt_eq(&times, &Int64Array::new_scalar(window_start))?;
+        let window_times = filter(&times, &in_window)?;
+        let window = in_window.true_count() as u64;
+        counts.insert(
+            template.to_string(),
+            TemplateStats {
+                baseline: group.len() as u64 - window,
+                window,
+                first_in_window: aggregate::min(window_times.as_primitive::<Int64Type>()),
+            },
+        );
+    }
+
+    Ok(Some(Summary {
+        start,
+        failure,
+        cause,
+        window_start,
+        templates: counts,
+    }))
+}
+
+/// Print the templates that are new or spike in the `window` nanoseconds
+/// before the failure, which is the first fatal event or else the end of
+/// the log, when their rate grows at least `spike` times
+pub fn anomalies(records: Records, window: u64, spike: f64) -> Result<(), Box<dyn Error>> {
+    let Some(Summary {
+        start,
+        failure,
+        cause,
+        window_start,
+        templates,
+    }) = collect(records, window)?
+    else {
+        println!("No timestamped records found");
+        return Ok(());
+    };
+
+    println!(
+        "Window of {} before the {} at {}",
+        format_duration(window as f64),
+        cause,
+        format_timestamp(failure)
+    );
+    println!();
+
+    // Rates are per second of the baseline and of the window
+    let baseline_secs = (window_start - start).max(0) as f64 / 1e9;
+    let window_secs = (window as f64 / 1e9).max(f64::EPSILON);
+    let mut found: Vec<(f64, Vec<String>)> = Vec::new();
+    for (template, stats) in &templates {
+        let Some(first) = stats.first_in_window else {
+            continue;
+        };
+        let window_rate = stats.window as f64 / window_secs;
+        let (kind, ratio) = if stats.baseline == 0 {
+            // Without a baseline there is nothing to call new
+            if baseline_secs == 0.0 {
+                continue;
+            }
+            ("new", f64::INFINITY)
+        } else {
+            let baseline_rate = stats.baseline as f64 / baseline_secs.max(f64::EPSILON);
+            let ratio = window_rate / baseline_rate;
+            if stats.window < MIN_SPIKE_COUNT || ratio < spike {
+                continue;
+            }
+            ("spike", ratio)
+        };
+
+        found.push((
+            ratio,
+            vec![
+                kind.to_string(),
+                stats.window.to_string(),
+                stats.baseline.to_string(),
+                if ratio.is_finite() {
+                    format!("{:.1}x", ratio)
+                } else {
+                    "-".to_string()
+                },
+                format_timestamp(first),
+                template.to_string(),
+            ],
+        ));
+    }
+
+    if found.is_empty() {
+        println!("No anomalies found");
+        return Ok(());
+    }
+
+    // New templates first, then the largest spikes, each by first sighting
+    found.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1[4].cmp(&b.1[4])));
+    let rows: Vec<Vec<String>> = found.into_iter().map(|(_, row)| row).collect();
+    print_table(
+        &[
+            "KIND",
+            "IN WINDOW",
+            "BEFORE",
+            "RATE CHANGE",
+            "FIRST",
+            "TEMPLATE",
+        ],
+        &rows,
+    );
+    Ok(())
+}
@@ -0,0 +1,46 @@
+//! Removal of ANSI escape sequences, like the color codes some guests
+//! embed in their messages
+
+use regex::Regex;
+use serde_json::Value;
+use std::borrow::Cow;
+
+/// Pattern of a CSI, OSC or two-character escape sequence
+pub const ESCAPE_PATTERN: &str = r"\x1b(\[[0-?]*[ -/]*[@-~]|\][^\x07\x1b]*(\x07|\x1b\\)|[@-Z\\-_])";
+
+/// Strips escape sequences from text and the strings of records
+pub struct AnsiStripper {
+    regex: Regex,
+}
+
+impl AnsiStripper {
+    pub fn new() -> Self {
+        AnsiStripper {
+            regex: Regex::new(ESCAPE_PATTERN).unwrap(),
+        }
+    }
+
+    /// Check whether a message may hold an escape sequence, raw or escaped
+    /// in JSON
+    pub fn may_contain(message: &str) -> bool {
+        message.contains('\x1b') || message.contains("\\u001b") || message.contains("\\u001B")
+    }
+
+    pub fn strip<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        self.regex.replace_all(text, "")
+    }
+
+    /// Strip every string in a JSON value
+    pub fn strip_value(&self, value: &mut Value) {
+        match value {
+            Value::String(text) => {
+                if let Cow::Owned(stripped) = self.strip(text) {
+                    *text = stripped;
+                }
+            }
+            Value::Array(values) => values.iter_mut().for_each(|value| self.strip_value(value)),
+            Value::Object(map) => map.values_mut().for_each(|value| self.strip_value(value)),
+            _ => {}
+        }
+    }
+}
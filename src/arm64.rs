@@ -0,0 +1,374 @@
+//! Transforms and decoders for aarch64 register dumps
+
+use regex::Regex;
+use serde_json::{Map, Value};
+
+use crate::decoder::{Conditions, Decoded, Decoder, FieldContext};
+use crate::numbers::{self, DECIMAL};
+use crate::validate;
+
+/// Exception class names from ESR_ELx.EC
+const EXCEPTION_CLASSES: &[(u64, &str)] = &[
+    (0x00, "unknown reason"),
+    (0x01, "WFI/WFE"),
+    (0x03, "MCR/MRC CP15"),
+    (0x04, "MCRR/MRRC CP15"),
+    (0x05, "MCR/MRC CP14"),
+    (0x06, "LDC/STC CP14"),
+    (0x07, "SVE/SIMD/FP access"),
+    (0x0a, "LD64B/ST64B"),
+    (0x0c, "MRRC CP14"),
+    (0x0d, "branch target exception"),
+    (0x0e, "illegal execution state"),
+    (0x11, "SVC AArch32"),
+    (0x12, "HVC AArch32"),
+    (0x13, "SMC AArch32"),
+    (0x15, "SVC AArch64"),
+    (0x16, "HVC AArch64"),
+    (0x17, "SMC AArch64"),
+    (0x18, "MSR/MRS/system instruction"),
+    (0x19, "SVE access"),
+    (0x1a, "ERET"),
+    (0x1c, "pointer authentication failure"),
+    (0x1d, "SME access"),
+    (0x20, "instruction abort from lower EL"),
+    (0x21, "instruction abort from same EL"),
+    (0x22, "PC alignment fault"),
+    (0x24, "data abort from lower EL"),
+    (0x25, "data abort from same EL"),
+    (0x26, "SP alignment fault"),
+    (0x28, "FP exception AArch32"),
+    (0x2c, "FP exception AArch64"),
+    (0x2f, "SError"),
+    (0x30, "breakpoint from lower EL"),
+    (0x31, "breakpoint from same EL"),
+    (0x32, "software step from lower EL"),
+    (0x33, "software step from same EL"),
+    (0x34, "watchpoint from lower EL"),
+    (0x35, "watchpoint from same EL"),
+    (0x38, "BKPT AArch32"),
+    (0x3a, "vector catch AArch32"),
+    (0x3c, "BRK AArch64"),
+];
+
+pub const ESR_KEYS: &[&str] = &["esr", "esr_el1", "esr_el2"];
+const PSTATE_KEYS: &[&str] = &["pstate", "cpsr", "spsr", "spsr_el1", "spsr_el2"];
+
+/// Describe a data or instruction fault status code
+fn decode_fault_status(fsc: u64) -> String {
+    let level = fsc & 3;
+
+    match fsc {
+        0x00..=0x03 => format!("address size fault level {}", level),
+        0x04..=0x07 => format!("translation fault level {}", level),
+        0x08..=0x0b => format!("access flag fault level {}", level),
+        0x0c..=0x0f => format!("permission fault level {}", level),
+        0x10 => "synchronous external abort".to_string(),
+        0x11 => "synchronous tag check fault".to_string(),
+        0x14..=0x17 => format!("external abort on table walk level {}", level),
+        0x18 => "synchronous parity/ECC error".to_string(),
+        0x1c..=0x1f => format!("parity/ECC error on table walk level {}", level),
+        0x21 => "alignment fault".to_string(),
+        0x30 => "TLB conflict abort".to_string(),
+        0x31 => "unsupported atomic hardware update".to_string(),
+        _ => format!("fault status 0x{:02x}", fsc),
+    }
+}
+
+/// Decode the ISS of a data abort
+fn decode_data_abort_iss(iss: u64) -> Vec<String> {
+    let mut details = Vec::new();
+
+    // Access size and register are only valid with ISV set
+    if iss & (1 << 24) != 0 {
+        let size = 8 << ((iss >> 22) & 3);
+        let register = (iss >> 16) & 0x1f;
+        let width = if iss & (1 << 15) != 0 { 'x' } else { 'w' };
+        let signed = if iss & (1 << 21) != 0 {
+            " sign-extended"
+        } else {
+            ""
+        };
+        details.push(format!(
+            "{}-bit{} access via {}{}",
+            size, signed, width, register
+        ));
+        if iss & (1 << 14) != 0 {
+            details.push("acquire/release".to_string());
+        }
+    }
+
+    details.push(if iss & (1 << 6) != 0 { "write" } else { "read" }.to_string());
+    details.push(decode_fault_status(iss & 0x3f));
+    if iss & (1 << 7) != 0 {
+        details.push("during stage 1 table walk".to_string());
+    }
+    if iss & (1 << 8) != 0 {
+        details.push("cache maintenance".to_string());
+    }
+    if iss & (1 << 9) != 0 {
+        details.push("external abort".to_string());
+    }
+
+    details
+}
+
+/// Decode the ISS of an instruction abort
+fn decode_instruction_abort_iss(iss: u64) -> Vec<String> {
+    let mut details = vec![decode_fault_status(iss & 0x3f)];
+
+    if iss & (1 << 7) != 0 {
+        details.push("during stage 1 table walk".to_string());
+    }
+    if iss & (1 << 9) != 0 {
+        details.push("external abort".to_string());
+    }
+
+    details
+}
+
+/// Decode the ISS of a trapped MSR, MRS or system instruction
+fn decode_sysreg_iss(iss: u64) -> Vec<String> {
+    let op0 = (iss >> 20) & 3;
+    let op2 = (iss >> 17) & 7;
+    let op1 = (iss >> 14) & 7;
+    let crn = (iss >> 10) & 0xf;
+    let rt = (iss >> 5) & 0x1f;
+    let crm = (iss >> 1) & 0xf;
+    let register = format!("S{}_{}_C{}_C{}_{}", op0, op1, crn, crm, op2);
+
+    let access = if iss & 1 != 0 {
+        format!("MRS x{}, {}", rt, register)
+    } else {
+        format!("MSR {}, x{}", register, rt)
+    };
+
+    vec![access]
+}
+
+/// Whether FAR holds the faulting address for an exception class
+fn has_fault_address(ec: u64) -> bool {
+    matches!(ec, 0x20 | 0x21 | 0x22 | 0x24 | 0x25 | 0x34 | 0x35)
+}
+
+/// Decode an ESR value, including the ISS of common exception classes and
+/// the faulting address from FAR (and the IPA from HPFAR) when supplied
+pub fn decode_fault(esr: u64, far: Option<u64>, hpfar: Option<u64>) -> String {
+    let ec = (esr >> 26) & 0x3f;
+    let il = (esr >> 25) & 1;
+    let iss = esr & 0x1ff_ffff;
+
+    let name = EXCEPTION_CLASSES
+        .iter()
+        .find(|(class, _)| *class == ec)
+        .map(|(_, name)| name.to_string())
+        .unwrap_or_else(|| "reserved".to_string());
+
+    let mut details = match ec {
+        0x01 => vec![["WFI", "WFE", "WFIT", "WFET"][(iss & 3) as usize].to_string()],
+        0x15..=0x17 | 0x3c => vec![format!("imm=0x{:x}", iss & 0xffff)],
+        0x18 => decode_sysreg_iss(iss),
+        0x20 | 0x21 => decode_instruction_abort_iss(iss),
+        0x24 | 0x25 => decode_data_abort_iss(iss),
+        _ => vec![format!("ISS=0x{:x}", iss)],
+    };
+
+    // FnV marks FAR as not holding a valid address for aborts
+    let far_valid =
+        has_fault_address(ec) && !(matches!(ec, 0x20 | 0x21 | 0x24 | 0x25) && iss & (1 << 10) != 0);
+    if let Some(far) = far.filter(|_| far_valid) {
+        match hpfar {
+            Some(hpfar) => details.push(format!(
+                "FAR=0x{:x} IPA=0x{:x}",
+                far,
+                ((hpfar >> 4) << 12) | (far & 0xfff)
+            )),
+            None => details.push(format!("FAR=0x{:x}", far)),
+        }
+    }
+
+    format!(
+        "EC=0x{:02x} {}, IL={}, {}",
+        ec,
+        name,
+        il,
+        details.join(", ")
+    )
+}
+
+/// Decode the condition flags, interrupt masks and mode of a PSTATE/SPSR
+/// value, with set flags in upper case
+pub fn decode_pstate(pstate: u64) -> String {
+    let flag = |bit: u32, name: char| {
+        if pstate & (1 << bit) != 0 {
+            name
+        } else {
+            name.to_ascii_lowercase()
+        }
+    };
+
+    let nzcv: String = [(31, 'N'), (30, 'Z'), (29, 'C'), (28, 'V')]
+        .iter()
+        .map(|&(bit, name)| flag(bit, name))
+        .collect();
+    let daif: String = [(9, 'D'), (8, 'A'), (7, 'I'), (6, 'F')]
+        .iter()
+        .map(|&(bit, name)| flag(bit, name))
+        .collect();
+
+    let mode = if pstate & 0x10 != 0 {
+        "AArch32".to_string()
+    } else {
+        let el = (pstate >> 2) & 3;
+        let sp = if pstate & 1 != 0 { 'h' } else { 't' };
+        format!("EL{}{}", el, sp)
+    };
+
+    format!("{} {} {}", mode, nzcv, daif)
+}
+
+/// Check whether a string looks like an aarch64 register dump
+pub fn is_register_dump(text: &str) -> bool {
+    text.contains("pstate: ")
+        || text.contains("esr_el2: ")
+        || (text.contains("x0: ") && (text.contains("pc: ") || text.contains("sp: ")))
+}
+
+/// Transform aarch64 register dump values to hex format, annotating PSTATE
+/// and ESR values with their decoded meaning
+pub fn transform_register_dump(text: &str) -> String {
+    let far = Regex::new(&format!(r"\bfar(?:_el\d)?: ({})", DECIMAL))
+        .unwrap()
+        .captures(text)
+        .and_then(|caps| numbers::parse_decimal(&caps[1]));
+    let hpfar = Regex::new(&format!(r"\bhpfar_el2: ({})", DECIMAL))
+        .unwrap()
+        .captures(text)
+        .and_then(|caps| numbers::parse_decimal(&caps[1]));
+
+    let register_regex = Regex::new(&format!(
+        r"\b(x\d{{1,2}}|fp|lr|sp|pc|pstate|cpsr|(?:elr|spsr|esr|far|hpfar|sp|vbar|tpidr)_el\d): ({})",
+        DECIMAL
+    ))
+    .unwrap();
+
+    register_regex
+        .replace_all(text, |caps: &regex::Captures| {
+            let reg = &caps[1];
+            let num = numbers::parse_decimal(&caps[2]).unwrap_or(0);
+
+            if PSTATE_KEYS.contains(&reg) {
+                format!("{}: 0x{:x} ({})", reg, num, decode_pstate(num))
+            } else if ESR_KEYS.contains(&reg) {
+                format!("{}: 0x{:x} ({})", reg, num, decode_fault(num, far, hpfar))
+            } else {
+                format!("{}: 0x{:x}", reg, num)
+            }
+        })
+        .to_string()
+}
+
+/// Produce an annotation for a numeric ESR or PSTATE field
+///
+/// ESR values are paired with the FAR of the same exception level, and
+/// HPFAR_EL2 for stage 2 faults, when the record carries them.
+pub fn annotate_field(key: &str, value: &Value, fields: &Map<String, Value>) -> Option<String> {
+    let num = value.as_u64()?;
+
+    if ESR_KEYS.contains(&key) {
+        let far_key = key.replacen("esr", "far", 1);
+        let far = fields.get(&far_key).and_then(Value::as_u64);
+        let hpfar = fields.get("hpfar_el2").and_then(Value::as_u64);
+        Some(decode_fault(num, far, hpfar))
+    } else if PSTATE_KEYS.contains(&key) {
+        Some(decode_pstate(num))
+    } else {
+        None
+    }
+}
+
+/// Decoder for aarch64 register dumps in any string field
+pub struct RegisterDumpDecoder {
+    conditions: Conditions,
+}
+
+impl RegisterDumpDecoder {
+    pub fn new() -> Self {
+        RegisterDumpDecoder {
+            conditions: Conditions::any(),
+        }
+    }
+}
+
+impl Decoder for RegisterDumpDecoder {
+    fn name(&self) -> &str {
+        "arm64-register-dump"
+    }
+
+    fn description(&self) -> String {
+        "Rewrites aarch64 register dumps, strings holding pstate, esr_el2 or x0 with pc or sp, as hex and decodes their PSTATE and ESR".to_string()
+    }
+
+    fn conditions(&self) -> &Conditions {
+        &self.conditions
+    }
+
+    fn example(&self) -> Option<(&str, Value)> {
+        Some((
+            "registers",
+            Value::from("x0: 1, x1: 4096, sp: 65536, pc: 4198400, pstate: 965"),
+        ))
+    }
+
+    fn decode(&self, _key: &str, value: &Value, _ctx: &FieldContext) -> Option<Decoded> {
+        let text = value.as_str().filter(|text| is_register_dump(text))?;
+        Some(Decoded::Replace(transform_register_dump(text)))
+    }
+
+    fn validate(&self, _key: &str, value: &Value, _ctx: &FieldContext) -> Option<String> {
+        let text = value.as_str().filter(|text| is_register_dump(text))?;
+        validate::check_dump(text, &[])
+    }
+}
+
+/// Decoder for numeric ESR and PSTATE fields
+pub struct SyndromeDecoder {
+    conditions: Conditions,
+}
+
+impl SyndromeDecoder {
+    pub fn new() -> Self {
+        let keys: Vec<&str> = ESR_KEYS.iter().chain(PSTATE_KEYS).copied().collect();
+        SyndromeDecoder {
+            conditions: Conditions::any().keys(&keys),
+        }
+    }
+}
+
+impl Decoder for SyndromeDecoder {
+    fn name(&self) -> &str {
+        "arm64-syndrome"
+    }
+
+    fn description(&self) -> String {
+        "Annotates ESR values with the exception they report, and PSTATE values with their flags and mode".to_string()
+    }
+
+    fn conditions(&self) -> &Conditions {
+        &self.conditions
+    }
+
+    fn example(&self) -> Option<(&str, Value)> {
+        Some(("esr_el2", Value::from(0x9200_0046u64)))
+    }
+
+    fn decode(&self, key: &str, value: &Value, ctx: &FieldContext) -> Option<Decoded> {
+        annotate_field(key, value, ctx.fields).map(Decoded::Annotate)
+    }
+
+    fn validate(&self, key: &str, value: &Value, _ctx: &FieldContext) -> Option<String> {
+        let num = value.as_u64()?;
+        (ESR_KEYS.contains(&key) && num >> 56 != 0)
+            .then(|| format!("{} 0x{:x} sets the reserved bits 56-63", key, num))
+    }
+}
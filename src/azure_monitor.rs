@@ -0,0 +1,210 @@
+//! Forwarding of records to a Log Analytics workspace through the Azure
+//! Monitor Logs Ingestion API
+//!
+//! Records are posted to a stream of a data collection rule (DCR) as rows
+//! with these columns, which the stream declaration must match:
+//!
+//! | Column        | Type     |
+//! |---------------|----------|
+//! | TimeGenerated | datetime |
+//! | Level         | string   |
+//! | Target        | string   |
+//! | Vp            | long     |
+//! | Message       | string   |
+//!
+//! Requests are authorized with an Entra ID token for
+//! `https://monitor.azure.com`, taken from `AZURE_MONITOR_TOKEN` or else
+//! from the Azure CLI.
+
+use serde::Serialize;
+use std::error::Error;
+use std::process::Command;
+use std::time::SystemTime;
+
+use crate::sink::{RecordInfo, Sink};
+use crate::time::{format_timestamp, parse_timestamp};
+use crate::upload::{Upload, UploadError, UploadOptions, Uploader};
+
+/// Version of the Logs Ingestion API
+const API_VERSION: &str = "2023-01-01";
+
+/// Resource the access token must be issued for
+const TOKEN_RESOURCE: &str = "https://monitor.azure.com";
+
+/// Environment variable holding an access token to use instead of one from
+/// the Azure CLI
+const TOKEN_VARIABLE: &str = "AZURE_MONITOR_TOKEN";
+
+/// Largest request body sent, below the 1 MB limit of the API
+const MAX_BATCH_BYTES: usize = 900 * 1024;
+
+/// A row of the DCR stream
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Row {
+    time_generated: String,
+    level: String,
+    target: String,
+    vp: Option<u64>,
+    message: String,
+}
+
+/// Posts batches to the stream of a DCR
+struct AzureMonitorClient {
+    url: String,
+    token: String,
+}
+
+/// Sends records to a Log Analytics workspace in batches
+pub struct AzureMonitorSink {
+    uploader: Uploader,
+    /// Pending rows, each serialized as JSON
+    rows: Vec<String>,
+    bytes: usize,
+}
+
+/// Access token for the Logs Ingestion API
+fn access_token() -> Result<String, Box<dyn Error>> {
+    if let Ok(token) = std::env::var(TOKEN_VARIABLE) {
+        return Ok(token);
+    }
+
+    let az = if cfg!(windows) { "az.cmd" } else { "az" };
+    let output = Command::new(az)
+        .args(["account", "get-access-token", "--resource", TOKEN_RESOURCE])
+        .args(["--query", "accessToken", "--output", "tsv"])
+        .output()
+        .map_err(|err| {
+            format!(
+                "Failed to run the Azure CLI for an access token, set {} instead: {}",
+                TOKEN_VARIABLE, err
+            )
+        })?;
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to get an access token from the Azure CLI: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8(output.stdout)?.trim().to_string())
+}
+
+impl Upload for AzureMonitorClient {
+    /// Post a JSON array body, refreshing the token once if it expired
+    fn upload(&mut self, body: &str) -> Result<(), UploadError> {
+        let mut refreshed = false;
+        loop {
+            let result = ureq::post(&self.url)
+                .set("Authorization", &format!("Bearer {}", self.token))
+                .set("Content-Type", "application/json")
+                .send_string(body);
+            match result {
+                Ok(_) => return Ok(()),
+                // Tokens last about an hour, less than a long followed run
+                Err(ureq::Error::Status(401, _)) if !refreshed => {
+                    self.token = access_token().map_err(|err| err.to_string())?;
+                    refreshed = true;
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    fn failure(&self) -> String {
+        "Failed to send records to Azure Monitor".to_string()
+    }
+
+    fn destination(&self) -> String {
+        format!("Azure Monitor at {}", self.url)
+    }
+}
+
+impl AzureMonitorSink {
+    /// Post to the stream `stream` of the DCR with immutable ID `dcr_id`
+    /// through the data collection endpoint `endpoint`
+    pub fn new(
+        endpoint: &str,
+        dcr_id: &str,
+        stream: &str,
+        options: &UploadOptions,
+    ) -> Result<Self, Box<dyn Error>> {
+        if !endpoint.starts_with("http://") && !endpoint.starts_with("https://") {
+            return Err(format!(
+                "Invalid data collection endpoint '{}', expected http:// or https://",
+                endpoint
+            )
+            .into());
+        }
+
+        let client = AzureMonitorClient {
+            url: format!(
+                "{}/dataCollectionRules/{}/streams/{}?api-version={}",
+                endpoint.trim_end_matches('/'),
+                dcr_id,
+                stream,
+                API_VERSION
+            ),
+            // A dry run sends nothing, so it needs no sign-in
+            token: if options.dry_run {
+                String::new()
+            } else {
+                access_token()?
+            },
+        };
+        Ok(AzureMonitorSink {
+            uploader: Uploader::new(client, options.clone())?,
+            rows: Vec::new(),
+            bytes: 0,
+        })
+    }
+}
+
+impl Sink for AzureMonitorSink {
+    fn write_record(&mut self, text: &str, info: &RecordInfo) -> Result<(), Box<dyn Error>> {
+        // Lines without a time are stamped with the time they were sent
+        let time_generated = match parse_timestamp(&info.timestamp) {
+            Some(ns) => format_timestamp(ns),
+            None => format_timestamp(
+                SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .map_or(0, |duration| duration.as_nanos() as i64),
+            ),
+        };
+        let row = serde_json::to_string(&Row {
+            time_generated,
+            level: info.level.clone(),
+            target: info.target.clone(),
+            vp: info.vp,
+            message: text.to_string(),
+        })?;
+
+        // The body is an opening bracket, then each row followed by a comma
+        // or the closing bracket
+        if !self.rows.is_empty() && 1 + self.bytes + row.len() + 1 > MAX_BATCH_BYTES {
+            self.flush()?;
+        }
+        self.bytes += row.len() + 1;
+        self.rows.push(row);
+        if self.uploader.add_record() {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+
+        let body = format!("[{}]", self.rows.join(","));
+        self.rows.clear();
+        self.bytes = 0;
+        self.uploader.send(body)
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.flush()?;
+        self.uploader.finish()
+    }
+}
@@ -0,0 +1,203 @@
+//! Reading of exports stored in Azure Blob Storage, with the `blob-inputs`
+//! feature
+//!
+//! Inputs given as `https://` URLs, usually a blob URL with a SAS token,
+//! are downloaded in blocks with ranged GETs. A tokio runtime keeps the
+//! next few blocks downloading on its blocking pool while the current one
+//! is decoded, so fetching overlaps with decoding instead of alternating
+//! with it. Blocks throttled with a 429 or 503 are retried after the
+//! `Retry-After` delay of the service, or else after a delay doubling each
+//! time.
+//!
+//! The query string of a URL is left out of messages, as it holds the SAS
+//! token.
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::io::{self, Cursor, Read};
+use std::ops::Range;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use tokio::task::JoinHandle;
+
+/// Bytes downloaded in each request
+const BLOCK_SIZE: u64 = 8 << 20;
+
+/// Blocks downloading ahead of the one being read
+const PREFETCH: usize = 4;
+
+/// Times a throttled block is retried before giving up
+const RETRIES: u32 = 5;
+
+/// Delay before the first retry of a throttled block without `Retry-After`
+const FIRST_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Longest delay before retrying a throttled block
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Reads a blob block by block, downloading the next blocks in the
+/// background
+pub struct BlobReader {
+    runtime: Runtime,
+    url: String,
+    size: u64,
+    /// Offset of the next block to start downloading
+    next: u64,
+    pending: VecDeque<JoinHandle<Result<Vec<u8>, String>>>,
+    block: Cursor<Vec<u8>>,
+}
+
+/// The URL without its query string, to show in messages
+fn redact(url: &str) -> &str {
+    url.split_once('?').map_or(url, |(path, _)| path)
+}
+
+/// Describe a failed request without its URL, which holds the SAS token
+fn describe(err: &ureq::Error) -> String {
+    match err {
+        ureq::Error::Status(status, response) => {
+            format!("{} {}", status, response.status_text())
+        }
+        ureq::Error::Transport(transport) => match transport.message() {
+            Some(message) => format!("{}: {}", transport.kind(), message),
+            None => transport.kind().to_string(),
+        },
+    }
+}
+
+/// Delay a throttling service asked for, if the error is a 429 or 503
+fn throttled(err: &ureq::Error) -> Option<Option<Duration>> {
+    match err {
+        ureq::Error::Status(429 | 503, response) => Some(
+            response
+                .header("Retry-After")
+                .and_then(|value| value.trim().parse().ok())
+                .map(Duration::from_secs),
+        ),
+        _ => None,
+    }
+}
+
+/// Download a range of bytes of a blob
+fn get_range(url: &str, range: &Range<u64>) -> Result<Vec<u8>, Box<ureq::Error>> {
+    let response = ureq::get(url)
+        .set("Range", &format!("bytes={}-{}", range.start, range.end - 1))
+        .call()?;
+    let mut bytes = Vec::with_capacity((range.end - range.start) as usize);
+    response
+        .into_reader()
+        .take(range.end - range.start)
+        .read_to_end(&mut bytes)
+        .map_err(ureq::Error::from)?;
+    Ok(bytes)
+}
+
+/// Download a block on the blocking pool, retrying while the service
+/// throttles it
+async fn download(url: String, range: Range<u64>) -> Result<Vec<u8>, String> {
+    let failure = |err: &dyn std::fmt::Display| {
+        format!(
+            "Failed to download bytes {}..{} of {}: {}",
+            range.start,
+            range.end,
+            redact(&url),
+            err
+        )
+    };
+
+    let mut backoff = FIRST_BACKOFF;
+    let mut attempt = 0;
+    loop {
+        let (block_url, block_range) = (url.clone(), range.clone());
+        let result = tokio::task::spawn_blocking(move || get_range(&block_url, &block_range))
+            .await
+            .map_err(|err| failure(&err))?;
+        let err = match result {
+            Ok(bytes) if bytes.len() as u64 == range.end - range.start => return Ok(bytes),
+            Ok(bytes) => {
+                return Err(failure(&format!(
+                    "received {} bytes, expected {}",
+                    bytes.len(),
+                    range.end - range.start
+                )))
+            }
+            Err(err) => err,
+        };
+        let Some(retry_after) = throttled(&err).filter(|_| attempt < RETRIES) else {
+            return Err(failure(&describe(&err)));
+        };
+
+        let delay = retry_after.unwrap_or(backoff).min(MAX_BACKOFF);
+        eprintln!(
+            "Download throttled, retrying in {}s: {}",
+            delay.as_secs_f64(),
+            describe(&err)
+        );
+        tokio::time::sleep(delay).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+        attempt += 1;
+    }
+}
+
+/// Open a blob for reading, starting the download of its first blocks
+pub fn open(url: &str) -> Result<BlobReader, Box<dyn Error>> {
+    let response = ureq::head(url)
+        .call()
+        .map_err(|err| format!("Failed to open {}: {}", redact(url), describe(&err)))?;
+    let size = response
+        .header("Content-Length")
+        .and_then(|value| value.trim().parse().ok())
+        .ok_or_else(|| format!("Failed to open {}: no Content-Length", redact(url)))?;
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .max_blocking_threads(PREFETCH)
+        .thread_name("download")
+        .enable_time()
+        .build()
+        .map_err(|err| format!("Failed to start the download runtime: {}", err))?;
+    let mut reader = BlobReader {
+        runtime,
+        url: url.to_string(),
+        size,
+        next: 0,
+        pending: VecDeque::new(),
+        block: Cursor::new(Vec::new()),
+    };
+    reader.prefetch();
+    Ok(reader)
+}
+
+impl BlobReader {
+    /// Start downloading blocks until enough are on their way
+    fn prefetch(&mut self) {
+        while self.pending.len() < PREFETCH && self.next < self.size {
+            let range = self.next..(self.next + BLOCK_SIZE).min(self.size);
+            self.next = range.end;
+            let download = download(self.url.clone(), range);
+            self.pending.push_back(self.runtime.spawn(download));
+        }
+    }
+}
+
+impl Read for BlobReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = self.block.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+
+            let Some(pending) = self.pending.pop_front() else {
+                return Ok(0);
+            };
+            let block = self
+                .runtime
+                .block_on(pending)
+                .map_err(io::Error::other)?
+                .map_err(io::Error::other)?;
+            self.block = Cursor::new(block);
+            self.prefetch();
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Conversion of boot-relative record timestamps to absolute time
+//!
+//! Some sources stamp records with the seconds since boot, like dmesg,
+//! either as a number or as text such as `[   12.345678]`. Given the time
+//! of boot, these are converted to ISO 8601 so the records sort and merge
+//! with other logs. The boot time may also be derived from a record that
+//! carries both a boot-relative time and a wall-clock time, such as a
+//! message logging the time the clock was set to.
+
+use regex::Regex;
+use serde_json::Value;
+use std::error::Error;
+
+use crate::input::Records;
+use crate::time::parse_timestamp;
+
+/// Rows searched for a record giving the boot time
+const DETECT_ROWS: usize = 10_000;
+
+/// Where the time of boot comes from
+#[derive(Clone, Copy, Debug)]
+pub enum BootTime {
+    /// Nanoseconds since the Unix epoch
+    At(i64),
+    /// Derived from the first record with a wall-clock time
+    Auto,
+}
+
+/// Parse a `--boot-time`, which is a timestamp or `auto`, used as a clap
+/// value parser
+pub fn parse_boot_time(text: &str) -> Result<BootTime, String> {
+    if text == "auto" {
+        return Ok(BootTime::Auto);
+    }
+    parse_timestamp(text).map(BootTime::At).ok_or_else(|| {
+        format!(
+            "Invalid boot time '{}', expected a timestamp like 2024-05-01T10:00:00Z or auto",
+            text
+        )
+    })
+}
+
+/// Seconds since boot held by a record timestamp, if it is boot-relative
+pub fn relative_seconds(value: &Value) -> Option<f64> {
+    let seconds = match value {
+        Value::Number(number) => number.as_f64()?,
+        Value::String(text) => {
+            let text = text.trim();
+            let text = text
+                .strip_prefix('[')
+                .and_then(|text| text.strip_suffix(']'))
+                .unwrap_or(text);
+            text.trim().parse().ok()?
+        }
+        _ => return None,
+    };
+    (seconds.is_finite() && seconds >= 0.0).then_some(seconds)
+}
+
+/// Find the time of boot from the first record pairing a boot-relative
+/// time with a wall-clock one
+///
+/// The boot-relative time is the record timestamp or the monotonic time of
+/// the source, and the wall-clock time is the record timestamp or an
+/// ISO 8601 time in its message.
+pub fn detect(records: Records) -> Result<Option<i64>, Box<dyn Error>> {
+    let wall_clock =
+        Regex::new(r"\d{4}-\d\d-\d\d[T ]\d\d:\d\d:\d\d(\.\d+)?(Z|[+-]\d\d:?\d\d| ?UTC)?").unwrap();
+
+    for record in records.take(DETECT_ROWS) {
+        let record = record?;
+        let Some(json) = record.json() else {
+            continue;
+        };
+        let timestamp = json.get("timestamp").unwrap_or(&Value::Null);
+        let relative_ns = match relative_seconds(timestamp) {
+            Some(seconds) => (seconds * 1e9) as i64,
+            None => match record.monotonic_us {
+                Some(us) => us as i64 * 1000,
+                None => continue,
+            },
+        };
+
+        let message = json
+            .pointer("/fields/message")
+            .and_then(Value::as_str)
+            .unwrap_or("");
+        let wall_ns = timestamp.as_str().and_then(parse_timestamp).or_else(|| {
+            let found = wall_clock.find(message)?.as_str();
+            parse_timestamp(found.trim_end_matches("UTC").trim_end())
+        });
+        if let Some(wall_ns) = wall_ns {
+            return Ok(Some(wall_ns - relative_ns));
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,158 @@
+//! Build information logged at boot, telling which build produced a log
+
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::error::Error;
+
+use crate::input::Records;
+use crate::report::{for_each_event, print_table};
+
+/// Fields holding the version of the build
+pub const VERSION_KEYS: &[&str] = &[
+    "version",
+    "build_version",
+    "firmware_version",
+    "openhcl_version",
+    "crate_version",
+];
+
+/// Fields holding the commit the build was made from
+const COMMIT_KEYS: &[&str] = &[
+    "git_revision",
+    "git_commit",
+    "git_hash",
+    "commit",
+    "commit_hash",
+    "revision",
+];
+
+/// Fields holding the branch the build was made from
+const BRANCH_KEYS: &[&str] = &["git_branch", "branch"];
+
+/// Fields holding the time of the build
+const BUILD_TIME_KEYS: &[&str] = &["build_time", "build_timestamp", "build_date", "built_at"];
+
+/// Rows read looking for the build version before giving up, as it is
+/// logged early in boot
+const DETECT_ROWS: usize = 10_000;
+
+/// Text of a field holding a string or a number
+fn field_text(fields: &Map<String, Value>, keys: &[&str]) -> Option<String> {
+    keys.iter().find_map(|key| match fields.get(*key)? {
+        Value::String(text) if !text.is_empty() => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        _ => None,
+    })
+}
+
+/// What a build banner says about the build
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct Banner {
+    version: Option<String>,
+    commit: Option<String>,
+    branch: Option<String>,
+    build_time: Option<String>,
+}
+
+impl Banner {
+    /// Read the build information of a record, if it is a build banner
+    fn parse(message: &str, fields: &Map<String, Value>) -> Option<Self> {
+        let banner = Banner {
+            version: field_text(fields, VERSION_KEYS),
+            commit: field_text(fields, COMMIT_KEYS),
+            branch: field_text(fields, BRANCH_KEYS),
+            build_time: field_text(fields, BUILD_TIME_KEYS),
+        };
+
+        // A version field alone is common in other records, so it only
+        // counts in a message about the build
+        let message = message.to_lowercase();
+        let about_build = message.contains("build") || message.contains("version");
+        let found = banner.commit.is_some()
+            || banner.build_time.is_some()
+            || (about_build && (banner.version.is_some() || banner.branch.is_some()));
+        found.then_some(banner)
+    }
+}
+
+/// Find the build version in the first records of a log
+pub fn detect_version(records: Records) -> Result<Option<String>, Box<dyn Error>> {
+    for record in records.take(DETECT_ROWS) {
+        let record = record?;
+        let Some(json) = record.json() else {
+            continue;
+        };
+        let Some(fields) = json.get("fields").and_then(Value::as_object) else {
+            continue;
+        };
+        let message = fields.get("message").and_then(Value::as_str).unwrap_or("");
+        if let Some(version) = Banner::parse(message, fields).and_then(|banner| banner.version) {
+            return Ok(Some(version));
+        }
+    }
+    Ok(None)
+}
+
+/// Sightings of one build banner
+struct Sightings {
+    first: String,
+    target: String,
+    count: u64,
+}
+
+/// Print the distinct build banners of a log, with when each was first
+/// logged and how many times, one per boot
+pub fn buildinfo(records: Records) -> Result<(), Box<dyn Error>> {
+    let mut banners: BTreeMap<Banner, Sightings> = BTreeMap::new();
+
+    for_each_event(records, |event| {
+        let Some(banner) = Banner::parse(event.message, event.fields) else {
+            return;
+        };
+        banners
+            .entry(banner)
+            .or_insert_with(|| Sightings {
+                first: event.timestamp.to_string(),
+                target: event.target.to_string(),
+                count: 0,
+            })
+            .count += 1;
+    })?;
+
+    if banners.is_empty() {
+        eprintln!("No build information found");
+        return Ok(());
+    }
+
+    let mut rows: Vec<(&Banner, &Sightings)> = banners.iter().collect();
+    rows.sort_by(|a, b| a.1.first.cmp(&b.1.first));
+    let text = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+    let rows: Vec<Vec<String>> = rows
+        .into_iter()
+        .map(|(banner, sightings)| {
+            vec![
+                text(&banner.version),
+                text(&banner.commit),
+                text(&banner.branch),
+                text(&banner.build_time),
+                sightings.first.clone(),
+                sightings.target.clone(),
+                sightings.count.to_string(),
+            ]
+        })
+        .collect();
+
+    print_table(
+        &[
+            "VERSION",
+            "COMMIT",
+            "BRANCH",
+            "BUILT",
+            "FIRST SEEN",
+            "TARGET",
+            "COUNT",
+        ],
+        &rows,
+    );
+    Ok(())
+}
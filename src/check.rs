@@ -0,0 +1,269 @@
+//! Diagnosis of a CSV export that decodes to nothing or less than expected
+//!
+//! Reports the encoding of the file, the columns found, which column holds
+//! the tracing messages, how many sampled messages parse, and the build
+//! version logged at boot, ending with what to change when the export can't
+//! be decoded as it is.
+
+use csv::ReaderBuilder;
+use serde_json::Value;
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use crate::buildinfo;
+use crate::input::{self, InputFormat, InputOptions};
+
+/// Name of the column the messages are read from
+const MESSAGE_COLUMN: &str = "ExtractedMessage";
+
+/// Bytes read to detect the encoding and delimiter
+const PROBE_BYTES: u64 = 64 * 1024;
+
+/// Encoding of a file, from its byte order mark or content
+enum Encoding {
+    Utf8,
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+    Gzip,
+    /// Not valid UTF-8, with the offset of the first invalid byte
+    Unknown(usize),
+}
+
+impl Encoding {
+    fn detect(data: &[u8]) -> Self {
+        if data.starts_with(&[0xef, 0xbb, 0xbf]) {
+            return Encoding::Utf8Bom;
+        }
+        if data.starts_with(&[0xff, 0xfe]) {
+            return Encoding::Utf16Le;
+        }
+        if data.starts_with(&[0xfe, 0xff]) {
+            return Encoding::Utf16Be;
+        }
+        if data.starts_with(&[0x1f, 0x8b]) {
+            return Encoding::Gzip;
+        }
+        // UTF-16 without a byte order mark has a zero byte in each ASCII
+        // character
+        if data.len() >= 2 && data[1] == 0 && data[0] != 0 {
+            return Encoding::Utf16Le;
+        }
+        if data.len() >= 2 && data[0] == 0 && data[1] != 0 {
+            return Encoding::Utf16Be;
+        }
+        match std::str::from_utf8(data) {
+            Ok(_) => Encoding::Utf8,
+            // A character cut off at the end of the probe is fine
+            Err(err) if err.error_len().is_none() => Encoding::Utf8,
+            Err(err) => Encoding::Unknown(err.valid_up_to()),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Encoding::Utf8 => "UTF-8".to_string(),
+            Encoding::Utf8Bom => "UTF-8 with byte order mark".to_string(),
+            Encoding::Utf16Le => "UTF-16LE".to_string(),
+            Encoding::Utf16Be => "UTF-16BE".to_string(),
+            Encoding::Gzip => "gzip compressed".to_string(),
+            Encoding::Unknown(offset) => format!("not UTF-8, invalid byte at offset {}", offset),
+        }
+    }
+}
+
+/// What a sampled message looks like
+#[derive(Default)]
+struct Sample {
+    rows: usize,
+    /// Tracing JSON objects with a message field
+    tracing: usize,
+    /// JSON values that aren't tracing records
+    other_json: usize,
+    empty: usize,
+}
+
+impl Sample {
+    fn add(&mut self, text: &str) {
+        self.rows += 1;
+        let text = text.trim();
+        if text.is_empty() {
+            self.empty += 1;
+            return;
+        }
+        match serde_json::from_str::<Value>(text) {
+            Ok(json) if json.pointer("/fields/message").is_some() => self.tracing += 1,
+            Ok(_) => self.other_json += 1,
+            Err(_) => {}
+        }
+    }
+
+    fn percent(&self, count: usize) -> f64 {
+        count as f64 * 100.0 / self.rows.max(1) as f64
+    }
+}
+
+/// Delimiter used most in the header line
+fn detect_delimiter(data: &[u8]) -> u8 {
+    let line = data.split(|&b| b == b'\n').next().unwrap_or(data);
+    [b',', b'\t', b';', b'|']
+        .into_iter()
+        .max_by_key(|delimiter| line.iter().filter(|b| *b == delimiter).count())
+        .unwrap()
+}
+
+fn delimiter_name(delimiter: u8) -> &'static str {
+    match delimiter {
+        b',' => "comma",
+        b'\t' => "tab",
+        b';' => "semicolon",
+        _ => "pipe",
+    }
+}
+
+/// Print a diagnosis of a CSV export, sampling the messages of its first
+/// `sample_rows` rows
+pub fn check(path: &Path, sample_rows: usize) -> Result<(), Box<dyn Error>> {
+    let mut probe = Vec::new();
+    File::open(path)
+        .map_err(|err| format!("Failed to open {}: {}", path.display(), err))?
+        .take(PROBE_BYTES)
+        .read_to_end(&mut probe)?;
+
+    let encoding = Encoding::detect(&probe);
+    println!("Encoding: {}", encoding.describe());
+    let mut problems = Vec::new();
+    match encoding {
+        Encoding::Utf8 | Encoding::Utf8Bom => {}
+        Encoding::Gzip => problems.push("Decompress the file first".to_string()),
+        _ => problems.push("Convert the file to UTF-8, or export it again as UTF-8".to_string()),
+    }
+    if !problems.is_empty() {
+        print_problems(&problems);
+        return Ok(());
+    }
+
+    let delimiter = detect_delimiter(&probe);
+    println!("Delimiter: {}", delimiter_name(delimiter));
+
+    let mut reader = ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_path(path)
+        .map_err(|err| format!("Failed to open {}: {}", path.display(), err))?;
+    let headers = reader.headers()?.clone();
+    println!("Columns: {}", headers.iter().collect::<Vec<_>>().join(", "));
+
+    // Score every column, to point at the message column when it has
+    // another name
+    let mut samples: Vec<Sample> = headers.iter().map(|_| Sample::default()).collect();
+    let mut short_rows = 0;
+    let mut bad_rows = 0;
+    for record in reader.records().take(sample_rows) {
+        let Ok(record) = record else {
+            bad_rows += 1;
+            continue;
+        };
+        if record.len() != headers.len() {
+            short_rows += 1;
+        }
+        for (sample, field) in samples.iter_mut().zip(record.iter()) {
+            sample.add(field);
+        }
+    }
+
+    let message_column = headers.iter().position(|h| h == MESSAGE_COLUMN);
+    let likely = samples
+        .iter()
+        .enumerate()
+        .filter(|(_, sample)| sample.tracing > 0)
+        .max_by_key(|(_, sample)| sample.tracing)
+        .map(|(index, _)| index);
+
+    match (message_column, likely) {
+        (Some(index), _) => println!("Message column: {} (column {})", MESSAGE_COLUMN, index + 1),
+        (None, Some(index)) => {
+            println!(
+                "Message column: none named {}, but {} (column {}) holds tracing JSON",
+                MESSAGE_COLUMN,
+                &headers[index],
+                index + 1
+            );
+            problems.push(format!(
+                "Rename the '{}' column to {}, or project it under that name in the query",
+                &headers[index], MESSAGE_COLUMN
+            ));
+        }
+        (None, None) => {
+            println!("Message column: none found");
+            problems.push(format!(
+                "Export a column named {} holding the tracing JSON messages",
+                MESSAGE_COLUMN
+            ));
+        }
+    }
+    if delimiter != b',' {
+        problems.push(format!(
+            "Export the file with comma delimiters rather than {}",
+            delimiter_name(delimiter)
+        ));
+    }
+
+    let sample = message_column.or(likely).map(|index| &samples[index]);
+    if let Some(sample) = sample {
+        println!(
+            "Sampled rows: {}, {:.1}% tracing JSON, {:.1}% other JSON, {:.1}% empty, {:.1}% plain text",
+            sample.rows,
+            sample.percent(sample.tracing),
+            sample.percent(sample.other_json),
+            sample.percent(sample.empty),
+            sample.percent(sample.rows - sample.tracing - sample.other_json - sample.empty)
+        );
+        if sample.rows > 0 && sample.tracing == 0 {
+            problems.push(
+                "No sampled message is a tracing JSON record with a message field, so nothing \
+                 will be shown"
+                    .to_string(),
+            );
+        }
+    }
+    if short_rows > 0 || bad_rows > 0 {
+        println!(
+            "Malformed rows: {} with a different number of fields than the header, {} that \
+             failed to parse",
+            short_rows, bad_rows
+        );
+    }
+
+    if message_column.is_some() {
+        let options = InputOptions {
+            format: InputFormat::Csv,
+            strict: false,
+            follow: false,
+            mmap: true,
+        };
+        let version = buildinfo::detect_version(input::open(path, &options)?)?;
+        println!(
+            "Schema version: {}",
+            version
+                .as_deref()
+                .unwrap_or("not found in the build info logged at boot")
+        );
+    }
+
+    print_problems(&problems);
+    Ok(())
+}
+
+fn print_problems(problems: &[String]) {
+    if problems.is_empty() {
+        println!("No problems found");
+        return;
+    }
+    println!("Problems:");
+    for problem in problems {
+        println!("  {}", problem);
+    }
+}
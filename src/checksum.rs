@@ -0,0 +1,85 @@
+//! SHA-256 checksums of input files, to catch partially downloaded exports
+//! before analyzing them
+
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Hash a file, returning its size and its SHA-256 digest as hex
+pub fn sha256_file(path: &Path) -> Result<(u64, String), Box<dyn Error>> {
+    let mut file =
+        File::open(path).map_err(|err| format!("Failed to open {}: {}", path.display(), err))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 1 << 16];
+    let mut bytes = 0u64;
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        bytes += read as u64;
+    }
+
+    let digest = hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    Ok((bytes, digest))
+}
+
+fn is_digest(text: &str) -> bool {
+    text.len() == 64 && text.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Expected digest of `input` from a checksum file in `sha256sum` format,
+/// using the line naming the input when it lists several files
+fn read_checksum_file(path: &Path, input: &Path) -> Result<String, Box<dyn Error>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+    let entries: Vec<(&str, &str)> = content
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.trim().splitn(2, char::is_whitespace);
+            let digest = parts.next().filter(|digest| is_digest(digest))?;
+            let name = parts.next().unwrap_or("").trim().trim_start_matches('*');
+            Some((digest, name))
+        })
+        .collect();
+
+    let input_name = input.file_name().map(|name| name.to_string_lossy());
+    let entry = match entries.as_slice() {
+        [entry] => Some(entry),
+        entries => entries.iter().find(|(_, name)| {
+            Path::new(name).file_name().map(|n| n.to_string_lossy()) == input_name
+        }),
+    };
+    entry
+        .map(|(digest, _)| digest.to_string())
+        .ok_or_else(|| format!("No checksum for {} in {}", input.display(), path.display()).into())
+}
+
+/// Check that `input` has the SHA-256 digest given as hex or by a
+/// checksum file such as `export.csv.sha256`
+pub fn verify(input: &Path, expected: &str) -> Result<(), Box<dyn Error>> {
+    let expected = if is_digest(expected) {
+        expected.to_string()
+    } else {
+        read_checksum_file(Path::new(expected), input)?
+    };
+
+    let (_, actual) = sha256_file(input)?;
+    if !actual.eq_ignore_ascii_case(&expected) {
+        return Err(format!(
+            "Checksum mismatch for {}, the export may be incomplete: expected {}, got {}",
+            input.display(),
+            expected.to_lowercase(),
+            actual
+        )
+        .into());
+    }
+    Ok(())
+}
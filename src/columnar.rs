@@ -0,0 +1,203 @@
+//! Columnar representation of the records of an input as Arrow record
+//! batches, with the `arrow` feature
+//!
+//! Each requested field becomes a dictionary column of its values as shown,
+//! so values are counted by their dictionary key instead of hashing every
+//! string, and a column of its integer values for the aggregate kernels.
+//!
+//! The analysis subcommands load the columns they need into arrays and
+//! group their rows with [`group_by`], which sorts and partitions them with
+//! the Arrow kernels, before aggregating each group.
+
+use arrow_arith::aggregate;
+use arrow_array::builder::{StringDictionaryBuilder, UInt64Builder};
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Int32Type, UInt64Type};
+use arrow_array::{Array, ArrayRef, RecordBatch, UInt32Array};
+use arrow_ord::partition::partition;
+use arrow_ord::sort::{lexsort_to_indices, SortColumn};
+use arrow_schema::{ArrowError, DataType, Field, Schema};
+use arrow_select::take::take;
+use std::error::Error;
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::fields_stats::{field_value, FieldStats};
+use crate::input::Records;
+use crate::report::for_each_event;
+
+/// Rows of each record batch
+const BATCH_ROWS: usize = 64 * 1024;
+
+/// Builders of the columns of the batch being filled
+struct BatchBuilder {
+    values: Vec<StringDictionaryBuilder<Int32Type>>,
+    integers: Vec<UInt64Builder>,
+    rows: usize,
+}
+
+/// The values of some fields across the tracing records of an input, two
+/// columns per field
+pub struct EventTable {
+    schema: Arc<Schema>,
+    batches: Vec<RecordBatch>,
+}
+
+impl EventTable {
+    /// Read the values of `fields` from every tracing record of an input
+    pub fn load(records: Records, fields: &[String]) -> Result<Self, Box<dyn Error>> {
+        let mut columns = Vec::with_capacity(fields.len() * 2);
+        for field in fields {
+            let dictionary =
+                DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8));
+            columns.push(Field::new(field.as_str(), dictionary, true));
+            columns.push(Field::new(
+                format!("{}.integer", field),
+                DataType::UInt64,
+                true,
+            ));
+        }
+        let mut table = EventTable {
+            schema: Arc::new(Schema::new(columns)),
+            batches: Vec::new(),
+        };
+
+        let mut builder = BatchBuilder {
+            values: fields
+                .iter()
+                .map(|_| StringDictionaryBuilder::new())
+                .collect(),
+            integers: fields.iter().map(|_| UInt64Builder::new()).collect(),
+            rows: 0,
+        };
+        let mut result = Ok(());
+        for_each_event(records, |event| {
+            let columns = builder.values.iter_mut().zip(&mut builder.integers);
+            for (field, (values, integers)) in fields.iter().zip(columns) {
+                match field_value(event, field) {
+                    Some((text, integer)) => {
+                        values.append_value(text);
+                        integers.append_option(integer);
+                    }
+                    None => {
+                        values.append_null();
+                        integers.append_null();
+                    }
+                }
+            }
+            builder.rows += 1;
+            if builder.rows == BATCH_ROWS && result.is_ok() {
+                result = table.push(&mut builder);
+            }
+        })?;
+        result?;
+        if builder.rows > 0 {
+            table.push(&mut builder)?;
+        }
+        Ok(table)
+    }
+
+    /// Finish the batch being filled
+    fn push(&mut self, builder: &mut BatchBuilder) -> Result<(), ArrowError> {
+        let mut columns: Vec<ArrayRef> = Vec::with_capacity(self.schema.fields().len());
+        for (values, integers) in builder.values.iter_mut().zip(&mut builder.integers) {
+            columns.push(Arc::new(values.finish()));
+            columns.push(Arc::new(integers.finish()));
+        }
+        builder.rows = 0;
+        self.batches
+            .push(RecordBatch::try_new(self.schema.clone(), columns)?);
+        Ok(())
+    }
+
+    /// Count the values of the field in column pair `index`
+    pub fn field_stats(&self, index: usize) -> FieldStats {
+        let mut stats = FieldStats::default();
+        for batch in &self.batches {
+            let values = batch.column(index * 2).as_dictionary::<Int32Type>();
+            let dictionary = values.values().as_string::<i32>();
+            let mut counts = vec![0u64; dictionary.len()];
+            for key in values.keys().iter().flatten() {
+                counts[key as usize] += 1;
+            }
+            for (key, count) in counts.into_iter().enumerate() {
+                if count > 0 {
+                    *stats
+                        .counts
+                        .entry(dictionary.value(key).to_string())
+                        .or_default() += count;
+                }
+            }
+            stats.records += (values.len() - values.null_count()) as u64;
+
+            let integers = batch.column(index * 2 + 1).as_primitive::<UInt64Type>();
+            if let (Some(min), Some(max)) = (aggregate::min(integers), aggregate::max(integers)) {
+                stats.range = Some(match stats.range {
+                    Some((low, high)) => (low.min(min), high.max(max)),
+                    None => (min, max),
+                });
+            }
+        }
+        stats
+    }
+}
+
+/// Rows of a table grouped by the values of some key columns
+pub struct Groups {
+    /// Row indices sorted by key, with the rows of each group together
+    indices: UInt32Array,
+    /// Range of `indices` holding each group, in key order
+    pub ranges: Vec<Range<usize>>,
+}
+
+impl Groups {
+    /// The rows of `array` in a group, in their order within the group
+    pub fn take(&self, array: &dyn Array, group: &Range<usize>) -> Result<ArrayRef, ArrowError> {
+        let indices = self.indices.slice(group.start, group.len());
+        take(array, &indices, None)
+    }
+
+    /// The index of the first row of a group
+    pub fn first(&self, group: &Range<usize>) -> usize {
+        self.indices.value(group.start) as usize
+    }
+
+    /// The index of the last row of a group
+    pub fn last(&self, group: &Range<usize>) -> usize {
+        self.indices.value(group.end - 1) as usize
+    }
+}
+
+/// Group the rows of a table by the values of `keys`, ordering the rows
+/// within each group by `order` and then by their position in the table
+pub fn group_by(keys: &[ArrayRef], order: &[ArrayRef]) -> Result<Groups, ArrowError> {
+    let rows = keys.first().map_or(0, |key| key.len());
+    if rows == 0 {
+        return Ok(Groups {
+            indices: UInt32Array::from(Vec::<u32>::new()),
+            ranges: Vec::new(),
+        });
+    }
+
+    // The position breaks ties, so rows keep their order as read
+    let position: ArrayRef = Arc::new(UInt32Array::from_iter_values(0..rows as u32));
+    let columns: Vec<SortColumn> = keys
+        .iter()
+        .chain(order)
+        .chain([&position])
+        .map(|values| SortColumn {
+            values: values.clone(),
+            options: None,
+        })
+        .collect();
+    let indices = lexsort_to_indices(&columns, None)?;
+
+    let sorted = keys
+        .iter()
+        .map(|key| take(key.as_ref(), &indices, None))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Groups {
+        ranges: partition(&sorted)?.ranges(),
+        indices,
+    })
+}
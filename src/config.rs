@@ -0,0 +1,207 @@
+//! Layered configuration files setting default options
+//!
+//! Configuration files have the format of pipeline files, a TOML table of
+//! options keyed by option name. They are read from, in order of
+//! increasing precedence:
+//!
+//! - the system file, `/etc/kusto-kmsg-extract/config.toml` or
+//!   `%ProgramData%\kusto-kmsg-extract\config.toml` on Windows
+//! - the user file, `$XDG_CONFIG_HOME/kusto-kmsg-extract/config.toml`,
+//!   `~/.config/kusto-kmsg-extract/config.toml` or
+//!   `%APPDATA%\kusto-kmsg-extract\config.toml` on Windows
+//! - the project file, `.kusto-kmsg-extract.toml` in the current directory
+//!   or the nearest directory above it
+//!
+//! Every option can also be set by an environment variable named after it,
+//! like `KKE_LEVEL` for `--level` or `KKE_NO_MMAP` for `--no-mmap`. Flags
+//! take `1`, `true`, `yes` or `on` to turn them on, and `0`, `false`, `no`
+//! or `off` to leave them off.
+//!
+//! Options given on the command line or in a `--pipeline` file take
+//! precedence over environment variables, and environment variables over
+//! all of the files. Repeatable options are combined across the files.
+
+use clap::builder::{BoolishValueParser, TypedValueParser};
+use clap::{Arg, ArgAction, Command};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+/// Prefix of the environment variables setting options
+const ENV_PREFIX: &str = "KKE_";
+
+/// Options that can't be set from the environment
+const NO_ENV: &[&str] = &["help", "version", "help_examples"];
+
+/// Directory holding the system and user files
+const DIRECTORY: &str = "kusto-kmsg-extract";
+
+/// Name of the system and user files
+const FILE_NAME: &str = "config.toml";
+
+/// Name of the project file
+const PROJECT_FILE_NAME: &str = ".kusto-kmsg-extract.toml";
+
+/// Name of the environment variable setting `arg`, if it can be set
+fn env_name(arg: &Arg) -> Option<String> {
+    let long = arg.get_long()?;
+    if NO_ENV.contains(&arg.get_id().as_str()) {
+        return None;
+    }
+    Some(format!(
+        "{}{}",
+        ENV_PREFIX,
+        long.to_uppercase().replace('-', "_")
+    ))
+}
+
+/// Let every option of `command` and its subcommands be set by its
+/// environment variable
+pub fn with_env(command: Command) -> Command {
+    let mut command = command.mut_args(|arg| match env_name(&arg) {
+        // Flags otherwise only take `true` and `false`
+        Some(name) if matches!(arg.get_action(), ArgAction::SetTrue) => {
+            arg.env(name).value_parser(BoolishValueParser::new())
+        }
+        Some(name) => arg.env(name),
+        None => arg,
+    });
+    let names: Vec<String> = command
+        .get_subcommands()
+        .map(|subcommand| subcommand.get_name().to_string())
+        .collect();
+    for name in names {
+        command = command.mut_subcommand(name, with_env);
+    }
+    command
+}
+
+fn env_path(name: &str) -> Option<PathBuf> {
+    std::env::var_os(name)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
+
+fn system_path() -> Option<PathBuf> {
+    if cfg!(windows) {
+        Some(env_path("ProgramData")?.join(DIRECTORY).join(FILE_NAME))
+    } else {
+        Some(Path::new("/etc").join(DIRECTORY).join(FILE_NAME))
+    }
+}
+
+/// Directory holding the user file and other per-user data
+pub fn user_directory() -> Option<PathBuf> {
+    let directory = if cfg!(windows) {
+        env_path("APPDATA")?
+    } else {
+        env_path("XDG_CONFIG_HOME").or_else(|| Some(env_path("HOME")?.join(".config")))?
+    };
+    Some(directory.join(DIRECTORY))
+}
+
+fn user_path() -> Option<PathBuf> {
+    Some(user_directory()?.join(FILE_NAME))
+}
+
+fn project_path() -> Option<PathBuf> {
+    let current = std::env::current_dir().ok()?;
+    current
+        .ancestors()
+        .map(|directory| directory.join(PROJECT_FILE_NAME))
+        .find(|path| path.is_file())
+}
+
+/// The configuration layers with the file each is read from, lowest
+/// precedence first, whether or not the file exists
+pub fn layers() -> Vec<(&'static str, Option<PathBuf>)> {
+    vec![
+        ("system", system_path()),
+        ("user", user_path()),
+        ("project", project_path()),
+    ]
+}
+
+/// The configuration files that exist, highest precedence first
+pub fn files() -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = layers()
+        .into_iter()
+        .filter_map(|(_, path)| path.filter(|path| path.is_file()))
+        .collect();
+    files.reverse();
+    files
+}
+
+fn load(path: &Path) -> Result<Table, Box<dyn Error>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+    Ok(toml::from_str(&content)
+        .map_err(|err| format!("Failed to parse {}: {}", path.display(), err))?)
+}
+
+/// Print the configuration files and the options they set once merged
+/// with the environment, with the layer setting each, using `command` to
+/// tell which options are repeatable
+pub fn show(command: &Command) -> Result<(), Box<dyn Error>> {
+    let mut merged: BTreeMap<String, (Value, Vec<&str>)> = BTreeMap::new();
+
+    println!("# Configuration files, lowest precedence first:");
+    for (layer, path) in layers() {
+        let Some(path) = path.filter(|path| path.is_file()) else {
+            println!("#   {:<8} not found", layer);
+            continue;
+        };
+        println!("#   {:<8} {}", layer, path.display());
+
+        for (key, value) in load(&path)? {
+            let arg = command
+                .get_arguments()
+                .find(|arg| arg.get_id() == key.as_str())
+                .ok_or_else(|| format!("{}: unknown option '{}'", path.display(), key))?;
+            let repeatable = matches!(arg.get_action(), ArgAction::Append);
+            let value = match value {
+                Value::Array(values) => Value::Array(values),
+                value if repeatable => Value::Array(vec![value]),
+                value => value,
+            };
+            match (merged.get_mut(&key), value) {
+                (Some((Value::Array(values), layers)), Value::Array(more)) if repeatable => {
+                    values.extend(more);
+                    layers.push(layer);
+                }
+                (_, value) => {
+                    merged.insert(key, (value, vec![layer]));
+                }
+            }
+        }
+    }
+
+    // The environment takes precedence over every file
+    for arg in command.get_arguments() {
+        let Some(name) = env_name(arg) else {
+            continue;
+        };
+        let Some(text) = std::env::var_os(&name).filter(|text| !text.is_empty()) else {
+            continue;
+        };
+        let value = match arg.get_action() {
+            ArgAction::SetTrue => Value::Boolean(
+                BoolishValueParser::new()
+                    .parse_ref(command, Some(arg), &text)
+                    .map_err(|err| format!("{}: {}", name, err))?,
+            ),
+            ArgAction::Append => Value::Array(vec![Value::String(text.to_string_lossy().into())]),
+            _ => Value::String(text.to_string_lossy().into()),
+        };
+        merged.insert(arg.get_id().to_string(), (value, vec!["env"]));
+    }
+
+    if merged.is_empty() {
+        println!("# No options set");
+    }
+    for (key, (value, layers)) in merged {
+        println!("{} = {}  # {}", key, value, layers.join(", "));
+    }
+    Ok(())
+}
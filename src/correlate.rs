@@ -0,0 +1,142 @@
+//! Grouping of records by the correlation ID they carry
+//!
+//! Records of one request or activity share an ID in a field such as
+//! `request_id` or `activity_id`, either among their own fields or in the
+//! fields of the spans they were logged in.
+
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+
+use crate::escape::escape_controls;
+use crate::input::Records;
+
+/// Field names holding correlation IDs, in order of preference
+pub const CORRELATION_KEYS: &[&str] = &[
+    "activity_id",
+    "request_id",
+    "correlation_id",
+    "trace_id",
+    "transaction_id",
+    "transaction",
+];
+
+/// Text of an ID held in a field value
+fn id_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        _ => None,
+    }
+}
+
+/// Objects of a tracing record that may hold a correlation ID: its fields,
+/// then its current span and the spans it was logged in
+fn id_sources(json: &Value) -> impl Iterator<Item = &Map<String, Value>> {
+    let spans = json.get("spans").and_then(Value::as_array);
+    json.get("fields")
+        .into_iter()
+        .chain(json.get("span"))
+        .chain(spans.into_iter().flatten())
+        .filter_map(Value::as_object)
+}
+
+/// The first correlation ID of a tracing record, with the field holding it
+pub fn correlation_id(json: &Value, keys: &[String]) -> Option<(String, String)> {
+    id_sources(json).find_map(|object| {
+        keys.iter()
+            .find_map(|key| Some((key.clone(), id_text(object.get(key)?)?)))
+    })
+}
+
+/// Check whether a tracing record carries the ID `id` in any correlation
+/// field, matching numeric IDs given in decimal or hex
+pub fn has_id(json: &Value, id: &str) -> bool {
+    id_sources(json).any(|object| {
+        CORRELATION_KEYS.iter().any(|key| match object.get(*key) {
+            Some(Value::String(text)) => text.eq_ignore_ascii_case(id),
+            Some(Value::Number(number)) => {
+                number.to_string() == id
+                    || number
+                        .as_u64()
+                        .is_some_and(|n| format!("{:#x}", n).eq_ignore_ascii_case(id))
+            }
+            _ => false,
+        })
+    })
+}
+
+/// Records sharing a correlation ID
+#[derive(Default)]
+struct Group {
+    key: String,
+    targets: BTreeSet<String>,
+    /// Timestamp and rendering of each record
+    lines: Vec<(String, String)>,
+}
+
+/// Print the records of each correlation ID as a timeline, skipping IDs
+/// with fewer than `min_records` records
+pub fn correlate(
+    records: Records,
+    keys: &[String],
+    min_records: usize,
+) -> Result<(), Box<dyn Error>> {
+    let mut groups: BTreeMap<String, Group> = BTreeMap::new();
+
+    for record in records {
+        let record = record?;
+        let Some(json) = record.json() else {
+            continue;
+        };
+        let Some((key, id)) = correlation_id(&json, keys) else {
+            continue;
+        };
+
+        let field = |name: &str| json.get(name).and_then(Value::as_str).unwrap_or("");
+        let message = json
+            .pointer("/fields/message")
+            .and_then(Value::as_str)
+            .unwrap_or("");
+
+        let group = groups.entry(id).or_default();
+        group.key = key;
+        group.targets.insert(field("target").to_string());
+        group.lines.push((
+            field("timestamp").to_string(),
+            escape_controls(&format!(
+                "[{}][{}] {}",
+                field("level"),
+                field("target"),
+                message
+            ))
+            .into_owned(),
+        ));
+    }
+
+    for (id, mut group) in groups {
+        if group.lines.len() < min_records.max(1) {
+            continue;
+        }
+
+        // Records may arrive out of order when merged from several sources
+        group.lines.sort_by(|a, b| a.0.cmp(&b.0));
+        let first = &group.lines[0].0;
+        let last = &group.lines[group.lines.len() - 1].0;
+        println!(
+            "{}={} ({} records, {} to {}, targets: {})",
+            group.key,
+            id,
+            group.lines.len(),
+            first,
+            last,
+            group.targets.into_iter().collect::<Vec<_>>().join(", ")
+        );
+        for (timestamp, line) in &group.lines {
+            println!("  [{}]{}", timestamp, line);
+        }
+        println!();
+    }
+
+    Ok(())
+}
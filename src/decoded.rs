@@ -0,0 +1,138 @@
+//! Intermediate format holding an export with its records parsed, written
+//! by the `decode` subcommand
+//!
+//! Parsing the JSON of every record takes most of the time of a run, so an
+//! export analyzed many times can be parsed once with `decode`, then read
+//! with `--format decoded` by the analysis subcommands and `search`.
+//!
+//! The file starts with the magic `KKED` and a version byte, followed by a
+//! MessagePack entry per record: the parsed tracing record, or the text of
+//! a record that isn't one. Parsed records are written as compact JSON by
+//! `--raw` and `--exec-decoder`, so their text may differ from the export.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::input::{Record, Records};
+
+/// Start of every file in the intermediate format
+const MAGIC: &[u8; 4] = b"KKED";
+
+/// Version of the format, changed whenever entries are read differently
+const VERSION: u8 = 1;
+
+#[derive(Serialize, Deserialize)]
+enum Message {
+    /// A JSON object, usually a tracing record
+    Json(Value),
+    /// Anything else, kept as it is
+    Text(String),
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    message: Message,
+    monotonic_us: Option<u64>,
+}
+
+/// Write the records of an input to a file in the intermediate format
+pub fn decode(records: Records, output: &Path) -> Result<(), Box<dyn Error>> {
+    let file = File::create(output)
+        .map_err(|err| format!("Failed to create {}: {}", output.display(), err))?;
+    let mut writer = BufWriter::new(file);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[VERSION])?;
+
+    let (mut parsed, mut text) = (0u64, 0u64);
+    for record in records {
+        let record = record?;
+        let message = match serde_json::from_str::<Value>(&record.message) {
+            Ok(json) if json.is_object() => {
+                parsed += 1;
+                Message::Json(json)
+            }
+            _ => {
+                text += 1;
+                Message::Text(record.message)
+            }
+        };
+        let entry = Entry {
+            message,
+            monotonic_us: record.monotonic_us,
+        };
+        rmp_serde::encode::write(&mut writer, &entry)
+            .map_err(|err| format!("Failed to write {}: {}", output.display(), err))?;
+    }
+    writer
+        .flush()
+        .map_err(|err| format!("Failed to write {}: {}", output.display(), err))?;
+
+    eprintln!(
+        "Decoded {} records, {} more that aren't JSON, into {}",
+        parsed,
+        text,
+        output.display()
+    );
+    Ok(())
+}
+
+/// Reads the entries of a file in the intermediate format
+struct DecodedReader {
+    reader: BufReader<File>,
+}
+
+impl Iterator for DecodedReader {
+    type Item = Result<Record, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.reader.fill_buf() {
+            Ok([]) => return None,
+            Ok(_) => {}
+            Err(err) => return Some(Err(err.into())),
+        }
+        let entry: Entry = match rmp_serde::decode::from_read(&mut self.reader) {
+            Ok(entry) => entry,
+            Err(err) => return Some(Err(format!("Invalid decoded file: {}", err).into())),
+        };
+
+        let (message, parsed) = match entry.message {
+            Message::Json(json) => (String::new(), Some(json)),
+            Message::Text(text) => (text, None),
+        };
+        Some(Ok(Record {
+            message,
+            parsed,
+            monotonic_us: entry.monotonic_us,
+            end_offset: None,
+        }))
+    }
+}
+
+/// Open a file in the intermediate format
+pub fn open(path: &Path) -> Result<Records, Box<dyn Error>> {
+    let file =
+        File::open(path).map_err(|err| format!("Failed to open {}: {}", path.display(), err))?;
+    let mut reader = BufReader::new(file);
+
+    let mut header = [0; 5];
+    let valid = reader.read_exact(&mut header).is_ok() && &header[..4] == MAGIC;
+    if !valid {
+        return Err(format!(
+            "{} isn't a file written by the decode subcommand",
+            path.display()
+        )
+        .into());
+    }
+    if header[4] != VERSION {
+        return Err(format!(
+            "{} was decoded by another version, decode the export again",
+            path.display()
+        )
+        .into());
+    }
+    Ok(Box::new(DecodedReader { reader }))
+}
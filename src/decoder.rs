@@ -0,0 +1,288 @@
+//! Field decoders and the registry that applies them
+
+use serde_json::{Map, Value};
+
+use crate::guid::{GuidDecoder, GuidNames};
+use crate::payload::PayloadDecoder;
+use crate::units::UnitsDecoder;
+use crate::{arm64, disasm, error_chain, format, pagewalk, snp, vmbus, x86};
+
+/// The record a field being decoded belongs to
+pub struct FieldContext<'a> {
+    /// Tracing target of the record
+    pub target: &'a str,
+    /// Message of the record
+    pub message: &'a str,
+    /// All fields of the record, for decoders that pair related values
+    pub fields: &'a Map<String, Value>,
+}
+
+/// Conditions a field must meet before a decoder is tried
+///
+/// All conditions that are set must hold. A decoder may still decline a
+/// field that meets them by returning None from [`Decoder::decode`].
+#[derive(Clone, Debug, Default)]
+pub struct Conditions {
+    /// Field names the decoder applies to, or any field when empty
+    pub keys: Vec<String>,
+    /// Substring the field value must contain, which implies a string value
+    pub contains: Option<String>,
+    /// Substring the record target must contain
+    pub target: Option<String>,
+}
+
+impl Conditions {
+    /// Conditions matching every field
+    pub fn any() -> Self {
+        Conditions::default()
+    }
+
+    /// Restrict to fields with one of the given names
+    pub fn keys(mut self, keys: &[&str]) -> Self {
+        self.keys = keys.iter().map(|key| key.to_string()).collect();
+        self
+    }
+
+    /// Restrict to string values containing `text`
+    pub fn contains(mut self, text: &str) -> Self {
+        self.contains = Some(text.to_string());
+        self
+    }
+
+    /// Describe the fields meeting the conditions, like `raw_exit fields
+    /// containing "tdx"`
+    pub fn describe(&self) -> String {
+        let mut text = match self.keys.as_slice() {
+            [] => "any field".to_string(),
+            keys => format!("{} fields", keys.join(", ")),
+        };
+        if let Some(contains) = &self.contains {
+            text.push_str(&format!(" containing {:?}", contains));
+        }
+        if let Some(target) = &self.target {
+            text.push_str(&format!(" of targets containing {:?}", target));
+        }
+        text
+    }
+
+    /// Check whether a field of a record meets the conditions
+    pub fn matches(&self, key: &str, value: &Value, target: &str) -> bool {
+        (self.keys.is_empty() || self.keys.iter().any(|k| k == key))
+            && self
+                .contains
+                .as_ref()
+                .is_none_or(|text| value.as_str().is_some_and(|v| v.contains(text.as_str())))
+            && self
+                .target
+                .as_ref()
+                .is_none_or(|t| target.contains(t.as_str()))
+    }
+}
+
+/// How a decoded field is rendered
+pub enum Decoded {
+    /// Replace a string value, rendered as `key="..."`
+    Replace(String),
+    /// Keep the value and append a note, rendered as `key=value (note)`
+    Annotate(String),
+    /// Write a number in place of the value, rendered as `key=text`
+    Value(String),
+    /// Render a short inline summary as `key=summary`, with a multi-line
+    /// rendering on indented lines following the record
+    Expand { summary: String, lines: String },
+}
+
+/// A decoder for fields with a known structure
+pub trait Decoder: Send + Sync {
+    /// Short name identifying the decoder
+    fn name(&self) -> &str;
+
+    /// Version of the decoding logic, recorded in run manifests
+    fn version(&self) -> String {
+        env!("CARGO_PKG_VERSION").to_string()
+    }
+
+    /// What the decoder does, shown by `decoders list`
+    fn description(&self) -> String;
+
+    /// Conditions under which the decoder is tried
+    fn conditions(&self) -> &Conditions;
+
+    /// Name and value of a field the decoder decodes, shown decoded by
+    /// `decoders list`
+    fn example(&self) -> Option<(&str, Value)> {
+        None
+    }
+
+    /// Decode a field that met the conditions, or return None to leave it
+    /// to later decoders
+    fn decode(&self, key: &str, value: &Value, ctx: &FieldContext) -> Option<Decoded>;
+
+    /// Why a field the decoder is tried on holds a value that can't be
+    /// right, checked with --validate
+    fn validate(&self, _key: &str, _value: &Value, _ctx: &FieldContext) -> Option<String> {
+        None
+    }
+
+    /// Steps taken to decode a field, for decoders made of several steps,
+    /// shown by --explain
+    fn explain(&self, _key: &str, _value: &Value, _ctx: &FieldContext) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// An ordered set of decoders, where the first to decode a field wins
+pub struct DecoderRegistry {
+    decoders: Vec<Box<dyn Decoder>>,
+}
+
+impl DecoderRegistry {
+    /// Create a registry with no decoders
+    pub fn new() -> Self {
+        DecoderRegistry {
+            decoders: Vec::new(),
+        }
+    }
+
+    /// Create a registry holding all built-in decoders
+    ///
+    /// Structure dumps are transformed before field annotations, and the
+    /// generic byte payload decoder comes last since it matches any field
+    /// holding a byte array.
+    pub fn builtin(
+        guid_names: GuidNames,
+        units: UnitsDecoder,
+        hexdump_threshold: usize,
+        decode_base64: bool,
+    ) -> Self {
+        let mut registry = DecoderRegistry::new();
+
+        registry.register(Box::new(x86::TdxExitInfoDecoder::new()));
+        registry.register(Box::new(x86::TdxGuestStateDecoder::new()));
+        registry.register(Box::new(x86::SegmentRegisterDecoder::new()));
+        registry.register(Box::new(arm64::RegisterDumpDecoder::new()));
+        registry.register(Box::new(snp::VmsaDecoder::new()));
+        registry.register(Box::new(arm64::SyndromeDecoder::new()));
+        registry.register(Box::new(snp::SevFeaturesDecoder::new()));
+        registry.register(Box::new(GuidDecoder::new(guid_names)));
+        registry.register(Box::new(vmbus::VmbusDecoder::new()));
+        registry.register(Box::new(disasm::InstructionBytesDecoder::new()));
+        registry.register(Box::new(pagewalk::PageWalkDecoder::new()));
+        registry.register(Box::new(error_chain::ErrorChainDecoder::new()));
+        registry.register(Box::new(units));
+        registry.register(Box::new(PayloadDecoder::new(
+            hexdump_threshold,
+            decode_base64,
+        )));
+
+        registry
+    }
+
+    /// Add a decoder after all existing ones
+    pub fn register(&mut self, decoder: Box<dyn Decoder>) {
+        self.decoders.push(decoder);
+    }
+
+    /// Add a decoder before all existing ones, so it is tried first
+    pub fn register_first(&mut self, decoder: Box<dyn Decoder>) {
+        self.decoders.insert(0, decoder);
+    }
+
+    /// Remove the decoder with the given name
+    pub fn disable(&mut self, name: &str) -> Result<(), String> {
+        let count = self.decoders.len();
+        self.decoders.retain(|decoder| decoder.name() != name);
+
+        if self.decoders.len() == count {
+            let names: Vec<&str> = self.decoders.iter().map(|d| d.name()).collect();
+            return Err(format!(
+                "Unknown decoder '{}', expected one of: {}",
+                name,
+                names.join(", ")
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// The decoders in the order they are tried
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Decoder> {
+        self.decoders.iter().map(|decoder| decoder.as_ref())
+    }
+
+    /// Decode a field with the first decoder that accepts it
+    pub fn decode(&self, key: &str, value: &Value, ctx: &FieldContext) -> Option<Decoded> {
+        self.decoders
+            .iter()
+            .filter(|decoder| decoder.conditions().matches(key, value, ctx.target))
+            .find_map(|decoder| decoder.decode(key, value, ctx))
+    }
+
+    /// Why a field holds a value that can't be right, as found by the first
+    /// decoder tried on it that finds a reason, for --validate
+    pub fn validate(&self, key: &str, value: &Value, ctx: &FieldContext) -> Option<String> {
+        self.decoders
+            .iter()
+            .filter(|decoder| decoder.conditions().matches(key, value, ctx.target))
+            .find_map(|decoder| decoder.validate(key, value, ctx))
+    }
+
+    /// Describe how each decoder tried on a field handled it, for
+    /// --explain
+    pub fn explain(&self, key: &str, value: &Value, ctx: &FieldContext) -> Vec<String> {
+        let mut lines = Vec::new();
+        for decoder in &self.decoders {
+            if !decoder.conditions().matches(key, value, ctx.target) {
+                continue;
+            }
+            let decoded = decoder.decode(key, value, ctx);
+            let outcome = match decoded {
+                Some(Decoded::Replace(_)) => "replaced the value",
+                Some(Decoded::Annotate(_)) => "annotated the value",
+                Some(Decoded::Value(_)) => "rewrote the value",
+                Some(Decoded::Expand { .. }) => "expanded the value",
+                None => "declined",
+            };
+            lines.push(format!("{}: {}", decoder.name(), outcome));
+            let steps = decoder.explain(key, value, ctx);
+            lines.extend(steps.into_iter().map(|step| format!("  {}", step)));
+            if decoded.is_some() {
+                return lines;
+            }
+        }
+        lines.push("no decoder, integers shown as hex".to_string());
+        lines
+    }
+}
+
+/// Print the decoders in the order they are tried, with what each does, the
+/// fields it is tried on and an example field as logged and as shown
+pub fn list(decoders: &DecoderRegistry) {
+    for (index, decoder) in decoders.iter().enumerate() {
+        if index > 0 {
+            println!();
+        }
+        println!("{}", decoder.name());
+        println!("  {}", decoder.description());
+        println!("  Applies to: {}", decoder.conditions().describe());
+
+        let Some((key, value)) = decoder.example() else {
+            continue;
+        };
+        let fields = Map::from_iter([(key.to_string(), value.clone())]);
+        let ctx = FieldContext {
+            target: "",
+            message: "",
+            fields: &fields,
+        };
+        let decoded = decoder.decode(key, &value, &ctx);
+        let mut shown = String::new();
+        let mut continuation = Vec::new();
+        format::write_field(&mut shown, key, &value, decoded, false, &mut continuation);
+        println!("  Logged: {}={}", key, value);
+        println!("  Shown: {}", shown.trim_start());
+        for lines in continuation {
+            println!("{}", lines);
+        }
+    }
+}
@@ -0,0 +1,47 @@
+//! Removal of duplicate records, which overlapping exports of the same
+//! time window hold twice
+
+use std::collections::HashSet;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/// Remembers the tracing records seen so far
+///
+/// Records are compared by a hash of their whole tracing JSON, which holds
+/// their timestamp, so only records logged twice at the same instant with
+/// the same payload are dropped.
+pub struct Dedupe {
+    seen: HashSet<u64>,
+    dropped: u64,
+}
+
+impl Dedupe {
+    pub fn new() -> Self {
+        Dedupe {
+            seen: HashSet::new(),
+            dropped: 0,
+        }
+    }
+
+    /// Check whether a message was seen before, remembering it if not
+    ///
+    /// Lines that aren't tracing JSON have no timestamp to tell repeats
+    /// apart, so they are never duplicates.
+    pub fn is_duplicate(&mut self, message: &str) -> bool {
+        if !message.trim_start().starts_with('{') {
+            return false;
+        }
+
+        let mut hasher = DefaultHasher::new();
+        message.hash(&mut hasher);
+        if self.seen.insert(hasher.finish()) {
+            return false;
+        }
+        self.dropped += 1;
+        true
+    }
+
+    /// Print how many duplicates were dropped to stderr
+    pub fn report(&self) {
+        eprintln!("Dropped {} duplicate records", self.dropped);
+    }
+}
@@ -0,0 +1,114 @@
+//! Disassembly of x86 instruction bytes carried in fields
+
+use iced_x86::{Decoder as X86Decoder, DecoderOptions, Formatter, IntelFormatter};
+use serde_json::{Map, Value};
+
+use crate::decoder::{Conditions, Decoded, Decoder, FieldContext};
+
+/// Field names known to carry raw instruction bytes
+const INSTRUCTION_BYTES_KEYS: &[&str] = &["instruction_bytes", "instr_bytes", "insn_bytes"];
+
+/// Parse instruction bytes from a JSON byte array, a hex string such as
+/// `0f 01 d9` or `0f01d9`, or a Debug formatted array such as `[15, 1, 217]`
+fn parse_instruction_bytes(value: &Value) -> Option<Vec<u8>> {
+    match value {
+        Value::Array(items) => items
+            .iter()
+            .map(|item| item.as_u64().filter(|&b| b <= 0xff).map(|b| b as u8))
+            .collect(),
+        Value::String(text) => {
+            let text = text.trim();
+            if let Some(inner) = text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+                return inner
+                    .split(',')
+                    .map(|s| {
+                        let s = s.trim();
+                        match s.strip_prefix("0x") {
+                            Some(hex) => u8::from_str_radix(hex, 16).ok(),
+                            None => s.parse::<u8>().ok(),
+                        }
+                    })
+                    .collect();
+            }
+
+            let hex: String = text
+                .trim_start_matches("0x")
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .collect();
+            if hex.is_empty() || !hex.is_ascii() || !hex.len().is_multiple_of(2) {
+                return None;
+            }
+            (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+                .collect()
+        }
+        _ => None,
+    }
+}
+
+/// Disassemble the first instruction in an instruction bytes field
+///
+/// Guest instruction buffers are usually fetched at a fixed length, so only
+/// the first instruction is meaningful. The decode uses 64-bit mode unless
+/// the record carries a `bitness` field, and `rip` when present so branch
+/// targets are shown as absolute addresses.
+pub fn annotate_field(key: &str, value: &Value, fields: &Map<String, Value>) -> Option<String> {
+    if !INSTRUCTION_BYTES_KEYS.contains(&key) {
+        return None;
+    }
+
+    let bytes = parse_instruction_bytes(value).filter(|bytes| !bytes.is_empty())?;
+    let bitness = match fields.get("bitness").and_then(Value::as_u64) {
+        Some(bits @ (16 | 32)) => bits as u32,
+        _ => 64,
+    };
+    let rip = fields.get("rip").and_then(Value::as_u64).unwrap_or(0);
+
+    let mut decoder = X86Decoder::with_ip(bitness, &bytes, rip, DecoderOptions::NONE);
+    let instruction = decoder.decode();
+    if instruction.is_invalid() {
+        return Some("invalid instruction".to_string());
+    }
+
+    let mut text = String::new();
+    IntelFormatter::new().format(&instruction, &mut text);
+
+    Some(format!("{}; {} bytes", text, instruction.len()))
+}
+
+/// Decoder disassembling instruction bytes fields
+pub struct InstructionBytesDecoder {
+    conditions: Conditions,
+}
+
+impl InstructionBytesDecoder {
+    pub fn new() -> Self {
+        InstructionBytesDecoder {
+            conditions: Conditions::any().keys(INSTRUCTION_BYTES_KEYS),
+        }
+    }
+}
+
+impl Decoder for InstructionBytesDecoder {
+    fn name(&self) -> &str {
+        "x86-instruction-bytes"
+    }
+
+    fn description(&self) -> String {
+        "Disassembles x86 instruction bytes, for the bitness and rip of the record".to_string()
+    }
+
+    fn conditions(&self) -> &Conditions {
+        &self.conditions
+    }
+
+    fn example(&self) -> Option<(&str, Value)> {
+        Some(("instruction_bytes", Value::from("0f 01 d9")))
+    }
+
+    fn decode(&self, key: &str, value: &Value, ctx: &FieldContext) -> Option<Decoded> {
+        annotate_field(key, value, ctx.fields).map(Decoded::Annotate)
+    }
+}
@@ -0,0 +1,187 @@
+//! Expansion of Debug formatted error chains into one cause per line
+//!
+//! Two forms are recognized: anyhow's Debug output, with its `Caused by:`
+//! list, and nested Debug structs linked by a `source` or `cause` field,
+//! like `Error { kind: Io, source: Some(Os { code: 2, .. }) }`. Tuple
+//! variants wrapping another error, like `Io(Os { .. })`, are followed
+//! too.
+
+use serde_json::Value;
+
+use crate::decoder::{Conditions, Decoded, Decoder, FieldContext};
+
+/// Fields of a Debug struct holding the error that caused it
+const SOURCE_FIELDS: &[&str] = &["source", "cause"];
+
+/// Split text at top-level commas, outside brackets and string literals
+fn split_top_level(text: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0i32;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut start = 0;
+
+    for (index, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(text[start..index].trim());
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(text[start..].trim());
+    parts.retain(|part| !part.is_empty());
+    parts
+}
+
+/// Check whether text is a type or variant name, like `Os` or `io::Error`
+fn is_name(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_ascii_uppercase())
+        && text
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == ':')
+}
+
+/// Split a Debug value into its description and the error it wraps, and
+/// whether that error was named by a source field
+fn split_level(text: &str) -> Option<(String, Option<&str>, bool)> {
+    let text = text.trim();
+
+    let brace = text.find(" {");
+    let paren = text.find('(');
+    if let Some(open) = brace.filter(|brace| paren.is_none_or(|paren| *brace < paren)) {
+        let name = &text[..open];
+        let inner = text[open + 2..].strip_suffix('}')?;
+        if !is_name(name) {
+            return None;
+        }
+
+        let mut fields = Vec::new();
+        let mut source = None;
+        for part in split_top_level(inner) {
+            match part.split_once(": ") {
+                Some((field, value)) if SOURCE_FIELDS.contains(&field) => {
+                    source = match value.strip_prefix("Some(") {
+                        Some(value) => Some(value.strip_suffix(')')?),
+                        None if value == "None" => None,
+                        None => Some(value),
+                    };
+                }
+                _ => fields.push(part),
+            }
+        }
+        let description = match fields.is_empty() {
+            true => name.to_string(),
+            false => format!("{} {{ {} }}", name, fields.join(", ")),
+        };
+        return Some((description, source, source.is_some()));
+    }
+
+    if let Some(open) = text.find('(') {
+        let name = &text[..open];
+        let inner = text[open + 1..].strip_suffix(')')?;
+        // Only variants wrapping a single error, not values like Custom("x")
+        if is_name(name) && split_top_level(inner).len() == 1 && inner.ends_with(['}', ')']) {
+            return Some((name.to_string(), Some(inner), false));
+        }
+    }
+
+    Some((text.to_string(), None, false))
+}
+
+/// Causes of a chain of nested Debug structs, outermost first
+fn debug_chain(text: &str) -> Option<Vec<String>> {
+    let mut chain = Vec::new();
+    let mut linked = false;
+    let mut current = text;
+
+    loop {
+        let (description, source, from_field) = split_level(current)?;
+        chain.push(description);
+        linked |= from_field;
+        match source {
+            Some(source) => current = source,
+            None => break,
+        }
+    }
+
+    (linked && chain.len() > 1).then_some(chain)
+}
+
+/// Causes of an anyhow error chain, outermost first
+fn anyhow_chain(text: &str) -> Option<Vec<String>> {
+    let (message, causes) = text.split_once("\n\nCaused by:\n")?;
+    let mut chain = vec![message.trim().to_string()];
+    for line in causes
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+    {
+        // Causes are numbered when there is more than one
+        let cause = match line.split_once(": ") {
+            Some((number, cause)) if number.parse::<u32>().is_ok() => cause,
+            _ => line,
+        };
+        chain.push(cause.to_string());
+    }
+    Some(chain)
+}
+
+/// Decoder for error chain fields
+pub struct ErrorChainDecoder {
+    conditions: Conditions,
+}
+
+impl ErrorChainDecoder {
+    pub fn new() -> Self {
+        ErrorChainDecoder {
+            conditions: Conditions::any(),
+        }
+    }
+}
+
+impl Decoder for ErrorChainDecoder {
+    fn name(&self) -> &str {
+        "error-chain"
+    }
+
+    fn description(&self) -> String {
+        "Expands anyhow and Debug formatted error chains into one cause per line".to_string()
+    }
+
+    fn conditions(&self) -> &Conditions {
+        &self.conditions
+    }
+
+    fn example(&self) -> Option<(&str, Value)> {
+        Some((
+            "error",
+            Value::from("Error { kind: Io, source: Some(Os { code: 2, kind: NotFound }) }"),
+        ))
+    }
+
+    fn decode(&self, key: &str, value: &Value, _ctx: &FieldContext) -> Option<Decoded> {
+        let text = value.as_str()?;
+        let chain = anyhow_chain(text).or_else(|| debug_chain(text))?;
+
+        let mut lines = format!("    {} chain:\n      {}", key, chain[0]);
+        for cause in &chain[1..] {
+            lines.push_str(&format!("\n      caused by: {}", cause));
+        }
+        let summary = format!("<error: {}>", chain.last().unwrap());
+        Some(Decoded::Expand { summary, lines })
+    }
+}
@@ -0,0 +1,53 @@
+//! Escaping of control characters from guest messages, so a corrupted or
+//! malicious message can't move the cursor, overwrite a line with a
+//! carriage return, forge records with a line feed or reorder text with
+//! bidirectional overrides
+//!
+//! Tabs are left alone. Line feeds and carriage returns are shown as `\n`
+//! and `\r`, other characters as `\x1b` or `\u{202e}`.
+
+use std::borrow::Cow;
+use std::fmt::Write;
+
+/// Marks text cut off by `--truncate`
+pub const ELLIPSIS: char = '…';
+
+/// Check whether a character is escaped
+fn needs_escape(c: char) -> bool {
+    (c.is_control() && c != '\t')
+        // Bidirectional embeddings, overrides and isolates
+        || matches!(c, '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}')
+}
+
+/// Escape the control characters of text
+pub fn escape_controls(text: &str) -> Cow<'_, str> {
+    if !text.chars().any(needs_escape) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut escaped = String::with_capacity(text.len() + 8);
+    for c in text.chars() {
+        match c {
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c if !needs_escape(c) => escaped.push(c),
+            c if (c as u32) < 0x80 => {
+                let _ = write!(escaped, "\\x{:02x}", c as u32);
+            }
+            c => {
+                let _ = write!(escaped, "\\u{{{:x}}}", c as u32);
+            }
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// Cut the text of `output` after `start` to `max` characters, ending it
+/// with an ellipsis when anything was cut
+pub fn truncate_chars(output: &mut String, start: usize, max: usize) {
+    let Some((cut, _)) = output[start..].char_indices().nth(max) else {
+        return;
+    };
+    output.truncate(start + cut);
+    output.push(ELLIPSIS);
+}
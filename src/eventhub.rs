@@ -0,0 +1,231 @@
+//! Publishing of records to Azure Event Hubs as JSON events
+//!
+//! Events are sent in batches through the Event Hubs REST API, authorized
+//! with a shared access signature made from the key in the connection
+//! string. Each event body is a JSON object with the `timestamp`, `level`,
+//! `target` and `vp` of the record and its formatted `message`.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::error::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::sink::{RecordInfo, Sink};
+use crate::upload::{Upload, UploadError, UploadOptions, Uploader};
+
+/// Content type of a batch of events
+const BATCH_CONTENT_TYPE: &str = "application/vnd.microsoft.servicebus.json";
+
+/// Largest request body sent, below the 1 MB limit of a batch
+const MAX_BATCH_BYTES: usize = 900 * 1024;
+
+/// Lifetime of a shared access signature in seconds
+const SIGNATURE_LIFETIME: u64 = 60 * 60;
+
+/// Signatures are renewed when they have less than this many seconds left
+const SIGNATURE_RENEWAL: u64 = 5 * 60;
+
+/// Parts of an Event Hubs connection string
+struct ConnectionString {
+    /// Base URL of the namespace
+    endpoint: String,
+    key_name: String,
+    key: String,
+    hub: String,
+}
+
+impl ConnectionString {
+    /// Parse `Endpoint=sb://...;SharedAccessKeyName=...;SharedAccessKey=...;EntityPath=...`
+    fn parse(text: &str) -> Result<Self, Box<dyn Error>> {
+        let mut endpoint = None;
+        let mut key_name = None;
+        let mut key = None;
+        let mut hub = None;
+        for part in text.split(';').filter(|part| !part.is_empty()) {
+            let (name, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid connection string part '{}'", part))?;
+            let value = Some(value.to_string());
+            match name {
+                "Endpoint" => endpoint = value,
+                "SharedAccessKeyName" => key_name = value,
+                "SharedAccessKey" => key = value,
+                "EntityPath" => hub = value,
+                _ => {}
+            }
+        }
+
+        let missing = |name: &str| format!("Event Hubs connection string has no {}", name);
+        let endpoint = endpoint.ok_or_else(|| missing("Endpoint"))?;
+        // Namespaces are reached over HTTPS, emulators may use plain HTTP
+        let endpoint = match endpoint.strip_prefix("sb://") {
+            Some(host) => format!("https://{}", host),
+            None => endpoint,
+        };
+
+        Ok(ConnectionString {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            key_name: key_name.ok_or_else(|| missing("SharedAccessKeyName"))?,
+            key: key.ok_or_else(|| missing("SharedAccessKey"))?,
+            hub: hub.ok_or_else(|| missing("EntityPath"))?,
+        })
+    }
+}
+
+/// Percent-encode text for a query string
+fn url_encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// Body of an event
+#[derive(Serialize)]
+struct Event<'a> {
+    timestamp: &'a str,
+    level: &'a str,
+    target: &'a str,
+    vp: Option<u64>,
+    message: &'a str,
+}
+
+/// Entry of a batch, holding the event JSON as its body
+#[derive(Serialize)]
+struct BatchEntry<'a> {
+    #[serde(rename = "Body")]
+    body: &'a str,
+}
+
+/// Posts batches to an event hub
+struct EventHubClient {
+    connection: ConnectionString,
+    /// Resource URI the signature is made for
+    resource: String,
+    signature: String,
+    expiry: u64,
+}
+
+impl EventHubClient {
+    /// Shared access signature authorizing requests, renewed before it
+    /// expires
+    fn signature(&mut self) -> &str {
+        let now = now_secs();
+        if self.expiry < now + SIGNATURE_RENEWAL {
+            self.expiry = now + SIGNATURE_LIFETIME;
+            let resource = url_encode(&self.resource);
+            let mut mac = Hmac::<Sha256>::new_from_slice(self.connection.key.as_bytes()).unwrap();
+            mac.update(format!("{}\n{}", resource, self.expiry).as_bytes());
+            let sig = STANDARD.encode(mac.finalize().into_bytes());
+            self.signature = format!(
+                "SharedAccessSignature sr={}&sig={}&se={}&skn={}",
+                resource,
+                url_encode(&sig),
+                self.expiry,
+                url_encode(&self.connection.key_name)
+            );
+        }
+        &self.signature
+    }
+}
+
+impl Upload for EventHubClient {
+    fn upload(&mut self, body: &str) -> Result<(), UploadError> {
+        let url = format!("{}/messages", self.resource);
+        let signature = self.signature().to_string();
+        ureq::post(&url)
+            .set("Authorization", &signature)
+            .set("Content-Type", BATCH_CONTENT_TYPE)
+            .send_string(body)?;
+        Ok(())
+    }
+
+    fn failure(&self) -> String {
+        format!("Failed to publish to event hub {}", self.connection.hub)
+    }
+
+    fn destination(&self) -> String {
+        format!("event hub {}", self.resource)
+    }
+}
+
+/// Publishes records to an event hub in batches
+pub struct EventHubSink {
+    uploader: Uploader,
+    /// Pending batch entries, each serialized as JSON
+    entries: Vec<String>,
+    bytes: usize,
+}
+
+impl EventHubSink {
+    /// Publish to the event hub named by a connection string
+    pub fn new(connection_string: &str, options: &UploadOptions) -> Result<Self, Box<dyn Error>> {
+        let connection = ConnectionString::parse(connection_string)?;
+        let resource = format!("{}/{}", connection.endpoint, connection.hub);
+        let client = EventHubClient {
+            connection,
+            resource,
+            signature: String::new(),
+            expiry: 0,
+        };
+        Ok(EventHubSink {
+            uploader: Uploader::new(client, options.clone())?,
+            entries: Vec::new(),
+            bytes: 0,
+        })
+    }
+}
+
+impl Sink for EventHubSink {
+    fn write_record(&mut self, text: &str, info: &RecordInfo) -> Result<(), Box<dyn Error>> {
+        let event = serde_json::to_string(&Event {
+            timestamp: &info.timestamp,
+            level: &info.level,
+            target: &info.target,
+            vp: info.vp,
+            message: text,
+        })?;
+        let entry = serde_json::to_string(&BatchEntry { body: &event })?;
+
+        // The body is an opening bracket, then each entry followed by a
+        // comma or the closing bracket
+        if !self.entries.is_empty() && 1 + self.bytes + entry.len() + 1 > MAX_BATCH_BYTES {
+            self.flush()?;
+        }
+        self.bytes += entry.len() + 1;
+        self.entries.push(entry);
+        if self.uploader.add_record() {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.entries.is_empty() {
+            return Ok(());
+        }
+
+        let body = format!("[{}]", self.entries.join(","));
+        self.entries.clear();
+        self.bytes = 0;
+        self.uploader.send(body)
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.flush()?;
+        self.uploader.finish()
+    }
+}
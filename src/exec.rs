@@ -0,0 +1,80 @@
+//! External record decoder run as a subprocess
+//!
+//! The command is started once and receives each tracing JSON record as a
+//! single line on stdin. For every line it must write exactly one line to
+//! stdout, holding the transformed record, and flush it before reading the
+//! next record. An empty line keeps the record unchanged.
+
+use std::error::Error;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+pub struct ExecDecoder {
+    command: String,
+    child: Child,
+    stdin: Option<BufWriter<ChildStdin>>,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl ExecDecoder {
+    /// Start the decoder command through the platform shell
+    pub fn spawn(command: &str) -> Result<Self, Box<dyn Error>> {
+        let mut shell = if cfg!(windows) {
+            let mut shell = Command::new("cmd");
+            shell.arg("/C");
+            shell
+        } else {
+            let mut shell = Command::new("sh");
+            shell.arg("-c");
+            shell
+        };
+
+        let mut child = shell
+            .arg(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|err| format!("Failed to start decoder '{}': {}", command, err))?;
+
+        let stdin = child.stdin.take().map(BufWriter::new);
+        let stdout = BufReader::new(child.stdout.take().ok_or("Decoder has no stdout")?);
+
+        Ok(ExecDecoder {
+            command: command.to_string(),
+            child,
+            stdin,
+            stdout,
+        })
+    }
+
+    /// Send a record to the decoder and return its transformed version
+    pub fn transform(&mut self, record: &str) -> Result<String, Box<dyn Error>> {
+        let stdin = self.stdin.as_mut().ok_or("Decoder stdin is closed")?;
+
+        // Records are sent on a single line, so embedded newlines in raw
+        // messages would break the framing
+        let line = record.replace(['\r', '\n'], " ");
+        writeln!(stdin, "{}", line)?;
+        stdin.flush()?;
+
+        let mut response = String::new();
+        if self.stdout.read_line(&mut response)? == 0 {
+            return Err(format!("Decoder '{}' exited unexpectedly", self.command).into());
+        }
+
+        let response = response.trim_end_matches(['\r', '\n']);
+        if response.is_empty() {
+            Ok(record.to_string())
+        } else {
+            Ok(response.to_string())
+        }
+    }
+}
+
+impl Drop for ExecDecoder {
+    fn drop(&mut self) {
+        // Closing stdin signals the end of input to the decoder
+        self.stdin.take();
+        let _ = self.child.wait();
+    }
+}
@@ -0,0 +1,73 @@
+//! Extraction of the rows of a CSV export that pass the filters into a new
+//! export with the same columns, so a small subset can be shared with tools
+//! expecting the original format
+
+use csv::{ByteRecord, ReaderBuilder, WriterBuilder};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// Copy the rows of `files` whose message is `shown` to a CSV file at
+/// `output`, with the header of the first file
+///
+/// All files must have the same columns, like the chunks of one export.
+pub fn extract(
+    files: &[PathBuf],
+    output: &Path,
+    strict: bool,
+    mut shown: impl FnMut(&str) -> bool,
+) -> Result<(), Box<dyn Error>> {
+    let mut writer = WriterBuilder::new()
+        .flexible(!strict)
+        .from_path(output)
+        .map_err(|err| format!("Failed to create {}: {}", output.display(), err))?;
+
+    let mut header: Option<ByteRecord> = None;
+    let (mut rows, mut extracted) = (0u64, 0u64);
+    for path in files {
+        let mut reader = ReaderBuilder::new()
+            .flexible(!strict)
+            .from_path(path)
+            .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+        let file_header = reader.byte_headers()?.clone();
+        let message_idx = file_header
+            .iter()
+            .position(|h| h == b"ExtractedMessage")
+            .ok_or_else(|| format!("No 'ExtractedMessage' column found in {}", path.display()))?;
+        match &header {
+            Some(header) if *header != file_header => {
+                return Err(format!(
+                    "{} has different columns than {}",
+                    path.display(),
+                    files[0].display()
+                )
+                .into());
+            }
+            Some(_) => {}
+            None => {
+                writer.write_byte_record(&file_header)?;
+                header = Some(file_header);
+            }
+        }
+
+        let mut record = ByteRecord::new();
+        while reader.read_byte_record(&mut record)? {
+            rows += 1;
+            let Some(message) = record.get(message_idx) else {
+                continue;
+            };
+            if shown(&String::from_utf8_lossy(message)) {
+                writer.write_byte_record(&record)?;
+                extracted += 1;
+            }
+        }
+    }
+
+    writer.flush()?;
+    eprintln!(
+        "Extracted {} of {} rows to {}",
+        extracted,
+        rows,
+        output.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,124 @@
+//! Distributions of field values, for the simple questions that would
+//! otherwise need another query in Kusto
+//!
+//! Integers are counted by value, so `16` and `"0x10"` are the same value,
+//! and shown as hex like the formatted records. The names `level` and
+//! `target` stand for the header of the records, for a breakdown by level
+//! or target.
+//!
+//! With the `arrow` feature the values are collected into Arrow record
+//! batches and counted with columnar kernels, which is quicker on large
+//! exports.
+
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+
+use crate::input::Records;
+use crate::numbers;
+use crate::report::{print_table, Event};
+
+/// Values seen in one field
+#[derive(Default)]
+pub struct FieldStats {
+    pub records: u64,
+    pub counts: HashMap<String, u64>,
+    /// Smallest and largest integer value
+    pub range: Option<(u64, u64)>,
+}
+
+/// The value of a field of a record as shown, with its integer value if it
+/// has one
+pub fn field_value(event: &Event, field: &str) -> Option<(String, Option<u64>)> {
+    let value = match field {
+        "level" => return Some((event.level.to_string(), None)),
+        "target" => return Some((event.target.to_string(), None)),
+        _ => event.fields.get(field)?,
+    };
+    let integer = match value {
+        Value::String(text) => numbers::parse_integer(text),
+        other => match numbers::integer_value(other) {
+            Some(numbers::Integer::Unsigned(number)) => u64::try_from(number).ok(),
+            _ => None,
+        },
+    };
+    let text = match (integer, value) {
+        (Some(number), _) => format!("{:#x}", number),
+        (None, Value::String(text)) => text.clone(),
+        (None, other) => other.to_string(),
+    };
+    Some((text, integer))
+}
+
+/// Collect the values of each of `fields`
+#[cfg(not(feature = "arrow"))]
+fn collect(records: Records, fields: &[String]) -> Result<Vec<FieldStats>, Box<dyn Error>> {
+    let mut stats: Vec<FieldStats> = fields.iter().map(|_| FieldStats::default()).collect();
+
+    crate::report::for_each_event(records, |event| {
+        for (field, stats) in fields.iter().zip(&mut stats) {
+            let Some((text, integer)) = field_value(event, field) else {
+                continue;
+            };
+            stats.records += 1;
+            *stats.counts.entry(text).or_default() += 1;
+            if let Some(number) = integer {
+                stats.range = Some(match stats.range {
+                    Some((min, max)) => (min.min(number), max.max(number)),
+                    None => (number, number),
+                });
+            }
+        }
+    })?;
+    Ok(stats)
+}
+
+/// Collect the values of each of `fields` into record batches
+#[cfg(feature = "arrow")]
+fn collect(records: Records, fields: &[String]) -> Result<Vec<FieldStats>, Box<dyn Error>> {
+    let table = crate::columnar::EventTable::load(records, fields)?;
+    Ok((0..fields.len())
+        .map(|index| table.field_stats(index))
+        .collect())
+}
+
+/// Print, for each of `fields`, how many records hold it, its number of
+/// distinct values, its smallest and largest integer value and its `top`
+/// most common values
+pub fn fields_stats(records: Records, fields: &[String], top: usize) -> Result<(), Box<dyn Error>> {
+    let stats = collect(records, fields)?;
+
+    for (index, (field, stats)) in fields.iter().zip(stats).enumerate() {
+        if index > 0 {
+            println!();
+        }
+        println!("Field: {}", field);
+        if stats.records == 0 {
+            println!("  Not found in any record");
+            continue;
+        }
+        println!("  Records: {}", stats.records);
+        println!("  Distinct values: {}", stats.counts.len());
+        if let Some((min, max)) = stats.range {
+            println!("  Min: {:#x}", min);
+            println!("  Max: {:#x}", max);
+        }
+
+        let mut counts: Vec<(String, u64)> = stats.counts.into_iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let rows: Vec<Vec<String>> = counts
+            .iter()
+            .take(top)
+            .map(|(value, count)| {
+                vec![
+                    value.clone(),
+                    count.to_string(),
+                    format!("{:.1}%", *count as f64 * 100.0 / stats.records as f64),
+                ]
+            })
+            .collect();
+        println!();
+        print_table(&["VALUE", "COUNT", "SHARE"], &rows);
+    }
+    Ok(())
+}
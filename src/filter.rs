@@ -0,0 +1,144 @@
+//! Filtering of records by time and level
+
+use clap::ValueEnum;
+use serde_json::Value;
+
+use crate::correlate;
+
+/// Tracing levels, from most to least severe
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+/// Names of the levels of tracing records, and the common names of
+/// severities used instead, in upper case
+pub const LEVEL_NAMES: &[(&str, Level)] = &[
+    ("ERROR", Level::Error),
+    ("CRITICAL", Level::Error),
+    ("FATAL", Level::Error),
+    ("WARN", Level::Warn),
+    ("WARNING", Level::Warn),
+    ("INFO", Level::Info),
+    ("INFORMATION", Level::Info),
+    ("DEBUG", Level::Debug),
+    ("TRACE", Level::Trace),
+    ("VERBOSE", Level::Trace),
+];
+
+impl Level {
+    /// Parse the level of a tracing record, or the common names of
+    /// severities used instead
+    pub fn parse(text: &str) -> Option<Level> {
+        LEVEL_NAMES
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(text))
+            .map(|(_, level)| *level)
+    }
+
+    /// Bit representing the level in a set of levels
+    pub fn bit(self) -> u8 {
+        1 << self as u8
+    }
+
+    /// Set of levels at least as severe as this one
+    pub fn at_least(self) -> u8 {
+        (self.bit() << 1) - 1
+    }
+}
+
+/// Bit for records whose level is missing or unrecognized
+pub const OTHER_LEVEL_BIT: u8 = 1 << 7;
+
+/// Restrictions on which records are shown
+///
+/// Timestamps are compared as text, which orders the ISO 8601 UTC
+/// timestamps of tracing records chronologically and lets a prefix such as
+/// `2024-05-01T10` stand for the start of that hour.
+#[derive(Clone, Debug, Default)]
+pub struct RecordFilter {
+    /// Earliest timestamp to show
+    pub since: Option<String>,
+    /// Timestamp to stop before
+    pub until: Option<String>,
+    /// Least severe level to show
+    pub level: Option<Level>,
+    /// Lowercase text the message or target must contain
+    pub text: Option<String>,
+    /// Correlation ID the record must carry
+    pub trace_id: Option<String>,
+}
+
+impl RecordFilter {
+    /// Check whether any restriction is set
+    pub fn is_active(&self) -> bool {
+        self.since.is_some()
+            || self.until.is_some()
+            || self.level.is_some()
+            || self.text.is_some()
+            || self.trace_id.is_some()
+    }
+
+    /// Check whether a tracing record carries the correlation ID to show
+    pub fn matches_trace(&self, json: &Value) -> bool {
+        self.trace_id
+            .as_deref()
+            .is_none_or(|id| correlate::has_id(json, id))
+    }
+
+    /// Check whether a record is shown
+    pub fn matches(&self, timestamp: &str, level: &str, target: &str, message: &str) -> bool {
+        self.since.as_deref().is_none_or(|since| timestamp >= since)
+            && self.until.as_deref().is_none_or(|until| timestamp < until)
+            && self
+                .level
+                .is_none_or(|min| Level::parse(level).is_some_and(|level| level <= min))
+            && self.text.as_deref().is_none_or(|text| {
+                target.to_lowercase().contains(text) || message.to_lowercase().contains(text)
+            })
+    }
+
+    /// Check whether a record with this timestamp and level may be shown,
+    /// before the rest of it is read
+    pub fn matches_header(&self, timestamp: Option<&str>, level: Option<&str>) -> bool {
+        let in_time = match timestamp {
+            Some(timestamp) => {
+                self.since.as_deref().is_none_or(|since| timestamp >= since)
+                    && self.until.as_deref().is_none_or(|until| timestamp < until)
+            }
+            // Records without a time can't be placed within --since and
+            // --until
+            None => self.since.is_none() && self.until.is_none(),
+        };
+        in_time
+            && self.level.is_none_or(|min| {
+                level
+                    .and_then(Level::parse)
+                    .is_some_and(|level| level <= min)
+            })
+    }
+
+    /// Check whether a range of records may hold a shown one, given the
+    /// bounds of its timestamps and the set of its levels
+    pub fn may_match_range(&self, min: Option<&str>, max: Option<&str>, levels: u8) -> bool {
+        let (Some(min), Some(max)) = (min, max) else {
+            // Records without a timestamp are never shown while filtering
+            // by time
+            return self.since.is_none()
+                && self.until.is_none()
+                && self
+                    .level
+                    .is_none_or(|level| levels & level.at_least() != 0);
+        };
+
+        self.since.as_deref().is_none_or(|since| max >= since)
+            && self.until.as_deref().is_none_or(|until| min < until)
+            && self
+                .level
+                .is_none_or(|level| levels & level.at_least() != 0)
+    }
+}
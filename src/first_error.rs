@@ -0,0 +1,102 @@
+//! Best-effort starting point for finding the root cause of a failure
+//!
+//! The earliest error is usually closer to the cause than the last one.
+//! When a log ends in a fatal event without any error before it, the first
+//! warning before that event is reported instead.
+
+use regex::Regex;
+use std::error::Error;
+use std::fmt::Write as _;
+
+use crate::escape::escape_controls;
+use crate::filter::Level;
+use crate::input::Records;
+use crate::report::for_each_event;
+use crate::suppress::Suppressions;
+use crate::time::parse_timestamp;
+
+/// Messages of events that end a run
+pub const FATAL_PATTERN: &str = r"(?i)panic|triple fault|fatal|bugcheck|crash";
+
+/// A record of the timeline
+struct Entry {
+    /// Time in nanoseconds since the epoch, that of the record before for
+    /// records without one
+    time: i64,
+    level: Option<Level>,
+    fatal: bool,
+    suppressed: bool,
+    line: String,
+}
+
+/// Print the earliest error of the log, or the first warning before a
+/// fatal event, with `context` records around it
+pub fn first_error(
+    records: Records,
+    suppressions: &Suppressions,
+    context: usize,
+) -> Result<(), Box<dyn Error>> {
+    let fatal = Regex::new(FATAL_PATTERN).unwrap();
+    let mut timeline = Vec::new();
+    let mut time = i64::MIN;
+
+    for_each_event(records, |event| {
+        time = parse_timestamp(event.timestamp).unwrap_or(time);
+        let mut line = format!(
+            "[{}][{}][{}] {}",
+            event.timestamp, event.level, event.target, event.message
+        );
+        for (key, value) in event.fields {
+            if key != "message" {
+                let _ = write!(line, " {}={}", key, value);
+            }
+        }
+        timeline.push(Entry {
+            time,
+            level: Level::parse(event.level),
+            fatal: fatal.is_match(event.message),
+            suppressed: suppressions.is_suppressed(event.target, event.message),
+            line: escape_controls(&line).into_owned(),
+        });
+    })?;
+
+    // Exports aren't always in order
+    timeline.sort_by_key(|entry| entry.time);
+
+    let relevant = |entry: &Entry, level: Level| !entry.suppressed && entry.level == Some(level);
+    let first_fatal = timeline.iter().position(|entry| entry.fatal);
+    let first_error = timeline
+        .iter()
+        .position(|entry| relevant(entry, Level::Error));
+
+    let (index, reason) = match (first_error, first_fatal) {
+        (Some(error), Some(fatal)) if error <= fatal => (error, "First error"),
+        (_, Some(fatal)) => match timeline[..fatal]
+            .iter()
+            .position(|entry| relevant(entry, Level::Warn))
+        {
+            Some(warn) => (warn, "First warning before a fatal event"),
+            None => (fatal, "Fatal event without an earlier error or warning"),
+        },
+        (Some(error), None) => (error, "First error"),
+        (None, None) => {
+            println!("No errors found");
+            return Ok(());
+        }
+    };
+
+    println!("{}:", reason);
+    let start = index.saturating_sub(context);
+    let end = (index + context + 1).min(timeline.len());
+    for (position, entry) in timeline[start..end].iter().enumerate() {
+        let marker = if start + position == index { ">" } else { " " };
+        println!("{} {}", marker, entry.line);
+    }
+    if let Some(fatal) = first_fatal.filter(|fatal| *fatal != index) {
+        println!();
+        println!("Followed by fatal event:");
+        println!("  {}", timeline[fatal].line);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,141 @@
+//! Flagging of records whose numeric fields cross a threshold
+//!
+//! A flag rule such as `duration_ns > 1ms` compares a field of each record
+//! with a threshold. Thresholds with a unit are converted to the unit of
+//! the field, known from its name, so `1ms` matches a `duration_ns` of
+//! 1000000.
+
+use serde_json::{Map, Value};
+use std::error::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::units::{self, UnitsDecoder};
+
+/// Comparison between a field and a threshold
+#[derive(Clone, Copy)]
+enum Comparison {
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+    Equal,
+    NotEqual,
+}
+
+impl Comparison {
+    fn holds(self, value: f64, threshold: f64) -> bool {
+        match self {
+            Comparison::Greater => value > threshold,
+            Comparison::GreaterOrEqual => value >= threshold,
+            Comparison::Less => value < threshold,
+            Comparison::LessOrEqual => value <= threshold,
+            Comparison::Equal => value == threshold,
+            Comparison::NotEqual => value != threshold,
+        }
+    }
+}
+
+/// A threshold on a numeric field
+struct FlagRule {
+    /// The rule as given, for the summary
+    text: String,
+    key: String,
+    comparison: Comparison,
+    /// Threshold in the unit of the field
+    threshold: f64,
+    /// Number of records flagged by the rule
+    count: AtomicU64,
+}
+
+/// Parse a rule like `duration_ns > 1ms`
+fn parse_rule(text: &str, units: &UnitsDecoder) -> Result<FlagRule, Box<dyn Error>> {
+    // Two character operators come first so `>=` isn't read as `>`
+    const OPERATORS: &[(&str, Comparison)] = &[
+        (">=", Comparison::GreaterOrEqual),
+        ("<=", Comparison::LessOrEqual),
+        ("==", Comparison::Equal),
+        ("!=", Comparison::NotEqual),
+        (">", Comparison::Greater),
+        ("<", Comparison::Less),
+        ("=", Comparison::Equal),
+    ];
+
+    let (key, comparison, threshold) = OPERATORS
+        .iter()
+        .find_map(|(operator, comparison)| {
+            let (key, threshold) = text.split_once(operator)?;
+            Some((key.trim(), *comparison, threshold.trim()))
+        })
+        .ok_or_else(|| format!("Invalid flag '{}', expected '<field> <op> <value>'", text))?;
+
+    if key.is_empty() {
+        return Err(format!("Invalid flag '{}', missing field name", text).into());
+    }
+
+    let (mut threshold, threshold_unit) = units::parse_quantity(threshold)
+        .ok_or_else(|| format!("Invalid flag '{}', bad value '{}'", text, threshold))?;
+
+    if let Some(threshold_unit) = threshold_unit {
+        let field_unit = units
+            .unit_of(key)
+            .ok_or_else(|| format!("Invalid flag '{}', '{}' has no known unit", text, key))?;
+        if field_unit.is_duration() != threshold_unit.is_duration() {
+            return Err(
+                format!("Invalid flag '{}', '{}' is in a different unit", text, key).into(),
+            );
+        }
+        threshold = threshold_unit.to_base(threshold) / field_unit.to_base(1.0);
+    }
+
+    Ok(FlagRule {
+        text: text.to_string(),
+        key: key.to_string(),
+        comparison,
+        threshold,
+        count: AtomicU64::new(0),
+    })
+}
+
+/// A set of flag rules with counts of the records each flagged
+pub struct FlagSet {
+    rules: Vec<FlagRule>,
+}
+
+impl FlagSet {
+    /// Parse flag rules, using the field units known to `units`
+    pub fn parse(rules: &[String], units: &UnitsDecoder) -> Result<Self, Box<dyn Error>> {
+        let rules = rules
+            .iter()
+            .map(|rule| parse_rule(rule, units))
+            .collect::<Result<_, _>>()?;
+        Ok(FlagSet { rules })
+    }
+
+    /// Check whether any rule flags a record with these fields, counting it
+    /// for each rule that does
+    pub fn check(&self, fields: &Map<String, Value>) -> bool {
+        let mut flagged = false;
+        for rule in &self.rules {
+            let holds = fields
+                .get(&rule.key)
+                .and_then(Value::as_f64)
+                .is_some_and(|value| rule.comparison.holds(value, rule.threshold));
+            if holds {
+                rule.count.fetch_add(1, Ordering::Relaxed);
+                flagged = true;
+            }
+        }
+        flagged
+    }
+
+    /// Print the number of records flagged by each rule
+    pub fn report(&self) {
+        for rule in &self.rules {
+            eprintln!(
+                "Flagged {} records: {}",
+                rule.count.load(Ordering::Relaxed),
+                rule.text
+            );
+        }
+    }
+}
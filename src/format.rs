@@ -0,0 +1,590 @@
+//! Formatting of tracing records as readable lines, the core of the tool
+
+use serde::Deserialize;
+use serde_json::Value;
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt::Write as _;
+use std::sync::Arc;
+
+use crate::ansi::AnsiStripper;
+use crate::decoder::{Decoded, DecoderRegistry, FieldContext};
+use crate::escape::{self, escape_controls};
+use crate::filter::RecordFilter;
+use crate::flag::FlagSet;
+use crate::guid::GuidNames;
+use crate::header::{HeaderField, HeaderNames, PLACEHOLDER};
+use crate::input::RecordRef;
+use crate::json_path::JsonPath;
+use crate::rename::FieldMap;
+use crate::rules::RuleSet;
+use crate::scrub::Scrubber;
+use crate::sink::RecordInfo;
+use crate::theme::{self, Theme};
+use crate::time::{self, TimestampFormat};
+use crate::units::UnitsDecoder;
+use crate::vp_links::VpEvent;
+use crate::wrap::Wrapper;
+use crate::{boot, links, numbers, payload, report, tables, validate};
+
+/// Available JSON parsers
+#[cfg(feature = "simd-json")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum JsonParser {
+    /// serde_json
+    Serde,
+    /// simd-json, faster on large records
+    Simd,
+}
+
+/// Options controlling how records are formatted
+pub struct FormatOptions {
+    /// Decoders applied to each field
+    pub decoders: DecoderRegistry,
+    /// Records to show
+    pub filter: RecordFilter,
+    /// Redaction of sensitive values
+    pub scrubber: Option<Scrubber>,
+    /// Thresholds marking records with `!!`
+    pub flags: Option<FlagSet>,
+    /// Readable names of fields
+    pub field_map: Option<FieldMap>,
+    /// Format of record timestamps, converted to ISO 8601
+    pub timestamp_format: Option<TimestampFormat>,
+    /// Time of boot in nanoseconds since the Unix epoch, for boot-relative
+    /// timestamps
+    pub boot_time: Option<i64>,
+    /// Colors of the records, when they are colored
+    pub theme: Option<Theme>,
+    /// Wrap documented fields in terminal hyperlinks
+    pub links: bool,
+    /// Classify VP entries and exits for --vp-links
+    pub vp_links: bool,
+    /// Field the records are grouped by
+    pub group_by: Option<String>,
+    /// Write shown records as their original message
+    pub raw: bool,
+    /// Write negative integers with a sign instead of in two's complement
+    pub signed_hex: bool,
+    /// Transform rules file, also registered as a decoder, reloaded when
+    /// following the input
+    pub rules: Option<Arc<RuleSet>>,
+    /// Location of the tracing record inside wrapped messages
+    pub json_path: Option<JsonPath>,
+    /// Removal of escape sequences from messages, with --strip-ansi
+    pub ansi: Option<AnsiStripper>,
+    /// Longest message or field value shown, in characters
+    pub truncate: Option<usize>,
+    /// Wrapping of long lines, with --wrap
+    pub wrapper: Option<Wrapper>,
+    /// Names the header fields are read from
+    pub header_names: HeaderNames,
+    /// Describe how each field was formatted in the record info
+    pub explain: bool,
+    /// Mark values that can't be right with `[suspect]`
+    pub validate: bool,
+    /// Parser for the tracing JSON of each record
+    #[cfg(feature = "simd-json")]
+    pub parser: JsonParser,
+}
+
+impl FormatOptions {
+    /// Options formatting records like the tool given no option, with
+    /// `decoders`
+    pub fn new(decoders: DecoderRegistry) -> Self {
+        FormatOptions {
+            decoders,
+            filter: RecordFilter::default(),
+            scrubber: None,
+            flags: None,
+            field_map: None,
+            timestamp_format: None,
+            boot_time: None,
+            theme: None,
+            links: false,
+            vp_links: false,
+            group_by: None,
+            raw: false,
+            signed_hex: false,
+            rules: None,
+            json_path: None,
+            ansi: None,
+            truncate: None,
+            wrapper: None,
+            header_names: HeaderNames::default(),
+            explain: false,
+            validate: false,
+            #[cfg(feature = "simd-json")]
+            parser: JsonParser::Serde,
+        }
+    }
+}
+
+/// The decoders applied given no option: the built-in ones, with the
+/// installed decode tables
+pub fn default_decoders() -> Result<DecoderRegistry, Box<dyn Error>> {
+    let mut guid_names = GuidNames::new();
+    tables::load_guids(&mut guid_names)?;
+    let mut decoders = DecoderRegistry::builtin(
+        guid_names,
+        UnitsDecoder::new(),
+        payload::DEFAULT_HEXDUMP_THRESHOLD,
+        false,
+    );
+    tables::register(&mut decoders)?;
+    Ok(decoders)
+}
+
+/// Parse the tracing JSON of a record, found at --json-path when given,
+/// unless the source held it parsed
+fn parse_json<'a>(
+    message_field: &str,
+    parsed: Option<&'a Value>,
+    options: &FormatOptions,
+) -> Option<Cow<'a, Value>> {
+    let mut json = match parsed {
+        // Records held parsed are only copied to be changed
+        Some(json) if options.ansi.is_none() && options.json_path.is_none() => {
+            return Some(Cow::Borrowed(json));
+        }
+        Some(json) => json.clone(),
+        None => parse_message(message_field, options)?,
+    };
+    if let Some(ansi) = &options.ansi {
+        if parsed.is_some() || AnsiStripper::may_contain(message_field) {
+            ansi.strip_value(&mut json);
+        }
+    }
+    match &options.json_path {
+        Some(path) => path.select(json),
+        None => Some(json),
+    }
+    .map(Cow::Owned)
+}
+
+/// The header of a tracing record, borrowing its strings from the message
+/// where they hold no escapes
+#[derive(Deserialize)]
+struct BorrowedHeader<'a> {
+    #[serde(borrow, default, alias = "time")]
+    timestamp: Option<Cow<'a, str>>,
+    #[serde(borrow, default, alias = "severity")]
+    level: Option<Cow<'a, str>>,
+}
+
+/// Check whether a message may pass the --since, --until and --level
+/// filters, reading only its header so records filtered out are never
+/// parsed into values
+///
+/// Messages whose header can't be read this way, such as those logging a
+/// field under both of its names, are left to the full parse.
+fn may_pass_header(message_field: &str, parsed: Option<&Value>, options: &FormatOptions) -> bool {
+    let filter = &options.filter;
+    let header_filtered =
+        filter.since.is_some() || filter.until.is_some() || filter.level.is_some();
+    // The header read must be the one the filters see once the record is
+    // parsed
+    if !header_filtered
+        || parsed.is_some()
+        || options.ansi.is_some()
+        || options.json_path.is_some()
+        || options.boot_time.is_some()
+        || options.timestamp_format.is_some()
+        || !options.header_names.has_usual_names()
+    {
+        return true;
+    }
+    match serde_json::from_str::<BorrowedHeader>(message_field) {
+        Ok(header) => filter.matches_header(header.timestamp.as_deref(), header.level.as_deref()),
+        Err(_) => true,
+    }
+}
+
+/// Parse the JSON of a message
+fn parse_message(message_field: &str, options: &FormatOptions) -> Option<Value> {
+    #[cfg(feature = "simd-json")]
+    if options.parser == JsonParser::Simd {
+        // simd-json parses in place, so it works on a copy of the message
+        let mut bytes = message_field.as_bytes().to_vec();
+        return simd_json::serde::from_slice(&mut bytes).ok();
+    }
+
+    let _ = options;
+    serde_json::from_str(message_field).ok()
+}
+
+/// Write an integer value as hex if possible, including integers logged as
+/// strings, and negative ones as `-0x..` when `signed` is set
+fn write_value_as_hex(output: &mut String, key: &str, value: &Value, signed: bool) {
+    match numbers::integer_value(value) {
+        Some(integer) => {
+            let _ = write!(output, " {}={}", key, numbers::format_hex(integer, signed));
+        }
+        // Fall back to default for floats and other values
+        None => {
+            let _ = write!(output, " {}={}", key, value);
+        }
+    }
+}
+
+/// Write a field as ` key=value`, as decoded when a decoder took it, adding
+/// the lines of expanded fields to `continuation`
+pub fn write_field(
+    output: &mut String,
+    shown_key: &str,
+    value: &Value,
+    decoded: Option<Decoded>,
+    signed: bool,
+    continuation: &mut Vec<String>,
+) {
+    match decoded {
+        Some(Decoded::Replace(transformed)) => {
+            let transformed = escape_controls(&transformed);
+            let _ = write!(output, " {}=\"{}\"", shown_key, transformed);
+        }
+        Some(Decoded::Value(text)) => {
+            let _ = write!(output, " {}={}", shown_key, escape_controls(&text));
+        }
+        Some(Decoded::Annotate(note)) => {
+            write_value_as_hex(output, shown_key, value, signed);
+            let _ = write!(output, " ({})", escape_controls(&note));
+        }
+        Some(Decoded::Expand { summary, lines }) => {
+            let _ = write!(output, " {}={}", shown_key, escape_controls(&summary));
+            // The line breaks between the lines are kept
+            let lines: Vec<Cow<str>> = lines.split('\n').map(escape_controls).collect();
+            continuation.push(lines.join("\n"));
+        }
+        // Format regular values
+        None => write_value_as_hex(output, shown_key, value, signed),
+    }
+}
+
+/// Cut the text of `output` after `start` to the --truncate length,
+/// scrubbing it first so a cut value can't escape the patterns redacting it
+fn truncate(output: &mut String, start: usize, options: &FormatOptions) {
+    let Some(max) = options.truncate else {
+        return;
+    };
+    if let Some(scrubber) = &options.scrubber {
+        let scrubbed = scrubber.scrub_text(&output[start..]);
+        output.truncate(start);
+        output.push_str(&scrubbed);
+    }
+    escape::truncate_chars(output, start, max);
+}
+
+/// Write the `[timestamp][level][target]` header of a record
+fn write_header(
+    output: &mut String,
+    timestamp: &str,
+    level: &str,
+    target: &str,
+    theme: Option<&Theme>,
+) {
+    let timestamp = escape_controls(timestamp);
+    let level = escape_controls(level);
+    let target = escape_controls(target);
+    let Some(theme) = theme else {
+        let _ = write!(output, "[{}][{}][{}]", timestamp, level, target);
+        return;
+    };
+    output.push('[');
+    theme::write_styled(output, &timestamp, theme.timestamp());
+    output.push_str("][");
+    theme::write_styled(output, &level, theme.level(&level));
+    output.push_str("][");
+    theme::write_styled(output, &target, theme.target(&target));
+    output.push(']');
+}
+
+/// Write how a field was formatted for --explain: its value as logged, the
+/// decoders tried on it and the text shown
+fn explain_field(
+    explanation: &mut String,
+    logged_key: &str,
+    key: &str,
+    value: &Value,
+    shown: &str,
+    options: &FormatOptions,
+    ctx: &FieldContext,
+) {
+    let _ = write!(explanation, "\n  {}={}", escape_controls(logged_key), value);
+    if key != logged_key {
+        let _ = write!(explanation, "\n    renamed to {}", escape_controls(key));
+    }
+    for line in options.decoders.explain(key, value, ctx) {
+        let _ = write!(explanation, "\n    {}", escape_controls(&line));
+    }
+    let _ = write!(explanation, "\n    shown as{}", shown);
+}
+
+/// Process a single message field, writing it in the desired output format
+/// to `output`
+///
+/// The buffer is cleared first so a single one can be reused across
+/// records. It is left empty for empty message fields. The header of
+/// tracing records is stored in `info`. Returns whether a --flag rule
+/// marked the record.
+pub fn process_message(
+    message_field: &str,
+    parsed: Option<&Value>,
+    options: &FormatOptions,
+    output: &mut String,
+    info: &mut RecordInfo,
+) -> bool {
+    output.clear();
+
+    // Skip empty fields
+    if message_field.is_empty() && parsed.is_none() {
+        return false;
+    }
+    // Sources holding the record parsed leave the text empty, so it is
+    // only written out for records passed through unformatted
+    let raw_text = |message_field: &str| match parsed {
+        Some(json) if message_field.is_empty() => escape_controls(&json.to_string()).into_owned(),
+        _ => escape_controls(message_field).into_owned(),
+    };
+
+    // Escape sequences in lines that aren't JSON are stripped here, those
+    // escaped in JSON strings once parsed
+    let stripped;
+    let message_field = match &options.ansi {
+        Some(ansi) if message_field.contains('\x1b') => {
+            stripped = ansi.strip(message_field);
+            &*stripped
+        }
+        _ => message_field,
+    };
+
+    // Parse the JSON message, return raw message on failure
+    // Records that aren't tracing JSON are hidden while filtering, since
+    // their time and level are unknown
+    let passthrough = !options.filter.is_active();
+    if !may_pass_header(message_field, parsed, options) {
+        return false;
+    }
+
+    let json = match parse_json(message_field, parsed, options) {
+        Some(json) => json,
+        None if passthrough => {
+            output.push_str(&raw_text(message_field));
+            if options.explain {
+                info.explanation
+                    .push_str("\n  not tracing JSON, shown as logged");
+            }
+            return false;
+        }
+        None => return false,
+    };
+
+    // Extract the header fields
+    let names = &options.header_names;
+    let converted;
+    let timestamp = names.get(&json, HeaderField::Timestamp);
+    let relative = options
+        .boot_time
+        .zip(timestamp.and_then(boot::relative_seconds));
+    let timestamp = match (relative, &options.timestamp_format) {
+        (Some((boot, seconds)), _) => {
+            converted = Some(time::format_timestamp(boot + (seconds * 1e9) as i64));
+            converted.as_deref()
+        }
+        (_, Some(format)) => {
+            converted = timestamp
+                .and_then(|value| format.parse(value))
+                .map(time::format_timestamp);
+            converted.as_deref()
+        }
+        _ => timestamp.and_then(Value::as_str),
+    };
+    let level = names.get(&json, HeaderField::Level).and_then(Value::as_str);
+    let target = names
+        .get(&json, HeaderField::Target)
+        .and_then(Value::as_str);
+
+    // Only the fields are required, other header fields missing are shown
+    // as placeholders
+    let Some(fields) = json.get("fields") else {
+        if passthrough {
+            output.push_str(&raw_text(message_field));
+        }
+        return false;
+    };
+    // Records without a time can't be placed within --since and --until
+    if timestamp.is_none() && (options.filter.since.is_some() || options.filter.until.is_some()) {
+        return false;
+    }
+
+    if !options.filter.matches_trace(&json) {
+        return false;
+    }
+    info.set_header(
+        timestamp.unwrap_or_default(),
+        level.unwrap_or_default(),
+        target.unwrap_or_default(),
+    );
+    let timestamp = timestamp.unwrap_or(PLACEHOLDER);
+    let level = level.unwrap_or(PLACEHOLDER);
+    let target = target.unwrap_or(PLACEHOLDER);
+
+    // Extract message and other fields if possible, falling back to the
+    // default output format
+    let Some((obj, message)) = fields
+        .as_object()
+        .and_then(|o| Some((o, o.get("message")?.as_str()?)))
+    else {
+        if options.filter.matches(timestamp, level, target, "") {
+            write_header(output, timestamp, level, target, options.theme.as_ref());
+            let _ = write!(output, " {}", fields);
+        }
+        return false;
+    };
+
+    if !options.filter.matches(timestamp, level, target, message) {
+        return false;
+    }
+
+    let flagged = options.flags.as_ref().is_some_and(|flags| flags.check(obj));
+    info.vp = report::field_u64(obj, report::VP_KEYS);
+    if options.vp_links && info.vp.is_some() {
+        info.vp_event = VpEvent::classify(message);
+    }
+    if let Some(field) = &options.group_by {
+        info.group = obj.get(field).map(|value| match value {
+            Value::String(text) => text.clone(),
+            other => other.to_string(),
+        });
+    }
+
+    // Start with the timestamp, level, target, and message
+    write_header(output, timestamp, level, target, options.theme.as_ref());
+    let start = output.len() + 1;
+    let _ = write!(output, " {}", escape_controls(message));
+    truncate(output, start, options);
+
+    let ctx = FieldContext {
+        target,
+        message,
+        fields: obj,
+    };
+
+    // Hex dumps and other multi-line renderings are collected and emitted
+    // on lines following the record
+    let mut continuation = Vec::new();
+
+    // Add remaining fields
+    for (logged_key, value) in obj {
+        if logged_key == "message" {
+            continue;
+        }
+        let key = match &options.field_map {
+            Some(field_map) => field_map.rename(target, logged_key),
+            None => logged_key,
+        };
+
+        let shown_key = escape_controls(key);
+        let start = output.len() + 1;
+        if let Some(token) = options
+            .scrubber
+            .as_ref()
+            .and_then(|scrubber| scrubber.scrub_field(key, value))
+        {
+            let _ = write!(output, " {}={}", shown_key, token);
+            if options.explain {
+                let _ = write!(info.explanation, "\n  {}: scrubbed", shown_key);
+            }
+            continue;
+        }
+
+        let decoded = options.decoders.decode(key, value, &ctx);
+        write_field(
+            output,
+            &shown_key,
+            value,
+            decoded,
+            options.signed_hex,
+            &mut continuation,
+        );
+        truncate(output, start + shown_key.len() + 1, options);
+        if options.explain {
+            let shown = &output[start - 1..];
+            explain_field(
+                &mut info.explanation,
+                logged_key,
+                key,
+                value,
+                shown,
+                options,
+                &ctx,
+            );
+        }
+        if let Some(style) = options.theme.as_ref().and_then(|theme| theme.field(key)) {
+            theme::paint(output, start, style);
+        }
+        if let Some(url) = options.links.then(|| links::link(key, value)).flatten() {
+            links::wrap(output, start, url);
+        }
+        if options.validate {
+            let suspect = options
+                .decoders
+                .validate(key, value, &ctx)
+                .or_else(|| validate::check_field(key, value));
+            if let Some(reason) = suspect {
+                let _ = write!(output, " [suspect: {}]", escape_controls(&reason));
+            }
+        }
+    }
+
+    for lines in continuation {
+        output.push('\n');
+        output.push_str(&lines);
+    }
+
+    flagged
+}
+
+/// Format a record as an output line, leaving `output` empty when it has
+/// nothing to show
+pub fn format_record(
+    record: RecordRef<'_>,
+    options: &FormatOptions,
+    output: &mut String,
+    info: &mut RecordInfo,
+) {
+    info.reset(record.end_offset);
+    let flagged = process_message(record.message, record.parsed, options, output, info);
+
+    if options.raw {
+        if !output.is_empty() {
+            output.clear();
+            output.push_str(&record.text());
+        }
+        return;
+    }
+
+    if let Some(scrubber) = &options.scrubber {
+        *output = scrubber.scrub_text(output);
+        // The explanation repeats the values as logged
+        if !info.explanation.is_empty() {
+            info.explanation = scrubber.scrub_text(&info.explanation);
+        }
+    }
+
+    // Prefix with the boot-relative time in dmesg style when the source
+    // recorded one
+    if let Some(us) = record.monotonic_us.filter(|_| !output.is_empty()) {
+        output.insert_str(
+            0,
+            &format!("[{:>5}.{:06}] ", us / 1_000_000, us % 1_000_000),
+        );
+    }
+
+    if flagged {
+        output.insert_str(0, "!! ");
+    }
+
+    if let Some(wrapper) = &options.wrapper {
+        *output = wrapper.wrap(output);
+    }
+}
@@ -0,0 +1,129 @@
+//! Records grouped by the value of a field with `--group-by`, for exports
+//! covering several VMs or sessions
+//!
+//! Records are held until the end of the input, then written group by
+//! group in the order each group first appears: to a file per group when
+//! the output is a file, or else as sections under a header line.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::sink::{RecordInfo, Sink};
+
+/// Records of a group, by the value of the field or `None` for records
+/// without it
+type Group = (Option<String>, Vec<(String, RecordInfo)>);
+
+/// Where the groups are written
+enum Destination {
+    /// A section of this sink per group
+    Sections(Box<dyn Sink>),
+    /// A file per group, named after this file
+    Files(PathBuf),
+}
+
+/// Holds records by group and writes each group on its own
+pub struct GroupSink {
+    field: String,
+    destination: Destination,
+    /// Groups in the order they first appear
+    groups: Vec<Group>,
+    index: HashMap<Option<String>, usize>,
+}
+
+impl GroupSink {
+    /// Write the groups of `field` as sections of `sink`
+    pub fn sections(field: &str, sink: Box<dyn Sink>) -> Self {
+        Self::new(field, Destination::Sections(sink))
+    }
+
+    /// Write the groups of `field` to files named after `path`, with the
+    /// value of the field before the extension
+    pub fn files(field: &str, path: &Path) -> Self {
+        Self::new(field, Destination::Files(path.to_path_buf()))
+    }
+
+    fn new(field: &str, destination: Destination) -> Self {
+        GroupSink {
+            field: field.to_string(),
+            destination,
+            groups: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+
+    /// Path of the file of a group, like `decoded.vm-1.txt` for
+    /// `decoded.txt`
+    fn group_path(path: &Path, group: Option<&str>) -> PathBuf {
+        let name: String = group
+            .unwrap_or("ungrouped")
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+                _ => '_',
+            })
+            .collect();
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let file_name = match path.extension() {
+            Some(extension) => format!("{}.{}.{}", stem, name, extension.to_string_lossy()),
+            None => format!("{}.{}", stem, name),
+        };
+        path.with_file_name(file_name)
+    }
+}
+
+impl Sink for GroupSink {
+    fn write_record(&mut self, text: &str, info: &RecordInfo) -> Result<(), Box<dyn Error>> {
+        let index = *self.index.entry(info.group.clone()).or_insert_with(|| {
+            self.groups.push((info.group.clone(), Vec::new()));
+            self.groups.len() - 1
+        });
+        self.groups[index].1.push((text.to_string(), info.clone()));
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        // Nothing is written before the end of the input
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        let groups = std::mem::take(&mut self.groups);
+        match &mut self.destination {
+            Destination::Sections(sink) => {
+                for (index, (group, records)) in groups.iter().enumerate() {
+                    let header = match group {
+                        Some(value) => format!("==> {}={} <==", self.field, value),
+                        None => format!("==> no {} <==", self.field),
+                    };
+                    if index > 0 {
+                        sink.write_record("", &RecordInfo::default())?;
+                    }
+                    sink.write_record(&header, &RecordInfo::default())?;
+                    for (text, info) in records {
+                        sink.write_record(text, info)?;
+                    }
+                }
+                sink.finish()
+            }
+            Destination::Files(path) => {
+                for (group, records) in &groups {
+                    let path = Self::group_path(path, group.as_deref());
+                    let file = File::create(&path)
+                        .map_err(|err| format!("Failed to create {}: {}", path.display(), err))?;
+                    let mut writer = BufWriter::new(file);
+                    for (text, _) in records {
+                        writeln!(writer, "{}", text)?;
+                    }
+                    writer
+                        .flush()
+                        .map_err(|err| format!("Failed to write {}: {}", path.display(), err))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,160 @@
+//! Recognition of GUIDs in field values and mapping them to friendly names
+
+use regex::Regex;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+
+use crate::decoder::{Conditions, Decoded, Decoder, FieldContext};
+
+/// Well-known VMBus device class and partition GUIDs
+const BUILTIN_GUIDS: &[(&str, &str)] = &[
+    ("00000000-0000-0000-0000-000000000000", "null GUID"),
+    ("f8615163-df3e-46c5-913f-f2d2f965ed0e", "synthetic network"),
+    ("32412632-86cb-44a2-9b5c-50d1417354f5", "synthetic IDE"),
+    ("ba6163d9-04a1-4d29-b605-72e2ffb1dc7f", "synthetic SCSI"),
+    (
+        "2f9bcc4a-0069-4af3-b76b-6fd0be528cda",
+        "synthetic Fibre Channel",
+    ),
+    ("da0a7802-e377-4aac-8e77-0558eb1073f8", "synthetic video"),
+    ("cfa8b69e-5b4a-4cc0-b98b-8ba1a1f3f95a", "synthetic mouse"),
+    ("f912ad6d-2b17-48ea-bd65-f927a61c7684", "synthetic keyboard"),
+    ("525074dc-8985-46e2-8057-a307dc18a502", "dynamic memory"),
+    ("44c4f61d-4444-4400-9d52-802e27ede19f", "PCI passthrough"),
+    ("8c2eaf3d-32a7-4b09-ab99-bd1f1c86b501", "NetworkDirect"),
+    (
+        "276aacf4-ac15-426c-98dd-7521ad3f01fe",
+        "remote desktop virtualization",
+    ),
+    ("0e0b6031-5213-4934-818b-38d90ced39db", "shutdown IC"),
+    ("9527e630-d0ae-497b-adce-e80ab0175caf", "time sync IC"),
+    ("57164f39-9115-4e78-ab55-382f3bd5422d", "heartbeat IC"),
+    ("a9a0f4e7-5a45-4d96-b827-8a841e8c03e6", "KVP exchange IC"),
+    ("35fa2e29-ea23-4236-96ae-3a6ebacba440", "VSS IC"),
+    ("34d14be3-dee4-41c8-9ae7-6b174977c192", "file copy IC"),
+];
+
+/// Table of GUIDs with friendly names
+pub struct GuidNames {
+    names: HashMap<String, String>,
+    guid_regex: Regex,
+}
+
+impl GuidNames {
+    /// Create a table holding only the built-in names
+    pub fn new() -> Self {
+        let names = BUILTIN_GUIDS
+            .iter()
+            .map(|(guid, name)| (guid.to_string(), name.to_string()))
+            .collect();
+
+        GuidNames {
+            names,
+            guid_regex: Regex::new(
+                r"[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}",
+            )
+            .unwrap(),
+        }
+    }
+
+    /// Add names from a mapping file, overriding built-in names
+    ///
+    /// Each line holds a GUID followed by its name, separated by whitespace
+    /// or `=`. Blank lines and lines starting with `#` are ignored.
+    pub fn load(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let contents = std::fs::read_to_string(path)?;
+
+        for (line_number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (guid, name) = line
+                .split_once(|c: char| c == '=' || c.is_whitespace())
+                .ok_or_else(|| {
+                    format!(
+                        "{}:{}: expected '<guid> <name>'",
+                        path.display(),
+                        line_number + 1
+                    )
+                })?;
+
+            let guid = guid.trim().trim_matches(['{', '}']);
+            if !self.guid_regex.is_match(guid) {
+                return Err(format!(
+                    "{}:{}: '{}' is not a GUID",
+                    path.display(),
+                    line_number + 1,
+                    guid
+                )
+                .into());
+            }
+
+            self.names.insert(
+                guid.to_ascii_lowercase(),
+                name.trim_start_matches([' ', '\t', '=']).trim().to_string(),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Return the friendly names of all known GUIDs appearing in `text`
+    pub fn annotate(&self, text: &str) -> Option<String> {
+        let names: Vec<&str> = self
+            .guid_regex
+            .find_iter(text)
+            .filter_map(|m| self.names.get(&m.as_str().to_ascii_lowercase()))
+            .map(String::as_str)
+            .collect();
+
+        if names.is_empty() {
+            None
+        } else {
+            Some(names.join(", "))
+        }
+    }
+}
+
+/// Decoder appending friendly names to GUIDs in string fields
+pub struct GuidDecoder {
+    names: GuidNames,
+    conditions: Conditions,
+}
+
+impl GuidDecoder {
+    pub fn new(names: GuidNames) -> Self {
+        GuidDecoder {
+            names,
+            conditions: Conditions::any(),
+        }
+    }
+}
+
+impl Decoder for GuidDecoder {
+    fn name(&self) -> &str {
+        "guid-names"
+    }
+
+    fn description(&self) -> String {
+        "Annotates GUIDs in strings with their name, from the built-in names, the installed table and --guid-map".to_string()
+    }
+
+    fn conditions(&self) -> &Conditions {
+        &self.conditions
+    }
+
+    fn example(&self) -> Option<(&str, Value)> {
+        Some((
+            "class_id",
+            Value::from("f8615163-df3e-46c5-913f-f2d2f965ed0e"),
+        ))
+    }
+
+    fn decode(&self, _key: &str, value: &Value, _ctx: &FieldContext) -> Option<Decoded> {
+        self.names.annotate(value.as_str()?).map(Decoded::Annotate)
+    }
+}
@@ -0,0 +1,92 @@
+//! Names of the header fields of tracing records, for emitters that name
+//! them differently, like `severity` instead of `level`
+//!
+//! Each header field is read from the first of its names a record holds.
+//! Records missing the level, target or timestamp are still shown, with a
+//! placeholder for the missing field.
+
+use serde_json::Value;
+
+/// Shown for a header field a record doesn't hold
+pub const PLACEHOLDER: &str = "-";
+
+/// Names each header field is read from when no alias is given
+const TIMESTAMP_NAMES: &[&str] = &["timestamp", "time"];
+const LEVEL_NAMES: &[&str] = &["level", "severity"];
+const TARGET_NAMES: &[&str] = &["target"];
+
+/// A header field of tracing records
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeaderField {
+    Timestamp,
+    Level,
+    Target,
+}
+
+/// Parse `FIELD=NAME`, like `level=severity`, used as a clap value parser
+pub fn parse_alias(text: &str) -> Result<(HeaderField, String), String> {
+    let (field, name) = text
+        .split_once('=')
+        .filter(|(_, name)| !name.is_empty())
+        .ok_or_else(|| format!("Invalid field alias '{}', expected FIELD=NAME", text))?;
+    let field = match field {
+        "timestamp" => HeaderField::Timestamp,
+        "level" => HeaderField::Level,
+        "target" => HeaderField::Target,
+        _ => {
+            return Err(format!(
+                "Invalid field alias '{}', the field is one of timestamp, level or target",
+                text
+            ))
+        }
+    };
+    Ok((field, name.to_string()))
+}
+
+/// Names of each header field, in the order they are tried
+#[derive(Clone, Debug)]
+pub struct HeaderNames {
+    timestamp: Vec<String>,
+    level: Vec<String>,
+    target: Vec<String>,
+}
+
+impl HeaderNames {
+    /// The usual names, tried after the given aliases
+    pub fn new(aliases: &[(HeaderField, String)]) -> Self {
+        let names = |field: HeaderField, usual: &[&str]| -> Vec<String> {
+            let aliases = aliases.iter().filter(|(f, _)| *f == field);
+            let aliases = aliases.map(|(_, name)| name.clone());
+            aliases
+                .chain(usual.iter().map(|name| name.to_string()))
+                .collect()
+        };
+        HeaderNames {
+            timestamp: names(HeaderField::Timestamp, TIMESTAMP_NAMES),
+            level: names(HeaderField::Level, LEVEL_NAMES),
+            target: names(HeaderField::Target, TARGET_NAMES),
+        }
+    }
+
+    /// Check whether the timestamp and level are only tried under their
+    /// usual names
+    pub fn has_usual_names(&self) -> bool {
+        self.timestamp.len() == TIMESTAMP_NAMES.len() && self.level.len() == LEVEL_NAMES.len()
+    }
+
+    /// The value of a header field of a record
+    pub fn get<'a>(&self, json: &'a Value, field: HeaderField) -> Option<&'a Value> {
+        let names = match field {
+            HeaderField::Timestamp => &self.timestamp,
+            HeaderField::Level => &self.level,
+            HeaderField::Target => &self.target,
+        };
+        names.iter().find_map(|name| json.get(name))
+    }
+}
+
+impl Default for HeaderNames {
+    fn default() -> Self {
+        Self::new(&[])
+    }
+}
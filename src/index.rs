@@ -0,0 +1,350 @@
+//! Sidecar index of a CSV export for fast filtered runs
+//!
+//! The index splits the export into blocks of rows and records, for each
+//! block, its byte offset, the range of its timestamps, the set of levels
+//! and targets it holds, and the message templates it uses. A filtered run
+//! then reads only the blocks that may hold matching records.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::filter::{Level, RecordFilter, OTHER_LEVEL_BIT};
+use crate::header::{HeaderField, HeaderNames};
+use crate::input::{self, InputFormat, InputOptions, LendRecords};
+
+/// Version of the index file layout, bumped on incompatible changes
+const INDEX_VERSION: u32 = 1;
+
+/// A block of consecutive rows in the export
+#[derive(Serialize, Deserialize)]
+pub struct Block {
+    /// Byte offset of the first row
+    pub offset: u64,
+    /// Number of rows
+    pub rows: u64,
+    /// Earliest timestamp of the rows
+    pub min_timestamp: Option<String>,
+    /// Latest timestamp of the rows
+    pub max_timestamp: Option<String>,
+    /// Set of levels of the rows, one bit per [`Level`]
+    pub levels: u8,
+    /// Indices into [`Index::targets`] of the targets of the rows
+    pub targets: Vec<u32>,
+    /// Indices into [`Index::templates`] of the message templates of the rows
+    pub templates: Vec<u32>,
+}
+
+/// Index of a CSV export
+#[derive(Serialize, Deserialize)]
+pub struct Index {
+    pub version: u32,
+    /// Size of the indexed file, to detect a changed export
+    pub source_len: u64,
+    /// Modification time of the indexed file in seconds since the epoch
+    pub source_modified: Option<u64>,
+    /// Column holding the tracing JSON
+    pub message_column: usize,
+    /// Distinct record targets
+    pub targets: Vec<String>,
+    /// Distinct messages with runs of digits replaced by `#`
+    pub templates: Vec<String>,
+    pub blocks: Vec<Block>,
+}
+
+/// Path of the index of a file, next to the file
+pub fn index_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(".index.json");
+    PathBuf::from(name)
+}
+
+fn source_identity(path: &Path) -> Result<(u64, Option<u64>), Box<dyn Error>> {
+    let metadata = std::fs::metadata(path)?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs());
+    Ok((metadata.len(), modified))
+}
+
+/// Assigns a stable ID to each distinct string
+#[derive(Default)]
+struct Interner {
+    ids: HashMap<String, u32>,
+    strings: Vec<String>,
+}
+
+impl Interner {
+    fn intern(&mut self, text: &str) -> u32 {
+        if let Some(&id) = self.ids.get(text) {
+            return id;
+        }
+        let id = self.strings.len() as u32;
+        self.ids.insert(text.to_string(), id);
+        self.strings.push(text.to_string());
+        id
+    }
+}
+
+/// Accumulates the summary of the block being built
+struct BlockBuilder {
+    offset: u64,
+    rows: u64,
+    min_timestamp: Option<String>,
+    max_timestamp: Option<String>,
+    levels: u8,
+    targets: BTreeSet<u32>,
+    templates: BTreeSet<u32>,
+}
+
+impl BlockBuilder {
+    fn new(offset: u64) -> Self {
+        BlockBuilder {
+            offset,
+            rows: 0,
+            min_timestamp: None,
+            max_timestamp: None,
+            levels: 0,
+            targets: BTreeSet::new(),
+            templates: BTreeSet::new(),
+        }
+    }
+
+    fn add_timestamp(&mut self, timestamp: &str) {
+        if self
+            .min_timestamp
+            .as_deref()
+            .is_none_or(|min| timestamp < min)
+        {
+            self.min_timestamp = Some(timestamp.to_string());
+        }
+        if self
+            .max_timestamp
+            .as_deref()
+            .is_none_or(|max| timestamp > max)
+        {
+            self.max_timestamp = Some(timestamp.to_string());
+        }
+    }
+
+    fn finish(self) -> Block {
+        Block {
+            offset: self.offset,
+            rows: self.rows,
+            min_timestamp: self.min_timestamp,
+            max_timestamp: self.max_timestamp,
+            levels: self.levels,
+            targets: self.targets.into_iter().collect(),
+            templates: self.templates.into_iter().collect(),
+        }
+    }
+}
+
+/// Build the index of a CSV export, with `block_rows` rows per block
+pub fn build(path: &Path, block_rows: u64) -> Result<Index, Box<dyn Error>> {
+    let (source_len, source_modified) = source_identity(path)?;
+    let number_regex = Regex::new(r"\d+").unwrap();
+
+    let mut rdr = csv::ReaderBuilder::new()
+        .flexible(true)
+        .double_quote(true)
+        .from_path(path)?;
+
+    let message_column = rdr
+        .headers()?
+        .iter()
+        .position(|h| h == "ExtractedMessage")
+        .ok_or("No 'ExtractedMessage' column found in CSV")?;
+
+    let mut targets = Interner::default();
+    let mut templates = Interner::default();
+    let mut blocks = Vec::new();
+    let mut block: Option<BlockBuilder> = None;
+    let mut record = csv::ByteRecord::new();
+    let names = HeaderNames::default();
+
+    while rdr.read_byte_record(&mut record)? {
+        let offset = record.position().map_or(0, |pos| pos.byte());
+        let current = block.get_or_insert_with(|| BlockBuilder::new(offset));
+        current.rows += 1;
+
+        let json = record
+            .get(message_column)
+            .and_then(|message| serde_json::from_slice::<Value>(message).ok());
+        let json = json.as_ref();
+
+        let level = json
+            .and_then(|json| names.get(json, HeaderField::Level))
+            .and_then(Value::as_str)
+            .and_then(Level::parse);
+        current.levels |= level.map_or(OTHER_LEVEL_BIT, Level::bit);
+
+        if let Some(timestamp) = json
+            .and_then(|json| names.get(json, HeaderField::Timestamp))
+            .and_then(Value::as_str)
+        {
+            current.add_timestamp(timestamp);
+        }
+        if let Some(target) = json
+            .and_then(|json| names.get(json, HeaderField::Target))
+            .and_then(Value::as_str)
+        {
+            current.targets.insert(targets.intern(target));
+        }
+        if let Some(message) = json
+            .and_then(|json| json.pointer("/fields/message"))
+            .and_then(Value::as_str)
+        {
+            let template = number_regex.replace_all(message, "#");
+            current.templates.insert(templates.intern(&template));
+        }
+
+        if current.rows >= block_rows {
+            blocks.extend(block.take().map(BlockBuilder::finish));
+        }
+    }
+    blocks.extend(block.map(BlockBuilder::finish));
+
+    Ok(Index {
+        version: INDEX_VERSION,
+        source_len,
+        source_modified,
+        message_column,
+        targets: targets.strings,
+        templates: templates.strings,
+        blocks,
+    })
+}
+
+impl Index {
+    /// Write the index next to the file it describes
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let writer = BufWriter::new(File::create(index_path(path))?);
+        serde_json::to_writer(writer, self)?;
+        Ok(())
+    }
+
+    /// Load the index of a file, returning None when there is none or it
+    /// no longer matches the file
+    pub fn load(path: &Path) -> Result<Option<Index>, Box<dyn Error>> {
+        let index_path = index_path(path);
+        if !index_path.exists() {
+            return Ok(None);
+        }
+
+        let reader = BufReader::new(File::open(&index_path)?);
+        let index: Index = match serde_json::from_reader(reader) {
+            Ok(index) => index,
+            Err(err) => {
+                eprintln!(
+                    "Ignoring unreadable index {}: {}",
+                    index_path.display(),
+                    err
+                );
+                return Ok(None);
+            }
+        };
+
+        if index.version != INDEX_VERSION
+            || (index.source_len, index.source_modified) != source_identity(path)?
+        {
+            eprintln!(
+                "Ignoring stale index {}, rebuild it with the index command",
+                index_path.display()
+            );
+            return Ok(None);
+        }
+
+        Ok(Some(index))
+    }
+
+    /// IDs of the templates and targets that may contain lowercase `text`
+    ///
+    /// Templates keep everything but digits, so text without digits is in a
+    /// message exactly when it is in its template. Text with digits can be
+    /// in any message.
+    fn text_ids(&self, text: &str) -> (HashSet<u32>, HashSet<u32>) {
+        let has_digits = text.chars().any(|c| c.is_ascii_digit());
+        let matching = |strings: &[String], any: bool| {
+            strings
+                .iter()
+                .enumerate()
+                .filter(|(_, string)| any || string.to_lowercase().contains(text))
+                .map(|(id, _)| id as u32)
+                .collect()
+        };
+
+        (
+            matching(&self.templates, has_digits),
+            matching(&self.targets, false),
+        )
+    }
+
+    /// Byte offset and row count of each run of blocks for which `matches`
+    /// holds, merging adjacent blocks
+    pub fn ranges(&self, matches: impl Fn(&Block) -> bool) -> Vec<(u64, u64)> {
+        let mut ranges: Vec<(u64, u64)> = Vec::new();
+        let mut previous_matched = false;
+
+        for block in &self.blocks {
+            let matched = matches(block);
+            if matched {
+                match ranges.last_mut() {
+                    Some((_, rows)) if previous_matched => *rows += block.rows,
+                    _ => ranges.push((block.offset, block.rows)),
+                }
+            }
+            previous_matched = matched;
+        }
+
+        ranges
+    }
+}
+
+/// Open a CSV export through its index, reading only the blocks that may
+/// hold records passing the filter
+///
+/// Returns None when the index can't be used, in which case the whole file
+/// must be read.
+pub fn open_filtered(
+    path: &Path,
+    options: &InputOptions,
+    filter: &RecordFilter,
+) -> Result<Option<Box<dyn LendRecords>>, Box<dyn Error>> {
+    if options.format != InputFormat::Csv || options.follow || !filter.is_active() {
+        return Ok(None);
+    }
+
+    let Some(index) = Index::load(path)? else {
+        return Ok(None);
+    };
+
+    let text_ids = filter.text.as_deref().map(|text| index.text_ids(text));
+
+    let ranges = index.ranges(|block| {
+        filter.may_match_range(
+            block.min_timestamp.as_deref(),
+            block.max_timestamp.as_deref(),
+            block.levels,
+        ) && text_ids.as_ref().is_none_or(|(templates, targets)| {
+            block.templates.iter().any(|id| templates.contains(id))
+                || block.targets.iter().any(|id| targets.contains(id))
+        })
+    });
+
+    Ok(Some(input::open_csv_ranges(
+        path,
+        index.message_column,
+        ranges,
+        options.strict,
+    )))
+}
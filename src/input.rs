@@ -0,0 +1,1270 @@
+//! Input sources that produce raw log messages for the formatting pipeline
+
+use clap::ValueEnum;
+use csv::{ByteRecord, ReaderBuilder};
+use memmap2::Mmap;
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use crate::ansi::AnsiStripper;
+use crate::decoded;
+use crate::interrupt;
+use crate::paths;
+use crate::units;
+
+/// Supported input file formats
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum InputFormat {
+    /// Kusto CSV export with an ExtractedMessage column
+    #[default]
+    Csv,
+    /// Windows Event Log file with forwarded kmsg JSON payloads
+    Evtx,
+    /// journald output from `journalctl -o export` or `-o json`
+    Journal,
+    /// Raw serial console capture mixing plain text and tracing JSON
+    Serial,
+    /// Records parsed once by the decode subcommand
+    Decoded,
+}
+
+/// A single raw message pulled from an input source
+pub struct Record {
+    /// The message text, usually a tracing JSON object
+    pub message: String,
+    /// The message already parsed, for sources that hold it parsed, which
+    /// leave `message` empty
+    pub parsed: Option<Value>,
+    /// Boot-relative timestamp in microseconds, when the source records one
+    pub monotonic_us: Option<u64>,
+    /// Byte offset just past the record in the input, for sources that can
+    /// be resumed from there
+    pub end_offset: Option<u64>,
+}
+
+impl Record {
+    /// The record borrowed, as readers lend theirs
+    pub fn borrowed(&self) -> RecordRef<'_> {
+        RecordRef {
+            message: &self.message,
+            parsed: self.parsed.as_ref(),
+            monotonic_us: self.monotonic_us,
+            end_offset: self.end_offset,
+        }
+    }
+
+    /// The text of the message, written out from its JSON for sources
+    /// holding it parsed
+    pub fn text(&self) -> Cow<'_, str> {
+        self.borrowed().text()
+    }
+
+    /// The JSON of the message, parsed unless the source held it parsed
+    pub fn json(&self) -> Option<Cow<'_, Value>> {
+        match &self.parsed {
+            Some(json) => Some(Cow::Borrowed(json)),
+            None => serde_json::from_str(&self.message).ok().map(Cow::Owned),
+        }
+    }
+}
+
+pub type Records = Box<dyn Iterator<Item = Result<Record, Box<dyn Error>>>>;
+
+/// A record borrowed from the reader that read it, valid until the next
+/// record is read
+#[derive(Clone, Copy, Default)]
+pub struct RecordRef<'a> {
+    /// The message text, usually a tracing JSON object
+    pub message: &'a str,
+    /// The message already parsed, for sources that hold it parsed
+    pub parsed: Option<&'a Value>,
+    /// Boot-relative timestamp in microseconds, when the source records one
+    pub monotonic_us: Option<u64>,
+    /// Byte offset just past the record in the input
+    pub end_offset: Option<u64>,
+}
+
+impl<'a> RecordRef<'a> {
+    /// The text of the message, written out from its JSON for sources
+    /// holding it parsed
+    pub fn text(&self) -> Cow<'a, str> {
+        match self.parsed {
+            Some(json) => Cow::Owned(json.to_string()),
+            None => Cow::Borrowed(self.message),
+        }
+    }
+
+    /// A copy of the record owning its message
+    pub fn to_record(&self) -> Record {
+        Record {
+            message: self.message.to_string(),
+            parsed: self.parsed.cloned(),
+            monotonic_us: self.monotonic_us,
+            end_offset: self.end_offset,
+        }
+    }
+}
+
+/// Records read one at a time and lent out, rather than handed out owning
+/// their message, so a reader can reuse its buffers from one record to the
+/// next
+pub trait LendRecords {
+    /// Read the next record, returning whether there was one
+    fn advance(&mut self) -> Result<bool, Box<dyn Error>>;
+
+    /// The record last read, empty before the first
+    fn get(&self) -> RecordRef<'_>;
+}
+
+/// Lends the records of an iterator, holding one at a time
+pub struct Lent {
+    records: Records,
+    current: Option<Record>,
+}
+
+impl Lent {
+    pub fn new(records: Records) -> Self {
+        Lent {
+            records,
+            current: None,
+        }
+    }
+}
+
+impl LendRecords for Lent {
+    fn advance(&mut self) -> Result<bool, Box<dyn Error>> {
+        self.current = self.records.next().transpose()?;
+        Ok(self.current.is_some())
+    }
+
+    fn get(&self) -> RecordRef<'_> {
+        self.current
+            .as_ref()
+            .map(Record::borrowed)
+            .unwrap_or_default()
+    }
+}
+
+/// Lends the records of several inputs one after another
+pub struct Chained {
+    inputs: VecDeque<Box<dyn LendRecords>>,
+}
+
+impl Chained {
+    pub fn new(inputs: Vec<Box<dyn LendRecords>>) -> Self {
+        Chained {
+            inputs: inputs.into(),
+        }
+    }
+}
+
+impl LendRecords for Chained {
+    fn advance(&mut self) -> Result<bool, Box<dyn Error>> {
+        while let Some(input) = self.inputs.front_mut() {
+            if input.advance()? {
+                return Ok(true);
+            }
+            self.inputs.pop_front();
+        }
+        Ok(false)
+    }
+
+    fn get(&self) -> RecordRef<'_> {
+        self.inputs
+            .front()
+            .map(|input| input.get())
+            .unwrap_or_default()
+    }
+}
+
+/// Adapts a lender into an iterator of records owning their messages
+pub struct Owned<L>(pub L);
+
+impl<L: LendRecords> Iterator for Owned<L> {
+    type Item = Result<Record, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.0.advance() {
+            Ok(true) => Some(Ok(self.0.get().to_record())),
+            Ok(false) => None,
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+/// How an input file is opened and parsed
+pub struct InputOptions {
+    /// Format of the input file
+    pub format: InputFormat,
+    /// Reject malformed rows instead of parsing them flexibly
+    pub strict: bool,
+    /// Keep waiting for data appended to the file instead of stopping at
+    /// its end
+    pub follow: bool,
+    /// Read the file through a memory mapping
+    pub mmap: bool,
+}
+
+/// Open an input file and return an iterator over its messages
+pub fn open(path: &Path, options: &InputOptions) -> Result<Records, Box<dyn Error>> {
+    match options.format {
+        InputFormat::Csv => Ok(Box::new(Owned(open_csv(
+            open_file(path, options)?,
+            options.strict,
+        )?))),
+        InputFormat::Evtx if options.follow => {
+            Err("Following is not supported for EVTX input".into())
+        }
+        InputFormat::Decoded if options.follow => {
+            Err("Following is not supported for decoded input".into())
+        }
+        InputFormat::Evtx => open_evtx(open_file(path, options)?),
+        InputFormat::Journal => open_journal(open_file(path, options)?),
+        InputFormat::Serial => open_serial(open_file(path, options)?),
+        InputFormat::Decoded => decoded::open(path),
+    }
+}
+
+/// Open an input file and lend out its messages one at a time, borrowed
+/// from the reused row of the reader for CSV exports
+pub fn open_lending(
+    path: &Path,
+    options: &InputOptions,
+) -> Result<Box<dyn LendRecords>, Box<dyn Error>> {
+    match options.format {
+        InputFormat::Csv => Ok(Box::new(open_csv(
+            open_file(path, options)?,
+            options.strict,
+        )?)),
+        _ => Ok(Box::new(Lent::new(open(path, options)?))),
+    }
+}
+
+/// Read messages from an export held in memory, as uploaded to the WASM
+/// build
+pub fn open_bytes(bytes: Vec<u8>, format: InputFormat) -> Result<Records, Box<dyn Error>> {
+    let reader: Box<dyn Read> = Box::new(Cursor::new(bytes));
+    match format {
+        InputFormat::Csv => Ok(Box::new(Owned(open_csv(reader, false)?))),
+        InputFormat::Evtx => open_evtx(reader),
+        InputFormat::Journal => open_journal(reader),
+        InputFormat::Serial => open_serial(reader),
+        InputFormat::Decoded => Err("Decoded input can only be read from a file".into()),
+    }
+}
+
+/// Time to wait before checking a followed file for new data
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Reader that waits for more data at the end of a file, like `tail -f`
+struct FollowReader {
+    file: File,
+}
+
+impl Read for FollowReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let read = self.file.read(buf)?;
+            // Ctrl-C ends a followed file like its end would otherwise
+            if read > 0 || buf.is_empty() || interrupt::is_interrupted() {
+                return Ok(read);
+            }
+            std::thread::sleep(FOLLOW_POLL_INTERVAL);
+        }
+    }
+}
+
+/// Map a file into memory
+fn map_file(file: &File) -> Result<Mmap, Box<dyn Error>> {
+    // SAFETY: the mapping is only read. Truncating the file while it is
+    // mapped makes later reads fault, which is the accepted trade-off for
+    // avoiding a read syscall per buffer on large exports.
+    Ok(unsafe { Mmap::map(file)? })
+}
+
+/// Map a file into memory when that is worth it
+fn map_local_file(path: &Path, file: &File) -> Result<Option<Mmap>, Box<dyn Error>> {
+    // Empty files cannot be mapped, and mapping only pays off for regular
+    // files, not pipes or devices. Reads of a mapped file on a share fault
+    // if the connection drops, so those are read normally.
+    let metadata = file.metadata()?;
+    if metadata.is_file() && metadata.len() > 0 && !paths::is_network(path) {
+        return Ok(Some(map_file(file)?));
+    }
+    Ok(None)
+}
+
+/// Check whether an input is given as an HTTP URL rather than a path
+fn is_url(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|text| text.starts_with("https://") || text.starts_with("http://"))
+}
+
+/// Open an input given as a URL, downloading it as it is read
+#[cfg(feature = "blob-inputs")]
+fn open_url(path: &Path, options: &InputOptions) -> Result<Box<dyn Read>, Box<dyn Error>> {
+    if options.follow {
+        return Err("Following is not supported for inputs read from URLs".into());
+    }
+    Ok(Box::new(crate::blob::open(&path.to_string_lossy())?))
+}
+
+#[cfg(not(feature = "blob-inputs"))]
+fn open_url(_path: &Path, _options: &InputOptions) -> Result<Box<dyn Read>, Box<dyn Error>> {
+    Err("Reading inputs from URLs needs the blob-inputs feature".into())
+}
+
+fn open_file(path: &Path, options: &InputOptions) -> Result<Box<dyn Read>, Box<dyn Error>> {
+    if is_url(path) {
+        return open_url(path, options);
+    }
+    let file =
+        File::open(path).map_err(|err| format!("Failed to open {}: {}", path.display(), err))?;
+    if options.follow {
+        return Ok(Box::new(FollowReader { file }));
+    }
+
+    if options.mmap {
+        if let Some(map) = map_local_file(path, &file)? {
+            return Ok(Box::new(Cursor::new(map)));
+        }
+    }
+
+    Ok(Box::new(file))
+}
+
+/// Describe the location of a CSV record, read by a reader starting at
+/// `base_offset`
+///
+/// Readers starting past the header can't tell the line a record is on,
+/// so only the byte offset is given for those.
+fn describe_csv_position(pos: Option<&csv::Position>, base_offset: u64) -> String {
+    match pos {
+        Some(pos) if base_offset > 0 => format!("byte offset {}", base_offset + pos.byte()),
+        Some(pos) => format!(
+            "line {} (record {}, byte offset {})",
+            pos.line(),
+            pos.record(),
+            pos.byte()
+        ),
+        None => "unknown location".to_string(),
+    }
+}
+
+/// Describe a CSV parse error with its row, column and byte offset
+fn describe_csv_error(err: &csv::Error, base_offset: u64) -> String {
+    let location = describe_csv_position(err.position(), base_offset);
+
+    match err.kind() {
+        csv::ErrorKind::UnequalLengths {
+            expected_len, len, ..
+        } => format!(
+            "malformed CSV at {}, column {}: found {} fields, expected {}",
+            location,
+            expected_len.min(len) + 1,
+            len,
+            expected_len
+        ),
+        csv::ErrorKind::Utf8 { err: utf8_err, .. } => format!(
+            "malformed CSV at {}, column {}: {}",
+            location,
+            utf8_err.field() + 1,
+            utf8_err
+        ),
+        _ => format!("malformed CSV at {}: {}", location, err),
+    }
+}
+
+/// Lends the messages of a CSV export
+///
+/// Rows are read into a single reused `ByteRecord`, and only the message
+/// column is validated as UTF-8. Messages are lent as slices of the row,
+/// so reading allocates nothing per row once the row has grown to fit the
+/// largest.
+struct CsvRecords {
+    reader: csv::Reader<Box<dyn Read>>,
+    record: ByteRecord,
+    /// Byte offset just past the row last read
+    end_offset: u64,
+    message_idx: usize,
+    strict: bool,
+    /// Number of rows left to read, when only part of the file is read
+    remaining: Option<u64>,
+    /// Byte offset in the file where the reader started
+    base_offset: u64,
+    /// Byte offset rows must start before to be read
+    limit: u64,
+}
+
+impl CsvRecords {
+    fn new(reader: csv::Reader<Box<dyn Read>>, message_idx: usize, strict: bool) -> Self {
+        CsvRecords {
+            reader,
+            record: ByteRecord::new(),
+            end_offset: 0,
+            message_idx,
+            strict,
+            remaining: None,
+            base_offset: 0,
+            limit: u64::MAX,
+        }
+    }
+
+    /// The message of the row last read, checked to be UTF-8 when read
+    fn message(&self) -> &str {
+        let message = self.record.get(self.message_idx).unwrap_or_default();
+        std::str::from_utf8(message).unwrap_or_default()
+    }
+}
+
+impl LendRecords for CsvRecords {
+    fn advance(&mut self) -> Result<bool, Box<dyn Error>> {
+        loop {
+            if let Some(remaining) = &mut self.remaining {
+                if *remaining == 0 {
+                    return Ok(false);
+                }
+                *remaining -= 1;
+            }
+            if self.base_offset + self.reader.position().byte() >= self.limit {
+                return Ok(false);
+            }
+
+            match self.reader.read_byte_record(&mut self.record) {
+                Ok(true) => {}
+                Ok(false) => return Ok(false),
+                Err(err) if self.strict => {
+                    return Err(describe_csv_error(&err, self.base_offset).into())
+                }
+                Err(err) => return Err(err.into()),
+            }
+
+            // Short rows without a message column are skipped
+            let Some(message) = self.record.get(self.message_idx) else {
+                continue;
+            };
+
+            std::str::from_utf8(message).map_err(|err| {
+                format!(
+                    "malformed CSV at {}, column {}: invalid UTF-8: {}",
+                    describe_csv_position(self.record.position(), self.base_offset),
+                    self.message_idx + 1,
+                    err
+                )
+            })?;
+
+            self.end_offset = self.base_offset + self.reader.position().byte();
+            return Ok(true);
+        }
+    }
+
+    fn get(&self) -> RecordRef<'_> {
+        RecordRef {
+            message: self.message(),
+            parsed: None,
+            monotonic_us: None,
+            end_offset: Some(self.end_offset),
+        }
+    }
+}
+
+fn open_csv(file: Box<dyn Read>, strict: bool) -> Result<CsvRecords, Box<dyn Error>> {
+    // Create a CSV reader with more flexible parsing options, unless strict
+    // mode asks for every row to match the header
+    let mut rdr = ReaderBuilder::new()
+        .flexible(!strict)
+        .double_quote(true)
+        .from_reader(file);
+
+    // Skip the header row
+    let headers = rdr.headers()?.clone();
+
+    // Find the index of the ExtractedMessage column
+    let message_idx = headers
+        .iter()
+        .position(|h| h == "ExtractedMessage")
+        .ok_or("No 'ExtractedMessage' column found in CSV")?;
+
+    Ok(CsvRecords::new(rdr, message_idx, strict))
+}
+
+/// Build a reader of the rows of a CSV export after its header
+fn row_reader(reader: Box<dyn Read>, strict: bool) -> csv::Reader<Box<dyn Read>> {
+    ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(!strict)
+        .double_quote(true)
+        .from_reader(reader)
+}
+
+/// Lends the messages in ranges of rows of a CSV export
+struct CsvRangeRecords {
+    path: PathBuf,
+    ranges: std::vec::IntoIter<(u64, u64)>,
+    current: Option<CsvRecords>,
+    message_idx: usize,
+    strict: bool,
+    /// Byte offset rows must start before to be read
+    limit: u64,
+}
+
+impl LendRecords for CsvRangeRecords {
+    fn advance(&mut self) -> Result<bool, Box<dyn Error>> {
+        loop {
+            if let Some(current) = &mut self.current {
+                if current.advance()? {
+                    return Ok(true);
+                }
+            }
+
+            let Some((offset, rows)) = self.ranges.next() else {
+                return Ok(false);
+            };
+
+            let mut file = File::open(&self.path)?;
+            file.seek(SeekFrom::Start(offset))?;
+            let reader = row_reader(Box::new(file), self.strict);
+            self.current = Some(CsvRecords {
+                remaining: Some(rows),
+                base_offset: offset,
+                limit: self.limit,
+                ..CsvRecords::new(reader, self.message_idx, self.strict)
+            });
+        }
+    }
+
+    fn get(&self) -> RecordRef<'_> {
+        self.current
+            .as_ref()
+            .map(|current| current.get())
+            .unwrap_or_default()
+    }
+}
+
+/// Find the message column of a CSV export and the byte offset just past
+/// its header row
+fn read_csv_header(path: &Path) -> Result<(usize, u64), Box<dyn Error>> {
+    csv_header(File::open(path)?)
+}
+
+fn csv_header(reader: impl Read) -> Result<(usize, u64), Box<dyn Error>> {
+    let mut reader = ReaderBuilder::new().from_reader(reader);
+    let message_idx = reader
+        .headers()?
+        .iter()
+        .position(|h| h == "ExtractedMessage")
+        .ok_or("No 'ExtractedMessage' column found in CSV")?;
+    Ok((message_idx, reader.position().byte()))
+}
+
+/// Read a CSV export from a byte offset where a row starts to its end
+pub fn open_csv_from(
+    path: &Path,
+    offset: u64,
+    strict: bool,
+) -> Result<Box<dyn LendRecords>, Box<dyn Error>> {
+    let (message_idx, _) = read_csv_header(path)?;
+
+    Ok(open_csv_ranges(
+        path,
+        message_idx,
+        vec![(offset, u64::MAX)],
+        strict,
+    ))
+}
+
+/// A CSV export mapped into memory once, to be read in slices of rows on
+/// several threads
+pub struct MappedCsv {
+    map: Arc<Mmap>,
+    message_idx: usize,
+    /// Byte offset just past the header row
+    header_end: u64,
+    strict: bool,
+}
+
+impl MappedCsv {
+    /// Map a CSV export, or return None when it is not a local file worth
+    /// mapping
+    pub fn open(path: &Path, strict: bool) -> Result<Option<Self>, Box<dyn Error>> {
+        if is_url(path) {
+            return Ok(None);
+        }
+        let file = File::open(path)
+            .map_err(|err| format!("Failed to open {}: {}", path.display(), err))?;
+        let Some(map) = map_local_file(path, &file)? else {
+            return Ok(None);
+        };
+        let (message_idx, header_end) = csv_header(&map[..])?;
+        Ok(Some(MappedCsv {
+            map: Arc::new(map),
+            message_idx,
+            header_end,
+            strict,
+        }))
+    }
+
+    /// Split the rows into slices of about `size` bytes
+    ///
+    /// As with --byte-range, a slice ends at the first line break after its
+    /// size, so a message holding a raw line break may be split.
+    pub fn slices(&self, size: u64) -> Vec<Range<u64>> {
+        let len = self.map.len() as u64;
+        let mut slices = Vec::new();
+        let mut start = self.header_end;
+        while start < len {
+            let end = (start + size.max(1)).min(len);
+            let end = self.map[end as usize - 1..]
+                .iter()
+                .position(|&byte| byte == b'\n')
+                .map_or(len, |newline| end + newline as u64);
+            slices.push(start..end);
+            start = end;
+        }
+        slices
+    }
+
+    /// Lend the messages of the rows in a slice
+    pub fn read(&self, slice: Range<u64>) -> Box<dyn LendRecords> {
+        let reader = MappedSlice {
+            map: Arc::clone(&self.map),
+            position: slice.start as usize,
+            end: slice.end as usize,
+        };
+        Box::new(CsvRecords {
+            base_offset: slice.start,
+            ..CsvRecords::new(
+                row_reader(Box::new(reader), self.strict),
+                self.message_idx,
+                self.strict,
+            )
+        })
+    }
+}
+
+/// Reads a slice of a file mapped into memory
+struct MappedSlice {
+    map: Arc<Mmap>,
+    position: usize,
+    end: usize,
+}
+
+impl Read for MappedSlice {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = (&self.map[self.position..self.end]).read(buf)?;
+        self.position += read;
+        Ok(read)
+    }
+}
+
+/// Parse a range of bytes such as `0..1GiB` or `1GiB..`, for command line
+/// options
+pub fn parse_byte_range(text: &str) -> Result<Range<u64>, String> {
+    let (start, end) = text
+        .split_once("..")
+        .ok_or_else(|| format!("Invalid byte range '{}', expected START..END", text))?;
+    let start = match start {
+        "" => 0,
+        start => units::parse_bytes(start)?,
+    };
+    let end = match end {
+        "" => u64::MAX,
+        end => units::parse_bytes(end)?,
+    };
+    if start > end {
+        return Err(format!("Byte range '{}' ends before it starts", text));
+    }
+    Ok(start..end)
+}
+
+/// Read the rows of a CSV export that start within a range of bytes, so a
+/// large file can be processed in slices
+///
+/// Rows are found by their line breaks, so a range must not start inside
+/// a message spanning several lines.
+pub fn open_csv_byte_range(
+    path: &Path,
+    range: Range<u64>,
+    strict: bool,
+) -> Result<Box<dyn LendRecords>, Box<dyn Error>> {
+    let (message_idx, header_end) = read_csv_header(path)?;
+
+    // The row holding the start of the range belongs to the slice before,
+    // unless the range starts right at a row
+    let offset = if range.start <= header_end {
+        header_end
+    } else {
+        let mut file = BufReader::new(File::open(path)?);
+        file.seek(SeekFrom::Start(range.start - 1))?;
+        let skipped = file.skip_until(b'\n')?;
+        range.start - 1 + skipped as u64
+    };
+
+    Ok(Box::new(CsvRangeRecords {
+        path: path.to_path_buf(),
+        ranges: vec![(offset, u64::MAX)].into_iter(),
+        current: None,
+        message_idx,
+        strict,
+        limit: range.end,
+    }))
+}
+
+/// Read only the given ranges of rows of a CSV export, each starting at a
+/// byte offset and spanning a number of rows
+pub fn open_csv_ranges(
+    path: &Path,
+    message_idx: usize,
+    ranges: Vec<(u64, u64)>,
+    strict: bool,
+) -> Box<dyn LendRecords> {
+    Box::new(CsvRangeRecords {
+        path: path.to_path_buf(),
+        ranges: ranges.into_iter(),
+        current: None,
+        message_idx,
+        strict,
+        limit: u64::MAX,
+    })
+}
+
+const EVTX_FILE_SIGNATURE: &[u8] = b"ElfFile\0";
+const EVTX_CHUNK_SIGNATURE: &[u8] = b"ElfChnk\0";
+const EVTX_RECORD_SIGNATURE: &[u8] = b"**\0\0";
+const EVTX_FILE_HEADER_SIZE: usize = 0x1000;
+const EVTX_CHUNK_SIZE: usize = 0x10000;
+const EVTX_CHUNK_HEADER_SIZE: usize = 0x200;
+const EVTX_RECORD_HEADER_SIZE: usize = 24;
+
+fn read_u32(data: &[u8], offset: usize) -> Option<usize> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?) as usize)
+}
+
+/// Find the first JSON object stored as a UTF-16LE string in BinXML data
+///
+/// Event payload strings are stored as UTF-16LE substitution values, so
+/// rather than fully rendering the BinXML we scan for a `{` code unit and
+/// let serde_json decide where the object ends.
+fn find_utf16_json(data: &[u8]) -> Option<String> {
+    for start in 0..data.len().saturating_sub(1) {
+        if data[start] != b'{' || data[start + 1] != 0 {
+            continue;
+        }
+
+        let units: Vec<u16> = data[start..]
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .take_while(|&unit| unit != 0)
+            .collect();
+        let text = String::from_utf16_lossy(&units);
+
+        let mut stream = serde_json::Deserializer::from_str(&text).into_iter::<Value>();
+        if let Some(Ok(Value::Object(_))) = stream.next() {
+            return Some(text[..stream.byte_offset()].to_string());
+        }
+    }
+
+    None
+}
+
+/// Extract the JSON payload of every event record in an EVTX file
+fn parse_evtx(data: &[u8]) -> Result<Vec<Record>, Box<dyn Error>> {
+    if !data.starts_with(EVTX_FILE_SIGNATURE) {
+        return Err("Not an EVTX file (missing ElfFile signature)".into());
+    }
+
+    let mut records = Vec::new();
+    let mut chunk_offset = EVTX_FILE_HEADER_SIZE;
+
+    while let Some(chunk) = data.get(chunk_offset..chunk_offset + EVTX_CHUNK_SIZE) {
+        chunk_offset += EVTX_CHUNK_SIZE;
+
+        // Unused chunks at the end of the file are zero filled
+        if !chunk.starts_with(EVTX_CHUNK_SIGNATURE) {
+            continue;
+        }
+
+        // Records are laid out back to back up to the free space offset
+        let free_space_offset = read_u32(chunk, 48).unwrap_or(EVTX_CHUNK_SIZE);
+        let end = free_space_offset.min(EVTX_CHUNK_SIZE);
+        let mut offset = EVTX_CHUNK_HEADER_SIZE;
+
+        while offset + EVTX_RECORD_HEADER_SIZE <= end {
+            if &chunk[offset..offset + 4] != EVTX_RECORD_SIGNATURE {
+                break;
+            }
+
+            let size = match read_u32(chunk, offset + 4) {
+                Some(size) if size >= EVTX_RECORD_HEADER_SIZE + 4 && offset + size <= end => size,
+                _ => break,
+            };
+
+            let event_data = &chunk[offset + EVTX_RECORD_HEADER_SIZE..offset + size - 4];
+            if let Some(message) = find_utf16_json(event_data) {
+                records.push(Record {
+                    message,
+                    parsed: None,
+                    monotonic_us: None,
+                    end_offset: None,
+                });
+            }
+
+            offset += size;
+        }
+    }
+
+    Ok(records)
+}
+
+fn open_evtx(mut file: Box<dyn Read>) -> Result<Records, Box<dyn Error>> {
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    let records = parse_evtx(&data)?;
+
+    Ok(Box::new(records.into_iter().map(Ok)))
+}
+
+/// Convert a journal field value from `-o json` output into text
+///
+/// Fields with non-printable content are serialized as byte arrays.
+fn journal_json_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Array(items) => {
+            let bytes: Vec<u8> = items
+                .iter()
+                .filter_map(|item| item.as_u64().map(|byte| byte as u8))
+                .collect();
+            Some(String::from_utf8_lossy(&bytes).into_owned())
+        }
+        _ => None,
+    }
+}
+
+/// Turn a set of journal entry fields into a record
+///
+/// The source monotonic timestamp is preferred since it reflects when the
+/// kernel logged the message, falling back to when journald received it.
+fn journal_record(
+    message: Option<String>,
+    source_monotonic: Option<String>,
+    monotonic: Option<String>,
+) -> Option<Record> {
+    let monotonic_us = source_monotonic
+        .or(monotonic)
+        .and_then(|ts| ts.trim().parse::<u64>().ok());
+
+    message.map(|message| Record {
+        message,
+        parsed: None,
+        monotonic_us,
+        end_offset: None,
+    })
+}
+
+fn parse_journal_json_line(line: &str) -> Result<Option<Record>, Box<dyn Error>> {
+    if line.trim().is_empty() {
+        return Ok(None);
+    }
+
+    let entry: Value = serde_json::from_str(line)?;
+    let field = |name: &str| entry.get(name).and_then(journal_json_text);
+
+    Ok(journal_record(
+        field("MESSAGE"),
+        field("_SOURCE_MONOTONIC_TIMESTAMP"),
+        field("__MONOTONIC_TIMESTAMP"),
+    ))
+}
+
+/// Reader for the journal export format
+///
+/// Entries are separated by an empty line. Each field is either `KEY=value`
+/// on a single line, or `KEY` followed by a little-endian 64-bit length,
+/// the raw data and a newline for values containing binary data.
+struct JournalExportReader<R> {
+    reader: R,
+}
+
+impl<R: BufRead> JournalExportReader<R> {
+    /// Read the next entry carrying a MESSAGE field
+    fn read_entry(&mut self) -> Result<Option<Record>, Box<dyn Error>> {
+        loop {
+            let mut message = None;
+            let mut source_monotonic = None;
+            let mut monotonic = None;
+            let mut saw_field = false;
+
+            loop {
+                let mut line = Vec::new();
+                if self.reader.read_until(b'\n', &mut line)? == 0 {
+                    break;
+                }
+
+                if line.last() == Some(&b'\n') {
+                    line.pop();
+                }
+
+                if line.is_empty() {
+                    if saw_field {
+                        break;
+                    }
+                    continue;
+                }
+
+                saw_field = true;
+                let (key, value) = match line.iter().position(|&b| b == b'=') {
+                    Some(eq) => (
+                        String::from_utf8_lossy(&line[..eq]).into_owned(),
+                        String::from_utf8_lossy(&line[eq + 1..]).into_owned(),
+                    ),
+                    None => {
+                        let mut len = [0u8; 8];
+                        self.reader.read_exact(&mut len)?;
+                        let len = u64::from_le_bytes(len);
+                        // Read as it comes rather than allocated up front, so
+                        // a corrupt length can't ask for more than the file
+                        // holds
+                        let mut data = Vec::new();
+                        (&mut self.reader).take(len).read_to_end(&mut data)?;
+                        if (data.len() as u64) < len {
+                            return Err(format!(
+                                "Truncated journal export field {}",
+                                String::from_utf8_lossy(&line)
+                            )
+                            .into());
+                        }
+                        let mut newline = [0u8; 1];
+                        self.reader.read_exact(&mut newline)?;
+                        (
+                            String::from_utf8_lossy(&line).into_owned(),
+                            String::from_utf8_lossy(&data).into_owned(),
+                        )
+                    }
+                };
+
+                match key.as_str() {
+                    "MESSAGE" => message = Some(value),
+                    "_SOURCE_MONOTONIC_TIMESTAMP" => source_monotonic = Some(value),
+                    "__MONOTONIC_TIMESTAMP" => monotonic = Some(value),
+                    _ => {}
+                }
+            }
+
+            if !saw_field {
+                return Ok(None);
+            }
+
+            // Entries without a MESSAGE field are skipped
+            if let Some(record) = journal_record(message, source_monotonic, monotonic) {
+                return Ok(Some(record));
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for JournalExportReader<R> {
+    type Item = Result<Record, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_entry().transpose()
+    }
+}
+
+fn open_journal(file: Box<dyn Read>) -> Result<Records, Box<dyn Error>> {
+    let mut reader = BufReader::new(file);
+
+    // JSON output has one object per line, anything else is export format
+    let is_json = reader
+        .fill_buf()?
+        .iter()
+        .find(|b| !b.is_ascii_whitespace())
+        .is_some_and(|&b| b == b'{');
+
+    if is_json {
+        let records = reader.lines().filter_map(|line| match line {
+            Ok(line) => parse_journal_json_line(&line).transpose(),
+            Err(err) => Some(Err(err.into())),
+        });
+        Ok(Box::new(records))
+    } else {
+        Ok(Box::new(JournalExportReader { reader }))
+    }
+}
+
+/// Remove ANSI escape sequences and carriage returns from a line
+fn clean_serial_line(line: &str) -> String {
+    static STRIPPER: OnceLock<AnsiStripper> = OnceLock::new();
+    let stripper = STRIPPER.get_or_init(AnsiStripper::new);
+
+    stripper.strip(line).replace('\r', "")
+}
+
+/// Find a tracing JSON object embedded in a line of console output
+fn find_json_fragment(line: &str) -> Option<&str> {
+    for (start, _) in line.match_indices('{') {
+        let mut stream = serde_json::Deserializer::from_str(&line[start..]).into_iter::<Value>();
+        if let Some(Ok(Value::Object(obj))) = stream.next() {
+            if obj.contains_key("fields") || obj.contains_key("timestamp") {
+                return Some(&line[start..start + stream.byte_offset()]);
+            }
+        }
+    }
+
+    None
+}
+
+fn parse_serial_line(line: &[u8]) -> Record {
+    let line = clean_serial_line(&String::from_utf8_lossy(line));
+
+    // Plain text lines are passed through unchanged
+    let message = match find_json_fragment(&line) {
+        Some(fragment) => fragment.to_string(),
+        None => line,
+    };
+
+    Record {
+        message,
+        parsed: None,
+        monotonic_us: None,
+        end_offset: None,
+    }
+}
+
+fn open_serial(file: Box<dyn Read>) -> Result<Records, Box<dyn Error>> {
+    let reader = BufReader::new(file);
+
+    let records = reader.split(b'\n').map(|line| match line {
+        Ok(line) => Ok(parse_serial_line(&line)),
+        Err(err) => Err(err.into()),
+    });
+
+    Ok(Box::new(records))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A string as the UTF-16LE code units BinXML stores it in, with the
+    /// terminating null
+    fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16()
+            .chain([0])
+            .flat_map(|unit| unit.to_le_bytes())
+            .collect()
+    }
+
+    /// An event record holding `event_data` as its BinXML
+    fn evtx_record(event_data: &[u8]) -> Vec<u8> {
+        let size = (EVTX_RECORD_HEADER_SIZE + event_data.len() + 4) as u32;
+        let mut record = EVTX_RECORD_SIGNATURE.to_vec();
+        record.extend(size.to_le_bytes());
+        // Record number and timestamp
+        record.extend([0u8; 16]);
+        record.extend(event_data);
+        record.extend(size.to_le_bytes());
+        record
+    }
+
+    /// A chunk holding `records` back to back, zero filled after them
+    fn evtx_chunk(records: &[Vec<u8>]) -> Vec<u8> {
+        let mut chunk = vec![0u8; EVTX_CHUNK_SIZE];
+        chunk[..8].copy_from_slice(EVTX_CHUNK_SIGNATURE);
+        let mut offset = EVTX_CHUNK_HEADER_SIZE;
+        for record in records {
+            chunk[offset..offset + record.len()].copy_from_slice(record);
+            offset += record.len();
+        }
+        chunk[48..52].copy_from_slice(&(offset as u32).to_le_bytes());
+        chunk
+    }
+
+    /// A file holding `chunks` after its header
+    fn evtx_file(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut file = vec![0u8; EVTX_FILE_HEADER_SIZE];
+        file[..8].copy_from_slice(EVTX_FILE_SIGNATURE);
+        for chunk in chunks {
+            file.extend(chunk);
+        }
+        file
+    }
+
+    fn messages(records: Vec<Record>) -> Vec<String> {
+        records.into_iter().map(|record| record.message).collect()
+    }
+
+    #[test]
+    fn evtx_finds_json_in_binxml() {
+        // Fragment and template tokens, then the payload substitution
+        let mut event_data = vec![0x0f, 0x01, 0x01, 0x00, 0x0c, 0x00];
+        event_data.extend(utf16("Microsoft-Windows-Hyper-V"));
+        event_data.extend(utf16(
+            r#"{"level":"INFO","fields":{"message":"hi"}} trailing"#,
+        ));
+
+        let file = evtx_file(&[evtx_chunk(&[evtx_record(&event_data)])]);
+        assert_eq!(
+            messages(parse_evtx(&file).unwrap()),
+            [r#"{"level":"INFO","fields":{"message":"hi"}}"#]
+        );
+    }
+
+    #[test]
+    fn evtx_skips_braces_that_are_not_json() {
+        let mut event_data = utf16("{not json");
+        event_data.extend(utf16(r#"{"a":1}"#));
+
+        let record = evtx_record(&event_data);
+        assert_eq!(
+            messages(parse_evtx(&evtx_file(&[evtx_chunk(&[record])])).unwrap()),
+            [r#"{"a":1}"#]
+        );
+    }
+
+    #[test]
+    fn evtx_reads_every_chunk() {
+        let first = evtx_chunk(&[
+            evtx_record(&utf16(r#"{"n":1}"#)),
+            evtx_record(&utf16("no payload")),
+            evtx_record(&utf16(r#"{"n":2}"#)),
+        ]);
+        // Unused chunks are zero filled
+        let unused = vec![0u8; EVTX_CHUNK_SIZE];
+        let last = evtx_chunk(&[evtx_record(&utf16(r#"{"n":3}"#))]);
+
+        let file = evtx_file(&[first, unused, last]);
+        assert_eq!(
+            messages(parse_evtx(&file).unwrap()),
+            [r#"{"n":1}"#, r#"{"n":2}"#, r#"{"n":3}"#]
+        );
+    }
+
+    #[test]
+    fn evtx_stops_chunk_at_bad_record() {
+        let mut bad = evtx_record(&utf16(r#"{"n":2}"#));
+        // A size running past the free space offset
+        bad[4..8].copy_from_slice(&0x8000u32.to_le_bytes());
+        let chunk = evtx_chunk(&[evtx_record(&utf16(r#"{"n":1}"#)), bad]);
+
+        assert_eq!(
+            messages(parse_evtx(&evtx_file(&[chunk])).unwrap()),
+            [r#"{"n":1}"#]
+        );
+    }
+
+    #[test]
+    fn evtx_rejects_other_files() {
+        assert!(parse_evtx(b"PK\x03\x04").is_err());
+    }
+
+    /// A field of the journal export format holding binary data
+    fn journal_binary_field(key: &str, data: &[u8]) -> Vec<u8> {
+        let mut field = format!("{}\n", key).into_bytes();
+        field.extend((data.len() as u64).to_le_bytes());
+        field.extend(data);
+        field.push(b'\n');
+        field
+    }
+
+    fn read_journal(export: Vec<u8>) -> Vec<Result<Record, Box<dyn Error>>> {
+        open_journal(Box::new(Cursor::new(export)))
+            .unwrap()
+            .collect()
+    }
+
+    #[test]
+    fn journal_reads_text_fields() {
+        let export = b"__MONOTONIC_TIMESTAMP=2000\n\
+            _SOURCE_MONOTONIC_TIMESTAMP=1500\n\
+            MESSAGE=first=with equals\n\
+            \n\
+            __MONOTONIC_TIMESTAMP=3000\n\
+            _HOSTNAME=host\n\
+            \n\
+            __MONOTONIC_TIMESTAMP=4000\n\
+            MESSAGE=second\n";
+
+        let records: Vec<Record> = read_journal(export.to_vec())
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        // The entry without a MESSAGE field is skipped
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].message, "first=with equals");
+        assert_eq!(records[0].monotonic_us, Some(1500));
+        assert_eq!(records[1].message, "second");
+        assert_eq!(records[1].monotonic_us, Some(4000));
+    }
+
+    #[test]
+    fn journal_reads_binary_fields() {
+        let mut export = b"__MONOTONIC_TIMESTAMP=10\n".to_vec();
+        export.extend(journal_binary_field("MESSAGE", b"two\nlines\x01"));
+        export.extend(b"PRIORITY=6\n\nMESSAGE=next\n\n");
+
+        let records: Vec<Record> = read_journal(export)
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].message, "two\nlines\x01");
+        assert_eq!(records[0].monotonic_us, Some(10));
+        assert_eq!(records[1].message, "next");
+    }
+
+    #[test]
+    fn journal_rejects_truncated_fields() {
+        let mut export = b"MESSAGE\n".to_vec();
+        // A length far past the end of the input
+        export.extend(u64::MAX.to_le_bytes());
+        export.extend(b"short");
+
+        let records = read_journal(export);
+        assert_eq!(records.len(), 1);
+        let Some(Err(err)) = records.into_iter().next() else {
+            panic!("truncated field read as a record");
+        };
+        assert_eq!(err.to_string(), "Truncated journal export field MESSAGE");
+
+        // Cut inside the length itself
+        assert!(read_journal(b"MESSAGE\n\x05\x00".to_vec())[0].is_err());
+    }
+
+    #[test]
+    fn mapped_slices_read_every_row_once() {
+        let path = std::env::temp_dir().join(format!("kke-slices-{}.csv", std::process::id()));
+        let mut csv = String::from("Id,ExtractedMessage\n");
+        for row in 0..100 {
+            csv.push_str(&format!("{},\"message {}\"\n", row, row));
+        }
+        std::fs::write(&path, &csv).unwrap();
+
+        let mapped = MappedCsv::open(&path, true).unwrap().unwrap();
+        let slices = mapped.slices(64);
+        assert!(slices.len() > 1);
+        let mut read = Vec::new();
+        let mut end_offset = None;
+        for slice in slices {
+            let mut records = mapped.read(slice);
+            while records.advance().unwrap() {
+                read.push(records.get().message.to_string());
+                end_offset = records.get().end_offset;
+            }
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        let expected: Vec<String> = (0..100).map(|row| format!("message {}", row)).collect();
+        assert_eq!(read, expected);
+        assert_eq!(end_offset, Some(csv.len() as u64));
+    }
+}
@@ -0,0 +1,41 @@
+//! Stopping a run cleanly on Ctrl-C
+//!
+//! The first Ctrl-C stops reading the input, so the records read so far are
+//! written and flushed and a summary is printed before exiting with
+//! [`EXIT_CODE`]. A second Ctrl-C exits immediately.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Exit code of an interrupted run, the usual one after SIGINT
+pub const EXIT_CODE: i32 = 130;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Catch Ctrl-C instead of being killed by it
+#[cfg(not(target_family = "wasm"))]
+pub fn install() {
+    use std::sync::Once;
+
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let handler = ctrlc::set_handler(|| {
+            if INTERRUPTED.swap(true, Ordering::SeqCst) {
+                std::process::exit(EXIT_CODE);
+            }
+            eprintln!("Interrupted, finishing the records read so far");
+        });
+        // Without the handler Ctrl-C kills the process as before
+        if let Err(err) = handler {
+            eprintln!("Failed to handle Ctrl-C: {}", err);
+        }
+    });
+}
+
+/// WASM builds have no Ctrl-C to catch
+#[cfg(target_family = "wasm")]
+pub fn install() {}
+
+/// Check whether Ctrl-C was pressed
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
@@ -0,0 +1,225 @@
+//! Summary of interrupts and exceptions injected into the guest
+
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+
+#[cfg(feature = "arrow")]
+use crate::columnar;
+use crate::input::Records;
+use crate::report::{field_u64, for_each_event, print_table, Event, VP_KEYS};
+use crate::time::parse_timestamp;
+use crate::units::format_duration;
+
+/// Fields holding the injected vector
+const VECTOR_KEYS: &[&str] = &["vector", "interrupt_vector", "irq"];
+
+/// Window within which repeated faults are reported
+const BURST_WINDOW_NS: i64 = 1_000_000_000;
+
+/// Mnemonics of the architectural exception vectors
+const EXCEPTIONS: &[&str] = &[
+    "#DE", "#DB", "NMI", "#BP", "#OF", "#BR", "#UD", "#NM", "#DF", "", "#TS", "#NP", "#SS", "#GP",
+    "#PF", "", "#MF", "#AC", "#MC", "#XM", "#VE", "#CP", "", "", "", "", "", "", "#HV", "#VC",
+    "#SX",
+];
+
+/// Exceptions that are a sign of trouble when injected repeatedly
+const FAULTS: &[u64] = &[0, 6, 10, 11, 12, 13, 14, 17];
+
+/// Exceptions that are a sign of trouble whenever they are injected
+const FATAL: &[u64] = &[8, 18];
+
+fn vector_name(vector: u64) -> &'static str {
+    EXCEPTIONS.get(vector as usize).copied().unwrap_or("")
+}
+
+/// Injections of one vector
+#[derive(Default)]
+struct VectorStats {
+    count: u64,
+    first: String,
+    last: String,
+    /// Number of injections with a time, and the earliest and latest of
+    /// those times in nanoseconds since the epoch
+    timed: u64,
+    span: Option<(i64, i64)>,
+}
+
+/// Finds the anomalies among the injections, in the order they are read
+struct Anomalies {
+    burst: usize,
+    found: Vec<String>,
+    /// Recent fault times per VP and vector, for spotting bursts
+    recent: HashMap<(Option<u64>, u64), Vec<i64>>,
+}
+
+impl Anomalies {
+    /// Check an injection of `vector` into `vp` at `time`
+    fn check(&mut self, event: &Event, vector: u64, vp: Option<u64>, time: Option<i64>) {
+        let on_vp = vp.map_or(String::new(), |vp| format!(" into VP {}", vp));
+        if FATAL.contains(&vector) {
+            self.found.push(format!(
+                "{}: {} injected{}",
+                event.timestamp,
+                vector_name(vector),
+                on_vp
+            ));
+        } else if let (true, Some(time)) = (FAULTS.contains(&vector), time) {
+            let times = self.recent.entry((vp, vector)).or_default();
+            times.retain(|earlier| time - earlier < BURST_WINDOW_NS);
+            times.push(time);
+            if times.len() == self.burst.max(2) {
+                self.found.push(format!(
+                    "{}: {} injected {} times within {}{}",
+                    event.timestamp,
+                    vector_name(vector),
+                    times.len(),
+                    format_duration((time - times[0]) as f64),
+                    on_vp
+                ));
+                times.clear();
+            }
+        }
+    }
+}
+
+/// The vector injected by a record, if it is an injection
+fn injected_vector(event: &Event) -> Option<u64> {
+    if !event.message.to_lowercase().contains("inject") {
+        return None;
+    }
+    field_u64(event.fields, VECTOR_KEYS)
+}
+
+/// Count the injections of each vector and find the anomalies among them
+#[cfg(not(feature = "arrow"))]
+fn collect(
+    records: Records,
+    anomalies: &mut Anomalies,
+) -> Result<BTreeMap<u64, VectorStats>, Box<dyn Error>> {
+    let mut vectors: BTreeMap<u64, VectorStats> = BTreeMap::new();
+
+    for_each_event(records, |event| {
+        let Some(vector) = injected_vector(event) else {
+            return;
+        };
+        let time = parse_timestamp(event.timestamp);
+
+        let stats = vectors.entry(vector).or_default();
+        stats.count += 1;
+        if stats.first.is_empty() {
+            stats.first = event.timestamp.to_string();
+        }
+        stats.last = event.timestamp.to_string();
+        if let Some(time) = time {
+            stats.timed += 1;
+            stats.span = Some(match stats.span {
+                Some((earliest, latest)) => (earliest.min(time), latest.max(time)),
+                None => (time, time),
+            });
+        }
+
+        anomalies.check(event, vector, field_u64(event.fields, VP_KEYS), time);
+    })?;
+    Ok(vectors)
+}
+
+/// Count the injections of each vector from columns of the injections, and
+/// find the anomalies among them
+#[cfg(feature = "arrow")]
+fn collect(
+    records: Records,
+    anomalies: &mut Anomalies,
+) -> Result<BTreeMap<u64, VectorStats>, Box<dyn Error>> {
+    use arrow_arith::aggregate;
+    use arrow_array::builder::{Int64Builder, StringBuilder, UInt64Builder};
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Int64Type, UInt64Type};
+    use arrow_array::{Array, ArrayRef};
+    use std::sync::Arc;
+
+    let mut vectors = UInt64Builder::new();
+    let mut timestamps = StringBuilder::new();
+    let mut times = Int64Builder::new();
+    for_each_event(records, |event| {
+        let Some(vector) = injected_vector(event) else {
+            return;
+        };
+        let time = parse_timestamp(event.timestamp);
+        vectors.append_value(vector);
+        timestamps.append_value(event.timestamp);
+        times.append_option(time);
+
+        anomalies.check(event, vector, field_u64(event.fields, VP_KEYS), time);
+    })?;
+    let vectors: ArrayRef = Arc::new(vectors.finish());
+    let timestamps = timestamps.finish();
+    let times = times.finish();
+
+    let mut stats = BTreeMap::new();
+    let groups = columnar::group_by(std::slice::from_ref(&vectors), &[])?;
+    for group in &groups.ranges {
+        let (first, last) = (groups.first(group), groups.last(group));
+        let times = groups.take(&times, group)?;
+        let times = times.as_primitive::<Int64Type>();
+        stats.insert(
+            vectors.as_primitive::<UInt64Type>().value(first),
+            VectorStats {
+                count: group.len() as u64,
+                first: timestamps.value(first).to_string(),
+                last: timestamps.value(last).to_string(),
+                timed: (times.len() - times.null_count()) as u64,
+                span: aggregate::min(times).zip(aggregate::max(times)),
+            },
+        );
+    }
+    Ok(stats)
+}
+
+/// Print a table of injected vectors with their counts and rates, then any
+/// anomalies: fatal exceptions, and faults injected `burst` or more times
+/// into one VP within a second
+pub fn irqs(records: Records, burst: usize) -> Result<(), Box<dyn Error>> {
+    let mut anomalies = Anomalies {
+        burst,
+        found: Vec::new(),
+        recent: HashMap::new(),
+    };
+    let vectors = collect(records, &mut anomalies)?;
+    let anomalies = anomalies.found;
+
+    let rows: Vec<Vec<String>> = vectors
+        .iter()
+        .map(|(vector, stats)| {
+            let interval = match stats.span {
+                Some((earliest, latest)) if stats.timed > 1 => {
+                    format_duration((latest - earliest) as f64 / (stats.timed - 1) as f64)
+                }
+                _ => "-".to_string(),
+            };
+            vec![
+                format!("{:#x}", vector),
+                vector_name(*vector).to_string(),
+                stats.count.to_string(),
+                interval,
+                stats.first.clone(),
+                stats.last.clone(),
+            ]
+        })
+        .collect();
+
+    print_table(
+        &["VECTOR", "NAME", "COUNT", "MEAN INTERVAL", "FIRST", "LAST"],
+        &rows,
+    );
+
+    if !anomalies.is_empty() {
+        println!();
+        println!("Anomalies:");
+        for anomaly in &anomalies {
+            println!("  {}", anomaly);
+        }
+    }
+
+    Ok(())
+}
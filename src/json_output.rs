@@ -0,0 +1,119 @@
+//! Records written as JSON lines with `--output json`, for tools that parse
+//! the output rather than read it
+//!
+//! Each line is an object holding the `schema_version` it follows, the
+//! header fields of the record when it is a tracing record, and its
+//! formatted `text`. The `schema` subcommand prints the JSON Schema of the
+//! objects.
+//!
+//! Within a schema version fields are only ever added, never removed,
+//! renamed or given another meaning, so parsers written against a version
+//! keep working. Any other change bumps [`SCHEMA_VERSION`].
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::error::Error;
+use std::io::{BufWriter, Stdout, Write};
+
+use crate::sink::{RecordInfo, Sink};
+
+/// Version of the schema the records follow
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    schema_version: u32,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    timestamp: &'a str,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    level: &'a str,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    target: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vp: Option<u64>,
+    text: &'a str,
+}
+
+/// Writes records to standard output as JSON lines
+pub struct JsonSink {
+    writer: BufWriter<Stdout>,
+}
+
+impl JsonSink {
+    pub fn new() -> Self {
+        JsonSink {
+            writer: BufWriter::new(std::io::stdout()),
+        }
+    }
+}
+
+impl Sink for JsonSink {
+    fn write_record(&mut self, text: &str, info: &RecordInfo) -> Result<(), Box<dyn Error>> {
+        let record = JsonRecord {
+            schema_version: SCHEMA_VERSION,
+            timestamp: &info.timestamp,
+            level: &info.level,
+            target: &info.target,
+            vp: info.vp,
+            text,
+        };
+        serde_json::to_writer(&mut self.writer, &record)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// JSON Schema of the records written with `--output json`
+pub fn schema() -> Value {
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "$id": format!(
+            "https://github.com/chris-oo/kusto-kmsg-extract/schema/output-v{}.json",
+            SCHEMA_VERSION
+        ),
+        "title": "kusto-kmsg-extract record",
+        "description": "A record written with --output json, one per line",
+        "type": "object",
+        "required": ["schema_version", "text"],
+        "properties": {
+            "schema_version": {
+                "description": "Version of this schema the record follows",
+                "const": SCHEMA_VERSION
+            },
+            "timestamp": {
+                "description": "Timestamp of the record as shown, absent for lines that aren't tracing records",
+                "type": "string"
+            },
+            "level": {
+                "description": "Level of the tracing record, like INFO or ERROR",
+                "type": "string"
+            },
+            "target": {
+                "description": "Target of the tracing record",
+                "type": "string"
+            },
+            "vp": {
+                "description": "Index of the VP the record is about, if it names one",
+                "type": "integer",
+                "minimum": 0
+            },
+            "text": {
+                "description": "The record formatted as it is shown without --output json, with any expanded lines after the first",
+                "type": "string"
+            }
+        },
+        // Fields added within a version must not fail validation
+        "additionalProperties": true
+    })
+}
+
+/// Print the JSON Schema of the records written with `--output json`
+pub fn print_schema() -> Result<(), Box<dyn Error>> {
+    println!("{}", serde_json::to_string_pretty(&schema())?);
+    Ok(())
+}
@@ -0,0 +1,46 @@
+//! Location of the tracing record inside a cell that wraps it, like
+//! `{"kmsg": {...}, "meta": {...}}` from pipelines writing Kusto dynamic
+//! columns
+//!
+//! A path is either dotted names, like `kmsg` or `payload.record`, or a
+//! JSON pointer, like `/records/0`. Numeric parts index into arrays. A
+//! string found at the path is parsed as JSON in turn, for wrappers that
+//! hold the record serialized.
+
+use serde_json::Value;
+
+/// Path of the tracing record inside the JSON of a cell
+#[derive(Clone, Debug)]
+pub struct JsonPath {
+    /// The path as a JSON pointer
+    pointer: String,
+}
+
+/// Parse a dotted path or JSON pointer, used as a clap value parser
+pub fn parse_json_path(text: &str) -> Result<JsonPath, String> {
+    let pointer = if text.starts_with('/') {
+        text.to_string()
+    } else {
+        if text.split('.').any(str::is_empty) {
+            return Err(format!(
+                "Invalid JSON path '{}', expected names separated by '.' or a JSON pointer",
+                text
+            ));
+        }
+        // Names are escaped as JSON pointer tokens
+        text.split('.')
+            .map(|name| format!("/{}", name.replace('~', "~0").replace('/', "~1")))
+            .collect()
+    };
+    Ok(JsonPath { pointer })
+}
+
+impl JsonPath {
+    /// The value at the path, if there is one
+    pub fn select(&self, mut json: Value) -> Option<Value> {
+        match json.pointer_mut(&self.pointer)?.take() {
+            Value::String(text) => serde_json::from_str(&text).ok(),
+            value => Some(value),
+        }
+    }
+}
@@ -0,0 +1,78 @@
+//! KQL equivalent of the applied filters, to rerun a query server-side
+
+use std::fmt::Write as _;
+
+use crate::correlate::CORRELATION_KEYS;
+use crate::filter::{RecordFilter, LEVEL_NAMES};
+
+/// Quote text as a KQL string literal
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Complete a timestamp prefix such as `2024-05-01T10` to a full
+/// timestamp, the instant it stands for when filtering
+fn complete_timestamp(prefix: &str) -> String {
+    const START: &str = "0000-01-01T00:00:00";
+    if prefix.len() >= START.len() {
+        return prefix.to_string();
+    }
+    match START.get(prefix.len()..) {
+        Some(rest) => format!("{}{}", prefix, rest),
+        None => prefix.to_string(),
+    }
+}
+
+/// KQL clauses selecting the records the filter shows, parsing the tracing
+/// JSON of the `ExtractedMessage` column
+pub fn where_clauses(filter: &RecordFilter) -> String {
+    let mut kql = String::from("| extend m = parse_json(ExtractedMessage)\n");
+
+    if let Some(since) = &filter.since {
+        let _ = writeln!(
+            kql,
+            "| where todatetime(m.timestamp) >= datetime({})",
+            complete_timestamp(since)
+        );
+    }
+    if let Some(until) = &filter.until {
+        let _ = writeln!(
+            kql,
+            "| where todatetime(m.timestamp) < datetime({})",
+            complete_timestamp(until)
+        );
+    }
+    if let Some(level) = filter.level {
+        let levels: Vec<String> = LEVEL_NAMES
+            .iter()
+            .filter(|(_, candidate)| *candidate <= level)
+            .map(|(name, _)| quote(name))
+            .collect();
+        let _ = writeln!(
+            kql,
+            "| where toupper(tostring(m.level)) in ({})",
+            levels.join(", ")
+        );
+    }
+    if let Some(text) = &filter.text {
+        let _ = writeln!(
+            kql,
+            "| where tostring(m.target) contains {0} or tostring(m.fields.message) contains {0}",
+            quote(text)
+        );
+    }
+    if let Some(id) = &filter.trace_id {
+        let conditions: Vec<String> = CORRELATION_KEYS
+            .iter()
+            .flat_map(|key| {
+                [
+                    format!("tostring(m.fields.{}) =~ {}", key, quote(id)),
+                    format!("tostring(m.span.{}) =~ {}", key, quote(id)),
+                ]
+            })
+            .collect();
+        let _ = writeln!(kql, "| where {}", conditions.join("\n    or "));
+    }
+
+    kql
+}
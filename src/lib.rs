@@ -0,0 +1,98 @@
+//! Decoding of the tracing records of kernel message exports from Kusto,
+//! shared by the command line tool and its bindings
+//!
+//! [`format::process_message`] formats a single message, and [`input::open`]
+//! streams the records of an export.
+
+// Decoders and other parts are created with `new` only, as the tool does
+#![allow(clippy::new_without_default)]
+
+pub mod anomalies;
+pub mod ansi;
+pub mod arm64;
+#[cfg(feature = "http-sinks")]
+pub mod azure_monitor;
+#[cfg(feature = "blob-inputs")]
+pub mod blob;
+pub mod boot;
+pub mod buildinfo;
+pub mod check;
+pub mod checksum;
+#[cfg(feature = "arrow")]
+pub mod columnar;
+pub mod config;
+pub mod correlate;
+pub mod decoded;
+pub mod decoder;
+pub mod dedupe;
+pub mod disasm;
+pub mod error_chain;
+pub mod escape;
+#[cfg(feature = "http-sinks")]
+pub mod eventhub;
+pub mod exec;
+pub mod extract;
+pub mod fields_stats;
+pub mod filter;
+pub mod first_error;
+pub mod flag;
+pub mod format;
+pub mod group;
+pub mod guid;
+pub mod header;
+pub mod index;
+pub mod input;
+pub mod interrupt;
+pub mod irqs;
+pub mod json_output;
+pub mod json_path;
+pub mod kql;
+pub mod links;
+#[cfg(feature = "http-sinks")]
+pub mod loki;
+pub mod manifest;
+pub mod measure;
+pub mod mmio;
+pub mod msrs;
+#[cfg(feature = "http-sinks")]
+pub mod notify;
+pub mod numbers;
+pub mod pagewalk;
+pub mod paths;
+pub mod payload;
+pub mod pipeline;
+#[cfg(feature = "wasm-plugins")]
+pub mod plugin;
+pub mod pseudonym;
+pub mod rename;
+pub mod replay;
+pub mod report;
+pub mod resume;
+pub mod rules;
+pub mod schema;
+pub mod scrub;
+pub mod select;
+#[cfg(feature = "server")]
+pub mod serve;
+pub mod sink;
+pub mod snp;
+pub mod spans;
+#[cfg(feature = "http-sinks")]
+pub mod splunk;
+pub mod suppress;
+pub mod syslog;
+pub mod tables;
+pub mod theme;
+pub mod time;
+pub mod tsc;
+pub mod units;
+#[cfg(feature = "self-update")]
+pub mod update;
+#[cfg(feature = "http-sinks")]
+pub mod upload;
+pub mod validate;
+pub mod vmbus;
+pub mod vp_links;
+pub mod vp_timeline;
+pub mod wrap;
+pub mod x86;
@@ -0,0 +1,65 @@
+//! Terminal hyperlinks from decoded fields to their reference documentation
+//!
+//! Fields such as MSR indexes, exit reasons and syndrome registers are
+//! wrapped in OSC 8 escape sequences, which terminals that support them
+//! render as links to the specification describing the value.
+
+use serde_json::Value;
+
+use crate::arm64::ESR_KEYS;
+use crate::msrs::MSR_KEYS;
+
+const INTEL_SDM: &str =
+    "https://www.intel.com/content/www/us/en/developer/articles/technical/intel-sdm.html";
+const AMD_APM: &str = "https://www.amd.com/content/dam/amd/en/documents/processor-tech-docs/programmer-references/24593.pdf";
+const HYPERV_TLFS: &str =
+    "https://learn.microsoft.com/en-us/virtualization/hyper-v-on-windows/tlfs/tlfs";
+const ARM_ESR_EL2: &str = "https://developer.arm.com/documentation/ddi0601/latest/AArch64-Registers/ESR-EL2--Exception-Syndrome-Register--EL2-";
+const INTEL_TDX: &str = "https://www.intel.com/content/www/us/en/developer/tools/trust-domain-extensions/documentation.html";
+
+/// Fields holding a VMX exit reason, listed in an appendix of the SDM
+const VMX_EXIT_KEYS: &[&str] = &["exit_reason", "vmx_exit_reason"];
+
+/// Fields holding an SVM exit code, listed in an appendix of the APM
+const SVM_EXIT_KEYS: &[&str] = &["exit_code", "sw_exit_code", "svm_exit_code"];
+
+/// Fields describing SEV-SNP guest state, covered by the APM
+const SEV_KEYS: &[&str] = &["sev_features", "efer"];
+
+/// Fields holding TDX module state
+const TDX_KEYS: &[&str] = &["raw_exit", "gprs"];
+
+/// First MSR index of the Hyper-V synthetic range
+const SYNTHETIC_MSR_BASE: u64 = 0x4000_0000;
+
+/// Documentation describing the value of a field, if any is known
+pub fn link(key: &str, value: &Value) -> Option<&'static str> {
+    if MSR_KEYS.contains(&key) {
+        let msr = match value {
+            Value::String(text) => u64::from_str_radix(text.trim_start_matches("0x"), 16).ok(),
+            other => other.as_u64(),
+        }?;
+        return Some(if msr >> 16 == SYNTHETIC_MSR_BASE >> 16 {
+            HYPERV_TLFS
+        } else {
+            INTEL_SDM
+        });
+    }
+    if VMX_EXIT_KEYS.contains(&key) {
+        Some(INTEL_SDM)
+    } else if SVM_EXIT_KEYS.contains(&key) || SEV_KEYS.contains(&key) {
+        Some(AMD_APM)
+    } else if ESR_KEYS.contains(&key) {
+        Some(ARM_ESR_EL2)
+    } else if TDX_KEYS.contains(&key) {
+        Some(INTEL_TDX)
+    } else {
+        None
+    }
+}
+
+/// Wrap the text from `start` to the end of `output` in a link to `url`
+pub fn wrap(output: &mut String, start: usize, url: &str) {
+    output.insert_str(start, &format!("\x1b]8;;{}\x1b\\", url));
+    output.push_str("\x1b]8;;\x1b\\");
+}
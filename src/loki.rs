@@ -0,0 +1,134 @@
+//! Forwarding of records to Grafana Loki through its push API
+//!
+//! Records are batched into streams labelled with their level, target and
+//! VP index, so they can be selected with LogQL stream selectors such as
+//! `{level="error", vp="3"}`.
+
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::sink::{RecordInfo, Sink};
+use crate::time::parse_timestamp;
+use crate::upload::{Upload, UploadError, UploadOptions, Uploader};
+
+/// Path of the push API, added to URLs given without it
+const PUSH_PATH: &str = "/loki/api/v1/push";
+
+/// Records sent in each push request, unless --sink-batch-records is given
+const BATCH_RECORDS: usize = 1000;
+
+/// Labels of a stream: level, target and VP index
+type Labels = (String, String, Option<u64>);
+
+/// Posts batches to the push API
+struct LokiClient {
+    url: String,
+}
+
+impl Upload for LokiClient {
+    fn upload(&mut self, body: &str) -> Result<(), UploadError> {
+        ureq::post(&self.url)
+            .set("Content-Type", "application/json")
+            .send_string(body)?;
+        Ok(())
+    }
+
+    fn failure(&self) -> String {
+        format!("Failed to push to Loki at {}", self.url)
+    }
+
+    fn destination(&self) -> String {
+        format!("Loki at {}", self.url)
+    }
+}
+
+/// Sends records to a Loki server in batches
+pub struct LokiSink {
+    uploader: Uploader,
+    /// Pending `[timestamp, line]` entries of each stream
+    streams: BTreeMap<Labels, Vec<[String; 2]>>,
+}
+
+impl LokiSink {
+    /// Push to the Loki server at `url`, such as `http://localhost:3100`
+    pub fn new(url: &str, options: &UploadOptions) -> Result<Self, Box<dyn Error>> {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(format!("Invalid Loki URL '{}', expected http:// or https://", url).into());
+        }
+
+        let url = if url.contains("/loki/api/") {
+            url.to_string()
+        } else {
+            format!("{}{}", url.trim_end_matches('/'), PUSH_PATH)
+        };
+
+        let mut options = options.clone();
+        options.batch_records.get_or_insert(BATCH_RECORDS);
+        Ok(LokiSink {
+            uploader: Uploader::new(LokiClient { url }, options)?,
+            streams: BTreeMap::new(),
+        })
+    }
+
+    /// Body of a push request holding the pending entries
+    fn body(&mut self) -> Value {
+        let streams: Vec<Value> = std::mem::take(&mut self.streams)
+            .into_iter()
+            .map(|((level, target, vp), values)| {
+                let mut labels = Map::new();
+                labels.insert("level".to_string(), Value::from(level));
+                if !target.is_empty() {
+                    labels.insert("target".to_string(), Value::from(target));
+                }
+                if let Some(vp) = vp {
+                    labels.insert("vp".to_string(), Value::from(vp.to_string()));
+                }
+                json!({ "stream": labels, "values": values })
+            })
+            .collect();
+        json!({ "streams": streams })
+    }
+}
+
+impl Sink for LokiSink {
+    fn write_record(&mut self, text: &str, info: &RecordInfo) -> Result<(), Box<dyn Error>> {
+        // Loki needs a time for every entry, so lines without one are sent
+        // with the time they were forwarded
+        let timestamp = parse_timestamp(&info.timestamp).unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_nanos() as i64)
+        });
+        let level = if info.level.is_empty() {
+            "unknown".to_string()
+        } else {
+            info.level.to_lowercase()
+        };
+
+        self.streams
+            .entry((level, info.target.clone(), info.vp))
+            .or_default()
+            .push([timestamp.to_string(), text.to_string()]);
+
+        if self.uploader.add_record() {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.streams.is_empty() {
+            return Ok(());
+        }
+
+        let body = self.body().to_string();
+        self.uploader.send(body)
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.flush()?;
+        self.uploader.finish()
+    }
+}
@@ -1,209 +1,1867 @@
-use clap::Parser;
-use csv::ReaderBuilder;
-use regex::Regex;
-use serde_json::Value;
+use ansi::AnsiStripper;
+use boot::BootTime;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
+use decoder::DecoderRegistry;
+use dedupe::Dedupe;
+use exec::ExecDecoder;
+use filter::{Level, RecordFilter};
+use flag::FlagSet;
+use group::GroupSink;
+use guid::GuidNames;
+use header::{HeaderField, HeaderNames};
+use input::{InputFormat, InputOptions, LendRecords, Record, RecordRef};
+use json_output::JsonSink;
+use json_path::JsonPath;
+#[cfg(feature = "http-sinks")]
+use kusto_kmsg_extract::azure_monitor;
+#[cfg(feature = "http-sinks")]
+use kusto_kmsg_extract::eventhub;
+#[cfg(feature = "simd-json")]
+use kusto_kmsg_extract::format::JsonParser;
+use kusto_kmsg_extract::format::{format_record, process_message, FormatOptions};
+#[cfg(feature = "http-sinks")]
+use kusto_kmsg_extract::loki;
+#[cfg(feature = "http-sinks")]
+use kusto_kmsg_extract::notify;
+#[cfg(feature = "wasm-plugins")]
+use kusto_kmsg_extract::plugin;
+#[cfg(feature = "server")]
+use kusto_kmsg_extract::serve;
+#[cfg(feature = "http-sinks")]
+use kusto_kmsg_extract::splunk;
+#[cfg(feature = "self-update")]
+use kusto_kmsg_extract::update;
+#[cfg(feature = "http-sinks")]
+use kusto_kmsg_extract::upload::UploadOptions;
+use kusto_kmsg_extract::{
+    anomalies, ansi, boot, buildinfo, check, checksum, config, correlate, decoded, decoder, dedupe,
+    exec, extract, fields_stats, filter, first_error, flag, group, guid, header, index, input,
+    interrupt, irqs, json_output, json_path, kql, manifest, measure, mmio, msrs, paths, payload,
+    pipeline, pseudonym, rename, replay, resume, rules, schema, scrub, select, sink, spans,
+    suppress, syslog, tables, theme, time, tsc, units, vp_links, vp_timeline, wrap,
+};
+use measure::{Measure, MeasureSpec};
+use pipeline::PipelineOptions;
+use pseudonym::PseudonymMap;
+use rename::FieldMap;
+use resume::ProgressWriter;
+use rules::{RuleSet, RulesDecoder};
+use scrub::Scrubber;
+use select::{Sample, Selection};
+use sink::{RecordInfo, Sink, StdoutSink};
+use std::cell::Cell;
 use std::error::Error;
-use std::fs::File;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+use suppress::Suppressions;
+use syslog::SyslogSink;
+use theme::{ColorChoice, Theme};
+use time::TimestampFormat;
+use tsc::TscFrequency;
+use units::{FloatFormat, Notation, UnitsDecoder};
+use vp_links::VpLinks;
+use wrap::Wrapper;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
-    /// Path to the CSV file to process
-    file: PathBuf,
-}
-
-/// Transform values inside tdx_tdg_vp_enter_exit_info to hex format
-fn transform_tdx_exit_info(text: &str) -> String {
-    let tdx_exit_regex = Regex::new(r"(rax|rcx|rdx|rsi|rdi|r\d+): (\d+)").unwrap();
-
-    tdx_exit_regex
-        .replace_all(text, |caps: &regex::Captures| {
-            let reg = &caps[1];
-            let num = caps[2].parse::<u64>().unwrap_or(0);
-            format!("{}: 0x{:x}", reg, num)
-        })
-        .to_string()
-}
-
-/// Transform TdxL2EnterGuestState contents to hex format
-fn transform_tdx_guest_state(text: &str) -> String {
-    let tdx_gpr_array_regex = Regex::new(r"\[([0-9, ]+)\]").unwrap();
-    let tdx_gpr_field_regex = Regex::new(r"(rflags|rip|ssp|rvi|svi): (\d+)").unwrap();
-
-    // Transform the array values to hex
-    let transformed = tdx_gpr_array_regex.replace_all(text, |caps: &regex::Captures| {
-        let numbers_str = &caps[1];
-        let numbers: Vec<String> = numbers_str
-            .split(',')
-            .map(|s| match s.trim().parse::<u64>() {
-                Ok(num) => format!("0x{:x}", num),
-                Err(_) => s.trim().to_string(),
-            })
-            .collect();
-        format!("[{}]", numbers.join(", "))
-    });
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Print examples of common invocations
+    #[arg(long, exclusive = true)]
+    help_examples: bool,
+
+    #[command(flatten)]
+    run: RunArgs,
+}
+
+/// Actions of the config subcommand
+#[derive(Subcommand, Debug)]
+enum ConfigAction {
+    /// Print the configuration files found and the options they set once
+    /// merged
+    Show,
+}
+
+/// Actions of the decoders subcommand
+#[derive(Subcommand, Debug)]
+enum DecodersAction {
+    /// List the decoders in the order they are tried, built-in and selected
+    /// by the options, with the fields each applies to and an example
+    #[command(mut_arg("file", |arg| arg.required(false)))]
+    List {
+        #[command(flatten)]
+        run: Box<RunArgs>,
+    },
+}
+
+/// Actions of the tables subcommand
+#[derive(Subcommand, Debug)]
+enum TablesAction {
+    /// Download the latest tables, or those of the pinned version
+    #[cfg(feature = "tables-update")]
+    Update {
+        /// URL or local directory holding the table versions
+        #[arg(long, value_name = "URL|DIR")]
+        source: Option<String>,
+
+        /// Install this version and keep to it in later updates
+        #[arg(long, value_name = "VERSION", conflicts_with = "latest")]
+        version: Option<String>,
+
+        /// Install the latest version, dropping a pinned one
+        #[arg(long)]
+        latest: bool,
+    },
+
+    /// List the installed tables with their version
+    List,
+}
+
+/// Common invocations, printed by --help-examples
+const EXAMPLES: &str = "\
+Format a Kusto CSV export:
+    kusto-kmsg-extract export.csv
+
+Show warnings and errors from one hour, writing them to a file:
+    kusto-kmsg-extract export.csv --level warn \\
+        --since 2024-05-01T10 --until 2024-05-01T11 -o triage.log
+
+Merge export chunks with overlapping time windows:
+    kusto-kmsg-extract part1.csv part2.csv --dedupe
+
+Index a large export once so later filtered runs skip unrelated rows:
+    kusto-kmsg-extract index export.csv
+    kusto-kmsg-extract search \"vp exit\" export.csv
+
+Follow the lifecycle of one request across targets:
+    kusto-kmsg-extract export.csv --trace-id 8c2e41f0
+    kusto-kmsg-extract correlate export.csv --min-records 5
+
+Flag slow operations while scrolling through:
+    kusto-kmsg-extract export.csv --flag 'duration_ns > 1ms'
+
+Scrub sensitive values with stable pseudonyms before sharing:
+    kusto-kmsg-extract export.csv --scrub --pseudonym-map partner.json
+
+Find which build produced a log and where it first went wrong:
+    kusto-kmsg-extract buildinfo export.csv
+    kusto-kmsg-extract first-error export.csv --suppress known-noise.txt
+    kusto-kmsg-extract anomalies export.csv --window 30s
+
+Summarize guest behavior:
+    kusto-kmsg-extract vp-timeline export.csv --chart
+    kusto-kmsg-extract msrs export.csv
+    kusto-kmsg-extract mmio export.csv --merge
+    kusto-kmsg-extract irqs export.csv
+
+Save a set of options and replay it on another export:
+    kusto-kmsg-extract a.csv --level warn --scrub --save-pipeline triage.toml
+    kusto-kmsg-extract b.csv --pipeline triage.toml
+
+Hand on filtered records as JSON or as a smaller export:
+    kusto-kmsg-extract export.csv --level error --raw | jq .fields
+    kusto-kmsg-extract extract export.csv --trace-id 8c2e41f0 -o subset.csv
+
+Rerun the applied filters server-side:
+    kusto-kmsg-extract export.csv --level warn --since 2024-05-01T10 --emit-kql
+
+Format a large export on several threads:
+    kusto-kmsg-extract export.csv --jobs 8 -o out.log
+
+Format many exports at once, each to <input>.decoded.txt:
+    kusto-kmsg-extract exports/*.csv --split-output
+";
+
+/// Options for formatting the records of a file
+#[derive(clap::Args, Clone, Debug)]
+struct RunArgs {
+    /// Paths to the files to process, read one after the other, such as
+    /// the chunks of a large export. With the blob-inputs feature, an
+    /// `https://` URL such as a blob URL with a SAS token is downloaded as
+    /// it is read
+    #[arg(required = true, value_parser = paths::parse_path)]
+    file: Vec<PathBuf>,
+
+    /// Format of the input file
+    #[arg(long, value_enum, default_value_t = InputFormat::Csv)]
+    format: InputFormat,
+
+    /// Verify the input against this SHA-256 digest, or the one in a
+    /// checksum file like export.csv.sha256, before processing it
+    #[arg(long, value_name = "HASH|FILE", conflicts_with = "follow")]
+    sha256: Option<String>,
+
+    /// Write negative integers as -0x1 instead of in 64-bit two's
+    /// complement
+    #[arg(long)]
+    signed_hex: bool,
+
+    /// Mark values that can't be right, like a selector above 0xffff or a
+    /// vector above 255, with [suspect], to catch corrupted records and
+    /// dumps parsed wrong
+    #[arg(long)]
+    validate: bool,
+
+    /// Render byte array fields longer than this many bytes as a hex dump
+    /// below the record
+    #[arg(long, value_name = "BYTES", default_value_t = payload::DEFAULT_HEXDUMP_THRESHOLD)]
+    hexdump_threshold: usize,
+
+    /// Detect base64 encoded payloads more aggressively, dumping them in
+    /// full and decoding recognized structures
+    #[arg(long)]
+    decode_base64: bool,
+
+    /// Reject malformed rows instead of parsing them flexibly, reporting
+    /// the exact location of the first error
+    #[arg(long)]
+    strict: bool,
+
+    /// How record timestamps are written when they aren't ISO 8601, as
+    /// epoch-s, epoch-ms, epoch-us, epoch-ns or a strftime pattern like
+    /// '%d/%m/%Y %H:%M:%S'. They are shown and filtered as ISO 8601.
+    #[arg(long, value_name = "FORMAT", value_parser = time::parse_timestamp_format)]
+    timestamp_format: Option<TimestampFormat>,
+
+    /// Time of boot for records stamped with the seconds since boot, like
+    /// dmesg, shown as absolute time. With `auto` it is derived from the
+    /// first record that also carries a wall-clock time. Hyper-V reference
+    /// times are then also shown as wall-clock times.
+    #[arg(long, value_name = "TIMESTAMP|auto", value_parser = boot::parse_boot_time)]
+    boot_time: Option<BootTime>,
+
+    /// TSC frequency, like 2.5GHz, annotating fields such as `tsc` with the
+    /// time they stand for. With `auto` it is found in the TSC calibration
+    /// messages logged at boot.
+    #[arg(long, value_name = "HZ|auto", value_parser = tsc::parse_tsc_frequency)]
+    tsc_freq: Option<TscFrequency>,
+
+    /// TSC value at boot, subtracted before converting TSC values
+    #[arg(long, value_name = "TICKS", default_value_t = 0, requires = "tsc_freq")]
+    tsc_offset: u64,
+
+    /// Only show records at or after this timestamp, such as
+    /// 2024-05-01T10:30
+    #[arg(long, value_name = "TIMESTAMP")]
+    since: Option<String>,
+
+    /// Only show records before this timestamp
+    #[arg(long, value_name = "TIMESTAMP")]
+    until: Option<String>,
+
+    /// Only show records at this level or more severe
+    #[arg(long, value_enum)]
+    level: Option<Level>,
+
+    /// Only show records carrying this correlation ID, such as a
+    /// request_id or activity_id, in their fields or spans
+    #[arg(long, value_name = "ID")]
+    trace_id: Option<String>,
+
+    /// Mark records where a numeric field crosses a threshold with `!!`,
+    /// like 'duration_ns > 1ms' (can be repeated)
+    #[arg(long, value_name = "RULE")]
+    flag: Vec<String>,
+
+    /// Report the time from each record matching START to the next one
+    /// matching END, given as 'START..END' regexes, with summary stats
+    #[arg(long, value_name = "START..END", value_parser = measure::parse_measure)]
+    measure: Option<MeasureSpec>,
+
+    /// Only pair --measure records holding the same value of this field,
+    /// like vp_index
+    #[arg(long, value_name = "FIELD", requires = "measure")]
+    measure_by: Option<String>,
+
+    /// Skip the first N rows of the input before anything else
+    #[arg(long, value_name = "N", default_value_t = 0, conflicts_with = "resume")]
+    skip_rows: usize,
+
+    /// Read at most N rows of the input, after --skip-rows
+    #[arg(long, value_name = "N", conflicts_with = "resume")]
+    max_rows: Option<usize>,
+
+    /// Only read the rows starting within this range of bytes of a CSV
+    /// export, like 0..1GiB or 1GiB.., to process a file in slices
+    #[arg(long, value_name = "START..END", value_parser = input::parse_byte_range, conflicts_with = "resume")]
+    byte_range: Option<std::ops::Range<u64>>,
+
+    /// Drop exact duplicates of earlier tracing records, such as those in
+    /// the overlapping time windows of merged export chunks
+    #[arg(long)]
+    dedupe: bool,
+
+    /// Only show the first N records passing the filters
+    #[arg(long, value_name = "N", conflicts_with = "tail")]
+    head: Option<u64>,
+
+    /// Only show the last N records passing the filters
+    #[arg(long, value_name = "N", conflicts_with_all = ["follow", "resume"])]
+    tail: Option<usize>,
+
+    /// Only show a sample of the records passing the filters, like 1/100
+    /// for one in every hundred
+    #[arg(long, value_name = "RATE", value_parser = select::parse_sample)]
+    sample: Option<Sample>,
+
+    /// Show records newest first, ordered by timestamp
+    #[arg(long, conflicts_with_all = ["follow", "resume"])]
+    reverse: bool,
+
+    /// Write the original message of each shown record instead of
+    /// formatting it, to pass a filtered subset on to other JSON tools
+    #[arg(long, conflicts_with_all = ["scrub", "scrub_rules", "pseudonym_map"])]
+    raw: bool,
+
+    /// Show below each record, or only the RECORDth one passing the
+    /// filters, which decoders and transform rules were tried on each
+    /// field, with its value as logged and as shown
+    #[arg(
+        long,
+        value_name = "RECORD",
+        num_args = 0..=1,
+        require_equals = true,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    explain: Option<Option<u64>>,
+
+    /// Redact IP and email addresses, and anything matched by
+    /// --scrub-rules, so the output can be shared
+    #[arg(long)]
+    scrub: bool,
+
+    /// TOML file of additional scrubbing rules, implies --scrub
+    #[arg(long, value_name = "FILE")]
+    scrub_rules: Option<PathBuf>,
+
+    /// Keyed mapping file giving scrubbed values the same pseudonym in every
+    /// run, created if missing, implies --scrub
+    #[arg(long, value_name = "FILE")]
+    pseudonym_map: Option<PathBuf>,
+
+    /// File mapping GUIDs to friendly names, one `<guid> <name>` per line
+    #[arg(long, value_name = "FILE")]
+    guid_map: Option<PathBuf>,
+
+    /// TOML file renaming fields, like `ei1 = "exit_info1"`, applied before
+    /// they are decoded
+    #[arg(long, value_name = "FILE")]
+    field_map: Option<PathBuf>,
+
+    /// TOML file describing struct layouts, used to decode Debug-formatted
+    /// dumps of those structs
+    #[arg(long, value_name = "FILE")]
+    struct_schema: Option<PathBuf>,
+
+    /// Build version selecting the --struct-schema layouts, found in the
+    /// build info logged at boot when not given
+    #[arg(long, value_name = "VERSION", requires = "struct_schema")]
+    schema_version: Option<String>,
+
+    /// When to color records written to standard output. Automatic color
+    /// follows NO_COLOR, CLICOLOR and CLICOLOR_FORCE and is off for dumb
+    /// terminals.
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    /// Colors of records: dark, light, minimal, or a TOML file with a
+    /// [theme] section adjusting one of them
+    #[arg(long, value_name = "NAME|FILE", default_value = "dark")]
+    theme: String,
+
+    /// Link MSR indexes, exit reasons and other decoded fields to their
+    /// reference documentation, in terminals supporting OSC 8 hyperlinks
+    #[arg(long)]
+    links: bool,
+
+    /// Wrap formatted lines longer than this many columns at spaces,
+    /// indenting the continued lines past the record header
+    #[arg(long, value_name = "COLS", value_parser = clap::value_parser!(u16).range(20..))]
+    wrap: Option<u16>,
+
+    /// Cut messages and field values longer than this many characters,
+    /// marking the cut with an ellipsis
+    #[arg(long, value_name = "CHARS")]
+    truncate: Option<usize>,
+
+    /// Remove ANSI escape sequences, like color codes, from messages and
+    /// field values before they are formatted
+    #[arg(long)]
+    strip_ansi: bool,
+
+    /// Point each VP entry and exit record to the line of the next record
+    /// of the opposite kind for the same VP. Records are held until the
+    /// end of the input to number the lines.
+    #[arg(long, conflicts_with_all = ["follow", "resume"])]
+    vp_links: bool,
+
+    /// Group records by the value of this field, like vm_id, writing each
+    /// group to its own file next to --output, or else as a section of
+    /// the output. Records are held until the end of the input.
+    #[arg(long, value_name = "FIELD", conflicts_with_all = ["follow", "resume"])]
+    group_by: Option<String>,
+
+    /// TOML file mapping field names to units, shown next to byte counts
+    /// and durations
+    #[arg(long, value_name = "FILE")]
+    units: Option<PathBuf>,
+
+    /// Digits after the point of fields holding floats
+    #[arg(long, value_name = "DIGITS")]
+    float_precision: Option<usize>,
+
+    /// Notation of fields holding floats
+    #[arg(long, value_enum, default_value_t = Notation::Plain)]
+    float_notation: Notation,
+
+    /// TOML file of regex transform rules applied to string fields
+    #[arg(long, value_name = "FILE")]
+    rules: Option<PathBuf>,
+
+    /// Keep reading data appended to the input file, reloading the rules
+    /// file whenever it changes
+    #[arg(long)]
+    follow: bool,
+
+    /// Read the input with regular reads instead of mapping it into
+    /// memory, for files on network filesystems
+    #[arg(long)]
+    no_mmap: bool,
+
+    /// Number of threads formatting records in parallel. Local CSV exports
+    /// are split into slices of rows, each read by the thread formatting
+    /// it
+    #[arg(long, value_name = "N", default_value_t = 1, conflicts_with = "follow")]
+    jobs: usize,
+
+    /// Format each input file on its own thread into `<input>.decoded.txt`,
+    /// instead of one output of all files in order. Not available with
+    /// --pseudonym-map, which the threads can't share
+    #[arg(long, conflicts_with_all = ["output", "resume", "follow", "byte_range", "manifest", "pseudonym_map"])]
+    split_output: bool,
+
+    /// Number of records handed to a thread at a time, for inputs read on
+    /// a single thread
+    #[arg(long, value_name = "ROWS", default_value_t = 1024)]
+    batch_size: usize,
+
+    /// Maximum number of batches or slices held in memory at once, four per
+    /// thread by default
+    #[arg(long, value_name = "BATCHES")]
+    max_in_flight: Option<usize>,
+
+    /// Write formatted records to this file instead of standard output,
+    /// saving progress so an interrupted run can be resumed, write them to
+    /// standard output as JSON lines with `json`, or forward them with
+    /// `syslog`, `loki=URL`, `azure-monitor=ENDPOINT`,
+    /// `eventhub=CONNECTION_STRING` or `splunk=URL`
+    #[arg(long, short, value_name = "FILE", value_parser = paths::parse_path)]
+    output: Option<PathBuf>,
+
+    /// Syslog server records are forwarded to with `--output syslog`, as
+    /// udp://HOST:PORT, tcp://HOST:PORT or unix://PATH
+    #[arg(long, value_name = "ADDRESS", default_value = "unix:///dev/log")]
+    syslog_address: String,
+
+    /// Immutable ID of the data collection rule records are sent to with
+    /// `--output azure-monitor=ENDPOINT`
+    #[cfg(feature = "http-sinks")]
+    #[arg(long, value_name = "ID")]
+    dcr_id: Option<String>,
+
+    /// Stream of the data collection rule records are sent to
+    #[cfg(feature = "http-sinks")]
+    #[arg(long, value_name = "STREAM", default_value = "Custom-KmsgExtract")]
+    dcr_stream: String,
+
+    /// Sourcetype of the events sent with `--output splunk=URL`, which
+    /// reads the collector token from SPLUNK_HEC_TOKEN
+    #[cfg(feature = "http-sinks")]
+    #[arg(long, value_name = "SOURCETYPE", default_value = "kmsg")]
+    splunk_sourcetype: String,
+
+    /// Most records in each batch sent to a log service, 1000 for Loki,
+    /// besides the size limit of each service
+    #[cfg(feature = "http-sinks")]
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    sink_batch_records: Option<u64>,
+
+    /// Longest time a record waits to be sent to a log service, like 5s
+    #[cfg(feature = "http-sinks")]
+    #[arg(long, value_name = "DURATION", value_parser = units::parse_duration)]
+    sink_flush_interval: Option<u64>,
+
+    /// Most records sent to a log service per second, to stay below its
+    /// ingestion quota on large replays
+    #[cfg(feature = "http-sinks")]
+    #[arg(long, value_name = "RECORDS", value_parser = clap::value_parser!(u32).range(1..))]
+    sink_rate_limit: Option<u32>,
+
+    /// Times a batch throttled by a log service with a 429 or 503 is
+    /// retried, after the delay it asks for or a growing backoff
+    #[cfg(feature = "http-sinks")]
+    #[arg(long, value_name = "N", default_value_t = 5)]
+    sink_retries: u32,
+
+    /// Post records matching --notify-on to this Slack or Teams incoming
+    /// webhook, to watch a followed log
+    #[cfg(feature = "http-sinks")]
+    #[arg(long, value_name = "URL", requires = "notify_on")]
+    notify_webhook: Option<String>,
+
+    /// Pattern of critical records posted to --notify-webhook, a regex
+    /// matched ignoring case, like 'panic|triple fault'
+    #[cfg(feature = "http-sinks")]
+    #[arg(long, value_name = "REGEX", requires = "notify_webhook")]
+    notify_on: Option<String>,
+
+    /// Decode and batch records as usual, but only print what would be
+    /// sent to syslog, a log service or --notify-webhook, with counts and
+    /// a sample, to check a run before pointing it at production
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Continue an interrupted run from the progress saved next to its
+    /// --output file (CSV input only)
+    #[arg(long, requires = "output")]
+    resume: bool,
+
+    /// Process the input without printing it and report throughput
+    #[arg(long)]
+    bench: bool,
+
+    /// Save the filter and transform options given on the command line to
+    /// a pipeline file, to replay later with --pipeline
+    #[arg(long, value_name = "FILE")]
+    save_pipeline: Option<PathBuf>,
+
+    /// Add the options saved in a pipeline file, where options given on
+    /// the command line take precedence
+    #[arg(long, value_name = "FILE")]
+    pipeline: Option<PathBuf>,
+
+    /// Ignore the system, user and project configuration files
+    #[arg(long)]
+    no_config: bool,
+
+    /// Write a JSON manifest of the run to this file, recording hashes of
+    /// the files read, the decoders and filters used, and row counts
+    #[arg(long, value_name = "FILE")]
+    manifest: Option<PathBuf>,
+
+    /// Print the KQL where clauses equivalent to the applied filters
+    /// instead of processing the input
+    #[arg(long)]
+    emit_kql: bool,
+
+    /// Turn off a decoder by name, as shown by `decoders list` (can be
+    /// repeated)
+    #[arg(long, value_name = "NAME")]
+    disable_decoder: Vec<String>,
+
+    /// Command that transforms tracing JSON records, one per line on stdin
+    /// and stdout, before they are formatted
+    #[arg(long, value_name = "COMMAND")]
+    exec_decoder: Option<String>,
+
+    /// Load every .wasm decoder plugin in this directory, tried after the
+    /// built-in decoders
+    #[cfg(feature = "wasm-plugins")]
+    #[arg(long, value_name = "DIR")]
+    plugins_dir: Option<PathBuf>,
+
+    /// Path of the tracing record inside the JSON of each message, for
+    /// messages that wrap it, like `kmsg` for `{"kmsg": {...}}`, given as
+    /// dotted names or a JSON pointer
+    #[arg(long, value_name = "PATH", value_parser = json_path::parse_json_path)]
+    json_path: Option<JsonPath>,
+
+    /// Read a header field of tracing records from another name, like
+    /// `level=severity`, for emitters that name it differently (can be
+    /// repeated). Records missing a header field are shown with `-` in its
+    /// place.
+    #[arg(long, value_name = "FIELD=NAME", value_parser = header::parse_alias)]
+    field_alias: Vec<(HeaderField, String)>,
+
+    /// JSON parser used for each record
+    #[cfg(feature = "simd-json")]
+    #[arg(long, value_enum, default_value_t = JsonParser::Serde)]
+    parser: JsonParser,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Build a sidecar index of a CSV export, which later runs filtered
+    /// with --since, --until or --level use to skip unrelated rows
+    Index {
+        /// Path to the CSV export
+        #[arg(value_parser = paths::parse_path)]
+        file: PathBuf,
+
+        /// Number of rows summarized by each index entry
+        #[arg(long, value_name = "ROWS", default_value_t = 4096)]
+        block_rows: u64,
+    },
+
+    /// Show the records whose message or target contains some text, using
+    /// the index of a CSV export to skip unrelated rows when there is one
+    Search {
+        /// Text to look for, ignoring case
+        query: String,
+
+        #[command(flatten)]
+        run: Box<RunArgs>,
+    },
+
+    /// Write the rows of a CSV export that pass the filters to a new CSV
+    /// file with the original columns, given with --output. The rows are
+    /// copied unscrubbed, so the scrubbing options are rejected
+    #[command(mut_arg("output", |arg| arg.required(true)))]
+    Extract {
+        #[command(flatten)]
+        run: Box<RunArgs>,
+    },
+
+    /// Parse the records of an export once into a compact intermediate
+    /// file, read with `--format decoded` by search and the subcommands
+    /// summarizing a log, so repeated analysis skips the JSON parsing
+    Decode {
+        /// Path to the input file
+        #[arg(value_parser = paths::parse_path)]
+        file: PathBuf,
+
+        /// Format of the input file
+        #[arg(long, value_enum, default_value_t = InputFormat::Csv)]
+        format: InputFormat,
+
+        /// Intermediate file to write
+        #[arg(long, short, value_name = "FILE", value_parser = paths::parse_path)]
+        output: PathBuf,
+    },
+
+    /// Group records by the correlation ID they carry and print the
+    /// timeline of each ID
+    Correlate {
+        /// Path to the input file
+        #[arg(value_parser = paths::parse_path)]
+        file: PathBuf,
+
+        /// Format of the input file
+        #[arg(long, value_enum, default_value_t = InputFormat::Csv)]
+        format: InputFormat,
+
+        /// Field holding the correlation ID (can be repeated), by default
+        /// common ones such as request_id and activity_id
+        #[arg(long, value_name = "FIELD")]
+        key: Vec<String>,
+
+        /// Only show IDs with at least this many records
+        #[arg(long, value_name = "N", default_value_t = 1)]
+        min_records: usize,
+    },
+
+    /// Reconstruct the run state transitions of each VP from its enter,
+    /// exit, intercept and halt records
+    VpTimeline {
+        /// Path to the input file
+        #[arg(value_parser = paths::parse_path)]
+        file: PathBuf,
+
+        /// Format of the input file
+        #[arg(long, value_enum, default_value_t = InputFormat::Csv)]
+        format: InputFormat,
+
+        /// Also draw a chart of the states over time, this many columns wide
+        #[arg(long, value_name = "COLUMNS", num_args = 0..=1, default_missing_value = "80")]
+        chart: Option<usize>,
+    },
+
+    /// Summarize the guest MSR reads and writes that were intercepted
+    Msrs {
+        /// Path to the input file
+        #[arg(value_parser = paths::parse_path)]
+        file: PathBuf,
+
+        /// Format of the input file
+        #[arg(long, value_enum, default_value_t = InputFormat::Csv)]
+        format: InputFormat,
+    },
+
+    /// Count the emulated MMIO and port IO accesses to each address range
+    Mmio {
+        /// Path to the input file
+        #[arg(value_parser = paths::parse_path)]
+        file: PathBuf,
+
+        /// Format of the input file
+        #[arg(long, value_enum, default_value_t = InputFormat::Csv)]
+        format: InputFormat,
+
+        /// Size of the ranges MMIO addresses are grouped into
+        #[arg(long, value_name = "BYTES", default_value = "4KiB", value_parser = units::parse_bytes)]
+        bucket: u64,
+
+        /// Combine ranges that touch into a single region
+        #[arg(long)]
+        merge: bool,
+    },
+
+    /// Count the interrupts and exceptions injected into the guest by
+    /// vector, reporting repeated faults
+    Irqs {
+        /// Path to the input file
+        #[arg(value_parser = paths::parse_path)]
+        file: PathBuf,
+
+        /// Format of the input file
+        #[arg(long, value_enum, default_value_t = InputFormat::Csv)]
+        format: InputFormat,
+
+        /// Report a fault injected into one VP this many times within a
+        /// second
+        #[arg(long, value_name = "N", default_value_t = 3)]
+        burst: usize,
+    },
+
+    /// Print the earliest error, or the first warning before a fatal event,
+    /// with the records around it, as a starting point for finding the
+    /// root cause of a failure
+    FirstError {
+        /// Path to the input file
+        #[arg(value_parser = paths::parse_path)]
+        file: PathBuf,
+
+        /// Format of the input file
+        #[arg(long, value_enum, default_value_t = InputFormat::Csv)]
+        format: InputFormat,
+
+        /// File of patterns of known benign records to skip, one regex per
+        /// line matched against `TARGET: MESSAGE`
+        #[arg(long, value_name = "FILE")]
+        suppress: Option<PathBuf>,
+
+        /// Number of records shown before and after
+        #[arg(long, short = 'C', value_name = "N", default_value_t = 5)]
+        context: usize,
+    },
+
+    /// List message templates that first appear or spike in rate shortly
+    /// before the failure, compared with the rest of the log
+    Anomalies {
+        /// Path to the input file
+        #[arg(value_parser = paths::parse_path)]
+        file: PathBuf,
+
+        /// Format of the input file
+        #[arg(long, value_enum, default_value_t = InputFormat::Csv)]
+        format: InputFormat,
+
+        /// Length of the window before the failure, like 30s or 5min
+        #[arg(long, value_name = "DURATION", value_parser = units::parse_duration, default_value = "1min")]
+        window: u64,
+
+        /// Growth in rate reported as a spike
+        #[arg(long, value_name = "FACTOR", default_value_t = 10.0)]
+        spike: f64,
+    },
+
+    /// Replace this binary with the latest release, after checking its
+    /// signature
+    #[cfg(feature = "self-update")]
+    SelfUpdate {
+        /// Only report whether a newer release is available
+        #[arg(long)]
+        check: bool,
+    },
+
+    /// Discover the decoders applied to fields
+    Decoders {
+        #[command(subcommand)]
+        action: DecodersAction,
+    },
+
+    /// Manage the decode tables installed outside of the binary
+    Tables {
+        #[command(subcommand)]
+        action: TablesAction,
+    },
+
+    /// Work with the layered configuration files
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+
+    /// Inspect a CSV export and report its encoding, columns, message
+    /// column, how many sampled messages parse and the build version, for
+    /// finding out why decoding shows nothing
+    Check {
+        /// Path to the input file
+        #[arg(value_parser = paths::parse_path)]
+        file: PathBuf,
+
+        /// Rows sampled from the start of the file
+        #[arg(long, value_name = "ROWS", default_value_t = 1000)]
+        sample: usize,
+    },
+
+    /// Print the distribution of the values of fields, with the number of
+    /// distinct values, the smallest and largest and the most common
+    FieldsStats {
+        /// Path to the input file
+        #[arg(value_parser = paths::parse_path)]
+        file: PathBuf,
+
+        /// Format of the input file
+        #[arg(long, value_enum, default_value_t = InputFormat::Csv)]
+        format: InputFormat,
+
+        /// Field to report on, like gpa, vector or exit_reason, or level or
+        /// target for the header of the records
+        #[arg(long, short, value_name = "NAME", required = true)]
+        field: Vec<String>,
+
+        /// Number of most common values shown for each field
+        #[arg(long, value_name = "N", default_value_t = 10)]
+        top: usize,
+    },
+
+    /// Print the time spent in each span by target and name, from the
+    /// time.busy and time.idle of span close events
+    Spans {
+        /// Path to the input file
+        #[arg(value_parser = paths::parse_path)]
+        file: PathBuf,
+
+        /// Format of the input file
+        #[arg(long, value_enum, default_value_t = InputFormat::Csv)]
+        format: InputFormat,
+    },
+
+    /// Print the build banners logged at boot, with the version, commit,
+    /// branch and build time of each build that ran
+    Buildinfo {
+        /// Path to the input file
+        #[arg(value_parser = paths::parse_path)]
+        file: PathBuf,
+
+        /// Format of the input file
+        #[arg(long, value_enum, default_value_t = InputFormat::Csv)]
+        format: InputFormat,
+    },
+
+    /// Print the JSON Schema of the records written with `--output json`
+    Schema,
+
+    /// Serve an HTTP endpoint decoding messages posted to /decode as JSON,
+    /// with the decoders and filters selected by the options
+    #[cfg(feature = "server")]
+    #[command(mut_arg("file", |arg| arg.required(false).hide(true)))]
+    Serve {
+        /// Address to listen on
+        #[arg(long, value_name = "ADDRESS", default_value = "127.0.0.1:8080")]
+        listen: String,
+
+        #[command(flatten)]
+        run: Box<RunArgs>,
+    },
+
+    /// Print a shell completion script, for example with
+    /// `source <(kusto-kmsg-extract completions bash)`
+    Completions {
+        /// Shell to complete in
+        shell: clap_complete::Shell,
+    },
+}
+
+/// Print the rate at which rows and bytes of input were processed
+fn report_throughput(rows: u64, bytes: u64, start: Instant) {
+    let seconds = start.elapsed().as_secs_f64().max(f64::EPSILON);
+    eprintln!(
+        "Processed {} rows ({} bytes) in {:.3}s: {:.0} rows/s, {:.2} MiB/s",
+        rows,
+        bytes,
+        seconds,
+        rows as f64 / seconds,
+        bytes as f64 / seconds / (1024.0 * 1024.0)
+    );
+}
+
+/// Build the index of a CSV export
+fn build_index(file: &Path, block_rows: u64) -> Result<(), Box<dyn Error>> {
+    let index = index::build(file, block_rows.max(1))?;
+    index.save(file)?;
+    eprintln!(
+        "Indexed {} rows in {} blocks to {}",
+        index.blocks.iter().map(|block| block.rows).sum::<u64>(),
+        index.blocks.len(),
+        index::index_path(file).display()
+    );
+    Ok(())
+}
+
+/// Open an input file for a subcommand that reads the whole of it once
+fn open_input(file: &Path, format: InputFormat) -> Result<input::Records, Box<dyn Error>> {
+    let input_options = InputOptions {
+        format,
+        strict: false,
+        follow: false,
+        mmap: true,
+    };
+    input::open(file, &input_options)
+}
 
-    // Transform individual field values to hex
-    tdx_gpr_field_regex
-        .replace_all(&transformed, |caps: &regex::Captures| {
-            let field = &caps[1];
-            let num = caps[2].parse::<u64>().unwrap_or(0);
-            format!("{}: 0x{:x}", field, num)
-        })
-        .to_string()
+/// Matches of the options for formatting a file, from the top level or the
+/// search subcommand, with the command they belong to
+fn run_matches<'a>(
+    command: &'a clap::Command,
+    matches: &'a ArgMatches,
+) -> Option<(&'a clap::Command, &'a ArgMatches)> {
+    match matches.subcommand() {
+        None => Some((command, matches)),
+        Some((name @ ("search" | "extract" | "serve"), matches)) => {
+            Some((command.find_subcommand(name)?, matches))
+        }
+        Some(_) => None,
+    }
 }
 
-/// Transform SegmentRegister values to hex format
-fn transform_segment_register(text: &str) -> String {
-    let segment_register_regex = Regex::new(r"(base|limit|selector|attributes): (\d+)").unwrap();
+/// Parse the command line, replaying and saving pipeline files
+fn parse_args() -> Result<Args, Box<dyn Error>> {
+    let command = config::with_env(Args::command());
+    let mut matches = command.clone().get_matches();
+    // Options added to the command line by the pipeline and configuration
+    // files
+    let mut extra = Vec::new();
 
-    segment_register_regex
-        .replace_all(text, |caps: &regex::Captures| {
-            let field = &caps[1];
-            let num = caps[2].parse::<u64>().unwrap_or(0);
-            format!("{}: 0x{:x}", field, num)
-        })
-        .to_string()
+    if let Some((run_command, run)) = run_matches(&command, &matches) {
+        if let Some(path) = run.get_one::<PathBuf>("pipeline") {
+            extra = replay::replay_args(path, run_command, run, false)?;
+            matches = command
+                .clone()
+                .get_matches_from(std::env::args_os().chain(extra.iter().cloned()));
+        }
+    }
+
+    if let Some((run_command, run)) = run_matches(&command, &matches) {
+        if let Some(path) = run.get_one::<PathBuf>("save_pipeline") {
+            replay::save(path, run_command, run)?;
+        }
+    }
+
+    // Configuration files add the options not given yet, from the highest
+    // precedence down, and are kept out of saved pipelines
+    for path in config::files() {
+        let Some((run_command, run)) = run_matches(&command, &matches) else {
+            break;
+        };
+        if run.get_flag("no_config") {
+            break;
+        }
+        extra.extend(replay::replay_args(&path, run_command, run, true)?);
+        matches = command
+            .clone()
+            .get_matches_from(std::env::args_os().chain(extra.iter().cloned()));
+    }
+
+    Ok(Args::from_arg_matches(&matches)?)
 }
 
-/// Format a numerical value as hex if possible
-fn format_value_as_hex(key: &str, value: &Value) -> String {
-    if value.is_number() {
-        // Handle unsigned integers
-        if let Some(num) = value.as_u64() {
-            format!(" {}=0x{:x}", key, num)
+fn main() -> Result<(), Box<dyn Error>> {
+    // Parse command line arguments
+    let args = parse_args()?;
+
+    let result = match &args.command {
+        Some(Command::Index { file, block_rows }) => build_index(file, *block_rows),
+        Some(Command::Search { query, run }) => run_file(run, Some(query)),
+        Some(Command::Extract { run }) => {
+            if run.format != InputFormat::Csv {
+                return Err("extract only supports CSV input".into());
+            }
+            // Rows are copied as they are, so the scrubbing options would
+            // leave sensitive values in a file thought to be scrubbed
+            if run.scrub || run.scrub_rules.is_some() || run.pseudonym_map.is_some() {
+                return Err(
+                    "extract copies the original rows, which can't be scrubbed with --scrub, --scrub-rules or --pseudonym-map"
+                        .into(),
+                );
+            }
+            let options = format_options(run, None)?;
+            let mut output = String::new();
+            let mut info = RecordInfo::default();
+            extract::extract(
+                &run.file,
+                run.output.as_ref().unwrap(),
+                run.strict,
+                |message| {
+                    info.reset(None);
+                    process_message(message, None, &options, &mut output, &mut info);
+                    !output.is_empty()
+                },
+            )
         }
-        // Handle signed integers
-        else if let Some(num) = value.as_i64() {
-            format!(" {}=0x{:x}", key, num)
+        Some(Command::Decode {
+            file,
+            format,
+            output,
+        }) => decoded::decode(open_input(file, *format)?, output),
+        Some(Command::Correlate {
+            file,
+            format,
+            key,
+            min_records,
+        }) => {
+            let keys = if key.is_empty() {
+                correlate::CORRELATION_KEYS
+                    .iter()
+                    .map(|key| key.to_string())
+                    .collect()
+            } else {
+                key.clone()
+            };
+            correlate::correlate(open_input(file, *format)?, &keys, *min_records)
         }
-        // Fall back to default for floats or other numeric types
-        else {
-            format!(" {}={}", key, value)
+        Some(Command::VpTimeline {
+            file,
+            format,
+            chart,
+        }) => vp_timeline::vp_timeline(open_input(file, *format)?, *chart),
+        Some(Command::Msrs { file, format }) => msrs::msrs(open_input(file, *format)?),
+        Some(Command::Mmio {
+            file,
+            format,
+            bucket,
+            merge,
+        }) => mmio::mmio(open_input(file, *format)?, *bucket, *merge),
+        Some(Command::Irqs {
+            file,
+            format,
+            burst,
+        }) => irqs::irqs(open_input(file, *format)?, *burst),
+        Some(Command::FirstError {
+            file,
+            format,
+            suppress,
+            context,
+        }) => {
+            let suppressions = match suppress {
+                Some(path) => Suppressions::load(path)?,
+                None => Suppressions::new(),
+            };
+            first_error::first_error(open_input(file, *format)?, &suppressions, *context)
         }
-    } else {
-        // Non-numeric values
-        format!(" {}={}", key, value)
+        Some(Command::Anomalies {
+            file,
+            format,
+            window,
+            spike,
+        }) => anomalies::anomalies(open_input(file, *format)?, *window, *spike),
+        #[cfg(feature = "self-update")]
+        Some(Command::SelfUpdate { check }) => update::self_update(*check),
+        #[cfg(feature = "tables-update")]
+        Some(Command::Tables {
+            action:
+                TablesAction::Update {
+                    source,
+                    version,
+                    latest,
+                },
+        }) => tables::update(source.as_deref(), version.as_deref(), *latest),
+        Some(Command::Tables {
+            action: TablesAction::List,
+        }) => tables::list(),
+        Some(Command::Decoders {
+            action: DecodersAction::List { run },
+        }) => {
+            decoder::list(&format_options(run, None)?.decoders);
+            Ok(())
+        }
+        Some(Command::Config {
+            action: ConfigAction::Show,
+        }) => config::show(&Args::command()),
+        Some(Command::Check { file, sample }) => check::check(file, *sample),
+        Some(Command::FieldsStats {
+            file,
+            format,
+            field,
+            top,
+        }) => fields_stats::fields_stats(open_input(file, *format)?, field, *top),
+        Some(Command::Schema) => json_output::print_schema(),
+        Some(Command::Spans { file, format }) => spans::spans(open_input(file, *format)?),
+        Some(Command::Buildinfo { file, format }) => {
+            buildinfo::buildinfo(open_input(file, *format)?)
+        }
+        #[cfg(feature = "server")]
+        Some(Command::Serve { listen, run }) => {
+            // Decoded text goes to other services, never to a terminal
+            let mut options = format_options(run, None)?;
+            options.theme = None;
+            let mut exec_decoder = match &run.exec_decoder {
+                Some(command) => Some(ExecDecoder::spawn(command)?),
+                None => None,
+            };
+            serve::serve(listen, |mut record| {
+                if let Some(exec_decoder) = &mut exec_decoder {
+                    if record.message.trim_start().starts_with('{') {
+                        record.message = exec_decoder.transform(&record.message)?;
+                    }
+                }
+                let mut output = String::new();
+                let mut info = RecordInfo::default();
+                format_record(record.borrowed(), &options, &mut output, &mut info);
+                if let Some(scrubber) = &options.scrubber {
+                    scrubber.save_pseudonyms()?;
+                }
+                Ok((output, info))
+            })
+        }
+        Some(Command::Completions { shell }) => {
+            let mut command = Args::command();
+            let name = command.get_name().to_string();
+            clap_complete::generate(*shell, &mut command, name, &mut std::io::stdout());
+            Ok(())
+        }
+        None if args.help_examples => {
+            print!("{}", EXAMPLES);
+            Ok(())
+        }
+        None => run_file(&args.run, None),
+    };
+
+    if interrupt::is_interrupted() {
+        result?;
+        std::process::exit(interrupt::EXIT_CODE);
     }
+    result
 }
 
-/// Process a single message field and convert it to the desired output format
-fn process_message(message_field: &str) -> String {
-    // Skip empty fields
-    if message_field.is_empty() {
-        return String::new();
+/// Write the manifest of a completed run
+fn write_manifest(
+    path: &Path,
+    files: &[PathBuf],
+    args: &RunArgs,
+    options: &FormatOptions,
+    rows: u64,
+    written: u64,
+) -> Result<(), Box<dyn Error>> {
+    let option_files = [
+        ("guid-map", &args.guid_map),
+        ("field-map", &args.field_map),
+        ("struct-schema", &args.struct_schema),
+        ("units", &args.units),
+        ("rules", &args.rules),
+        ("scrub-rules", &args.scrub_rules),
+        ("pipeline", &args.pipeline),
+    ];
+
+    let mut inputs = Vec::new();
+    for file in files {
+        inputs.push(manifest::InputFile::hash("input", file)?);
+    }
+    for (role, path) in option_files {
+        if let Some(path) = path {
+            inputs.push(manifest::InputFile::hash(role, path)?);
+        }
     }
 
-    // Parse the JSON message, return raw message on failure
-    let json: Value = match serde_json::from_str(message_field) {
-        Ok(json) => json,
-        Err(_) => return message_field.to_string(),
+    let mut manifest = manifest::Manifest::new(inputs, &options.decoders, &options.filter);
+    manifest.rows.read = rows;
+    manifest.rows.written = written;
+    manifest.rows.skipped = rows.saturating_sub(written);
+    manifest.save(path)
+}
+
+/// Log services records can be forwarded to with `--output NAME=DESTINATION`
+const SERVICES: &[&str] = &["loki", "azure-monitor", "eventhub", "splunk"];
+
+/// Name of the log service an --output value forwards to, if any
+fn service_name(output: &Path) -> Option<String> {
+    let output = output.to_str()?;
+    let (name, _) = output.split_once('=')?;
+    SERVICES.contains(&name).then(|| name.to_string())
+}
+
+/// Whether the header of the records is read elsewhere than the top-level
+/// names the index was built from
+fn rewrites_header(args: &RunArgs) -> bool {
+    args.json_path.is_some() || !args.field_alias.is_empty() || args.exec_decoder.is_some()
+}
+
+/// Whether a window of input rows is selected by position
+fn selects_rows(args: &RunArgs) -> bool {
+    args.skip_rows > 0 || args.max_rows.is_some()
+}
+
+/// Whether an --output value names a file rather than another destination
+fn is_output_file(output: &Path) -> bool {
+    output != Path::new("json") && output != Path::new("syslog") && service_name(output).is_none()
+}
+
+/// Sink forwarding records to the log service of an --output value
+#[cfg(feature = "http-sinks")]
+fn service_sink(output: &Path, args: &RunArgs) -> Result<Box<dyn Sink>, Box<dyn Error>> {
+    let output = output.to_string_lossy();
+    let (name, destination) = output.split_once('=').unwrap_or_default();
+    let options = UploadOptions {
+        batch_records: args.sink_batch_records.map(|records| records as usize),
+        flush_interval: args
+            .sink_flush_interval
+            .map(std::time::Duration::from_nanos),
+        rate_limit: args.sink_rate_limit,
+        retries: args.sink_retries,
+        dry_run: args.dry_run,
     };
+    Ok(match name {
+        "loki" => Box::new(loki::LokiSink::new(destination, &options)?),
+        "azure-monitor" => {
+            let dcr_id = args
+                .dcr_id
+                .as_deref()
+                .ok_or("--output azure-monitor= needs --dcr-id")?;
+            Box::new(azure_monitor::AzureMonitorSink::new(
+                destination,
+                dcr_id,
+                &args.dcr_stream,
+                &options,
+            )?)
+        }
+        "eventhub" => Box::new(eventhub::EventHubSink::new(destination, &options)?),
+        "splunk" => Box::new(splunk::SplunkSink::new(
+            destination,
+            &args.splunk_sourcetype,
+            &options,
+        )?),
+        _ => return Err(format!("Unknown log service '{}'", name).into()),
+    })
+}
 
-    // Extract required fields
-    let timestamp = json.get("timestamp").and_then(Value::as_str);
-    let level = json.get("level").and_then(Value::as_str);
-    let target = json.get("target").and_then(Value::as_str);
-    let fields = json.get("fields");
+#[cfg(not(feature = "http-sinks"))]
+fn service_sink(output: &Path, _args: &RunArgs) -> Result<Box<dyn Sink>, Box<dyn Error>> {
+    Err(format!(
+        "--output {}= needs a build with the http-sinks feature",
+        service_name(output).unwrap_or_default()
+    )
+    .into())
+}
+
+/// Build the decoders, filters and transforms selected by the options
+fn format_options(args: &RunArgs, search: Option<&str>) -> Result<FormatOptions, Box<dyn Error>> {
+    let mut guid_names = GuidNames::new();
+    tables::load_guids(&mut guid_names)?;
+    if let Some(path) = &args.guid_map {
+        guid_names.load(path)?;
+    }
 
-    // Ensure all required fields are present
-    let (timestamp, level, target, fields) = match (timestamp, level, target, fields) {
-        (Some(ts), Some(lvl), Some(tgt), Some(flds)) => (ts, lvl, tgt, flds),
-        _ => return message_field.to_string(),
+    let boot_time = match (args.boot_time, args.file.first()) {
+        (Some(BootTime::At(time)), _) => Some(time),
+        (Some(BootTime::Auto), Some(file)) => Some(
+            boot::detect(open_input(file, args.format)?)?
+                .ok_or("No record gives a wall-clock time to derive --boot-time from")?,
+        ),
+        _ => None,
     };
 
-    // Default output format
-    let mut output = format!("[{}][{}][{}] {}", timestamp, level, target, fields);
+    let mut units = UnitsDecoder::new();
+    if let Some(path) = &args.units {
+        units.load(path)?;
+    }
+    // Reference time counts from the creation of the partition, taken to
+    // be its boot
+    if let Some(time) = boot_time {
+        units.set_reference_start(time);
+    }
+    if args.float_precision.is_some() || args.float_notation != Notation::Plain {
+        units.set_float_format(FloatFormat {
+            precision: args.float_precision,
+            notation: args.float_notation,
+        });
+    }
 
-    // Extract message and other fields if possible
-    let obj = match fields.as_object() {
-        Some(o) => o,
-        None => return output,
+    let flags = if args.flag.is_empty() {
+        None
+    } else {
+        Some(FlagSet::parse(&args.flag, &units)?)
     };
 
-    let message = match obj.get("message").and_then(Value::as_str) {
-        Some(msg) => msg,
-        None => return output,
+    let mut decoders = DecoderRegistry::builtin(
+        guid_names,
+        units,
+        args.hexdump_threshold,
+        args.decode_base64,
+    );
+    if let Some(path) = &args.struct_schema {
+        // Without a version given, look for the one logged at boot
+        let version = match (&args.schema_version, args.file.first()) {
+            (Some(version), _) => Some(version.clone()),
+            (None, Some(file)) => buildinfo::detect_version(open_input(file, args.format)?)?,
+            (None, None) => None,
+        };
+        // Layouts given by the user take precedence over the built-in
+        // decoders of the same structures
+        decoders.register_first(Box::new(schema::SchemaDecoder::load(
+            path,
+            version.as_deref(),
+        )?));
+    }
+    tables::register(&mut decoders)?;
+    let tsc_hz = match (args.tsc_freq, args.file.first()) {
+        (Some(TscFrequency::Hz(hz)), _) => Some(hz),
+        (Some(TscFrequency::Auto), Some(file)) => Some(
+            tsc::detect(open_input(file, args.format)?)?
+                .ok_or("No TSC calibration message found to derive --tsc-freq from")?,
+        ),
+        _ => None,
+    };
+    if let Some(hz) = tsc_hz {
+        decoders.register(Box::new(tsc::TscDecoder::new(
+            hz,
+            args.tsc_offset,
+            boot_time,
+        )));
+    }
+    let theme = match !args.raw && theme::use_color(args.color, args.output.is_none()) {
+        true => Some(Theme::select(&args.theme)?),
+        false => None,
     };
+    let rules = match &args.rules {
+        Some(path) => Some(Arc::new(RuleSet::load(path)?)),
+        None => None,
+    };
+    if let Some(rules) = &rules {
+        decoders.register(Box::new(RulesDecoder::new(rules.clone())));
+    }
+    #[cfg(feature = "wasm-plugins")]
+    if let Some(dir) = &args.plugins_dir {
+        for plugin in plugin::load_plugins(dir)? {
+            decoders.register(Box::new(plugin));
+        }
+    }
+    for name in &args.disable_decoder {
+        decoders.disable(name)?;
+    }
 
-    // Start with the timestamp, level, target, and message
-    output = format!("[{}][{}][{}] {}", timestamp, level, target, message);
+    let scrubber = if args.scrub || args.scrub_rules.is_some() || args.pseudonym_map.is_some() {
+        let mut scrubber = Scrubber::new()?;
+        if let Some(path) = &args.pseudonym_map {
+            scrubber.use_pseudonyms(PseudonymMap::open(path)?);
+        }
+        if let Some(path) = &args.scrub_rules {
+            scrubber.load(path)?;
+        }
+        Some(scrubber)
+    } else {
+        None
+    };
+
+    Ok(FormatOptions {
+        decoders,
+        scrubber,
+        flags,
+        field_map: match &args.field_map {
+            Some(path) => Some(FieldMap::load(path)?),
+            None => None,
+        },
+        timestamp_format: args.timestamp_format.clone(),
+        boot_time,
+        theme,
+        links: args.links,
+        vp_links: args.vp_links,
+        group_by: args.group_by.clone(),
+        raw: args.raw,
+        signed_hex: args.signed_hex,
+        rules,
+        filter: RecordFilter {
+            since: args.since.clone(),
+            until: args.until.clone(),
+            level: args.level,
+            text: search.map(str::to_lowercase),
+            trace_id: args.trace_id.clone(),
+        },
+        json_path: args.json_path.clone(),
+        ansi: args.strip_ansi.then(AnsiStripper::new),
+        truncate: args.truncate,
+        wrapper: args.wrap.map(|columns| Wrapper::new(columns.into())),
+        header_names: HeaderNames::new(&args.field_alias),
+        explain: args.explain.is_some(),
+        validate: args.validate,
+        #[cfg(feature = "simd-json")]
+        parser: args.parser,
+    })
+}
+
+/// Format each input file to its own output, several files at a time
+fn run_split(args: &RunArgs, search: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let workers = std::thread::available_parallelism()
+        .map_or(1, |count| count.get())
+        .min(args.file.len());
+    let files = std::sync::Mutex::new(args.file.iter());
+
+    let errors: Vec<String> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut errors = Vec::new();
+                    while let Some(file) = files.lock().unwrap().next() {
+                        let mut output = file.as_os_str().to_owned();
+                        output.push(".decoded.txt");
+                        let file_args = RunArgs {
+                            file: vec![file.clone()],
+                            output: Some(output.into()),
+                            split_output: false,
+                            ..args.clone()
+                        };
+                        if let Err(err) = run_file(&file_args, search) {
+                            errors.push(format!("{}: {}", file.display(), err));
+                        }
+                    }
+                    errors
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    });
+
+    // Every file is attempted, so one bad file doesn't cost the others
+    match errors.as_slice() {
+        [] => Ok(()),
+        [error] => Err(error.clone().into()),
+        _ => Err(format!("{} files failed:\n{}", errors.len(), errors.join("\n")).into()),
+    }
+}
+
+/// Bytes of a mapped input read by a thread at a time
+const SLICE_BYTES: u64 = 4 << 20;
 
-    // Add remaining fields
-    for (key, value) in obj {
-        if key == "message" {
-            continue;
+/// Map every input to be read in slices on the formatting threads, or
+/// return None when they must be read in order on one thread
+fn map_inputs(
+    files: &[PathBuf],
+    args: &RunArgs,
+    options: &FormatOptions,
+) -> Result<Option<Vec<input::MappedCsv>>, Box<dyn Error>> {
+    // Records rewritten by the external decoder, compared with earlier ones
+    // or counted from the start of the input are read in order
+    if args.jobs <= 1
+        || args.no_mmap
+        || args.format != InputFormat::Csv
+        || args.resume
+        || args.byte_range.is_some()
+        || args.exec_decoder.is_some()
+        || args.dedupe
+        || selects_rows(args)
+    {
+        return Ok(None);
+    }
+
+    let mut mapped = Vec::new();
+    for file in files {
+        // Filtered runs read an indexed export through its index instead
+        if options.filter.is_active() && index::index_path(file).exists() {
+            return Ok(None);
+        }
+        match input::MappedCsv::open(file, args.strict)? {
+            Some(csv) => mapped.push(csv),
+            None => return Ok(None),
         }
+    }
+    Ok(Some(mapped))
+}
 
-        // Special case: tdx_tdg_vp_enter_exit_info
-        if key == "raw_exit" && value.is_string() {
-            if let Some(raw_exit_str) = value.as_str() {
-                if raw_exit_str.contains("tdx_tdg_vp_enter_exit_info") {
-                    let transformed = transform_tdx_exit_info(raw_exit_str);
-                    output.push_str(&format!(" {}=\"{}\"", key, transformed));
-                    continue;
-                }
+/// Prepares each record read for formatting, rewriting it through the
+/// external decoder and dropping the duplicates under --dedupe
+struct Preparer {
+    exec_decoder: Option<ExecDecoder>,
+    dedupe: Option<Dedupe>,
+    /// The last record rewritten by the external decoder
+    decoded: String,
+}
+
+impl Preparer {
+    /// The record to format, or None when it is dropped
+    fn prepare<'a>(
+        &'a mut self,
+        mut record: RecordRef<'a>,
+    ) -> Result<Option<RecordRef<'a>>, Box<dyn Error>> {
+        // Only tracing JSON records are handed to the external decoder
+        if let Some(exec_decoder) = &mut self.exec_decoder {
+            if record.parsed.is_some() || record.message.trim_start().starts_with('{') {
+                self.decoded = exec_decoder.transform(&record.text())?;
+                record.message = &self.decoded;
+                record.parsed = None;
             }
         }
-        // Special case: TdxL2EnterGuestState
-        else if key == "gprs" && value.is_string() {
-            if let Some(gprs_str) = value.as_str() {
-                if gprs_str.contains("TdxL2EnterGuestState") {
-                    let transformed = transform_tdx_guest_state(gprs_str);
-                    output.push_str(&format!(" {}=\"{}\"", key, transformed));
-                    continue;
-                }
+        if let Some(dedupe) = &mut self.dedupe {
+            if dedupe.is_duplicate(&record.text()) {
+                return Ok(None);
             }
         }
-        // Special case: SegmentRegister
-        else if value.is_string() && value.as_str().unwrap().contains("SegmentRegister") {
-            if let Some(str_val) = value.as_str() {
-                let transformed = transform_segment_register(str_val);
-                output.push_str(&format!(" {}=\"{}\"", key, transformed));
-                continue;
-            }
+        Ok(Some(record))
+    }
+}
+
+/// Format the records of a file, keeping only those containing `search`
+/// in their message or target when it is given
+fn run_file(args: &RunArgs, search: Option<&str>) -> Result<(), Box<dyn Error>> {
+    if args.split_output {
+        return run_split(args, search);
+    }
+    interrupt::install();
+    let files = &args.file;
+    let [file, ..] = files.as_slice() else {
+        return Err("No input file given".into());
+    };
+    if files.len() > 1 && (args.resume || args.follow || args.byte_range.is_some()) {
+        return Err("--resume, --follow and --byte-range need a single input file".into());
+    }
+    if let Some(expected) = &args.sha256 {
+        for file in files {
+            checksum::verify(file, expected)?;
         }
+    }
+    let options = format_options(args, search)?;
 
-        // Format regular values
-        output.push_str(&format_value_as_hex(key, value));
+    if args.emit_kql {
+        print!("{}", kql::where_clauses(&options.filter));
+        return Ok(());
     }
 
-    output
-}
+    let exec_decoder = match &args.exec_decoder {
+        Some(command) => Some(ExecDecoder::spawn(command)?),
+        None => None,
+    };
 
-fn main() -> Result<(), Box<dyn Error>> {
-    // Parse command line arguments
-    let args = Args::parse();
+    if args.resume && args.format != InputFormat::Csv {
+        return Err("--resume is only supported for CSV input".into());
+    }
+    if args.byte_range.is_some() && (args.format != InputFormat::Csv || args.follow) {
+        return Err("--byte-range is only supported for CSV input that isn't followed".into());
+    }
 
-    // Open the CSV file
-    let file = File::open(&args.file)?;
+    let (sink, resume_offset): (Box<dyn Sink>, Option<u64>) = match &args.output {
+        Some(path) if path == Path::new("json") => {
+            if args.resume {
+                return Err("--resume is only supported for output files".into());
+            }
+            (Box::new(JsonSink::new()), None)
+        }
+        Some(path) if path == Path::new("syslog") => {
+            if args.resume {
+                return Err("--resume is only supported for output files".into());
+            }
+            let sink = if args.dry_run {
+                SyslogSink::dry_run(&args.syslog_address)
+            } else {
+                SyslogSink::connect(&args.syslog_address)?
+            };
+            (Box::new(sink), None)
+        }
+        Some(path) if service_name(path).is_some() => {
+            if args.resume {
+                return Err("--resume is only supported for output files".into());
+            }
+            (service_sink(path, args)?, None)
+        }
+        Some(path) if args.resume => {
+            let (writer, offset) = ProgressWriter::resume(path)?;
+            (Box::new(writer), Some(offset))
+        }
+        Some(path) => match &args.group_by {
+            Some(field) => (Box::new(GroupSink::files(field, path)), None),
+            None => (Box::new(ProgressWriter::create(path)?), None),
+        },
+        None => (Box::new(StdoutSink::new()), None),
+    };
+    // Groups not written to files of their own are sections of the output
+    let grouped_to_files = args.output.as_deref().is_some_and(is_output_file);
+    let mut sink = match &args.group_by {
+        Some(field) if !grouped_to_files => Box::new(GroupSink::sections(field, sink)),
+        _ => sink,
+    };
+
+    // Open the input and process each record
+    let input_options = InputOptions {
+        format: args.format,
+        strict: args.strict,
+        follow: args.follow,
+        mmap: !args.no_mmap,
+    };
+    let mapped = map_inputs(files, args, &options)?;
+    let mut records = match (resume_offset, &args.byte_range) {
+        // Mapped inputs are read on the formatting threads instead
+        _ if mapped.is_some() => Box::new(input::Chained::new(Vec::new())),
+        (Some(offset), _) => input::open_csv_from(file, offset, args.strict)?,
+        (None, Some(range)) => input::open_csv_byte_range(file, range.clone(), args.strict)?,
+        (None, None) => {
+            let mut inputs = Vec::new();
+            for file in files {
+                // The index holds timestamps as written, which can't be
+                // compared with the filter once they are converted, read
+                // from the top-level header of the records. Rows are
+                // counted from the start of the input, before any block is
+                // skipped
+                let indexed = match (&options.timestamp_format, options.boot_time) {
+                    (None, None) if !rewrites_header(args) && !selects_rows(args) => {
+                        index::open_filtered(file, &input_options, &options.filter)?
+                    }
+                    _ => None,
+                };
+                inputs.push(match indexed {
+                    Some(records) => records,
+                    None => input::open_lending(file, &input_options)?,
+                });
+            }
+            Box::new(input::Chained::new(inputs))
+        }
+    };
+
+    let start = Instant::now();
+    let mut rows = 0u64;
+    let mut written = 0u64;
+    let mut selection = Selection::new(args.head, args.tail, args.sample, args.reverse);
+    let mut preparer = Preparer {
+        exec_decoder,
+        dedupe: args.dedupe.then(Dedupe::new),
+        decoded: String::new(),
+    };
+    // Set once --head records were written, to stop reading the input
+    let done = Cell::new(false);
+
+    let mut skip = args.skip_rows;
+    let mut remaining = args.max_rows.unwrap_or(usize::MAX);
+    let mut read_next = |records: &mut dyn LendRecords| -> Result<bool, Box<dyn Error>> {
+        loop {
+            if remaining == 0 || done.get() || interrupt::is_interrupted() {
+                return Ok(false);
+            }
+            // Rows skipped are not checked, so a malformed one is skipped too
+            let advanced = records.advance();
+            if skip > 0 && !matches!(advanced, Ok(false)) {
+                skip -= 1;
+                continue;
+            }
+            if !advanced? {
+                return Ok(false);
+            }
+            remaining -= 1;
+            rows += 1;
 
-    // Create a CSV reader with more flexible parsing options
-    let mut rdr = ReaderBuilder::new()
-        .flexible(true)
-        .double_quote(true)
-        .from_reader(file);
+            if args.follow {
+                if let Some(rules) = &options.rules {
+                    rules.reload_if_changed();
+                }
+            }
+            return Ok(true);
+        }
+    };
 
-    // Skip the header row
-    let headers = rdr.headers()?.clone();
+    #[cfg(feature = "http-sinks")]
+    let mut notifier = match (&args.notify_webhook, &args.notify_on) {
+        (Some(url), Some(pattern)) => Some(notify::Notifier::new(url, pattern, args.dry_run)?),
+        _ => None,
+    };
 
-    // Find the index of the ExtractedMessage column
-    let message_idx = headers
-        .iter()
-        .position(|h| h == "ExtractedMessage")
-        .ok_or("No 'ExtractedMessage' column found in CSV")?;
+    let mut measure = args
+        .measure
+        .clone()
+        .map(|spec| Measure::new(spec, args.measure_by.as_deref()));
 
-    // Process each record
-    for result in rdr.records() {
-        let record = result?;
+    let mut vp_links = args.vp_links.then(VpLinks::default);
+    // Records shown, to find the one picked by --explain
+    let mut shown = 0u64;
 
-        if let Some(message_field) = record.get(message_idx) {
-            let output = process_message(message_field);
+    let mut write = |output: &str, info: &RecordInfo| -> Result<(), Box<dyn Error>> {
+        if let Some(measure) = &mut measure {
             if !output.is_empty() {
-                println!("{}", output);
+                measure.record(output, info);
+            }
+        }
+        let explained;
+        let output = match args.explain {
+            Some(record) if !output.is_empty() => {
+                shown += 1;
+                if record.is_none_or(|record| record == shown) {
+                    explained = format!("{}{}", output, info.explanation);
+                    explained.as_str()
+                } else {
+                    output
+                }
+            }
+            _ => output,
+        };
+        if output.is_empty() || args.bench || !selection.select(output, info) {
+            return sink.skip_record(info.end_offset);
+        }
+
+        match &mut vp_links {
+            Some(vp_links) => vp_links.hold(output, info),
+            None => sink.write_record(output, info)?,
+        }
+        written += 1;
+        done.set(selection.is_done());
+
+        #[cfg(feature = "http-sinks")]
+        if let Some(notifier) = &mut notifier {
+            notifier.record(output);
+        }
+
+        // Followed input arrives slowly, so show each record as it comes
+        if args.follow {
+            sink.flush()?;
+        }
+        Ok(())
+    };
+
+    let pipeline_options = PipelineOptions {
+        jobs: args.jobs,
+        batch_size: args.batch_size,
+        max_in_flight: args.max_in_flight.unwrap_or(args.jobs * 4),
+    };
+    let result = if let Some(mapped) = &mapped {
+        // Each thread reads a slice of rows and formats it, and the slices
+        // are written back in order
+        let slices = mapped.iter().flat_map(|csv| {
+            let slices = csv.slices(SLICE_BYTES);
+            slices.into_iter().map(move |slice| Ok((csv, slice)))
+        });
+        let slices = slices.take_while(|_| !done.get() && !interrupt::is_interrupted());
+        pipeline::run(
+            slices,
+            &PipelineOptions {
+                batch_size: 1,
+                ..pipeline_options
+            },
+            |(csv, slice)| {
+                let mut records = csv.read(slice);
+                let mut formatted = Vec::new();
+                let mut read_error = None;
+                while !interrupt::is_interrupted() {
+                    match records.advance() {
+                        Ok(true) => {}
+                        Ok(false) => break,
+                        Err(err) => {
+                            read_error = Some(err.to_string());
+                            break;
+                        }
+                    }
+                    let mut output = String::new();
+                    let mut info = RecordInfo::default();
+                    format_record(records.get(), &options, &mut output, &mut info);
+                    formatted.push((output, info));
+                }
+                (formatted, read_error)
+            },
+            |(formatted, read_error)| {
+                for (output, info) in formatted {
+                    rows += 1;
+                    write(&output, &info)?;
+                }
+                match read_error {
+                    Some(err) => Err(err.into()),
+                    None => Ok(()),
+                }
+            },
+        )
+    } else if args.jobs > 1 {
+        // Records are handed to other threads, so each owns its message
+        let records = std::iter::from_fn(|| loop {
+            match read_next(&mut *records) {
+                Ok(true) => {}
+                Ok(false) => return None,
+                Err(err) => return Some(Err(err)),
             }
+            match preparer.prepare(records.get()) {
+                Ok(Some(record)) => return Some(Ok(record.to_record())),
+                Ok(None) => {}
+                Err(err) => return Some(Err(err)),
+            }
+        });
+        pipeline::run(
+            records,
+            &pipeline_options,
+            |record: Record| {
+                let mut output = String::new();
+                let mut info = RecordInfo::default();
+                format_record(record.borrowed(), &options, &mut output, &mut info);
+                (output, info)
+            },
+            |(output, info)| write(&output, &info),
+        )
+    } else {
+        // Each record is formatted while borrowed from the reader
+        let mut output = String::new();
+        let mut info = RecordInfo::default();
+        loop {
+            match read_next(&mut *records) {
+                Ok(true) => {}
+                Ok(false) => break Ok(()),
+                Err(err) => break Err(err),
+            }
+            let result = match preparer.prepare(records.get()) {
+                Ok(Some(record)) => {
+                    format_record(record, &options, &mut output, &mut info);
+                    write(&output, &info)
+                }
+                Ok(None) => Ok(()),
+                Err(err) => Err(err),
+            };
+            if result.is_err() {
+                break result;
+            }
+        }
+    };
+
+    // Pseudonyms already written out must be kept even if the run failed
+    if let Some(scrubber) = &options.scrubber {
+        scrubber.save_pseudonyms()?;
+    }
+    result?;
+    for (output, info) in selection.take_held() {
+        match &mut vp_links {
+            Some(vp_links) => vp_links.hold(&output, &info),
+            None => sink.write_record(&output, &info)?,
+        }
+        written += 1;
+    }
+    for (output, info) in vp_links.map(VpLinks::finish).unwrap_or_default() {
+        sink.write_record(&output, &info)?;
+    }
+    if interrupt::is_interrupted() {
+        sink.interrupt()?;
+    } else {
+        sink.finish()?;
+    }
+
+    if let Some(flags) = &options.flags {
+        flags.report();
+    }
+    if let Some(dedupe) = &preparer.dedupe {
+        dedupe.report();
+    }
+    if let Some(measure) = &measure {
+        measure.report();
+    }
+    if let Some(path) = &args.manifest {
+        write_manifest(path, files, args, &options, rows, written)?;
+    }
+    if interrupt::is_interrupted() {
+        eprintln!(
+            "Interrupted after reading {} rows, {} records written",
+            rows, written
+        );
+    }
+    if args.bench {
+        let mut bytes = 0;
+        for file in files {
+            bytes += std::fs::metadata(file)?.len();
         }
+        report_throughput(rows, bytes, start);
     }
 
     Ok(())
@@ -0,0 +1,114 @@
+//! Machine-readable record of a run, so an analysis can be audited and
+//! reproduced later
+
+use serde::Serialize;
+use std::error::Error;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::checksum::sha256_file;
+use crate::decoder::DecoderRegistry;
+use crate::filter::RecordFilter;
+
+/// A file read by the run
+#[derive(Serialize)]
+pub struct InputFile {
+    /// What the file was used for, such as `input` or `rules`
+    pub role: String,
+    pub path: String,
+    pub bytes: u64,
+    pub sha256: String,
+}
+
+impl InputFile {
+    /// Hash a file read by the run
+    pub fn hash(role: &str, path: &Path) -> Result<Self, Box<dyn Error>> {
+        let (bytes, sha256) = sha256_file(path)?;
+        Ok(InputFile {
+            role: role.to_string(),
+            path: path.display().to_string(),
+            bytes,
+            sha256,
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct DecoderVersion {
+    name: String,
+    version: String,
+}
+
+#[derive(Serialize)]
+struct Filters {
+    since: Option<String>,
+    until: Option<String>,
+    level: Option<String>,
+    text: Option<String>,
+    trace_id: Option<String>,
+}
+
+/// Counts of the records of the run
+#[derive(Serialize, Default)]
+pub struct RowCounts {
+    /// Records read from the input
+    pub read: u64,
+    /// Records written to the output
+    pub written: u64,
+    /// Records filtered out or with nothing to show
+    pub skipped: u64,
+}
+
+/// Everything needed to reproduce a run
+#[derive(Serialize)]
+pub struct Manifest {
+    tool: String,
+    version: String,
+    command_line: Vec<String>,
+    /// Start of the run in seconds since the epoch
+    started: u64,
+    inputs: Vec<InputFile>,
+    decoders: Vec<DecoderVersion>,
+    filters: Filters,
+    pub rows: RowCounts,
+}
+
+impl Manifest {
+    /// Describe a run about to read `inputs` with the given decoders and
+    /// filters
+    pub fn new(inputs: Vec<InputFile>, decoders: &DecoderRegistry, filter: &RecordFilter) -> Self {
+        Manifest {
+            tool: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            command_line: std::env::args().collect(),
+            started: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_secs()),
+            inputs,
+            decoders: decoders
+                .iter()
+                .map(|decoder| DecoderVersion {
+                    name: decoder.name().to_string(),
+                    version: decoder.version(),
+                })
+                .collect(),
+            filters: Filters {
+                since: filter.since.clone(),
+                until: filter.until.clone(),
+                level: filter
+                    .level
+                    .map(|level| format!("{:?}", level).to_lowercase()),
+                text: filter.text.clone(),
+                trace_id: filter.trace_id.clone(),
+            },
+            rows: RowCounts::default(),
+        }
+    }
+
+    /// Write the manifest as JSON
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+            .map_err(|err| format!("Failed to write {}: {}", path.display(), err))?;
+        Ok(())
+    }
+}
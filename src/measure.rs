@@ -0,0 +1,141 @@
+//! Durations between pairs of matching records, for latency questions
+//! answered from a log
+//!
+//! `--measure 'START..END'` pairs each record matching START with the next
+//! record matching END, optionally only with one holding the same value of
+//! a field given by `--measure-by`, like `vp_index`. Patterns are matched
+//! against the records as they are shown.
+
+use regex::Regex;
+use std::collections::HashMap;
+
+use crate::sink::RecordInfo;
+use crate::time::parse_timestamp;
+use crate::units::format_duration;
+
+/// Patterns of the records starting and ending a measured span
+#[derive(Clone, Debug)]
+pub struct MeasureSpec {
+    start: Regex,
+    end: Regex,
+}
+
+/// Parse `START..END`, split at the first `..`, used as a clap value parser
+pub fn parse_measure(text: &str) -> Result<MeasureSpec, String> {
+    let (start, end) = text
+        .split_once("..")
+        .filter(|(start, end)| !start.is_empty() && !end.is_empty())
+        .ok_or_else(|| format!("Invalid measure '{}', expected START..END", text))?;
+    let compile = |pattern: &str| {
+        Regex::new(pattern).map_err(|err| format!("Invalid measure pattern '{}': {}", pattern, err))
+    };
+    Ok(MeasureSpec {
+        start: compile(start)?,
+        end: compile(end)?,
+    })
+}
+
+/// A measured span
+struct Pair {
+    /// Timestamp of the start record
+    start: String,
+    key: Option<String>,
+    nanoseconds: i64,
+}
+
+/// Pairs start and end records and collects the time between them
+pub struct Measure {
+    spec: MeasureSpec,
+    /// The `--measure-by` field and the pattern finding its value in a
+    /// record
+    key: Option<(String, Regex)>,
+    /// Time and timestamp of the spans started, by key
+    open: HashMap<Option<String>, (i64, String)>,
+    pairs: Vec<Pair>,
+}
+
+impl Measure {
+    pub fn new(spec: MeasureSpec, key: Option<&str>) -> Self {
+        Measure {
+            spec,
+            // The field may be colored by the theme
+            key: key.map(|key| {
+                let pattern = format!(r"\s(?:\x1b\[[0-9;]*m)?{}=([^\s\x1b]+)", regex::escape(key));
+                (key.to_string(), Regex::new(&pattern).unwrap())
+            }),
+            open: HashMap::new(),
+            pairs: Vec::new(),
+        }
+    }
+
+    /// Look at a record as it is shown
+    pub fn record(&mut self, text: &str, info: &RecordInfo) {
+        let Some(time) = parse_timestamp(&info.timestamp) else {
+            return;
+        };
+        let key = match &self.key {
+            Some((field, pattern)) => match pattern.captures(text) {
+                Some(caps) => Some(format!("{}={}", field, &caps[1])),
+                // Records without the field can't be paired
+                None => return,
+            },
+            None => None,
+        };
+
+        // A record matching both ends one span and starts the next
+        if self.spec.end.is_match(text) {
+            if let Some((start, timestamp)) = self.open.remove(&key) {
+                self.pairs.push(Pair {
+                    start: timestamp,
+                    key: key.clone(),
+                    nanoseconds: time - start,
+                });
+            }
+        }
+        if self.spec.start.is_match(text) {
+            self.open.insert(key, (time, info.timestamp.clone()));
+        }
+    }
+
+    /// Write each span and a summary of their durations to stderr
+    pub fn report(&self) {
+        for pair in &self.pairs {
+            match &pair.key {
+                Some(key) => eprintln!(
+                    "{}  {}  {}",
+                    pair.start,
+                    key,
+                    format_duration(pair.nanoseconds as f64)
+                ),
+                None => eprintln!(
+                    "{}  {}",
+                    pair.start,
+                    format_duration(pair.nanoseconds as f64)
+                ),
+            }
+        }
+
+        let mut durations: Vec<i64> = self.pairs.iter().map(|pair| pair.nanoseconds).collect();
+        durations.sort_unstable();
+        let unmatched = match self.open.len() {
+            0 => String::new(),
+            count => format!(", {} started but not ended", count),
+        };
+        let Some((&min, &max)) = durations.first().zip(durations.last()) else {
+            eprintln!("Measured 0 spans{}", unmatched);
+            return;
+        };
+        let percentile = |p: usize| durations[((durations.len() - 1) * p + 50) / 100] as f64;
+        let mean = durations.iter().map(|&d| d as f64).sum::<f64>() / durations.len() as f64;
+        eprintln!(
+            "Measured {} spans{}: min {}, median {}, mean {}, p95 {}, max {}",
+            durations.len(),
+            unmatched,
+            format_duration(min as f64),
+            format_duration(percentile(50)),
+            format_duration(mean),
+            format_duration(percentile(95)),
+            format_duration(max as f64)
+        );
+    }
+}
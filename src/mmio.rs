@@ -0,0 +1,181 @@
+//! Heatmap of emulated MMIO and port IO accesses by address
+
+use std::collections::BTreeMap;
+use std::error::Error;
+
+#[cfg(feature = "arrow")]
+use crate::columnar;
+use crate::input::Records;
+use crate::report::{field_u64, for_each_event, is_write, print_table, Event};
+
+/// Fields holding the guest physical address of an MMIO access
+const ADDRESS_KEYS: &[&str] = &["gpa", "address", "addr"];
+
+/// Fields holding the port of a port IO access
+const PORT_KEYS: &[&str] = &["port", "io_port"];
+
+/// Width of the bar drawn for the busiest range
+const BAR_WIDTH: usize = 30;
+
+/// Kind of emulated access
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Space {
+    Mmio,
+    Pio,
+}
+
+/// Accesses to a range of addresses
+#[derive(Default)]
+struct Range {
+    /// One past the last address
+    end: u64,
+    reads: u64,
+    writes: u64,
+}
+
+/// The kind, first address and size of the range of an access, if the
+/// record is one
+fn access(event: &Event, bucket: u64) -> Option<(Space, u64, u64)> {
+    if let Some(port) = field_u64(event.fields, PORT_KEYS) {
+        return Some((Space::Pio, port, 1));
+    }
+    let address = field_u64(event.fields, ADDRESS_KEYS)?;
+    if !event.message.to_lowercase().contains("mmio") {
+        return None;
+    }
+    Some((Space::Mmio, address - address % bucket, bucket))
+}
+
+/// Count the accesses to each range
+#[cfg(not(feature = "arrow"))]
+fn collect(records: Records, bucket: u64) -> Result<BTreeMap<(Space, u64), Range>, Box<dyn Error>> {
+    let mut ranges: BTreeMap<(Space, u64), Range> = BTreeMap::new();
+
+    for_each_event(records, |event| {
+        let Some((space, start, size)) = access(event, bucket) else {
+            return;
+        };
+
+        let range = ranges.entry((space, start)).or_default();
+        range.end = start.saturating_add(size);
+        if is_write(event) {
+            range.writes += 1;
+        } else {
+            range.reads += 1;
+        }
+    })?;
+    Ok(ranges)
+}
+
+/// Count the accesses to each range from columns of the accesses
+#[cfg(feature = "arrow")]
+fn collect(records: Records, bucket: u64) -> Result<BTreeMap<(Space, u64), Range>, Box<dyn Error>> {
+    use arrow_arith::aggregate;
+    use arrow_array::builder::{BooleanBuilder, UInt64Builder, UInt8Builder};
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{UInt64Type, UInt8Type};
+    use arrow_array::ArrayRef;
+    use std::sync::Arc;
+
+    let mut spaces = UInt8Builder::new();
+    let mut starts = UInt64Builder::new();
+    let mut ends = UInt64Builder::new();
+    let mut writes = BooleanBuilder::new();
+    for_each_event(records, |event| {
+        let Some((space, start, size)) = access(event, bucket) else {
+            return;
+        };
+        spaces.append_value(space as u8);
+        starts.append_value(start);
+        ends.append_value(start.saturating_add(size));
+        writes.append_value(is_write(event));
+    })?;
+    let spaces: ArrayRef = Arc::new(spaces.finish());
+    let starts: ArrayRef = Arc::new(starts.finish());
+    let ends = ends.finish();
+    let writes = writes.finish();
+
+    let mut ranges = BTreeMap::new();
+    let groups = columnar::group_by(&[spaces.clone(), starts.clone()], &[])?;
+    for group in &groups.ranges {
+        let row = groups.first(group);
+        let space = match spaces.as_primitive::<UInt8Type>().value(row) {
+            0 => Space::Mmio,
+            _ => Space::Pio,
+        };
+        let start = starts.as_primitive::<UInt64Type>().value(row);
+        let written = groups.take(&writes, group)?.as_boolean().true_count() as u64;
+        let end = groups.take(&ends, group)?;
+        ranges.insert(
+            (space, start),
+            Range {
+                end: aggregate::max(end.as_primitive::<UInt64Type>()).unwrap_or(start),
+                reads: group.len() as u64 - written,
+                writes: written,
+            },
+        );
+    }
+    Ok(ranges)
+}
+
+/// Print a table of accessed address ranges with their read and write
+/// counts
+///
+/// MMIO addresses are grouped into `bucket` byte aligned ranges and ports
+/// are counted individually. With `merge`, ranges that touch are combined
+/// into regions.
+pub fn mmio(records: Records, bucket: u64, merge: bool) -> Result<(), Box<dyn Error>> {
+    let mut ranges = collect(records, bucket.max(1))?;
+
+    if merge {
+        let mut merged: BTreeMap<(Space, u64), Range> = BTreeMap::new();
+        for ((space, start), range) in ranges {
+            // Ranges come in address order, so only the last region can
+            // touch the next range
+            if let Some(mut last) = merged.last_entry() {
+                let (last_space, _) = *last.key();
+                let region = last.get_mut();
+                if last_space == space && region.end >= start {
+                    region.end = region.end.max(range.end);
+                    region.reads += range.reads;
+                    region.writes += range.writes;
+                    continue;
+                }
+            }
+            merged.insert((space, start), range);
+        }
+        ranges = merged;
+    }
+
+    let busiest = ranges
+        .values()
+        .map(|range| range.reads + range.writes)
+        .max()
+        .unwrap_or(1);
+
+    let rows: Vec<Vec<String>> = ranges
+        .iter()
+        .map(|((space, start), range)| {
+            let total = range.reads + range.writes;
+            let bar = (total as usize * BAR_WIDTH).div_ceil(busiest as usize);
+            vec![
+                match space {
+                    Space::Mmio => "mmio".to_string(),
+                    Space::Pio => "pio".to_string(),
+                },
+                if range.end - start > 1 {
+                    format!("{:#x}-{:#x}", start, range.end - 1)
+                } else {
+                    format!("{:#x}", start)
+                },
+                total.to_string(),
+                range.reads.to_string(),
+                range.writes.to_string(),
+                "#".repeat(bar),
+            ]
+        })
+        .collect();
+
+    print_table(&["KIND", "RANGE", "ACCESSES", "READS", "WRITES", ""], &rows);
+    Ok(())
+}
@@ -0,0 +1,256 @@
+//! Audit of guest MSR accesses intercepted by the paravisor
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+
+#[cfg(feature = "arrow")]
+use crate::columnar;
+use crate::input::Records;
+use crate::report::{field_u64, for_each_event, is_write, print_table};
+use crate::tables;
+
+/// Fields holding the MSR index of an access
+pub const MSR_KEYS: &[&str] = &["msr", "msr_index"];
+
+/// Fields holding the value read or written
+const VALUE_KEYS: &[&str] = &["value", "data", "msr_value"];
+
+/// Number of distinct written values listed in the report
+const LISTED_VALUES: usize = 3;
+
+/// Architectural and Hyper-V synthetic MSRs
+const MSR_NAMES: &[(u64, &str)] = &[
+    (0x10, "IA32_TIME_STAMP_COUNTER"),
+    (0x1b, "IA32_APIC_BASE"),
+    (0x3a, "IA32_FEATURE_CONTROL"),
+    (0x48, "IA32_SPEC_CTRL"),
+    (0x49, "IA32_PRED_CMD"),
+    (0x8b, "IA32_BIOS_SIGN_ID"),
+    (0xe7, "IA32_MPERF"),
+    (0xe8, "IA32_APERF"),
+    (0xfe, "IA32_MTRRCAP"),
+    (0x10a, "IA32_ARCH_CAPABILITIES"),
+    (0x174, "IA32_SYSENTER_CS"),
+    (0x175, "IA32_SYSENTER_ESP"),
+    (0x176, "IA32_SYSENTER_EIP"),
+    (0x179, "IA32_MCG_CAP"),
+    (0x17a, "IA32_MCG_STATUS"),
+    (0x1a0, "IA32_MISC_ENABLE"),
+    (0x1d9, "IA32_DEBUGCTL"),
+    (0x277, "IA32_PAT"),
+    (0x2ff, "IA32_MTRR_DEF_TYPE"),
+    (0x6e0, "IA32_TSC_DEADLINE"),
+    (0xda0, "IA32_XSS"),
+    (0x40000000, "HV_X64_MSR_GUEST_OS_ID"),
+    (0x40000001, "HV_X64_MSR_HYPERCALL"),
+    (0x40000002, "HV_X64_MSR_VP_INDEX"),
+    (0x40000003, "HV_X64_MSR_RESET"),
+    (0x40000010, "HV_X64_MSR_VP_RUNTIME"),
+    (0x40000020, "HV_X64_MSR_TIME_REF_COUNT"),
+    (0x40000021, "HV_X64_MSR_REFERENCE_TSC"),
+    (0x40000022, "HV_X64_MSR_TSC_FREQUENCY"),
+    (0x40000023, "HV_X64_MSR_APIC_FREQUENCY"),
+    (0x40000070, "HV_X64_MSR_EOI"),
+    (0x40000071, "HV_X64_MSR_ICR"),
+    (0x40000072, "HV_X64_MSR_TPR"),
+    (0x40000073, "HV_X64_MSR_VP_ASSIST_PAGE"),
+    (0x40000080, "HV_X64_MSR_SCONTROL"),
+    (0x40000081, "HV_X64_MSR_SVERSION"),
+    (0x40000082, "HV_X64_MSR_SIEFP"),
+    (0x40000083, "HV_X64_MSR_SIMP"),
+    (0x40000084, "HV_X64_MSR_EOM"),
+    (0x40000105, "HV_X64_MSR_CRASH_CTL"),
+    (0xc0000080, "IA32_EFER"),
+    (0xc0000081, "IA32_STAR"),
+    (0xc0000082, "IA32_LSTAR"),
+    (0xc0000083, "IA32_CSTAR"),
+    (0xc0000084, "IA32_FMASK"),
+    (0xc0000100, "IA32_FS_BASE"),
+    (0xc0000101, "IA32_GS_BASE"),
+    (0xc0000102, "IA32_KERNEL_GS_BASE"),
+    (0xc0000103, "IA32_TSC_AUX"),
+    (0xc0010114, "AMD_VM_CR"),
+    (0xc0010117, "AMD_VM_HSAVE_PA"),
+    (0xc0010130, "AMD_GHCB"),
+    (0xc0010131, "AMD_SEV_STATUS"),
+];
+
+/// Name of an MSR, including those in numbered ranges
+fn msr_name(msr: u64) -> Option<String> {
+    if let Some((_, name)) = MSR_NAMES.iter().find(|(index, _)| *index == msr) {
+        return Some(name.to_string());
+    }
+
+    match msr {
+        0x200..=0x20f if msr.is_multiple_of(2) => {
+            Some(format!("IA32_MTRR_PHYSBASE{}", (msr - 0x200) / 2))
+        }
+        0x200..=0x20f => Some(format!("IA32_MTRR_PHYSMASK{}", (msr - 0x200) / 2)),
+        0x250 | 0x258 | 0x259 | 0x268..=0x26f => Some("IA32_MTRR_FIX".to_string()),
+        0x800..=0x8ff => Some(format!("X2APIC_{:#x}", msr - 0x800)),
+        0x40000090..=0x4000009f => Some(format!("HV_X64_MSR_SINT{}", msr - 0x40000090)),
+        0x400000b0..=0x400000b7 if msr.is_multiple_of(2) => Some(format!(
+            "HV_X64_MSR_STIMER{}_CONFIG",
+            (msr - 0x400000b0) / 2
+        )),
+        0x400000b0..=0x400000b7 => {
+            Some(format!("HV_X64_MSR_STIMER{}_COUNT", (msr - 0x400000b0) / 2))
+        }
+        0x40000100..=0x40000104 => Some(format!("HV_X64_MSR_CRASH_P{}", msr - 0x40000100)),
+        _ => None,
+    }
+}
+
+/// Accesses to one MSR
+#[derive(Default)]
+struct MsrStats {
+    reads: u64,
+    writes: u64,
+    written: BTreeSet<u64>,
+    first: String,
+    last: String,
+}
+
+/// Count the accesses to each MSR
+#[cfg(not(feature = "arrow"))]
+fn collect(records: Records) -> Result<BTreeMap<u64, MsrStats>, Box<dyn Error>> {
+    let mut msrs: BTreeMap<u64, MsrStats> = BTreeMap::new();
+
+    for_each_event(records, |event| {
+        let Some(msr) = field_u64(event.fields, MSR_KEYS) else {
+            return;
+        };
+
+        let stats = msrs.entry(msr).or_default();
+        if is_write(event) {
+            stats.writes += 1;
+            stats.written.extend(field_u64(event.fields, VALUE_KEYS));
+        } else {
+            stats.reads += 1;
+        }
+        if stats.first.is_empty() || event.timestamp < stats.first.as_str() {
+            stats.first = event.timestamp.to_string();
+        }
+        if event.timestamp > stats.last.as_str() {
+            stats.last = event.timestamp.to_string();
+        }
+    })?;
+    Ok(msrs)
+}
+
+/// Count the accesses to each MSR from columns of the accesses
+#[cfg(feature = "arrow")]
+fn collect(records: Records) -> Result<BTreeMap<u64, MsrStats>, Box<dyn Error>> {
+    use arrow_arith::aggregate;
+    use arrow_array::builder::{BooleanBuilder, StringBuilder, UInt64Builder};
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::UInt64Type;
+    use arrow_array::ArrayRef;
+    use std::sync::Arc;
+
+    let mut indexes = UInt64Builder::new();
+    let mut writes = BooleanBuilder::new();
+    let mut values = UInt64Builder::new();
+    let mut timestamps = StringBuilder::new();
+    for_each_event(records, |event| {
+        let Some(msr) = field_u64(event.fields, MSR_KEYS) else {
+            return;
+        };
+        let write = is_write(event);
+        indexes.append_value(msr);
+        writes.append_value(write);
+        values.append_option(field_u64(event.fields, VALUE_KEYS).filter(|_| write));
+        // Records without a time don't count as the first or last access
+        timestamps.append_option(Some(event.timestamp).filter(|time| !time.is_empty()));
+    })?;
+    let indexes: ArrayRef = Arc::new(indexes.finish());
+    let writes = writes.finish();
+    let values = values.finish();
+    let timestamps = timestamps.finish();
+
+    let mut msrs = BTreeMap::new();
+    let groups = columnar::group_by(std::slice::from_ref(&indexes), &[])?;
+    for group in &groups.ranges {
+        let msr = indexes
+            .as_primitive::<UInt64Type>()
+            .value(groups.first(group));
+        let written = groups.take(&writes, group)?.as_boolean().true_count() as u64;
+        let values = groups.take(&values, group)?;
+        let timestamps = groups.take(&timestamps, group)?;
+        let timestamps = timestamps.as_string::<i32>();
+        msrs.insert(
+            msr,
+            MsrStats {
+                reads: group.len() as u64 - written,
+                writes: written,
+                written: values
+                    .as_primitive::<UInt64Type>()
+                    .iter()
+                    .flatten()
+                    .collect(),
+                first: aggregate::min_string(timestamps)
+                    .unwrap_or_default()
+                    .to_string(),
+                last: aggregate::max_string(timestamps)
+                    .unwrap_or_default()
+                    .to_string(),
+            },
+        );
+    }
+    Ok(msrs)
+}
+
+/// Print a table of every MSR accessed, with its access counts, the
+/// distinct values written to it and the times of its first and last
+/// access
+pub fn msrs(records: Records) -> Result<(), Box<dyn Error>> {
+    let names = tables::load_numbers("msrs")?.unwrap_or_default();
+    let msrs = collect(records)?;
+
+    let rows: Vec<Vec<String>> = msrs
+        .into_iter()
+        .map(|(msr, stats)| {
+            let mut values: Vec<String> = stats
+                .written
+                .iter()
+                .take(LISTED_VALUES)
+                .map(|value| format!("{:#x}", value))
+                .collect();
+            if stats.written.len() > LISTED_VALUES {
+                values.push("...".to_string());
+            }
+
+            vec![
+                format!("{:#x}", msr),
+                names
+                    .get(&msr)
+                    .cloned()
+                    .or_else(|| msr_name(msr))
+                    .unwrap_or_default(),
+                stats.reads.to_string(),
+                stats.writes.to_string(),
+                if values.is_empty() {
+                    "0".to_string()
+                } else {
+                    format!("{} ({})", stats.written.len(), values.join(", "))
+                },
+                stats.first,
+                stats.last,
+            ]
+        })
+        .collect();
+
+    print_table(
+        &[
+            "MSR",
+            "NAME",
+            "READS",
+            "WRITES",
+            "DISTINCT WRITTEN",
+            "FIRST",
+            "LAST",
+        ],
+        &rows,
+    );
+    Ok(())
+}
@@ -0,0 +1,111 @@
+//! Notifications of critical records posted to a Slack or Teams incoming
+//! webhook, for watching a followed log during a repro
+//!
+//! Each matching record is posted with the records shown just before it.
+//! Matches arriving within a short time of a notification are counted and
+//! mentioned in the next one instead of being posted separately.
+//!
+//! With `--dry-run` notifications are printed instead of posted.
+
+use regex::{Regex, RegexBuilder};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+/// Records before a match included in its notification
+const CONTEXT_RECORDS: usize = 5;
+
+/// Shortest time between two notifications
+const COOLDOWN: Duration = Duration::from_secs(10);
+
+/// Longest snippet posted, within the message limits of both services
+const MAX_SNIPPET_CHARS: usize = 3000;
+
+/// Posts records matching a pattern to a webhook
+pub struct Notifier {
+    url: String,
+    pattern: Regex,
+    /// Records shown before the current one
+    recent: VecDeque<String>,
+    last_post: Option<Instant>,
+    /// Matches not posted since the last notification
+    suppressed: u64,
+    /// Print notifications instead of posting them
+    dry_run: bool,
+}
+
+impl Notifier {
+    /// Post records matching `pattern`, ignoring case, to the webhook at
+    /// `url`, or only print them for a dry run
+    pub fn new(url: &str, pattern: &str, dry_run: bool) -> Result<Self, Box<dyn Error>> {
+        let pattern = RegexBuilder::new(pattern)
+            .case_insensitive(true)
+            .build()
+            .map_err(|err| format!("Invalid --notify-on pattern '{}': {}", pattern, err))?;
+        Ok(Notifier {
+            url: url.to_string(),
+            pattern,
+            recent: VecDeque::with_capacity(CONTEXT_RECORDS),
+            last_post: None,
+            suppressed: 0,
+            dry_run,
+        })
+    }
+
+    /// Look at a formatted record, posting it if it matches
+    pub fn record(&mut self, text: &str) {
+        if self.pattern.is_match(text) {
+            if self.last_post.is_some_and(|last| last.elapsed() < COOLDOWN) {
+                self.suppressed += 1;
+            } else {
+                // A failed notification shouldn't stop the run it watches
+                if let Err(err) = self.post(text) {
+                    eprintln!("{}", err);
+                }
+                self.last_post = Some(Instant::now());
+                self.suppressed = 0;
+            }
+        }
+
+        if self.recent.len() == CONTEXT_RECORDS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(text.to_string());
+    }
+
+    /// Post a matching record with its context
+    fn post(&self, text: &str) -> Result<(), Box<dyn Error>> {
+        let mut snippet: String = self
+            .recent
+            .iter()
+            .map(String::as_str)
+            .chain([text])
+            .collect::<Vec<_>>()
+            .join("\n");
+        if snippet.chars().count() > MAX_SNIPPET_CHARS {
+            let skip = snippet.chars().count() - MAX_SNIPPET_CHARS;
+            snippet = snippet.chars().skip(skip).collect();
+        }
+
+        let mut message = format!("Matched `{}`:\n```\n{}\n```", self.pattern, snippet);
+        if self.suppressed > 0 {
+            message.push_str(&format!(
+                "\n{} more matches since the last notification",
+                self.suppressed
+            ));
+        }
+
+        if self.dry_run {
+            eprintln!("Would post to {}:\n{}", self.url, message);
+            return Ok(());
+        }
+
+        // Slack and Teams incoming webhooks both take a `text` field
+        let body = serde_json::json!({ "text": message }).to_string();
+        ureq::post(&self.url)
+            .set("Content-Type", "application/json")
+            .send_string(&body)
+            .map_err(|err| format!("Failed to post notification: {}", err))?;
+        Ok(())
+    }
+}
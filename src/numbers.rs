@@ -0,0 +1,93 @@
+//! Parsing of integers in dumps, tolerating digit group separators
+//!
+//! Tools between the logger and the export sometimes render numbers with
+//! thousands separators, like `1,234,567`, and Rust code may log them with
+//! underscores. Transforms match and parse integers with these helpers so
+//! such values are still converted.
+
+use serde_json::Value;
+
+/// Pattern of a decimal integer, with optional `,` or `_` separators
+/// between groups of three digits
+pub const DECIMAL: &str = r"\d+(?:[,_]\d{3})*";
+
+/// Parse a decimal integer, ignoring digit group separators
+pub fn parse_decimal(text: &str) -> Option<u64> {
+    let text = text.trim();
+    if text.contains([',', '_']) {
+        text.replace([',', '_'], "").parse().ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+/// Parse a decimal or `0x` hex integer, ignoring digit group separators
+pub fn parse_integer(text: &str) -> Option<u64> {
+    match text.trim().strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(&hex.replace('_', ""), 16).ok(),
+        None => parse_decimal(text),
+    }
+}
+
+/// Split the inside of a Debug formatted list into its items
+///
+/// Debug output separates items with `, `, so a comma without a space is
+/// taken as a digit group separator whenever the list has both.
+pub fn split_list(inner: &str) -> Vec<&str> {
+    let separator = if inner.contains(", ") { ", " } else { "," };
+    inner.split(separator).map(str::trim).collect()
+}
+
+/// Write a decimal integer as hex, including ones too large for 64 bits,
+/// or return it unchanged if it isn't one
+pub fn decimal_to_hex(text: &str) -> String {
+    let digits = text.trim().replace([',', '_'], "");
+    match digits.parse::<u128>() {
+        Ok(number) => format!("0x{:x}", number),
+        Err(_) => text.to_string(),
+    }
+}
+
+/// An integer field value
+pub enum Integer {
+    Unsigned(u128),
+    Negative(i64),
+}
+
+/// The integer held by a field value
+///
+/// Integers beyond 64 bits are logged as strings, since JSON parsers lose
+/// their precision, so strings of decimal digits count as integers too.
+/// Those with leading zeros are left alone as likely identifiers.
+pub fn integer_value(value: &Value) -> Option<Integer> {
+    match value {
+        Value::Number(number) => match number.as_u64() {
+            Some(number) => Some(Integer::Unsigned(number.into())),
+            None => number.as_i64().map(Integer::Negative),
+        },
+        Value::String(text) => {
+            let digits = text.strip_prefix('-').unwrap_or(text);
+            if digits.is_empty()
+                || !digits.bytes().all(|b| b.is_ascii_digit())
+                || (digits.len() > 1 && digits.starts_with('0'))
+            {
+                return None;
+            }
+            match text.parse::<u128>() {
+                Ok(number) => Some(Integer::Unsigned(number)),
+                Err(_) => text.parse::<i64>().ok().map(Integer::Negative),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Format an integer as hex, negative ones in 64-bit two's complement
+/// unless `signed` is set
+pub fn format_hex(integer: Integer, signed: bool) -> String {
+    match integer {
+        Integer::Unsigned(number) => format!("0x{:x}", number),
+        Integer::Negative(number) if signed => format!("-0x{:x}", number.unsigned_abs()),
+        Integer::Negative(number) => format!("0x{:x}", number),
+    }
+}
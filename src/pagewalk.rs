@@ -0,0 +1,188 @@
+//! Pretty-printing of logged x86 page table walks
+
+use serde_json::{Map, Value};
+
+use crate::decoder::{Conditions, Decoded, Decoder, FieldContext};
+use crate::numbers;
+
+/// Field names known to carry the page table entries of a walk
+const WALK_KEYS: &[&str] = &["ptes", "pte_chain", "page_walk", "page_table_walk", "walk"];
+
+/// Field names known to carry the virtual address being translated
+const GVA_KEYS: &[&str] = &["gva", "va", "virtual_address"];
+
+const PTE_ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+/// A paging structure level, from the top of the walk down
+struct Level {
+    name: &'static str,
+    /// Shift of the virtual address bits indexing this level
+    shift: u32,
+    /// Whether PS on this level maps a large page
+    allows_large_page: bool,
+}
+
+const LEVELS: &[Level] = &[
+    Level {
+        name: "PML5E",
+        shift: 48,
+        allows_large_page: false,
+    },
+    Level {
+        name: "PML4E",
+        shift: 39,
+        allows_large_page: false,
+    },
+    Level {
+        name: "PDPTE",
+        shift: 30,
+        allows_large_page: true,
+    },
+    Level {
+        name: "PDE",
+        shift: 21,
+        allows_large_page: true,
+    },
+    Level {
+        name: "PTE",
+        shift: 12,
+        allows_large_page: false,
+    },
+];
+
+/// Parse a list of entries from a JSON array or a Debug formatted array
+fn parse_entries(value: &Value) -> Option<Vec<u64>> {
+    match value {
+        Value::Array(items) => items.iter().map(Value::as_u64).collect(),
+        Value::String(text) => {
+            let inner = text.trim().strip_prefix('[')?.strip_suffix(']')?;
+            numbers::split_list(inner)
+                .into_iter()
+                .map(numbers::parse_integer)
+                .collect()
+        }
+        _ => None,
+    }
+}
+
+/// Render the flags of a paging structure entry
+fn format_flags(entry: u64) -> String {
+    let flags = [(0, "P"), (1, "RW"), (2, "US"), (7, "PS"), (63, "NX")];
+
+    flags
+        .iter()
+        .filter(|(bit, _)| entry & (1 << bit) != 0)
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Decode a page table walk field into a one-line summary and an indented
+/// per-level breakdown
+///
+/// Entries are expected top level first. Walks of three to five entries are
+/// mapped onto the last levels of a five level hierarchy, so a four entry
+/// walk starts at the PML4E.
+pub fn decode_walk(
+    key: &str,
+    value: &Value,
+    fields: &Map<String, Value>,
+) -> Option<(String, String)> {
+    if !WALK_KEYS.contains(&key) {
+        return None;
+    }
+
+    let entries = parse_entries(value)?;
+    if entries.len() < 3 || entries.len() > LEVELS.len() {
+        return None;
+    }
+
+    let gva = GVA_KEYS
+        .iter()
+        .find_map(|key| fields.get(*key))
+        .and_then(Value::as_u64);
+    let levels = &LEVELS[LEVELS.len() - entries.len()..];
+
+    let mut lines = match gva {
+        Some(gva) => format!("    {} walk for gva 0x{:x}:", key, gva),
+        None => format!("    {} walk:", key),
+    };
+    let mut summary = String::from("not present");
+
+    for (level, &entry) in levels.iter().zip(&entries) {
+        let index = gva
+            .map(|gva| format!("[0x{:03x}]", (gva >> level.shift) & 0x1ff))
+            .unwrap_or_default();
+        lines.push_str(&format!(
+            "\n      {:<5}{:<7} = 0x{:016x} {}",
+            level.name,
+            index,
+            entry,
+            format_flags(entry)
+        ));
+
+        if entry & 1 == 0 {
+            summary = format!("not present at {}", level.name);
+            break;
+        }
+
+        let is_leaf = level.shift == 12 || (level.allows_large_page && entry & (1 << 7) != 0);
+        if is_leaf {
+            let page_size = 1u64 << level.shift;
+            let base = entry & PTE_ADDRESS_MASK & !(page_size - 1);
+            summary = match gva {
+                Some(gva) => format!("gpa 0x{:x}", base | (gva & (page_size - 1))),
+                None => format!("page 0x{:x}", base),
+            };
+            match level.shift {
+                30 => summary.push_str(" (1 GiB page)"),
+                21 => summary.push_str(" (2 MiB page)"),
+                _ => {}
+            }
+            break;
+        }
+    }
+
+    lines.push_str(&format!("\n      => {}", summary));
+
+    Some((format!("<walk: {}>", summary), lines))
+}
+
+/// Decoder for page table walk fields
+pub struct PageWalkDecoder {
+    conditions: Conditions,
+}
+
+impl PageWalkDecoder {
+    pub fn new() -> Self {
+        PageWalkDecoder {
+            conditions: Conditions::any().keys(WALK_KEYS),
+        }
+    }
+}
+
+impl Decoder for PageWalkDecoder {
+    fn name(&self) -> &str {
+        "x86-page-walk"
+    }
+
+    fn description(&self) -> String {
+        "Expands x86 page table walks into one line per level, with the page they map".to_string()
+    }
+
+    fn conditions(&self) -> &Conditions {
+        &self.conditions
+    }
+
+    fn example(&self) -> Option<(&str, Value)> {
+        Some((
+            "ptes",
+            Value::from(vec![0x1007u64, 0x2007, 0x3007, 0x8000_0000_0040_0063]),
+        ))
+    }
+
+    fn decode(&self, key: &str, value: &Value, ctx: &FieldContext) -> Option<Decoded> {
+        let (summary, lines) = decode_walk(key, value, ctx.fields)?;
+        Some(Decoded::Expand { summary, lines })
+    }
+}
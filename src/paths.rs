@@ -0,0 +1,58 @@
+//! Handling of Windows path forms, so exports on file shares and in deep
+//! directories open
+//!
+//! Paths longer than 260 characters and UNC paths like
+//! `\\server\share\export.csv` are handled by the standard library, which
+//! adds the `\\?\` prefix to long paths itself. Paths given with that
+//! prefix are passed to Windows verbatim, so it no longer accepts `/`
+//! separators or `.` and `..` components in them, which are normalized
+//! here instead.
+
+use std::path::{Component, Path, PathBuf, Prefix};
+
+/// Parse a path given on the command line, used as a clap value parser
+pub fn parse_path(text: &str) -> Result<PathBuf, String> {
+    if cfg!(windows) {
+        if let Some(rest) = text.strip_prefix(r"\\?\") {
+            return Ok(PathBuf::from(normalize_verbatim(rest)));
+        }
+    }
+    Ok(PathBuf::from(text))
+}
+
+/// Normalize the part of a verbatim path after `\\?\`, like
+/// `C:\exports/./a.csv` or `UNC\server\share\logs\..\a.csv`
+fn normalize_verbatim(rest: &str) -> String {
+    let rest = rest.replace('/', "\\");
+    let mut parts: Vec<&str> = rest.split('\\').collect();
+    // The drive, or the server and share of a UNC path, can't be left
+    let root = if parts[0].eq_ignore_ascii_case("UNC") {
+        3
+    } else {
+        1
+    };
+    let root = root.min(parts.len());
+
+    let mut normalized: Vec<&str> = parts.drain(..root).collect();
+    for part in parts {
+        match part {
+            "" | "." => {}
+            ".." => {
+                if normalized.len() > root {
+                    normalized.pop();
+                }
+            }
+            part => normalized.push(part),
+        }
+    }
+    format!(r"\\?\{}", normalized.join("\\"))
+}
+
+/// Check whether a path is on a network share
+pub fn is_network(path: &Path) -> bool {
+    matches!(
+        path.components().next(),
+        Some(Component::Prefix(prefix))
+            if matches!(prefix.kind(), Prefix::UNC(..) | Prefix::VerbatimUNC(..))
+    )
+}
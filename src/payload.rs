@@ -0,0 +1,298 @@
+//! Rendering and decoding of binary payloads carried in fields
+
+use base64::engine::general_purpose::{STANDARD, STANDARD_NO_PAD, URL_SAFE, URL_SAFE_NO_PAD};
+use base64::Engine;
+use serde_json::Value;
+
+use crate::decoder::{Conditions, Decoded, Decoder, FieldContext};
+
+/// Interpret a field value as a byte array
+///
+/// Accepts JSON arrays of numbers in the byte range, and strings that are
+/// canonical padded base64 (excluding plain hex strings, which are also
+/// valid base64) ending in `=` padding or long and varied enough to be
+/// unlikely text, since short words like `idle` decode too. With `relaxed`
+/// set, unpadded and URL-safe base64 are also accepted when the string is
+/// long and varied enough.
+pub fn value_as_bytes(value: &Value, relaxed: bool) -> Option<Vec<u8>> {
+    match value {
+        Value::Array(items) => items
+            .iter()
+            .map(|item| item.as_u64().filter(|&b| b <= 0xff).map(|b| b as u8))
+            .collect(),
+        Value::String(text) => {
+            if text.is_empty() || text.chars().all(|c| c.is_ascii_hexdigit()) {
+                return None;
+            }
+
+            let plausible = text.ends_with('=') || looks_like_unpadded_base64(text);
+            if text.len() % 4 == 0 && plausible {
+                if let Ok(bytes) = STANDARD.decode(text) {
+                    return Some(bytes);
+                }
+            }
+
+            if !relaxed || !looks_like_unpadded_base64(text) {
+                return None;
+            }
+
+            [STANDARD_NO_PAD, URL_SAFE, URL_SAFE_NO_PAD]
+                .iter()
+                .find_map(|engine| engine.decode(text).ok())
+        }
+        _ => None,
+    }
+}
+
+/// Heuristic for base64 without padding, which words and identifiers
+/// frequently satisfy by accident
+fn looks_like_unpadded_base64(text: &str) -> bool {
+    text.len() >= 16
+        && text.chars().any(|c| c.is_ascii_digit())
+        && text.chars().any(|c| c.is_ascii_uppercase())
+        && text.chars().any(|c| c.is_ascii_lowercase())
+}
+
+/// Render bytes as a hex dump with offsets, one indented line per 16 bytes
+pub fn format_hex_dump(key: &str, bytes: &[u8]) -> String {
+    let mut dump = format!("    {} ({} bytes):", key, bytes.len());
+
+    for (i, chunk) in bytes.chunks(16).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+        let ascii: String = chunk
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        dump.push_str(&format!(
+            "\n    {:04x}: {:<47}  {}",
+            i * 16,
+            hex.join(" "),
+            ascii
+        ));
+    }
+
+    dump
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        bytes.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+fn ascii_field(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes)
+        .trim_end_matches(['\0', ' '])
+        .to_string()
+}
+
+/// Decode an ELF header
+fn decode_elf(bytes: &[u8]) -> Option<String> {
+    if !bytes.starts_with(b"\x7fELF") {
+        return None;
+    }
+
+    let class = match bytes.get(4)? {
+        1 => "32-bit",
+        2 => "64-bit",
+        _ => "unknown class",
+    };
+    let elf_type = match read_u16(bytes, 16)? {
+        1 => "relocatable",
+        2 => "executable",
+        3 => "shared object",
+        4 => "core",
+        _ => "unknown type",
+    };
+    let machine = match read_u16(bytes, 18)? {
+        0x03 => "x86",
+        0x3e => "x86-64",
+        0xb7 => "aarch64",
+        _ => "unknown machine",
+    };
+
+    Some(format!("ELF {} {} {}", class, machine, elf_type))
+}
+
+/// Decode a PE image header
+fn decode_pe(bytes: &[u8]) -> Option<String> {
+    if !bytes.starts_with(b"MZ") {
+        return None;
+    }
+
+    let pe_offset = read_u32(bytes, 0x3c)? as usize;
+    if bytes.get(pe_offset..pe_offset + 4)? != b"PE\0\0" {
+        return Some("MZ executable".to_string());
+    }
+
+    let machine = match read_u16(bytes, pe_offset + 4)? {
+        0x14c => "i386",
+        0x8664 => "x64",
+        0xaa64 => "arm64",
+        _ => "unknown machine",
+    };
+    let sections = read_u16(bytes, pe_offset + 6)?;
+    let timestamp = read_u32(bytes, pe_offset + 8)?;
+
+    Some(format!(
+        "PE {} sections={} timestamp=0x{:x}",
+        machine, sections, timestamp
+    ))
+}
+
+/// Decode a gzip member header
+fn decode_gzip(bytes: &[u8]) -> Option<String> {
+    if !bytes.starts_with(&[0x1f, 0x8b]) {
+        return None;
+    }
+
+    let method = match bytes.get(2)? {
+        8 => "deflate",
+        _ => "unknown method",
+    };
+    let mtime = read_u32(bytes, 4)?;
+
+    Some(format!("gzip {} mtime={}", method, mtime))
+}
+
+/// Decode an ACPI table header
+///
+/// Requires a four character uppercase signature and a length field that
+/// matches the payload, so arbitrary data rarely qualifies.
+fn decode_acpi_table(bytes: &[u8]) -> Option<String> {
+    let signature = bytes.get(0..4)?;
+    if !signature
+        .iter()
+        .all(|b| b.is_ascii_uppercase() || b.is_ascii_digit())
+    {
+        return None;
+    }
+
+    let length = read_u32(bytes, 4)? as usize;
+    if length != bytes.len() || length < 36 {
+        return None;
+    }
+
+    let revision = bytes[8];
+    let checksum = bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+
+    Some(format!(
+        "ACPI {} rev={} oem=\"{}\" table=\"{}\" checksum={}",
+        ascii_field(signature),
+        revision,
+        ascii_field(&bytes[10..16]),
+        ascii_field(&bytes[16..24]),
+        if checksum == 0 { "ok" } else { "bad" }
+    ))
+}
+
+/// Decode the save area of an SEV-ES GHCB page
+fn decode_ghcb(bytes: &[u8]) -> Option<String> {
+    const GHCB_SIZE: usize = 0x1000;
+
+    if bytes.len() != GHCB_SIZE {
+        return None;
+    }
+
+    Some(format!(
+        "GHCB sw_exit_code=0x{:x} sw_exit_info_1=0x{:x} sw_exit_info_2=0x{:x} sw_scratch=0x{:x} protocol_version={} usage={}",
+        read_u64(bytes, 0x390)?,
+        read_u64(bytes, 0x398)?,
+        read_u64(bytes, 0x3a0)?,
+        read_u64(bytes, 0x3a8)?,
+        read_u16(bytes, 0xffa)?,
+        read_u32(bytes, 0xffc)?
+    ))
+}
+
+/// Produce a one-line description of a payload with a recognized layout
+pub fn decode_structure(bytes: &[u8]) -> Option<String> {
+    decode_elf(bytes)
+        .or_else(|| decode_pe(bytes))
+        .or_else(|| decode_gzip(bytes))
+        .or_else(|| decode_acpi_table(bytes))
+        .or_else(|| decode_ghcb(bytes))
+}
+
+/// Length of byte arrays beyond which they are rendered as a hex dump,
+/// unless `--hexdump-threshold` is given
+pub const DEFAULT_HEXDUMP_THRESHOLD: usize = 32;
+
+/// Decoder rendering byte arrays and base64 payloads as hex dumps
+pub struct PayloadDecoder {
+    /// Byte arrays longer than this are rendered as a hex dump
+    hexdump_threshold: usize,
+    /// Decode base64 payloads regardless of size and identify known layouts
+    decode_base64: bool,
+    conditions: Conditions,
+}
+
+impl PayloadDecoder {
+    pub fn new(hexdump_threshold: usize, decode_base64: bool) -> Self {
+        PayloadDecoder {
+            hexdump_threshold,
+            decode_base64,
+            conditions: Conditions::any(),
+        }
+    }
+}
+
+impl Decoder for PayloadDecoder {
+    fn name(&self) -> &str {
+        "byte-payload"
+    }
+
+    fn description(&self) -> String {
+        match self.decode_base64 {
+            true => "Renders byte arrays longer than the --hexdump-threshold, and base64 strings, as hex dumps".to_string(),
+            false => "Renders byte arrays longer than the --hexdump-threshold as hex dumps".to_string(),
+        }
+    }
+
+    fn conditions(&self) -> &Conditions {
+        &self.conditions
+    }
+
+    fn example(&self) -> Option<(&str, Value)> {
+        let bytes: Vec<u64> = (0..=self.hexdump_threshold as u64)
+            .map(|byte| byte % 256)
+            .collect();
+        Some(("payload", Value::from(bytes)))
+    }
+
+    fn decode(&self, key: &str, value: &Value, _ctx: &FieldContext) -> Option<Decoded> {
+        let bytes = value_as_bytes(value, self.decode_base64)?;
+        let decode = self.decode_base64 && value.is_string();
+        if !decode && bytes.len() <= self.hexdump_threshold {
+            return None;
+        }
+
+        let summary = match decode_structure(&bytes).filter(|_| self.decode_base64) {
+            Some(desc) => format!("<{} bytes: {}>", bytes.len(), desc),
+            None => format!("<{} bytes>", bytes.len()),
+        };
+
+        Some(Decoded::Expand {
+            summary,
+            lines: format_hex_dump(key, &bytes),
+        })
+    }
+}
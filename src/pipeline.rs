@@ -0,0 +1,169 @@
+//! Parallel formatting of records that preserves their order
+//!
+//! The calling thread reads records and groups them into batches, which a
+//! pool of workers formats. Finished batches are written back in the order
+//! they were read. At most `max_in_flight` batches are queued, being
+//! formatted or waiting for an earlier batch at any time, so memory stays
+//! bounded even when one batch is slow.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::sync::mpsc;
+use std::sync::Mutex;
+
+/// How records are split across workers
+pub struct PipelineOptions {
+    /// Number of worker threads
+    pub jobs: usize,
+    /// Number of records in each batch
+    pub batch_size: usize,
+    /// Maximum number of batches read but not yet written
+    pub max_in_flight: usize,
+}
+
+/// Format `items` on a pool of workers, writing each formatted item in the
+/// original order
+///
+/// An error reading an item stops reading, and is returned once every item
+/// before it has been written.
+pub fn run<T, U, I, F, W>(
+    items: I,
+    options: &PipelineOptions,
+    format: F,
+    mut write: W,
+) -> Result<(), Box<dyn Error>>
+where
+    T: Send,
+    U: Send,
+    I: Iterator<Item = Result<T, Box<dyn Error>>>,
+    F: Fn(T) -> U + Sync,
+    W: FnMut(U) -> Result<(), Box<dyn Error>>,
+{
+    let (batch_tx, batch_rx) = mpsc::channel::<(u64, Vec<T>)>();
+    let (output_tx, output_rx) = mpsc::channel::<(u64, Vec<U>)>();
+    let batch_rx = Mutex::new(batch_rx);
+
+    std::thread::scope(|scope| {
+        for _ in 0..options.jobs.max(1) {
+            let output_tx = output_tx.clone();
+            let batch_rx = &batch_rx;
+            let format = &format;
+            scope.spawn(move || loop {
+                let received = batch_rx.lock().unwrap().recv();
+                let Ok((sequence, batch)) = received else {
+                    break;
+                };
+
+                let output = batch.into_iter().map(format).collect();
+                if output_tx.send((sequence, output)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(output_tx);
+
+        // Moving the sender in closes the queue on every return path, which
+        // lets the workers exit before the scope joins them
+        let batch_tx = batch_tx;
+        let max_in_flight = options.max_in_flight.max(1);
+        let batch_size = options.batch_size.max(1);
+
+        let mut sequencer = Sequencer {
+            pending: BTreeMap::new(),
+            next: 0,
+            in_flight: 0,
+        };
+        let mut items = items;
+        let mut sent = 0u64;
+
+        loop {
+            let mut batch = Vec::with_capacity(batch_size);
+            let mut read_error = None;
+            for item in items.by_ref() {
+                match item {
+                    Ok(item) => batch.push(item),
+                    Err(err) => {
+                        read_error = Some(err);
+                        break;
+                    }
+                }
+                if batch.len() == batch_size {
+                    break;
+                }
+            }
+            let last = batch.len() < batch_size || read_error.is_some();
+
+            if !batch.is_empty() {
+                // Wait for room before queueing another batch
+                while sequencer.in_flight >= max_in_flight {
+                    sequencer.receive(&output_rx, &mut write)?;
+                }
+                batch_tx
+                    .send((sent, batch))
+                    .map_err(|_| "Formatting workers exited unexpectedly")?;
+                sent += 1;
+                sequencer.in_flight += 1;
+            }
+
+            if let Some(err) = read_error {
+                sequencer.drain(&output_rx, &mut write)?;
+                return Err(err);
+            }
+            if last {
+                break;
+            }
+        }
+
+        sequencer.drain(&output_rx, &mut write)
+    })
+}
+
+/// Reorders finished batches and writes them in sequence
+struct Sequencer<U> {
+    pending: BTreeMap<u64, Vec<U>>,
+    next: u64,
+    in_flight: usize,
+}
+
+impl<U> Sequencer<U> {
+    /// Wait for a finished batch, then write every batch that is now next
+    /// in sequence
+    fn receive<W>(
+        &mut self,
+        output_rx: &mpsc::Receiver<(u64, Vec<U>)>,
+        write: &mut W,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        W: FnMut(U) -> Result<(), Box<dyn Error>>,
+    {
+        let (sequence, output) = output_rx
+            .recv()
+            .map_err(|_| "Formatting worker exited unexpectedly")?;
+        self.pending.insert(sequence, output);
+
+        while let Some(output) = self.pending.remove(&self.next) {
+            self.next += 1;
+            self.in_flight -= 1;
+            for item in output {
+                write(item)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write every batch still in flight
+    fn drain<W>(
+        &mut self,
+        output_rx: &mpsc::Receiver<(u64, Vec<U>)>,
+        write: &mut W,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        W: FnMut(U) -> Result<(), Box<dyn Error>>,
+    {
+        while self.in_flight > 0 {
+            self.receive(output_rx, write)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,185 @@
+//! External decoder plugins compiled to WASM
+//!
+//! A plugin is a core WASM module exporting:
+//!
+//! - `memory`: its linear memory
+//! - `alloc(len: i32) -> i32`: allocate `len` bytes for the host to write into
+//! - `decode(key_ptr: i32, key_len: i32, value_ptr: i32, value_len: i32) -> i64`:
+//!   decode a field, given its key and its value serialized as JSON, and
+//!   return the transformed string packed as `(ptr << 32) | len`, or 0 to
+//!   leave the field to other decoders
+//!
+//! An optional `dealloc(ptr: i32, len: i32)` export is called to release
+//! the buffers once the host is done with them.
+
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::path::Path;
+use std::sync::Mutex;
+use wasmtime::{Config, Engine, Instance, Memory, Module, Store, TypedFunc};
+
+use crate::decoder::{Conditions, Decoded, Decoder, FieldContext};
+
+/// Fuel granted to each call, bounding how long a plugin may run
+const FUEL_PER_CALL: u64 = 10_000_000;
+
+struct PluginInstance {
+    store: Store<()>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    decode: TypedFunc<(i32, i32, i32, i32), i64>,
+    dealloc: Option<TypedFunc<(i32, i32), ()>>,
+}
+
+impl PluginInstance {
+    /// Copy bytes into plugin memory, returning their address
+    fn write(&mut self, bytes: &[u8]) -> Result<i32, Box<dyn Error>> {
+        let ptr = self.alloc.call(&mut self.store, bytes.len() as i32)?;
+        self.memory
+            .write(&mut self.store, ptr as usize, bytes)
+            .map_err(|err| format!("plugin allocation out of bounds: {}", err))?;
+        Ok(ptr)
+    }
+
+    fn release(&mut self, ptr: i32, len: i32) -> Result<(), Box<dyn Error>> {
+        if let Some(dealloc) = &self.dealloc {
+            dealloc.call(&mut self.store, (ptr, len))?;
+        }
+        Ok(())
+    }
+
+    fn call(&mut self, key: &str, value: &str) -> Result<Option<String>, Box<dyn Error>> {
+        self.store.set_fuel(FUEL_PER_CALL)?;
+
+        let key_ptr = self.write(key.as_bytes())?;
+        let value_ptr = self.write(value.as_bytes())?;
+        let result = self.decode.call(
+            &mut self.store,
+            (key_ptr, key.len() as i32, value_ptr, value.len() as i32),
+        )?;
+        self.release(key_ptr, key.len() as i32)?;
+        self.release(value_ptr, value.len() as i32)?;
+
+        if result == 0 {
+            return Ok(None);
+        }
+
+        let ptr = (result >> 32) as u32;
+        let len = result as u32;
+        // Checked before allocating, so a bogus length can't exhaust memory
+        let end = u64::from(ptr) + u64::from(len);
+        if end > self.memory.data_size(&self.store) as u64 {
+            return Err(format!(
+                "plugin result out of bounds: 0x{:x} bytes at 0x{:x}",
+                len, ptr
+            )
+            .into());
+        }
+        let mut output = vec![0u8; len as usize];
+        self.memory
+            .read(&self.store, ptr as usize, &mut output)
+            .map_err(|err| format!("plugin result out of bounds: {}", err))?;
+        self.release(ptr as i32, len as i32)?;
+
+        Ok(Some(String::from_utf8_lossy(&output).into_owned()))
+    }
+}
+
+/// A decoder backed by a WASM plugin
+pub struct WasmDecoder {
+    name: String,
+    /// Hash of the module, identifying the plugin build
+    version: String,
+    conditions: Conditions,
+    instance: Mutex<PluginInstance>,
+}
+
+impl WasmDecoder {
+    /// Load and instantiate a plugin module
+    pub fn load(engine: &Engine, path: &Path) -> Result<Self, Box<dyn Error>> {
+        let bytes = std::fs::read(path)
+            .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+        let module = Module::new(engine, &bytes)?;
+        let mut store = Store::new(engine, ());
+        let instance = Instance::new(&mut store, &module, &[])?;
+
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| format!("{}: plugin does not export 'memory'", path.display()))?;
+        let alloc = instance.get_typed_func(&mut store, "alloc")?;
+        let decode = instance.get_typed_func(&mut store, "decode")?;
+        let dealloc = instance.get_typed_func(&mut store, "dealloc").ok();
+
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.display().to_string());
+
+        let digest = Sha256::digest(&bytes);
+        let version = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+
+        Ok(WasmDecoder {
+            name,
+            version,
+            conditions: Conditions::any(),
+            instance: Mutex::new(PluginInstance {
+                store,
+                memory,
+                alloc,
+                decode,
+                dealloc,
+            }),
+        })
+    }
+}
+
+impl Decoder for WasmDecoder {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> String {
+        "Decodes fields with a WASM plugin".to_string()
+    }
+
+    fn version(&self) -> String {
+        format!("sha256:{}", self.version)
+    }
+
+    fn conditions(&self) -> &Conditions {
+        &self.conditions
+    }
+
+    fn decode(&self, key: &str, value: &Value, _ctx: &FieldContext) -> Option<Decoded> {
+        let mut instance = self.instance.lock().unwrap();
+
+        // A failing plugin leaves the field to other decoders rather than
+        // aborting the whole run
+        match instance.call(key, &value.to_string()) {
+            Ok(result) => result.map(Decoded::Replace),
+            Err(err) => {
+                eprintln!("plugin {}: {}", self.name, err);
+                None
+            }
+        }
+    }
+}
+
+/// Load every `.wasm` file in a directory, in file name order
+pub fn load_plugins(dir: &Path) -> Result<Vec<WasmDecoder>, Box<dyn Error>> {
+    let mut config = Config::new();
+    config.consume_fuel(true);
+    let engine = Engine::new(&config)?;
+
+    let mut paths: Vec<_> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "wasm"))
+        .collect();
+    paths.sort();
+
+    paths
+        .iter()
+        .map(|path| WasmDecoder::load(&engine, path))
+        .collect()
+}
@@ -0,0 +1,138 @@
+//! Consistent pseudonyms for scrubbed values, kept in a keyed mapping file
+//!
+//! The mapping file holds a random secret key and, for each value seen, the
+//! HMAC of the value under that key with the pseudonym assigned to it. The
+//! original values are never stored, so the file can be kept alongside
+//! shared logs without revealing them, while later runs with the same file
+//! give every value the same pseudonym.
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+#[derive(Serialize, Deserialize)]
+struct MapFile {
+    /// Hex encoded HMAC key
+    key: String,
+    /// Pseudonyms keyed by the hex encoded HMAC of `<label>\0<value>`
+    pseudonyms: BTreeMap<String, String>,
+}
+
+struct MapState {
+    pseudonyms: BTreeMap<String, String>,
+    /// Highest number assigned so far for each label
+    counters: HashMap<String, u64>,
+    changed: bool,
+}
+
+/// Assigns pseudonyms like `node-3` to values, consistently across runs
+pub struct PseudonymMap {
+    path: PathBuf,
+    key: Vec<u8>,
+    state: Mutex<MapState>,
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Hex encoded HMAC of `<label>\0<value>` under `key`
+pub fn keyed_hash(key: &[u8], label: &str, value: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    mac.update(label.as_bytes());
+    mac.update(&[0]);
+    mac.update(value.as_bytes());
+    to_hex(&mac.finalize().into_bytes())
+}
+
+impl PseudonymMap {
+    /// Load a mapping file, creating a new key when the file doesn't exist
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let (key, pseudonyms) = if path.exists() {
+            let content = std::fs::read_to_string(path)
+                .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+            let file: MapFile = serde_json::from_str(&content)
+                .map_err(|err| format!("Failed to parse {}: {}", path.display(), err))?;
+            let key = from_hex(&file.key)
+                .ok_or_else(|| format!("{}: key is not valid hex", path.display()))?;
+            (key, file.pseudonyms)
+        } else {
+            let mut key = vec![0u8; 32];
+            getrandom::fill(&mut key).map_err(|err| format!("Failed to generate key: {}", err))?;
+            (key, BTreeMap::new())
+        };
+
+        let mut counters: HashMap<String, u64> = HashMap::new();
+        for pseudonym in pseudonyms.values() {
+            if let Some((label, number)) = pseudonym.rsplit_once('-') {
+                if let Ok(number) = number.parse::<u64>() {
+                    let counter = counters.entry(label.to_string()).or_default();
+                    *counter = (*counter).max(number);
+                }
+            }
+        }
+
+        Ok(PseudonymMap {
+            path: path.to_path_buf(),
+            key,
+            state: Mutex::new(MapState {
+                pseudonyms,
+                counters,
+                changed: !path.exists(),
+            }),
+        })
+    }
+
+    /// Secret key of the map, also keying the hashes of scrubbed values
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    /// Pseudonym of a value scrubbed under a label
+    pub fn pseudonym(&self, label: &str, value: &str) -> String {
+        let digest = keyed_hash(&self.key, label, value);
+
+        let mut state = self.state.lock().unwrap();
+        if let Some(pseudonym) = state.pseudonyms.get(&digest) {
+            return pseudonym.clone();
+        }
+
+        let counter = state.counters.entry(label.to_string()).or_default();
+        *counter += 1;
+        let pseudonym = format!("{}-{}", label, counter);
+        state.pseudonyms.insert(digest, pseudonym.clone());
+        state.changed = true;
+        pseudonym
+    }
+
+    /// Write the mapping file if new pseudonyms were assigned
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let mut state = self.state.lock().unwrap();
+        if !state.changed {
+            return Ok(());
+        }
+
+        let file = MapFile {
+            key: to_hex(&self.key),
+            pseudonyms: state.pseudonyms.clone(),
+        };
+        std::fs::write(&self.path, serde_json::to_string_pretty(&file)?)
+            .map_err(|err| format!("Failed to write {}: {}", self.path.display(), err))?;
+        state.changed = false;
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+//! Renaming of cryptic field names, so records use the same readable names
+//! across builds that changed them
+//!
+//! A field map is a TOML file of old names and the names shown instead.
+//! Tables hold renames that only apply to records of one target:
+//!
+//! ```toml
+//! gpa_l = "gpa_low"
+//! ei1 = "exit_info1"
+//!
+//! ["virt_mshv_vtl::processor"]
+//! vp_idx = "vp"
+//! ```
+//!
+//! Fields are renamed before they are decoded, so decoders and scrubbing
+//! rules written for the new names apply to the old ones too.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+use toml::{Table, Value};
+
+/// Field renames loaded from a field map
+pub struct FieldMap {
+    names: HashMap<String, String>,
+    /// Renames of each target
+    targets: HashMap<String, HashMap<String, String>>,
+}
+
+/// Read a table of renames
+fn renames(path: &Path, table: Table) -> Result<HashMap<String, String>, Box<dyn Error>> {
+    table
+        .into_iter()
+        .map(|(old, new)| match new {
+            Value::String(new) => Ok((old, new)),
+            other => Err(format!(
+                "{}: expected a new name for '{}', found {}",
+                path.display(),
+                old,
+                other
+            )
+            .into()),
+        })
+        .collect()
+}
+
+impl FieldMap {
+    /// Load a field map file
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+        let table: Table = toml::from_str(&content)
+            .map_err(|err| format!("Failed to parse {}: {}", path.display(), err))?;
+
+        let mut names = Table::new();
+        let mut targets = HashMap::new();
+        for (key, value) in table {
+            match value {
+                Value::Table(target) => {
+                    targets.insert(key, renames(path, target)?);
+                }
+                value => {
+                    names.insert(key, value);
+                }
+            }
+        }
+
+        Ok(FieldMap {
+            names: renames(path, names)?,
+            targets,
+        })
+    }
+
+    /// Name a field of a record of `target` is shown with
+    pub fn rename<'a>(&'a self, target: &str, key: &'a str) -> &'a str {
+        self.targets
+            .get(target)
+            .and_then(|names| names.get(key))
+            .or_else(|| self.names.get(key))
+            .map_or(key, String::as_str)
+    }
+}
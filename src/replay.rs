@@ -0,0 +1,140 @@
+//! Saved pipelines of filter and transform options
+//!
+//! A pipeline file is a TOML table of the options given on the command
+//! line, keyed by option name:
+//!
+//! ```toml
+//! level = "warn"
+//! scrub = true
+//! flag = ["duration_ns > 1ms"]
+//! ```
+//!
+//! Replaying it adds those options to the command line. Options given on
+//! the command line take precedence, except repeatable ones, which are
+//! combined.
+
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, Command};
+use std::error::Error;
+use std::ffi::OsString;
+use std::path::Path;
+use toml::{Table, Value};
+
+/// Options describing a single run rather than how records are processed,
+/// which are never saved
+const UNSAVED: &[&str] = &[
+    "file",
+    "output",
+    "resume",
+    "bench",
+    "emit_kql",
+    "manifest",
+    "save_pipeline",
+    "pipeline",
+    "sha256",
+    // Webhook URLs carry their credentials
+    "notify_webhook",
+    "no_config",
+];
+
+/// Save the options given on the command line to a pipeline file
+pub fn save(path: &Path, command: &Command, matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let mut table = Table::new();
+
+    for arg in command.get_arguments() {
+        let id = arg.get_id().as_str();
+        if UNSAVED.contains(&id) || matches.value_source(id) != Some(ValueSource::CommandLine) {
+            continue;
+        }
+
+        let value = match arg.get_action() {
+            ArgAction::SetTrue => Value::Boolean(true),
+            action => {
+                let values: Vec<Value> = matches
+                    .get_raw(id)
+                    .into_iter()
+                    .flatten()
+                    .map(|raw| {
+                        let raw = raw.to_string_lossy();
+                        match raw.parse::<i64>() {
+                            Ok(number) => Value::Integer(number),
+                            Err(_) => Value::String(raw.into_owned()),
+                        }
+                    })
+                    .collect();
+                match (action, values.len()) {
+                    (ArgAction::Append, _) => Value::Array(values),
+                    (_, 1) => values.into_iter().next().unwrap(),
+                    _ => continue,
+                }
+            }
+        };
+        table.insert(id.to_string(), value);
+    }
+
+    std::fs::write(path, toml::to_string(&table)?)
+        .map_err(|err| format!("Failed to write {}: {}", path.display(), err))?;
+    Ok(())
+}
+
+/// Command line arguments replaying a pipeline file, leaving out options
+/// already given on the command line, or also in the environment when
+/// `below_env` is set
+pub fn replay_args(
+    path: &Path,
+    command: &Command,
+    matches: &ArgMatches,
+    below_env: bool,
+) -> Result<Vec<OsString>, Box<dyn Error>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+    let table: Table = toml::from_str(&content)
+        .map_err(|err| format!("Failed to parse {}: {}", path.display(), err))?;
+
+    let mut args = Vec::new();
+    for (key, value) in table {
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_id() == key.as_str())
+            .filter(|arg| arg.get_long().is_some() && !UNSAVED.contains(&key.as_str()))
+            .ok_or_else(|| format!("{}: unknown option '{}'", path.display(), key))?;
+        let long = format!("--{}", arg.get_long().unwrap());
+
+        let given = match matches.value_source(&key) {
+            Some(ValueSource::CommandLine) => true,
+            Some(ValueSource::EnvVariable) => below_env,
+            _ => false,
+        };
+        if given && !matches!(arg.get_action(), ArgAction::Append) {
+            continue;
+        }
+
+        let values = match value {
+            Value::Boolean(true) => {
+                args.push(OsString::from(&long));
+                continue;
+            }
+            Value::Boolean(false) => continue,
+            Value::Array(values) => values,
+            value => vec![value],
+        };
+        for value in values {
+            let text = match value {
+                Value::String(text) => text,
+                Value::Integer(number) => number.to_string(),
+                other => {
+                    return Err(format!(
+                        "{}: unsupported value {} for '{}'",
+                        path.display(),
+                        other,
+                        key
+                    )
+                    .into())
+                }
+            };
+            args.push(OsString::from(format!("{}={}", long, text)));
+        }
+    }
+
+    Ok(args)
+}
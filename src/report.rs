@@ -0,0 +1,99 @@
+//! Helpers shared by the subcommands that summarize a whole log
+
+use serde_json::{Map, Value};
+use std::borrow::Cow;
+use std::error::Error;
+
+use crate::escape::escape_controls;
+use crate::input::Records;
+use crate::numbers;
+
+/// Fields holding the index of the VP a record is about
+pub const VP_KEYS: &[&str] = &["vp", "vp_index", "cpu"];
+
+/// A tracing record with the parts reports look at
+pub struct Event<'a> {
+    pub timestamp: &'a str,
+    pub level: &'a str,
+    pub target: &'a str,
+    pub message: &'a str,
+    pub fields: &'a Map<String, Value>,
+    /// Name of the span the record was logged in, or empty
+    pub span: &'a str,
+}
+
+/// Call `f` with each tracing record of the input, skipping anything else
+pub fn for_each_event(records: Records, mut f: impl FnMut(&Event)) -> Result<(), Box<dyn Error>> {
+    for record in records {
+        let record = record?;
+        let Some(json) = record.json() else {
+            continue;
+        };
+        let Some(fields) = json.get("fields").and_then(Value::as_object) else {
+            continue;
+        };
+        let text = |key: &str| json.get(key).and_then(Value::as_str).unwrap_or("");
+
+        f(&Event {
+            timestamp: text("timestamp"),
+            level: text("level"),
+            target: text("target"),
+            message: fields.get("message").and_then(Value::as_str).unwrap_or(""),
+            fields,
+            span: json
+                .pointer("/span/name")
+                .and_then(Value::as_str)
+                .unwrap_or(""),
+        });
+    }
+    Ok(())
+}
+
+/// The first of `keys` holding an integer, given as a number or as
+/// decimal or `0x` hex text
+pub fn field_u64(fields: &Map<String, Value>, keys: &[&str]) -> Option<u64> {
+    keys.iter().find_map(|key| match fields.get(*key)? {
+        Value::Number(number) => number.as_u64(),
+        Value::String(text) => numbers::parse_integer(text),
+        _ => None,
+    })
+}
+
+/// Check whether an intercepted access is a write, from a write flag field
+/// or else from its message
+pub fn is_write(event: &Event) -> bool {
+    ["is_write", "write"]
+        .iter()
+        .find_map(|key| event.fields.get(*key)?.as_bool())
+        .unwrap_or_else(|| {
+            let message = event.message.to_lowercase();
+            message.contains("write") || message.contains("wrmsr")
+        })
+}
+
+/// Print rows of cells as left-aligned columns under a header
+pub fn print_table(header: &[&str], rows: &[Vec<String>]) {
+    let rows: Vec<Vec<Cow<str>>> = rows
+        .iter()
+        .map(|row| row.iter().map(|cell| escape_controls(cell)).collect())
+        .collect();
+    let mut widths: Vec<usize> = header.iter().map(|cell| cell.len()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let print_row = |cells: &mut dyn Iterator<Item = &str>| {
+        let line: Vec<String> = cells
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    };
+
+    print_row(&mut header.iter().copied());
+    for row in &rows {
+        print_row(&mut row.iter().map(|cell| &**cell));
+    }
+}
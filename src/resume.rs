@@ -0,0 +1,166 @@
+//! Progress tracking for output files, so an interrupted run can resume
+//!
+//! Progress is saved next to the output file as a checkpoint pairing the
+//! input offset just past the last written record with the output length
+//! at that point. Resuming truncates the output to that length, dropping
+//! anything written after the checkpoint, and continues reading the input
+//! from that offset.
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::sink::{RecordInfo, Sink};
+
+/// Minimum time between saved checkpoints
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Serialize, Deserialize)]
+struct Checkpoint {
+    /// Byte offset in the input just past the last written record
+    input_offset: u64,
+    /// Length of the output once that record was written
+    output_len: u64,
+}
+
+/// Path of the progress file of an output file
+fn progress_path(output: &Path) -> PathBuf {
+    let mut name = OsString::from(output.as_os_str());
+    name.push(".progress");
+    PathBuf::from(name)
+}
+
+/// An output file whose progress is saved as it is written
+pub struct ProgressWriter {
+    writer: BufWriter<File>,
+    progress_path: PathBuf,
+    output_len: u64,
+    last_saved: Instant,
+    /// Input offset just past the last written record, if not saved yet
+    unsaved_offset: Option<u64>,
+}
+
+impl ProgressWriter {
+    /// Create the output file, replacing any previous one
+    pub fn create(output: &Path) -> Result<Self, Box<dyn Error>> {
+        let file = File::create(output)
+            .map_err(|err| format!("Failed to create {}: {}", output.display(), err))?;
+
+        Ok(ProgressWriter {
+            writer: BufWriter::new(file),
+            progress_path: progress_path(output),
+            output_len: 0,
+            last_saved: Instant::now(),
+            unsaved_offset: None,
+        })
+    }
+
+    /// Reopen the output file of an interrupted run, returning it with the
+    /// input offset to continue from
+    pub fn resume(output: &Path) -> Result<(Self, u64), Box<dyn Error>> {
+        let progress_path = progress_path(output);
+        let content = std::fs::read_to_string(&progress_path).map_err(|err| {
+            format!(
+                "No progress to resume from in {}: {}",
+                progress_path.display(),
+                err
+            )
+        })?;
+        let checkpoint: Checkpoint = serde_json::from_str(&content)
+            .map_err(|err| format!("Failed to parse {}: {}", progress_path.display(), err))?;
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .open(output)
+            .map_err(|err| format!("Failed to open {}: {}", output.display(), err))?;
+        file.set_len(checkpoint.output_len)?;
+        file.seek(SeekFrom::End(0))?;
+
+        let writer = ProgressWriter {
+            writer: BufWriter::new(file),
+            progress_path,
+            output_len: checkpoint.output_len,
+            last_saved: Instant::now(),
+            unsaved_offset: None,
+        };
+        Ok((writer, checkpoint.input_offset))
+    }
+
+    /// Record that the input up to `input_offset` has been written, saving a
+    /// checkpoint if enough time passed since the last one
+    fn save_progress(&mut self, input_offset: Option<u64>) -> Result<(), Box<dyn Error>> {
+        if input_offset.is_some() {
+            self.unsaved_offset = input_offset;
+        }
+        if self.last_saved.elapsed() < CHECKPOINT_INTERVAL {
+            return Ok(());
+        }
+        self.save_checkpoint()
+    }
+
+    /// Save a checkpoint for the last written record, if it isn't saved yet
+    fn save_checkpoint(&mut self) -> Result<(), Box<dyn Error>> {
+        let Some(input_offset) = self.unsaved_offset.take() else {
+            return Ok(());
+        };
+        self.last_saved = Instant::now();
+
+        // The output must hold everything the checkpoint claims before the
+        // checkpoint is saved
+        self.writer.flush()?;
+
+        let checkpoint = Checkpoint {
+            input_offset,
+            output_len: self.output_len,
+        };
+
+        // Write the checkpoint to a temporary file and rename it, so an
+        // interruption never leaves a partial checkpoint behind
+        let mut temp = self.progress_path.clone().into_os_string();
+        temp.push(".tmp");
+        std::fs::write(&temp, serde_json::to_string(&checkpoint)?)?;
+        std::fs::rename(&temp, &self.progress_path)?;
+
+        Ok(())
+    }
+}
+
+impl Sink for ProgressWriter {
+    fn write_record(&mut self, text: &str, info: &RecordInfo) -> Result<(), Box<dyn Error>> {
+        self.writer.write_all(text.as_bytes())?;
+        self.writer.write_all(b"\n")?;
+        self.output_len += text.len() as u64 + 1;
+        self.save_progress(info.end_offset)
+    }
+
+    fn skip_record(&mut self, end_offset: Option<u64>) -> Result<(), Box<dyn Error>> {
+        self.save_progress(end_offset)
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Flush the output and remove the progress file, since there is nothing
+    /// left to resume
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.writer.flush()?;
+        match std::fs::remove_file(&self.progress_path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Flush the output and save a checkpoint for the last written record,
+    /// however recent the previous one, keeping the progress file so the
+    /// run can be resumed from exactly there
+    fn interrupt(&mut self) -> Result<(), Box<dyn Error>> {
+        self.writer.flush()?;
+        self.save_checkpoint()
+    }
+}
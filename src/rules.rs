@@ -0,0 +1,259 @@
+//! User-defined regex transform rules loaded from a TOML file
+//!
+//! ```toml
+//! [[rule]]
+//! # Only apply to fields with a matching name (optional)
+//! key = "^exit_info$"
+//! # Every capture group holding a decimal number is rewritten as hex
+//! pattern = "exit_reason: (\\d+)"
+//!
+//! [[rule]]
+//! # Or replace each match using `$1` style references to capture groups
+//! pattern = "VTL(\\d)"
+//! replace = "vtl$1"
+//!
+//! [[rule]]
+//! # Also only apply to records with a matching target, and to fields whose
+//! # value as logged matches (both optional)
+//! key = "^attributes$"
+//! target = "^virt_mshv::"
+//! value = "^0x"
+//! pattern = "gpa: (\\d+)"
+//! ```
+//!
+//! Rules apply to string fields in file order, each one seeing the output of
+//! the previous ones. A rule only applies when all of its conditions hold.
+
+use regex::{Captures, Regex};
+use serde::Deserialize;
+use serde_json::Value;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::decoder::{Conditions, Decoded, Decoder, FieldContext};
+use crate::numbers;
+
+/// Minimum time between checks of the rules file for changes
+const RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    #[serde(default, rename = "rule")]
+    rules: Vec<RuleSpec>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleSpec {
+    key: Option<String>,
+    target: Option<String>,
+    value: Option<String>,
+    pattern: String,
+    replace: Option<String>,
+}
+
+struct Rule {
+    key: Option<Regex>,
+    target: Option<Regex>,
+    /// Pattern the field value must match before any rule is applied
+    value: Option<Regex>,
+    pattern: Regex,
+    replace: Option<String>,
+}
+
+impl Rule {
+    /// Check whether the rule applies to a field of a record
+    fn applies(&self, key: &str, value: &str, target: &str) -> bool {
+        self.key.as_ref().is_none_or(|k| k.is_match(key))
+            && self.target.as_ref().is_none_or(|t| t.is_match(target))
+            && self.value.as_ref().is_none_or(|v| v.is_match(value))
+    }
+
+    fn apply(&self, text: &str) -> String {
+        match &self.replace {
+            Some(replace) => self
+                .pattern
+                .replace_all(text, replace.as_str())
+                .into_owned(),
+            None => self
+                .pattern
+                .replace_all(text, |caps: &Captures| hex_groups(caps))
+                .into_owned(),
+        }
+    }
+}
+
+/// Rewrite the decimal capture groups of a match as hex
+fn hex_groups(caps: &Captures) -> String {
+    let whole = caps.get(0).unwrap();
+    let mut output = String::new();
+    let mut last = whole.start();
+
+    for group in caps.iter().skip(1).flatten() {
+        // Nested groups overlap an already rewritten span
+        if group.start() < last {
+            continue;
+        }
+        output.push_str(&whole.as_str()[last - whole.start()..group.start() - whole.start()]);
+        output.push_str(&numbers::decimal_to_hex(group.as_str()));
+        last = group.end();
+    }
+
+    output.push_str(&whole.as_str()[last - whole.start()..]);
+    output
+}
+
+fn load_rules(path: &Path) -> Result<Vec<Rule>, Box<dyn Error>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+    let file: RulesFile = toml::from_str(&content)
+        .map_err(|err| format!("Failed to parse {}: {}", path.display(), err))?;
+
+    file.rules
+        .into_iter()
+        .enumerate()
+        .map(|(index, spec)| {
+            let compile = |pattern: &str| -> Result<Regex, Box<dyn Error>> {
+                Regex::new(pattern).map_err(|err| {
+                    format!("{}: rule {}: {}", path.display(), index + 1, err).into()
+                })
+            };
+            Ok(Rule {
+                key: spec.key.as_deref().map(compile).transpose()?,
+                target: spec.target.as_deref().map(compile).transpose()?,
+                value: spec.value.as_deref().map(compile).transpose()?,
+                pattern: compile(&spec.pattern)?,
+                replace: spec.replace,
+            })
+        })
+        .collect()
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// The rules loaded from a file, which can be reloaded while running
+pub struct RuleSet {
+    path: PathBuf,
+    rules: RwLock<Vec<Rule>>,
+    modified: Mutex<(Option<SystemTime>, Instant)>,
+}
+
+impl RuleSet {
+    /// Load the rules in a file
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let modified = modified_time(path);
+        Ok(RuleSet {
+            path: path.to_path_buf(),
+            rules: RwLock::new(load_rules(path)?),
+            modified: Mutex::new((modified, Instant::now())),
+        })
+    }
+
+    /// Reload the rules if the file changed since it was last read
+    ///
+    /// The file is checked at most once per [`RELOAD_INTERVAL`]. A file that
+    /// fails to load is reported and the previous rules are kept, so a typo
+    /// while editing doesn't stop a long running session.
+    pub fn reload_if_changed(&self) {
+        let mut modified = self.modified.lock().unwrap();
+        if modified.1.elapsed() < RELOAD_INTERVAL {
+            return;
+        }
+        modified.1 = Instant::now();
+
+        let current = modified_time(&self.path);
+        if current == modified.0 {
+            return;
+        }
+        modified.0 = current;
+
+        match load_rules(&self.path) {
+            Ok(rules) => {
+                eprintln!(
+                    "Reloaded {} rules from {}",
+                    rules.len(),
+                    self.path.display()
+                );
+                *self.rules.write().unwrap() = rules;
+            }
+            Err(err) => eprintln!("Keeping previous rules: {}", err),
+        }
+    }
+}
+
+/// Decoder applying the rules of a [`RuleSet`] to string fields
+pub struct RulesDecoder {
+    conditions: Conditions,
+    rules: Arc<RuleSet>,
+}
+
+impl RulesDecoder {
+    pub fn new(rules: Arc<RuleSet>) -> Self {
+        RulesDecoder {
+            conditions: Conditions::any(),
+            rules,
+        }
+    }
+}
+
+impl Decoder for RulesDecoder {
+    fn name(&self) -> &str {
+        "rules"
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "Applies the transform rules of {} to strings",
+            self.rules.path.display()
+        )
+    }
+
+    fn conditions(&self) -> &Conditions {
+        &self.conditions
+    }
+
+    fn decode(&self, key: &str, value: &Value, ctx: &FieldContext) -> Option<Decoded> {
+        let text = value.as_str()?;
+        let rules = self.rules.rules.read().unwrap();
+
+        let mut transformed = text.to_string();
+        for rule in rules.iter() {
+            if rule.applies(key, text, ctx.target) {
+                transformed = rule.apply(&transformed);
+            }
+        }
+
+        (transformed != text).then_some(Decoded::Replace(transformed))
+    }
+
+    fn explain(&self, key: &str, value: &Value, ctx: &FieldContext) -> Vec<String> {
+        let Some(text) = value.as_str() else {
+            return Vec::new();
+        };
+        let rules = self.rules.rules.read().unwrap();
+
+        let mut lines = Vec::new();
+        let mut transformed = text.to_string();
+        for (index, rule) in rules.iter().enumerate() {
+            let name = format!("rule {} `{}`", index + 1, rule.pattern);
+            if !rule.applies(key, text, ctx.target) {
+                lines.push(format!(
+                    "{} skipped, its key, target or value didn't match",
+                    name
+                ));
+            } else if !rule.pattern.is_match(&transformed) {
+                lines.push(format!("{} didn't match", name));
+            } else {
+                let after = rule.apply(&transformed);
+                lines.push(format!("{} fired: {:?} -> {:?}", name, transformed, after));
+                transformed = after;
+            }
+        }
+        lines
+    }
+}
@@ -0,0 +1,355 @@
+//! Generic decoding of Debug-formatted struct dumps from a layout file
+//!
+//! The layout file is TOML describing the logged structs and the enums and
+//! flag sets their fields use:
+//!
+//! ```toml
+//! [[struct]]
+//! name = "HvX64InterceptMessageHeader"
+//! fields.vp_index = { format = "dec" }
+//! fields.cr8 = { width = 8 }
+//! fields.intercept_access_type = { enum = "HvInterceptAccessType" }
+//! fields.execution_state = { flags = "HvX64VpExecutionState" }
+//!
+//! [enum.HvInterceptAccessType]
+//! 0 = "read"
+//! 1 = "write"
+//! 2 = "execute"
+//!
+//! [flags.HvX64VpExecutionState]
+//! 2 = "cr0_pe"
+//! 3 = "cr0_am"
+//! ```
+//!
+//! Every integer field inside a described struct is shown as hex unless its
+//! format is `dec`. A `width` in bits pads the hex value, enum fields are
+//! annotated with the matching name and flag fields with the names of the
+//! bits that are set.
+//!
+//! Builds that changed the layout of a struct can each have their own,
+//! listing the build versions it applies to. A trailing `*` matches any
+//! version starting with the text before it. The layout without versions
+//! is used for other builds, or when the build is unknown.
+//!
+//! ```toml
+//! [[struct]]
+//! name = "HvX64InterceptMessageHeader"
+//! versions = ["1.2.*", "1.3.0"]
+//! fields.vp_index = { format = "dec" }
+//! ```
+
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+
+use crate::decoder::{Conditions, Decoded, Decoder, FieldContext};
+use crate::numbers::{self, DECIMAL};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SchemaFile {
+    #[serde(default, rename = "struct")]
+    structs: Vec<StructLayout>,
+    #[serde(default, rename = "enum")]
+    enums: HashMap<String, HashMap<String, String>>,
+    #[serde(default)]
+    flags: HashMap<String, HashMap<String, String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StructLayout {
+    name: String,
+    /// Build versions the layout applies to, all of them when empty
+    #[serde(default)]
+    versions: Vec<String>,
+    #[serde(default)]
+    fields: HashMap<String, FieldLayout>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct FieldLayout {
+    #[serde(default)]
+    format: FieldFormat,
+    width: Option<u32>,
+    #[serde(rename = "enum")]
+    enum_name: Option<String>,
+    flags: Option<String>,
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum FieldFormat {
+    #[default]
+    Hex,
+    Dec,
+}
+
+/// How a single field of a struct is rendered
+struct FieldRule {
+    format: FieldFormat,
+    width: Option<u32>,
+    names: Option<FieldNames>,
+}
+
+enum FieldNames {
+    Enum(HashMap<u64, String>),
+    Flags(Vec<(u32, String)>),
+}
+
+struct StructRule {
+    name: String,
+    start_regex: Regex,
+    fields: HashMap<String, FieldRule>,
+}
+
+/// Parse a table of names keyed by integers written as TOML keys
+fn parse_names(
+    kind: &str,
+    name: &str,
+    table: &HashMap<String, String>,
+) -> Result<Vec<(u64, String)>, Box<dyn Error>> {
+    let mut names = Vec::new();
+    for (value, label) in table {
+        let number = match value.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16),
+            None => value.parse::<u64>(),
+        }
+        .map_err(|_| format!("{} {}: '{}' is not an integer", kind, name, value))?;
+        names.push((number, label.clone()));
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Check whether a build version matches a pattern like `1.2.*`
+fn version_matches(pattern: &str, version: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => version.starts_with(prefix),
+        None => pattern == version,
+    }
+}
+
+impl FieldRule {
+    fn render(&self, value: u64) -> String {
+        let mut text = match (self.format, self.width) {
+            (FieldFormat::Dec, _) => value.to_string(),
+            (FieldFormat::Hex, Some(width)) => {
+                format!("0x{:0width$x}", value, width = width.div_ceil(4) as usize)
+            }
+            (FieldFormat::Hex, None) => format!("0x{:x}", value),
+        };
+
+        match &self.names {
+            Some(FieldNames::Enum(names)) => {
+                let name = names.get(&value).map(String::as_str).unwrap_or("unknown");
+                text.push_str(&format!(" ({})", name));
+            }
+            Some(FieldNames::Flags(bits)) => {
+                let set: Vec<&str> = bits
+                    .iter()
+                    .filter(|(bit, _)| *bit < 64 && value & (1 << bit) != 0)
+                    .map(|(_, name)| name.as_str())
+                    .collect();
+                let set = if set.is_empty() {
+                    "none".to_string()
+                } else {
+                    set.join("|")
+                };
+                text.push_str(&format!(" ({})", set));
+            }
+            None => {}
+        }
+
+        text
+    }
+}
+
+/// Find the index just past the brace closing the one at `open`
+fn matching_brace(text: &str, open: usize) -> usize {
+    let mut depth = 0usize;
+    for (index, c) in text[open..].char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return open + index + 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    text.len()
+}
+
+/// Decoder for struct dumps described by a layout file
+pub struct SchemaDecoder {
+    conditions: Conditions,
+    structs: Vec<StructRule>,
+    field_regex: Regex,
+}
+
+impl SchemaDecoder {
+    /// Load the struct layouts for a build version from a TOML file
+    pub fn load(path: &Path, version: Option<&str>) -> Result<Self, Box<dyn Error>> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+        let schema: SchemaFile = toml::from_str(&content)
+            .map_err(|err| format!("Failed to parse {}: {}", path.display(), err))?;
+
+        // Layouts for the build replace the default layout of their struct
+        let applies = |layout: &StructLayout| {
+            version.is_some_and(|version| {
+                layout
+                    .versions
+                    .iter()
+                    .any(|pattern| version_matches(pattern, version))
+            })
+        };
+        let versioned: Vec<String> = schema
+            .structs
+            .iter()
+            .filter(|layout| applies(layout))
+            .map(|layout| layout.name.clone())
+            .collect();
+        let layouts = schema.structs.into_iter().filter(|layout| {
+            if layout.versions.is_empty() {
+                !versioned.contains(&layout.name)
+            } else {
+                applies(layout)
+            }
+        });
+
+        let mut structs = Vec::new();
+        for layout in layouts {
+            let mut fields = HashMap::new();
+            for (field, spec) in layout.fields {
+                let names = match (&spec.enum_name, &spec.flags) {
+                    (Some(_), Some(_)) => {
+                        return Err(format!(
+                            "{}.{}: a field cannot be both an enum and flags",
+                            layout.name, field
+                        )
+                        .into())
+                    }
+                    (Some(name), None) => {
+                        let table = schema.enums.get(name).ok_or_else(|| {
+                            format!("{}.{}: unknown enum '{}'", layout.name, field, name)
+                        })?;
+                        Some(FieldNames::Enum(
+                            parse_names("enum", name, table)?.into_iter().collect(),
+                        ))
+                    }
+                    (None, Some(name)) => {
+                        let table = schema.flags.get(name).ok_or_else(|| {
+                            format!("{}.{}: unknown flags '{}'", layout.name, field, name)
+                        })?;
+                        Some(FieldNames::Flags(
+                            parse_names("flags", name, table)?
+                                .into_iter()
+                                .map(|(bit, name)| (bit as u32, name))
+                                .collect(),
+                        ))
+                    }
+                    (None, None) => None,
+                };
+
+                fields.insert(
+                    field,
+                    FieldRule {
+                        format: spec.format,
+                        width: spec.width,
+                        names,
+                    },
+                );
+            }
+
+            let start_regex = Regex::new(&format!(r"\b{} \{{", regex::escape(&layout.name)))?;
+            structs.push(StructRule {
+                name: layout.name,
+                start_regex,
+                fields,
+            });
+        }
+
+        Ok(SchemaDecoder {
+            conditions: Conditions::any(),
+            structs,
+            field_regex: Regex::new(&format!(r"\b(\w+): (0x[0-9a-fA-F_]+|{})\b", DECIMAL)).unwrap(),
+        })
+    }
+
+    /// Rewrite the fields of every dump of one struct within `text`
+    fn transform_struct(&self, text: &str, rule: &StructRule) -> String {
+        let mut output = String::with_capacity(text.len());
+        let mut last = 0;
+
+        for start in rule.start_regex.find_iter(text) {
+            if start.start() < last {
+                continue;
+            }
+            let end = matching_brace(text, start.end() - 1);
+            output.push_str(&text[last..start.end()]);
+
+            let body = &text[start.end()..end];
+            let body = self
+                .field_regex
+                .replace_all(body, |caps: &regex::Captures| {
+                    let field = &caps[1];
+                    let raw = &caps[2];
+                    let Some(num) = numbers::parse_integer(raw) else {
+                        return caps[0].to_string();
+                    };
+                    match rule.fields.get(field) {
+                        Some(field_rule) => format!("{}: {}", field, field_rule.render(num)),
+                        // Numbers already shown as hex are left alone
+                        None if raw.starts_with("0x") => caps[0].to_string(),
+                        None => format!("{}: 0x{:x}", field, num),
+                    }
+                });
+            output.push_str(&body);
+            last = end;
+        }
+
+        output.push_str(&text[last..]);
+        output
+    }
+}
+
+impl Decoder for SchemaDecoder {
+    fn name(&self) -> &str {
+        "struct-schema"
+    }
+
+    fn description(&self) -> String {
+        let names: Vec<&str> = self.structs.iter().map(|rule| rule.name.as_str()).collect();
+        format!(
+            "Rewrites dumps of the structs of --struct-schema as hex, with enum and flag names: {}",
+            names.join(", ")
+        )
+    }
+
+    fn conditions(&self) -> &Conditions {
+        &self.conditions
+    }
+
+    fn decode(&self, _key: &str, value: &Value, _ctx: &FieldContext) -> Option<Decoded> {
+        let text = value.as_str()?;
+        let mut transformed = None;
+
+        for rule in &self.structs {
+            let current = transformed.as_deref().unwrap_or(text);
+            if !current.contains(rule.name.as_str()) || !rule.start_regex.is_match(current) {
+                continue;
+            }
+            transformed = Some(self.transform_struct(current, rule));
+        }
+
+        transformed.map(Decoded::Replace)
+    }
+}
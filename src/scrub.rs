@@ -0,0 +1,235 @@
+//! Redaction of sensitive values before output
+//!
+//! Scrubbing always removes IP and email addresses. Further rules are read
+//! from a TOML file:
+//!
+//! ```toml
+//! [[rule]]
+//! # Label used in the replacement token
+//! name = "node"
+//! # Scrub every match of a regex anywhere in the output
+//! pattern = "\\bnode-[0-9a-z]+\\b"
+//! # Replace matches with a hash rather than just the label, so equal
+//! # values can still be told apart (default "redact")
+//! action = "hash"
+//!
+//! [[rule]]
+//! name = "customer"
+//! # Or scrub the whole value of fields with a matching name
+//! key = "^(customer|tenant)_id$"
+//! ```
+//!
+//! Redacted values are shown as `<name>` and hashed ones as `<name:hash>`.
+//! Hashes are HMACs under a secret key, so values with few possibilities,
+//! like IP addresses, can't be recovered by hashing every candidate. The
+//! key is random for each run, or the key of the pseudonym map when there
+//! is one, so hashes only match across runs sharing a map.
+//!
+//! With a pseudonym map, rules without an `action` default to
+//! `action = "pseudonym"`, which replaces each distinct value with a token
+//! like `<node-3>` that stays the same across runs using the same map.
+
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+use std::error::Error;
+use std::path::Path;
+
+use crate::pseudonym::{self, PseudonymMap};
+
+/// Patterns scrubbed in every output
+const BUILTIN_RULES: &[(&str, &str)] = &[
+    ("email", r"\b[\w.+-]+@[\w-]+(\.[\w-]+)+\b"),
+    (
+        "ipv4",
+        r"\b(25[0-5]|2[0-4]\d|1?\d?\d)(\.(25[0-5]|2[0-4]\d|1?\d?\d)){3}\b",
+    ),
+    (
+        "ipv6",
+        r"\b([0-9a-fA-F]{1,4}:){7}[0-9a-fA-F]{1,4}\b|\b([0-9a-fA-F]{1,4}:){1,7}:([0-9a-fA-F]{1,4}(:[0-9a-fA-F]{1,4}){0,6})?\b",
+    ),
+];
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScrubFile {
+    #[serde(default, rename = "rule")]
+    rules: Vec<RuleSpec>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleSpec {
+    name: Option<String>,
+    key: Option<String>,
+    pattern: Option<String>,
+    action: Option<Action>,
+}
+
+/// How a scrubbed value is replaced
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum Action {
+    Redact,
+    Hash,
+    Pseudonym,
+}
+
+/// What a rule scrubs
+enum Target {
+    /// Whole values of fields whose name matches
+    Key(Regex),
+    /// Matches anywhere in the output
+    Pattern(Regex),
+}
+
+struct Rule {
+    name: String,
+    target: Target,
+    action: Action,
+}
+
+impl Rule {
+    fn token(&self, value: &str, key: &[u8], pseudonyms: Option<&PseudonymMap>) -> String {
+        match self.action {
+            Action::Redact => format!("<{}>", self.name),
+            Action::Hash => {
+                let hash = pseudonym::keyed_hash(key, &self.name, value);
+                format!("<{}:{}>", self.name, &hash[..12])
+            }
+            Action::Pseudonym => match pseudonyms {
+                Some(map) => format!("<{}>", map.pseudonym(&self.name, value)),
+                None => format!("<{}>", self.name),
+            },
+        }
+    }
+}
+
+/// Replaces sensitive values with tokens
+pub struct Scrubber {
+    rules: Vec<Rule>,
+    pseudonyms: Option<PseudonymMap>,
+    /// Secret key of the hashes of values
+    hash_key: Vec<u8>,
+}
+
+impl Scrubber {
+    /// Create a scrubber with the built-in rules
+    pub fn new() -> Result<Self, Box<dyn Error>> {
+        let rules = BUILTIN_RULES
+            .iter()
+            .map(|(name, pattern)| Rule {
+                name: name.to_string(),
+                target: Target::Pattern(Regex::new(pattern).unwrap()),
+                action: Action::Redact,
+            })
+            .collect();
+
+        let mut hash_key = vec![0u8; 32];
+        getrandom::fill(&mut hash_key).map_err(|err| format!("Failed to generate key: {}", err))?;
+
+        Ok(Scrubber {
+            rules,
+            pseudonyms: None,
+            hash_key,
+        })
+    }
+
+    /// Replace values with consistent pseudonyms from a mapping file,
+    /// rather than redacting them, unless a rule asks otherwise
+    pub fn use_pseudonyms(&mut self, map: PseudonymMap) {
+        for rule in &mut self.rules {
+            rule.action = Action::Pseudonym;
+        }
+        self.hash_key = map.key().to_vec();
+        self.pseudonyms = Some(map);
+    }
+
+    /// Save any newly assigned pseudonyms to the mapping file
+    pub fn save_pseudonyms(&self) -> Result<(), Box<dyn Error>> {
+        match &self.pseudonyms {
+            Some(map) => map.save(),
+            None => Ok(()),
+        }
+    }
+
+    /// Add the rules in a TOML file, applied before the built-in ones
+    pub fn load(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+        let file: ScrubFile = toml::from_str(&content)
+            .map_err(|err| format!("Failed to parse {}: {}", path.display(), err))?;
+
+        let mut rules = Vec::new();
+        for (index, spec) in file.rules.into_iter().enumerate() {
+            let compile = |pattern: &str| {
+                Regex::new(pattern)
+                    .map_err(|err| format!("{}: rule {}: {}", path.display(), index + 1, err))
+            };
+            let target = match (&spec.key, &spec.pattern) {
+                (Some(key), None) => Target::Key(compile(key)?),
+                (None, Some(pattern)) => Target::Pattern(compile(pattern)?),
+                _ => {
+                    return Err(format!(
+                        "{}: rule {}: expected exactly one of 'key' or 'pattern'",
+                        path.display(),
+                        index + 1
+                    )
+                    .into())
+                }
+            };
+
+            let action = match spec.action {
+                Some(Action::Pseudonym) if self.pseudonyms.is_none() => {
+                    return Err(format!(
+                        "{}: rule {}: action 'pseudonym' needs --pseudonym-map",
+                        path.display(),
+                        index + 1
+                    )
+                    .into())
+                }
+                Some(action) => action,
+                None if self.pseudonyms.is_some() => Action::Pseudonym,
+                None => Action::Redact,
+            };
+
+            rules.push(Rule {
+                name: spec.name.unwrap_or_else(|| "redacted".to_string()),
+                target,
+                action,
+            });
+        }
+
+        rules.append(&mut self.rules);
+        self.rules = rules;
+        Ok(())
+    }
+
+    /// Token replacing the whole value of a field, if a rule scrubs it
+    pub fn scrub_field(&self, key: &str, value: &Value) -> Option<String> {
+        self.rules.iter().find_map(|rule| match &rule.target {
+            Target::Key(regex) if regex.is_match(key) => Some(match value {
+                Value::String(text) => rule.token(text, &self.hash_key, self.pseudonyms.as_ref()),
+                other => rule.token(&other.to_string(), &self.hash_key, self.pseudonyms.as_ref()),
+            }),
+            _ => None,
+        })
+    }
+
+    /// Replace every pattern match in formatted output
+    pub fn scrub_text(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for rule in &self.rules {
+            if let Target::Pattern(regex) = &rule.target {
+                if regex.is_match(&text) {
+                    text = regex
+                        .replace_all(&text, |caps: &regex::Captures| {
+                            rule.token(&caps[0], &self.hash_key, self.pseudonyms.as_ref())
+                        })
+                        .into_owned();
+                }
+            }
+        }
+        text
+    }
+}
@@ -0,0 +1,136 @@
+//! Selection of a part of the records shown, for a quick look at a large
+//! export
+//!
+//! Records hidden by the filters are never counted. Sampling is applied
+//! first, then `--reverse`, then `--head` or `--tail` to the sampled
+//! records in the order they are shown.
+
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+
+use crate::sink::RecordInfo;
+use crate::time::parse_timestamp;
+
+/// Keep `keep` out of every `of` records, like `1/100`
+#[derive(Clone, Copy, Debug)]
+pub struct Sample {
+    keep: u64,
+    of: u64,
+}
+
+/// Parse a sampling rate given as `KEEP/OF`, used as a clap value parser
+pub fn parse_sample(text: &str) -> Result<Sample, String> {
+    let invalid = || format!("Invalid sample '{}', expected a rate like 1/100", text);
+    let (keep, of) = text.split_once('/').ok_or_else(invalid)?;
+    let keep: u64 = keep.trim().parse().map_err(|_| invalid())?;
+    let of: u64 = of.trim().parse().map_err(|_| invalid())?;
+    if keep == 0 || keep > of {
+        return Err(invalid());
+    }
+    Ok(Sample { keep, of })
+}
+
+/// Decides which formatted records are written
+pub struct Selection {
+    head: Option<u64>,
+    tail: Option<usize>,
+    sample: Option<Sample>,
+    reverse: bool,
+    /// Records offered for sampling
+    seen: u64,
+    /// Records selected so far
+    selected: u64,
+    /// Records held back for --tail or --reverse, written at the end
+    held: VecDeque<(String, RecordInfo)>,
+}
+
+impl Selection {
+    pub fn new(
+        head: Option<u64>,
+        tail: Option<usize>,
+        sample: Option<Sample>,
+        reverse: bool,
+    ) -> Self {
+        Selection {
+            head,
+            tail,
+            sample,
+            reverse,
+            seen: 0,
+            selected: 0,
+            held: VecDeque::new(),
+        }
+    }
+
+    /// Check whether a record should be written now, holding it back
+    /// instead when only the last records are wanted or the order changes
+    pub fn select(&mut self, text: &str, info: &RecordInfo) -> bool {
+        if self.is_done() {
+            return false;
+        }
+
+        // Spread the kept records evenly, so 2/5 keeps the first and fourth
+        // of every five
+        if let Some(Sample { keep, of }) = self.sample {
+            let position = self.seen % of;
+            self.seen += 1;
+            if position * keep % of >= keep {
+                return false;
+            }
+        }
+
+        self.selected += 1;
+        if self.reverse {
+            self.held.push_back((text.to_string(), info.clone()));
+            return false;
+        }
+        match self.tail {
+            Some(count) => {
+                if self.held.len() == count {
+                    self.held.pop_front();
+                }
+                if count > 0 {
+                    self.held.push_back((text.to_string(), info.clone()));
+                }
+                false
+            }
+            None => true,
+        }
+    }
+
+    /// Check whether --head records were written, so nothing more will be
+    pub fn is_done(&self) -> bool {
+        !self.reverse && self.head.is_some_and(|head| self.selected >= head)
+    }
+
+    /// The records held back, in the order they are written
+    pub fn take_held(&mut self) -> VecDeque<(String, RecordInfo)> {
+        let mut held = std::mem::take(&mut self.held);
+        if !self.reverse {
+            return held;
+        }
+
+        // Newest first by timestamp. Lines without one are given the time
+        // of the record before them, and records of the same time are
+        // reversed like the rest.
+        let mut time = i64::MIN;
+        let mut keyed: Vec<(i64, (String, RecordInfo))> = held
+            .drain(..)
+            .map(|(text, info)| {
+                time = parse_timestamp(&info.timestamp).unwrap_or(time);
+                (time, (text, info))
+            })
+            .collect();
+        keyed.reverse();
+        keyed.sort_by_key(|(time, _)| Reverse(*time));
+        held.extend(keyed.into_iter().map(|(_, record)| record));
+
+        if let Some(head) = self.head {
+            held.truncate(head as usize);
+        }
+        if let Some(tail) = self.tail {
+            held.drain(..held.len().saturating_sub(tail));
+        }
+        held
+    }
+}
@@ -0,0 +1,125 @@
+//! HTTP server decoding records on request, so other services can reuse
+//! the decoders without running the command line tool
+//!
+//! `POST /decode` takes a message as JSON, either a tracing JSON object or
+//! a string holding the raw message field, or an array of them. It answers
+//! with an object per message, or an array of them for an array:
+//!
+//! ```json
+//! {"text": "[2024-05-01T10:00:00Z][INFO][virt] ...", "timestamp": "...",
+//!  "level": "INFO", "target": "virt", "vp": 3}
+//! ```
+//!
+//! `text` is null for messages hidden by the filters. `GET /health`
+//! answers `ok` once the server is ready.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::error::Error;
+use std::io::Read;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::input::Record;
+use crate::sink::RecordInfo;
+
+/// Largest request body accepted
+const MAX_BODY_BYTES: u64 = 16 << 20;
+
+/// A decoded message
+#[derive(Serialize)]
+struct Decoded {
+    text: Option<String>,
+    timestamp: String,
+    level: String,
+    target: String,
+    vp: Option<u64>,
+}
+
+/// Decode the message or messages of a request body
+fn decode_body(
+    body: &str,
+    format: &mut impl FnMut(Record) -> Result<(String, RecordInfo), Box<dyn Error>>,
+) -> Result<Value, Box<dyn Error>> {
+    let mut decode = |message: Value| -> Result<Value, Box<dyn Error>> {
+        let message = match message {
+            Value::String(text) => text,
+            other => other.to_string(),
+        };
+        let (text, info) = format(Record {
+            message,
+            parsed: None,
+            monotonic_us: None,
+            end_offset: None,
+        })?;
+        Ok(serde_json::to_value(Decoded {
+            text: (!text.is_empty()).then_some(text),
+            timestamp: info.timestamp,
+            level: info.level,
+            target: info.target,
+            vp: info.vp,
+        })?)
+    };
+
+    match serde_json::from_str(body)? {
+        Value::Array(messages) => Ok(Value::Array(
+            messages
+                .into_iter()
+                .map(&mut decode)
+                .collect::<Result<_, _>>()?,
+        )),
+        message => decode(message),
+    }
+}
+
+fn text_response(status: u16, text: String) -> Response<std::io::Cursor<Vec<u8>>> {
+    Response::from_string(text).with_status_code(status)
+}
+
+/// Answer a single request
+fn respond(
+    mut request: Request,
+    format: &mut impl FnMut(Record) -> Result<(String, RecordInfo), Box<dyn Error>>,
+) -> std::io::Result<()> {
+    let response = match (request.method(), request.url()) {
+        (Method::Get, "/health") => text_response(200, "ok".to_string()),
+        (Method::Post, "/decode") => {
+            let mut body = String::new();
+            let read = request
+                .as_reader()
+                .take(MAX_BODY_BYTES)
+                .read_to_string(&mut body);
+            match read
+                .map_err(Box::<dyn Error>::from)
+                .and_then(|_| decode_body(&body, format))
+            {
+                Ok(decoded) => {
+                    let content_type =
+                        Header::from_bytes("Content-Type", "application/json").unwrap();
+                    text_response(200, decoded.to_string()).with_header(content_type)
+                }
+                Err(err) => text_response(400, err.to_string()),
+            }
+        }
+        (_, "/health") | (_, "/decode") => text_response(405, "Method not allowed".to_string()),
+        _ => text_response(404, "Not found".to_string()),
+    };
+    request.respond(response)
+}
+
+/// Serve decode requests on `address`, such as `127.0.0.1:8080`, until the
+/// process is stopped, formatting each message with `format`
+pub fn serve(
+    address: &str,
+    mut format: impl FnMut(Record) -> Result<(String, RecordInfo), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    let server =
+        Server::http(address).map_err(|err| format!("Failed to listen on {}: {}", address, err))?;
+    eprintln!("Listening on http://{}", server.server_addr());
+
+    for request in server.incoming_requests() {
+        if let Err(err) = respond(request, &mut format) {
+            eprintln!("Failed to answer request: {}", err);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,101 @@
+//! Destinations for formatted records
+
+use std::error::Error;
+use std::io::{BufWriter, Stdout, Write};
+
+use crate::vp_links::VpEvent;
+
+/// What is known about a formatted record, for sinks that label or route
+/// records
+///
+/// The header fields are empty for lines that aren't tracing records.
+#[derive(Clone, Debug, Default)]
+pub struct RecordInfo {
+    pub timestamp: String,
+    pub level: String,
+    pub target: String,
+    /// Index of the VP the record is about, if it names one
+    pub vp: Option<u64>,
+    /// Whether the record enters or leaves its VP, set for --vp-links
+    pub vp_event: Option<VpEvent>,
+    /// Value of the --group-by field, if the record has it
+    pub group: Option<String>,
+    /// Byte offset just past the record in the input, when the source
+    /// tracks one
+    pub end_offset: Option<u64>,
+    /// How the record was formatted, as lines to show below it, with
+    /// --explain
+    pub explanation: String,
+}
+
+impl RecordInfo {
+    /// Reset the info for another record, keeping the buffers
+    pub fn reset(&mut self, end_offset: Option<u64>) {
+        self.timestamp.clear();
+        self.level.clear();
+        self.target.clear();
+        self.vp = None;
+        self.vp_event = None;
+        self.group = None;
+        self.end_offset = end_offset;
+        self.explanation.clear();
+    }
+
+    /// Set the header fields of a tracing record
+    pub fn set_header(&mut self, timestamp: &str, level: &str, target: &str) {
+        self.timestamp.push_str(timestamp);
+        self.level.push_str(level);
+        self.target.push_str(target);
+    }
+}
+
+/// A destination for formatted records
+pub trait Sink {
+    /// Write a formatted record
+    fn write_record(&mut self, text: &str, info: &RecordInfo) -> Result<(), Box<dyn Error>>;
+
+    /// Note that a record produced no output, given the input offset just
+    /// past it
+    fn skip_record(&mut self, _end_offset: Option<u64>) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    /// Push out any buffered records
+    fn flush(&mut self) -> Result<(), Box<dyn Error>>;
+
+    /// Complete the output once the whole input has been written
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.flush()
+    }
+
+    /// Complete the output of a run interrupted before the end of its
+    /// input
+    fn interrupt(&mut self) -> Result<(), Box<dyn Error>> {
+        self.finish()
+    }
+}
+
+/// Writes records to standard output, one per line
+pub struct StdoutSink {
+    writer: BufWriter<Stdout>,
+}
+
+impl StdoutSink {
+    pub fn new() -> Self {
+        StdoutSink {
+            writer: BufWriter::new(std::io::stdout()),
+        }
+    }
+}
+
+impl Sink for StdoutSink {
+    fn write_record(&mut self, text: &str, _info: &RecordInfo) -> Result<(), Box<dyn Error>> {
+        writeln!(self.writer, "{}", text)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
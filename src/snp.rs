@@ -0,0 +1,172 @@
+//! Formatting of SEV-SNP VMSA dumps
+
+use regex::Regex;
+use serde_json::Value;
+
+use crate::decoder::{Conditions, Decoded, Decoder, FieldContext};
+use crate::numbers::{self, DECIMAL};
+use crate::validate;
+
+/// SEV_FEATURES bit names
+const SEV_FEATURES: &[(u32, &str)] = &[
+    (0, "SNPActive"),
+    (1, "vTOM"),
+    (2, "ReflectVC"),
+    (3, "RestrictedInjection"),
+    (4, "AlternateInjection"),
+    (5, "DebugSwap"),
+    (6, "PreventHostIBS"),
+    (7, "BTBIsolation"),
+    (8, "VmplSSS"),
+    (9, "SecureTSC"),
+    (10, "VmgexitParameter"),
+    (11, "PmcVirtualization"),
+    (12, "IbsVirtualization"),
+    (14, "VmsaRegProt"),
+    (15, "SmtProtection"),
+];
+
+/// EFER bit names
+const EFER_BITS: &[(u32, &str)] = &[
+    (0, "SCE"),
+    (8, "LME"),
+    (10, "LMA"),
+    (11, "NXE"),
+    (12, "SVME"),
+    (13, "LMSLE"),
+    (14, "FFXSR"),
+    (15, "TCE"),
+];
+
+fn decode_bits(value: u64, bits: &[(u32, &str)]) -> String {
+    let names: Vec<&str> = bits
+        .iter()
+        .filter(|(bit, _)| value & (1 << bit) != 0)
+        .map(|(_, name)| *name)
+        .collect();
+
+    if names.is_empty() {
+        "none".to_string()
+    } else {
+        names.join("|")
+    }
+}
+
+/// Decode the key VMSA fields, returning None for fields without a decoder
+fn decode_field(field: &str, value: u64) -> Option<String> {
+    match field {
+        "sev_features" => Some(decode_bits(value, SEV_FEATURES)),
+        "efer" => Some(decode_bits(value, EFER_BITS)),
+        "vmpl" => Some(format!("VMPL{}", value)),
+        _ => None,
+    }
+}
+
+/// Check whether a string embeds a VMSA dump
+pub fn is_vmsa_dump(text: &str) -> bool {
+    text.contains("SevVmsa") || text.contains("Vmsa {")
+}
+
+/// Transform VMSA dump contents to hex format, decoding sev_features, vmpl
+/// and efer
+pub fn transform_vmsa(text: &str) -> String {
+    let vmsa_field_regex = Regex::new(&format!(r"\b(\w+): ({})\b", DECIMAL)).unwrap();
+
+    vmsa_field_regex
+        .replace_all(text, |caps: &regex::Captures| {
+            let field = &caps[1];
+            let num = numbers::parse_decimal(&caps[2]).unwrap_or(0);
+            match decode_field(field, num) {
+                Some(decoded) => format!("{}: 0x{:x} ({})", field, num, decoded),
+                None => format!("{}: 0x{:x}", field, num),
+            }
+        })
+        .to_string()
+}
+
+/// Produce an annotation for a numeric sev_features or efer field
+pub fn annotate_field(key: &str, value: &Value) -> Option<String> {
+    match key {
+        "sev_features" | "efer" => decode_field(key, value.as_u64()?),
+        _ => None,
+    }
+}
+
+/// Decoder for SEV-SNP VMSA dumps in any string field
+pub struct VmsaDecoder {
+    conditions: Conditions,
+}
+
+impl VmsaDecoder {
+    pub fn new() -> Self {
+        VmsaDecoder {
+            conditions: Conditions::any().contains("Vmsa"),
+        }
+    }
+}
+
+impl Decoder for VmsaDecoder {
+    fn name(&self) -> &str {
+        "snp-vmsa"
+    }
+
+    fn description(&self) -> String {
+        "Rewrites SEV-SNP VMSA dumps as hex, decoding sev_features, vmpl and efer".to_string()
+    }
+
+    fn conditions(&self) -> &Conditions {
+        &self.conditions
+    }
+
+    fn example(&self) -> Option<(&str, Value)> {
+        Some((
+            "vmsa",
+            Value::from("SevVmsa { vmpl: 2, efer: 4352, sev_features: 33 }"),
+        ))
+    }
+
+    fn decode(&self, _key: &str, value: &Value, _ctx: &FieldContext) -> Option<Decoded> {
+        let text = value.as_str().filter(|text| is_vmsa_dump(text))?;
+        Some(Decoded::Replace(transform_vmsa(text)))
+    }
+
+    fn validate(&self, _key: &str, value: &Value, _ctx: &FieldContext) -> Option<String> {
+        let text = value.as_str().filter(|text| is_vmsa_dump(text))?;
+        validate::check_dump(text, &[])
+    }
+}
+
+/// Decoder for numeric sev_features and efer fields
+pub struct SevFeaturesDecoder {
+    conditions: Conditions,
+}
+
+impl SevFeaturesDecoder {
+    pub fn new() -> Self {
+        SevFeaturesDecoder {
+            conditions: Conditions::any().keys(&["sev_features", "efer"]),
+        }
+    }
+}
+
+impl Decoder for SevFeaturesDecoder {
+    fn name(&self) -> &str {
+        "snp-sev-features"
+    }
+
+    fn description(&self) -> String {
+        "Annotates sev_features and efer values with the names of the bits set".to_string()
+    }
+
+    fn conditions(&self) -> &Conditions {
+        &self.conditions
+    }
+
+    fn example(&self) -> Option<(&str, Value)> {
+        Some(("sev_features", Value::from(33u64)))
+    }
+
+    fn decode(&self, key: &str, value: &Value, _ctx: &FieldContext) -> Option<Decoded> {
+        annotate_field(key, value).map(Decoded::Annotate)
+    }
+}
@@ -0,0 +1,158 @@
+//! Time spent in each span, from the close events of tracing spans
+//!
+//! With span events enabled, tracing logs a `close` event when a span ends,
+//! carrying the time the span was busy and idle as `time.busy` and
+//! `time.idle`. Busy time of a span includes the time of spans entered
+//! within it.
+
+use std::collections::HashMap;
+use std::error::Error;
+
+#[cfg(feature = "arrow")]
+use crate::columnar;
+use crate::input::Records;
+use crate::report::{for_each_event, print_table, Event};
+use crate::units::{self, format_duration};
+
+/// Close events of one span name and target
+#[derive(Default)]
+struct SpanStats {
+    count: u64,
+    /// Total busy and idle time in nanoseconds
+    busy: f64,
+    idle: f64,
+    max_busy: f64,
+}
+
+/// Parse a duration as tracing writes it, like `1.23ms` or `45.6µs`
+fn parse_time(text: &str) -> Option<f64> {
+    match units::parse_quantity(text)? {
+        (value, Some(unit)) if unit.is_duration() => Some(unit.to_base(value)),
+        _ => None,
+    }
+}
+
+/// The busy and idle time of a span close event, if the record is one
+fn close_times(event: &Event) -> Option<(f64, f64)> {
+    let time = |key: &str| event.fields.get(key)?.as_str().and_then(parse_time);
+    Some((time("time.busy")?, time("time.idle").unwrap_or(0.0)))
+}
+
+/// Add up the close events of each span by target and name
+#[cfg(not(feature = "arrow"))]
+fn collect(records: Records) -> Result<HashMap<(String, String), SpanStats>, Box<dyn Error>> {
+    let mut spans: HashMap<(String, String), SpanStats> = HashMap::new();
+
+    for_each_event(records, |event| {
+        let Some((busy, idle)) = close_times(event) else {
+            return;
+        };
+
+        let stats = spans
+            .entry((event.target.to_string(), event.span.to_string()))
+            .or_default();
+        stats.count += 1;
+        stats.busy += busy;
+        stats.idle += idle;
+        stats.max_busy = stats.max_busy.max(busy);
+    })?;
+    Ok(spans)
+}
+
+/// Add up the close events of each span by target and name from columns of
+/// the events
+#[cfg(feature = "arrow")]
+fn collect(records: Records) -> Result<HashMap<(String, String), SpanStats>, Box<dyn Error>> {
+    use arrow_arith::aggregate;
+    use arrow_array::builder::{Float64Builder, StringBuilder};
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::Float64Type;
+    use arrow_array::ArrayRef;
+    use std::sync::Arc;
+
+    let mut targets = StringBuilder::new();
+    let mut names = StringBuilder::new();
+    let mut busy = Float64Builder::new();
+    let mut idle = Float64Builder::new();
+    for_each_event(records, |event| {
+        let Some((busy_time, idle_time)) = close_times(event) else {
+            return;
+        };
+        targets.append_value(event.target);
+        names.append_value(event.span);
+        busy.append_value(busy_time);
+        idle.append_value(idle_time);
+    })?;
+    let targets: ArrayRef = Arc::new(targets.finish());
+    let names: ArrayRef = Arc::new(names.finish());
+    let busy = busy.finish();
+    let idle = idle.finish();
+
+    let mut spans = HashMap::new();
+    let groups = columnar::group_by(&[targets.clone(), names.clone()], &[])?;
+    for group in &groups.ranges {
+        let row = groups.first(group);
+        let busy = groups.take(&busy, group)?;
+        let busy = busy.as_primitive::<Float64Type>();
+        let idle = groups.take(&idle, group)?;
+        spans.insert(
+            (
+                targets.as_string::<i32>().value(row).to_string(),
+                names.as_string::<i32>().value(row).to_string(),
+            ),
+            SpanStats {
+                count: group.len() as u64,
+                busy: aggregate::sum(busy).unwrap_or(0.0),
+                idle: aggregate::sum(idle.as_primitive::<Float64Type>()).unwrap_or(0.0),
+                max_busy: aggregate::max(busy).unwrap_or(0.0),
+            },
+        );
+    }
+    Ok(spans)
+}
+
+/// Print a table of spans by total busy time, with their idle time and
+/// share of the busy time of all spans
+pub fn spans(records: Records) -> Result<(), Box<dyn Error>> {
+    let spans = collect(records)?;
+
+    if spans.is_empty() {
+        println!("No span close events with time.busy found");
+        return Ok(());
+    }
+
+    let total: f64 = spans.values().map(|stats| stats.busy).sum();
+    let mut spans: Vec<_> = spans.into_iter().collect();
+    spans.sort_by(|(_, a), (_, b)| b.busy.total_cmp(&a.busy));
+
+    let rows: Vec<Vec<String>> = spans
+        .iter()
+        .map(|((target, name), stats)| {
+            vec![
+                target.clone(),
+                name.clone(),
+                stats.count.to_string(),
+                format_duration(stats.busy),
+                format!("{:.1}%", stats.busy / total.max(1.0) * 100.0),
+                format_duration(stats.busy / stats.count as f64),
+                format_duration(stats.max_busy),
+                format_duration(stats.idle),
+            ]
+        })
+        .collect();
+
+    print_table(
+        &[
+            "TARGET",
+            "SPAN",
+            "COUNT",
+            "BUSY",
+            "SHARE",
+            "MEAN BUSY",
+            "MAX BUSY",
+            "IDLE",
+        ],
+        &rows,
+    );
+    Ok(())
+}
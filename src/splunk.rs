@@ -0,0 +1,150 @@
+//! Forwarding of records to a Splunk HTTP Event Collector (HEC)
+//!
+//! Records are posted in batches to the event endpoint of the collector,
+//! authorized with the token in `SPLUNK_HEC_TOKEN`. Each event holds the
+//! `level`, `target` and `vp` of the record and its formatted `message`.
+
+use serde::Serialize;
+use std::error::Error;
+
+use crate::sink::{RecordInfo, Sink};
+use crate::time::parse_timestamp;
+use crate::upload::{Upload, UploadError, UploadOptions, Uploader};
+
+/// Path of the event endpoint, added to URLs given without it
+const EVENT_PATH: &str = "/services/collector/event";
+
+/// Environment variable holding the collector token, kept off the command
+/// line so it isn't saved with pipelines or shell history
+const TOKEN_VARIABLE: &str = "SPLUNK_HEC_TOKEN";
+
+/// Largest request body sent, below the default 1 MB limit of a collector
+const MAX_BATCH_BYTES: usize = 900 * 1024;
+
+#[derive(Serialize)]
+struct EventData<'a> {
+    level: &'a str,
+    target: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vp: Option<u64>,
+    message: &'a str,
+}
+
+/// Event in the HEC format
+#[derive(Serialize)]
+struct Event<'a> {
+    /// Seconds since the epoch, when the record has a time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    time: Option<f64>,
+    sourcetype: &'a str,
+    event: EventData<'a>,
+}
+
+/// Posts batches to the event endpoint
+struct SplunkClient {
+    url: String,
+    token: String,
+}
+
+impl Upload for SplunkClient {
+    fn upload(&mut self, body: &str) -> Result<(), UploadError> {
+        ureq::post(&self.url)
+            .set("Authorization", &format!("Splunk {}", self.token))
+            .set("Content-Type", "application/json")
+            .send_string(body)?;
+        Ok(())
+    }
+
+    fn failure(&self) -> String {
+        format!("Failed to send records to Splunk at {}", self.url)
+    }
+
+    fn destination(&self) -> String {
+        format!("Splunk at {}", self.url)
+    }
+}
+
+/// Sends records to a Splunk HTTP Event Collector in batches
+pub struct SplunkSink {
+    uploader: Uploader,
+    sourcetype: String,
+    /// Pending events, concatenated as the collector expects
+    batch: String,
+}
+
+impl SplunkSink {
+    /// Post to the collector at `url`, such as `https://splunk:8088`, with
+    /// events of the given sourcetype
+    pub fn new(
+        url: &str,
+        sourcetype: &str,
+        options: &UploadOptions,
+    ) -> Result<Self, Box<dyn Error>> {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(format!(
+                "Invalid Splunk HEC URL '{}', expected http:// or https://",
+                url
+            )
+            .into());
+        }
+        let token = match std::env::var(TOKEN_VARIABLE) {
+            Ok(token) => token,
+            // A dry run sends nothing, so it needs no token
+            Err(_) if options.dry_run => String::new(),
+            Err(_) => {
+                return Err(format!("Set {} to the token of the collector", TOKEN_VARIABLE).into())
+            }
+        };
+
+        let url = if url.contains("/services/collector") {
+            url.to_string()
+        } else {
+            format!("{}{}", url.trim_end_matches('/'), EVENT_PATH)
+        };
+
+        Ok(SplunkSink {
+            uploader: Uploader::new(SplunkClient { url, token }, options.clone())?,
+            sourcetype: sourcetype.to_string(),
+            batch: String::new(),
+        })
+    }
+}
+
+impl Sink for SplunkSink {
+    fn write_record(&mut self, text: &str, info: &RecordInfo) -> Result<(), Box<dyn Error>> {
+        // Events without a time are given the time they are received
+        let event = serde_json::to_string(&Event {
+            time: parse_timestamp(&info.timestamp).map(|ns| ns as f64 / 1e9),
+            sourcetype: &self.sourcetype,
+            event: EventData {
+                level: &info.level,
+                target: &info.target,
+                vp: info.vp,
+                message: text,
+            },
+        })?;
+
+        if !self.batch.is_empty() && self.batch.len() + event.len() > MAX_BATCH_BYTES {
+            self.flush()?;
+        }
+        self.batch.push_str(&event);
+        if self.uploader.add_record() {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+
+        let batch = std::mem::take(&mut self.batch);
+        self.uploader.send(batch)
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.flush()?;
+        self.uploader.finish()
+    }
+}
@@ -0,0 +1,58 @@
+//! Lists of known benign records, left out when looking for the cause of
+//! a failure
+//!
+//! A suppression list holds one regex per line, matched against
+//! `TARGET: MESSAGE` of each record, so a pattern can be limited to one
+//! target by anchoring it. Blank lines and lines starting with `#` are
+//! ignored.
+//!
+//! ```text
+//! # Retried and harmless
+//! ^underhill_core: failed to read optional config
+//! storvsp.*: scsi sense data
+//! ```
+
+use regex::Regex;
+use std::error::Error;
+use std::path::Path;
+
+/// Patterns of records to ignore
+pub struct Suppressions {
+    patterns: Vec<Regex>,
+}
+
+impl Suppressions {
+    /// A list that suppresses nothing
+    pub fn new() -> Self {
+        Suppressions {
+            patterns: Vec::new(),
+        }
+    }
+
+    /// Load a suppression list file
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+
+        let mut patterns = Vec::new();
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            patterns.push(Regex::new(line).map_err(|err| {
+                format!("{}:{}: invalid pattern: {}", path.display(), index + 1, err)
+            })?);
+        }
+        Ok(Suppressions { patterns })
+    }
+
+    /// Check whether a record is known to be benign
+    pub fn is_suppressed(&self, target: &str, message: &str) -> bool {
+        if self.patterns.is_empty() {
+            return false;
+        }
+        let text = format!("{}: {}", target, message);
+        self.patterns.iter().any(|pattern| pattern.is_match(&text))
+    }
+}
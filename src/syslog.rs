@@ -0,0 +1,190 @@
+//! Forwarding of records to a syslog server as RFC 5424 messages
+//!
+//! Record levels map to syslog severities and record targets become the
+//! app-name, so forwarded records can be routed by existing SIEM and
+//! syslog rules.
+//!
+//! With `--dry-run` messages are counted instead of sent, and the first is
+//! printed as a sample.
+
+use std::error::Error;
+use std::io::{BufWriter, Write};
+use std::net::{TcpStream, UdpSocket};
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+
+use crate::filter::Level;
+use crate::sink::{RecordInfo, Sink};
+use crate::time::{format_timestamp, parse_timestamp};
+
+/// Facility of forwarded messages, user-level messages
+const FACILITY_USER: u8 = 1;
+
+/// Longest app-name allowed by RFC 5424
+const MAX_APP_NAME: usize = 48;
+
+/// Connection to a syslog server
+enum Transport {
+    Udp(UdpSocket),
+    /// Messages are framed by octet counting, as in RFC 6587
+    Tcp(BufWriter<TcpStream>),
+    #[cfg(unix)]
+    Unix(UnixDatagram),
+    /// Messages counted for a dry run
+    DryRun {
+        address: String,
+        messages: u64,
+        bytes: u64,
+    },
+}
+
+/// Sends each record to a syslog server
+pub struct SyslogSink {
+    transport: Transport,
+    message: String,
+}
+
+/// Syslog severity of a record level
+fn severity(level: &str) -> u8 {
+    match Level::parse(level) {
+        Some(Level::Error) => 3,
+        Some(Level::Warn) => 4,
+        Some(Level::Info) => 6,
+        Some(Level::Debug) | Some(Level::Trace) => 7,
+        // Lines that aren't tracing records
+        None => 5,
+    }
+}
+
+/// App-name of a record target, printable ASCII without spaces
+fn app_name(target: &str) -> String {
+    let name: String = target
+        .chars()
+        .filter(|c| c.is_ascii_graphic())
+        .take(MAX_APP_NAME)
+        .collect();
+    if name.is_empty() {
+        "-".to_string()
+    } else {
+        name
+    }
+}
+
+impl SyslogSink {
+    /// Connect to a syslog server at `udp://host:port`, `tcp://host:port`
+    /// or `unix:///path`
+    pub fn connect(address: &str) -> Result<Self, Box<dyn Error>> {
+        let connect_error =
+            |err: std::io::Error| format!("Failed to connect to syslog at {}: {}", address, err);
+
+        let transport = if let Some(host) = address.strip_prefix("udp://") {
+            let socket = UdpSocket::bind("0.0.0.0:0").map_err(connect_error)?;
+            socket.connect(host).map_err(connect_error)?;
+            Transport::Udp(socket)
+        } else if let Some(host) = address.strip_prefix("tcp://") {
+            Transport::Tcp(BufWriter::new(
+                TcpStream::connect(host).map_err(connect_error)?,
+            ))
+        } else if let Some(path) = address.strip_prefix("unix://") {
+            #[cfg(unix)]
+            {
+                let socket = UnixDatagram::unbound().map_err(connect_error)?;
+                socket.connect(path).map_err(connect_error)?;
+                Transport::Unix(socket)
+            }
+            #[cfg(not(unix))]
+            return Err(format!("Unix sockets are not supported here: {}", path).into());
+        } else {
+            return Err(format!(
+                "Invalid syslog address '{}', expected udp://, tcp:// or unix://",
+                address
+            )
+            .into());
+        };
+
+        Ok(SyslogSink {
+            transport,
+            message: String::new(),
+        })
+    }
+
+    /// Count the messages that would be sent to `address` without
+    /// connecting to it
+    pub fn dry_run(address: &str) -> Self {
+        SyslogSink {
+            transport: Transport::DryRun {
+                address: address.to_string(),
+                messages: 0,
+                bytes: 0,
+            },
+            message: String::new(),
+        }
+    }
+}
+
+impl Sink for SyslogSink {
+    fn write_record(&mut self, text: &str, info: &RecordInfo) -> Result<(), Box<dyn Error>> {
+        use std::fmt::Write as _;
+
+        let timestamp = parse_timestamp(&info.timestamp)
+            .map(format_timestamp)
+            .unwrap_or_else(|| "-".to_string());
+
+        self.message.clear();
+        let _ = write!(
+            self.message,
+            "<{}>1 {} - {} - - - {}",
+            FACILITY_USER * 8 + severity(&info.level),
+            timestamp,
+            app_name(&info.target),
+            text
+        );
+
+        match &mut self.transport {
+            Transport::Udp(socket) => {
+                socket.send(self.message.as_bytes())?;
+            }
+            Transport::Tcp(stream) => {
+                write!(stream, "{} {}", self.message.len(), self.message)?;
+            }
+            #[cfg(unix)]
+            Transport::Unix(socket) => {
+                socket.send(self.message.as_bytes())?;
+            }
+            Transport::DryRun {
+                address,
+                messages,
+                bytes,
+            } => {
+                if *messages == 0 {
+                    eprintln!("First message for syslog at {}:\n{}", address, self.message);
+                }
+                *messages += 1;
+                *bytes += self.message.len() as u64;
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Transport::Tcp(stream) = &mut self.transport {
+            stream.flush()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Transport::DryRun {
+            address,
+            messages,
+            bytes,
+        } = &self.transport
+        {
+            eprintln!(
+                "Dry run: would send {} messages ({} bytes) to syslog at {}",
+                messages, bytes, address
+            );
+        }
+        self.flush()
+    }
+}
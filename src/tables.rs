@@ -0,0 +1,336 @@
+//! Decode tables installed outside of the binary, so names of new MSRs,
+//! exit reasons and GUIDs don't wait for a release
+//!
+//! `tables update` downloads the tables into the `tables` directory next to
+//! the user configuration file. A table source holds a directory per table
+//! version, plus `latest`, each with a `tables.toml` manifest:
+//!
+//! ```toml
+//! version = "2024.06"
+//!
+//! [[table]]
+//! name = "msrs"
+//! # Relative to the manifest
+//! file = "msrs.txt"
+//! sha256 = "..."
+//! ```
+//!
+//! The GUID table has the format of `--guid-map`. The other tables name
+//! numeric values, one `<number> <name>` per line with the number in
+//! decimal or `0x` hex, and annotate the fields holding such values.
+
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use crate::config;
+use crate::decoder::{Conditions, Decoded, Decoder, DecoderRegistry, FieldContext};
+use crate::guid::GuidNames;
+use crate::msrs::MSR_KEYS;
+use crate::numbers;
+use crate::report::print_table;
+
+/// Table of GUID names
+pub const GUID_TABLE: &str = "guids";
+
+/// Tables of numeric values with the fields they annotate
+pub const NUMBER_TABLES: &[(&str, &[&str])] = &[
+    ("msrs", MSR_KEYS),
+    ("vmx-exit-reasons", &["exit_reason", "vmx_exit_reason"]),
+    (
+        "svm-exit-codes",
+        &["exit_code", "sw_exit_code", "svm_exit_code"],
+    ),
+];
+
+/// Directory holding the installed tables
+pub fn directory() -> Option<PathBuf> {
+    Some(config::user_directory()?.join("tables"))
+}
+
+/// Path of an installed table
+fn table_path(name: &str) -> Option<PathBuf> {
+    Some(directory()?.join(format!("{}.txt", name)))
+}
+
+/// Parse a table of `<number> <name>` lines
+fn parse_numbers(path: &Path) -> Result<HashMap<u64, String>, Box<dyn Error>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+
+    let mut names = HashMap::new();
+    for (line_number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parsed = line
+            .split_once(char::is_whitespace)
+            .and_then(|(number, name)| Some((numbers::parse_integer(number)?, name.trim())));
+        let Some((number, name)) = parsed else {
+            return Err(format!(
+                "{}:{}: expected '<number> <name>'",
+                path.display(),
+                line_number + 1
+            )
+            .into());
+        };
+        names.insert(number, name.to_string());
+    }
+    Ok(names)
+}
+
+/// The names in an installed table of numeric values, if it is installed
+pub fn load_numbers(name: &str) -> Result<Option<HashMap<u64, String>>, Box<dyn Error>> {
+    match table_path(name).filter(|path| path.is_file()) {
+        Some(path) => Ok(Some(parse_numbers(&path)?)),
+        None => Ok(None),
+    }
+}
+
+/// Add the installed GUID table to a set of names
+pub fn load_guids(names: &mut GuidNames) -> Result<(), Box<dyn Error>> {
+    if let Some(path) = table_path(GUID_TABLE).filter(|path| path.is_file()) {
+        names.load(&path)?;
+    }
+    Ok(())
+}
+
+/// Decoder annotating numeric fields with their name in a table
+pub struct TableDecoder {
+    name: String,
+    names: HashMap<u64, String>,
+    conditions: Conditions,
+}
+
+impl Decoder for TableDecoder {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "Annotates numbers with their name in the installed {} table",
+            self.name.trim_start_matches("table-")
+        )
+    }
+
+    fn conditions(&self) -> &Conditions {
+        &self.conditions
+    }
+
+    fn example(&self) -> Option<(&str, Value)> {
+        let key = self.conditions.keys.first()?;
+        let number = self.names.keys().min()?;
+        Some((key, Value::from(*number)))
+    }
+
+    fn decode(&self, _key: &str, value: &Value, _ctx: &FieldContext) -> Option<Decoded> {
+        let number = match value {
+            Value::String(text) => numbers::parse_integer(text)?,
+            other => other.as_u64()?,
+        };
+        let name = self.names.get(&number)?;
+        Some(Decoded::Annotate(name.clone()))
+    }
+}
+
+/// Register a decoder for each installed table of numeric values
+pub fn register(decoders: &mut DecoderRegistry) -> Result<(), Box<dyn Error>> {
+    for (name, keys) in NUMBER_TABLES {
+        if let Some(names) = load_numbers(name)? {
+            decoders.register(Box::new(TableDecoder {
+                name: format!("table-{}", name),
+                names,
+                conditions: Conditions::any().keys(keys),
+            }));
+        }
+    }
+    Ok(())
+}
+
+/// Record of the installed tables
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Installed {
+    source: String,
+    version: String,
+    /// Whether updates keep to `version`
+    pinned: bool,
+}
+
+/// Path of the record of the installed tables
+fn installed_path(directory: &Path) -> PathBuf {
+    directory.join("installed.toml")
+}
+
+fn read_installed(directory: &Path) -> Result<Option<Installed>, Box<dyn Error>> {
+    let path = installed_path(directory);
+    let Ok(content) = std::fs::read_to_string(&path) else {
+        return Ok(None);
+    };
+    Ok(Some(toml::from_str(&content).map_err(|err| {
+        format!("Failed to parse {}: {}", path.display(), err)
+    })?))
+}
+
+/// Print the installed tables with their version and size
+pub fn list() -> Result<(), Box<dyn Error>> {
+    let directory = directory().ok_or("No user configuration directory holding tables")?;
+    println!("Tables directory: {}", directory.display());
+    match read_installed(&directory)? {
+        Some(installed) => println!(
+            "Version: {}{}, from {}",
+            installed.version,
+            if installed.pinned { " (pinned)" } else { "" },
+            installed.source
+        ),
+        None => println!("Version: none installed"),
+    }
+
+    let mut rows = Vec::new();
+    for name in std::iter::once(GUID_TABLE).chain(NUMBER_TABLES.iter().map(|(name, _)| *name)) {
+        let entries = match table_path(name).filter(|path| path.is_file()) {
+            Some(path) if name == GUID_TABLE => {
+                GuidNames::new().load(&path)?;
+                let content = std::fs::read_to_string(&path)?;
+                let lines = content.lines().map(str::trim);
+                let entries = lines.filter(|line| !line.is_empty() && !line.starts_with('#'));
+                entries.count().to_string()
+            }
+            Some(path) => parse_numbers(&path)?.len().to_string(),
+            None => "not installed".to_string(),
+        };
+        rows.push(vec![name.to_string(), entries]);
+    }
+    print_table(&["TABLE", "ENTRIES"], &rows);
+    Ok(())
+}
+
+/// Manifest of a table version
+#[cfg(feature = "tables-update")]
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    version: String,
+    #[serde(default, rename = "table")]
+    tables: Vec<ManifestTable>,
+}
+
+#[cfg(feature = "tables-update")]
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ManifestTable {
+    name: String,
+    file: String,
+    sha256: String,
+}
+
+/// Source of the tables when none is given
+#[cfg(feature = "tables-update")]
+pub const DEFAULT_SOURCE: &str =
+    "https://raw.githubusercontent.com/chris-oo/kusto-kmsg-extract/main/tables";
+
+/// Read a file of a table source, a URL or a local directory
+#[cfg(feature = "tables-update")]
+fn fetch(source: &str, path: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    use std::io::Read;
+
+    if source.starts_with("http://") || source.starts_with("https://") {
+        let url = format!("{}/{}", source.trim_end_matches('/'), path);
+        let mut data = Vec::new();
+        ureq::get(&url)
+            .call()
+            .map_err(|err| format!("Failed to download {}: {}", url, err))?
+            .into_reader()
+            .read_to_end(&mut data)
+            .map_err(|err| format!("Failed to download {}: {}", url, err))?;
+        Ok(data)
+    } else {
+        let path = Path::new(source).join(path);
+        Ok(std::fs::read(&path)
+            .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?)
+    }
+}
+
+/// Download the tables of a version, or of the pinned or latest version,
+/// into the tables directory. Giving a version pins it for later updates,
+/// until `latest` is set.
+#[cfg(feature = "tables-update")]
+pub fn update(
+    source: Option<&str>,
+    version: Option<&str>,
+    latest: bool,
+) -> Result<(), Box<dyn Error>> {
+    use sha2::{Digest, Sha256};
+
+    let directory = directory().ok_or("No user configuration directory to install tables in")?;
+    let installed = read_installed(&directory)?;
+
+    let source = source
+        .map(str::to_string)
+        .or_else(|| installed.as_ref().map(|installed| installed.source.clone()))
+        .unwrap_or_else(|| DEFAULT_SOURCE.to_string());
+    let (wanted, pinned) = match (version, &installed) {
+        (Some(version), _) => (version.to_string(), true),
+        (None, Some(installed)) if installed.pinned && !latest => (installed.version.clone(), true),
+        _ => ("latest".to_string(), false),
+    };
+
+    let manifest = fetch(&source, &format!("{}/tables.toml", wanted))?;
+    let manifest: Manifest = toml::from_str(&String::from_utf8_lossy(&manifest))
+        .map_err(|err| format!("Failed to parse the tables.toml of {}: {}", wanted, err))?;
+
+    std::fs::create_dir_all(&directory)
+        .map_err(|err| format!("Failed to create {}: {}", directory.display(), err))?;
+    let mut updated = Vec::new();
+    for table in &manifest.tables {
+        let known =
+            table.name == GUID_TABLE || NUMBER_TABLES.iter().any(|(name, _)| *name == table.name);
+        if !known {
+            eprintln!("Skipping table '{}', unknown to this version", table.name);
+            continue;
+        }
+
+        let data = fetch(&source, &format!("{}/{}", wanted, table.file))?;
+        let hash: String = Sha256::digest(&data)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        if !hash.eq_ignore_ascii_case(&table.sha256) {
+            return Err(format!(
+                "Table '{}' doesn't match its SHA-256 in tables.toml, nothing was installed",
+                table.name
+            )
+            .into());
+        }
+        updated.push((table.name.as_str(), data));
+    }
+
+    // Tables are only replaced once all of them downloaded and verified
+    for (name, data) in &updated {
+        let path = directory.join(format!("{}.txt", name));
+        let partial = directory.join(format!("{}.txt.partial", name));
+        std::fs::write(&partial, data)
+            .map_err(|err| format!("Failed to write {}: {}", partial.display(), err))?;
+        std::fs::rename(&partial, &path)
+            .map_err(|err| format!("Failed to write {}: {}", path.display(), err))?;
+    }
+    let installed = Installed {
+        source,
+        version: manifest.version.clone(),
+        pinned,
+    };
+    let path = installed_path(&directory);
+    std::fs::write(&path, toml::to_string(&installed)?)
+        .map_err(|err| format!("Failed to write {}: {}", path.display(), err))?;
+
+    let names: Vec<&str> = updated.iter().map(|(name, _)| *name).collect();
+    println!(
+        "Installed tables version {}{}: {}",
+        manifest.version,
+        if pinned { " (pinned)" } else { "" },
+        names.join(", ")
+    );
+    Ok(())
+}
@@ -0,0 +1,308 @@
+//! Colors of the records written to a terminal
+//!
+//! A theme styles the timestamp, the level and target of each record and
+//! the fields whose names match a pattern. Besides the built-in `dark`,
+//! `light` and `minimal` themes, a TOML file with a `[theme]` section can
+//! adjust one of them:
+//!
+//! ```toml
+//! [theme]
+//! base = "light"
+//! timestamp = "dim"
+//!
+//! [theme.levels]
+//! WARN = "bold magenta"
+//!
+//! [theme.targets]
+//! "virt_mshv_vtl" = "cyan"
+//!
+//! [theme.fields]
+//! "^(vp|vp_index)$" = "bright_blue"
+//! ```
+//!
+//! Styles are made of the words `bold`, `dim`, `italic`, `underline`, a
+//! color such as `red` or `bright_red`, and `on_` followed by a color for
+//! the background, or `none`. Targets match by prefix, the longest first,
+//! and field patterns are tried in alphabetical order.
+
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Write as _;
+use std::io::IsTerminal;
+use std::path::Path;
+
+/// Names of the built-in themes
+pub const BUILTIN_THEMES: &[&str] = &["dark", "light", "minimal"];
+
+const COLORS: &[&str] = &[
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+const RESET: &str = "\x1b[0m";
+
+/// When records are colored
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ColorChoice {
+    /// When writing to a terminal that supports it
+    Auto,
+    Always,
+    Never,
+}
+
+/// Check whether records are colored, given whether they go to standard
+/// output
+///
+/// Automatic color honors the NO_COLOR and CLICOLOR conventions, and
+/// CLICOLOR_FORCE colors output that isn't a terminal, like a CI log.
+pub fn use_color(choice: ColorChoice, to_stdout: bool) -> bool {
+    let set = |name: &str| std::env::var_os(name).filter(|value| !value.is_empty());
+    let colored = match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto if set("NO_COLOR").is_some() => false,
+        ColorChoice::Auto if set("CLICOLOR_FORCE").is_some_and(|value| value != "0") => to_stdout,
+        ColorChoice::Auto => {
+            to_stdout
+                && std::io::stdout().is_terminal()
+                && set("CLICOLOR").is_none_or(|value| value != "0")
+                && std::env::var("TERM").map_or(true, |term| term != "dumb")
+        }
+    };
+    // Consoles that can't be switched to escape sequences would show them
+    // as text
+    colored && (enable_escape_sequences() || choice == ColorChoice::Always)
+}
+
+/// Let the Windows console interpret escape sequences, which the legacy
+/// console and older PowerShell hosts leave off
+#[cfg(windows)]
+fn enable_escape_sequences() -> bool {
+    use std::os::windows::io::AsRawHandle;
+
+    const ENABLE_VIRTUAL_TERMINAL_PROCESSING: u32 = 0x0004;
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetConsoleMode(handle: *mut std::ffi::c_void, mode: *mut u32) -> i32;
+        fn SetConsoleMode(handle: *mut std::ffi::c_void, mode: u32) -> i32;
+    }
+
+    let handle = std::io::stdout().as_raw_handle();
+    let mut mode = 0;
+    // SAFETY: the handle is the process's standard output and `mode` is a
+    // valid place for the console mode
+    unsafe {
+        if GetConsoleMode(handle, &mut mode) == 0 {
+            // Not a console, such as a pipe forced to color
+            return true;
+        }
+        mode & ENABLE_VIRTUAL_TERMINAL_PROCESSING != 0
+            || SetConsoleMode(handle, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) != 0
+    }
+}
+
+#[cfg(not(windows))]
+fn enable_escape_sequences() -> bool {
+    true
+}
+
+/// An SGR escape sequence starting a style
+#[derive(Clone, Debug, PartialEq)]
+pub struct Style(String);
+
+impl Style {
+    /// Parse a style such as `bold bright_red`
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut codes = Vec::new();
+        for word in text.split_whitespace() {
+            let (background, color) = match word.strip_prefix("on_") {
+                Some(color) => (true, color),
+                None => (false, word),
+            };
+            let (bright, color) = match color.strip_prefix("bright_") {
+                Some(color) => (true, color),
+                None => (false, color),
+            };
+            let code = match (word, COLORS.iter().position(|name| *name == color)) {
+                ("none", _) => continue,
+                ("bold", _) => 1,
+                ("dim", _) => 2,
+                ("italic", _) => 3,
+                ("underline", _) => 4,
+                (_, Some(index)) => {
+                    index as u32 + if bright { 90 } else { 30 } + if background { 10 } else { 0 }
+                }
+                _ => {
+                    return Err(format!(
+                        "Invalid style word '{}', expected bold, dim, italic, underline or a color like red, bright_red or on_red",
+                        word
+                    ))
+                }
+            };
+            codes.push(code.to_string());
+        }
+        Ok(Style(if codes.is_empty() {
+            String::new()
+        } else {
+            format!("\x1b[{}m", codes.join(";"))
+        }))
+    }
+}
+
+/// Styles of the parts of a record
+pub struct Theme {
+    timestamp: Option<Style>,
+    /// Styles of levels, by uppercase name
+    levels: BTreeMap<String, Style>,
+    /// Styles of target prefixes, longest prefix first
+    targets: Vec<(String, Style)>,
+    /// Styles of fields whose name matches a pattern
+    fields: Vec<(Regex, Style)>,
+}
+
+/// The `[theme]` section of a theme file
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ThemeSection {
+    base: Option<String>,
+    timestamp: Option<String>,
+    levels: BTreeMap<String, String>,
+    targets: BTreeMap<String, String>,
+    fields: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct ThemeFile {
+    #[serde(default)]
+    theme: ThemeSection,
+}
+
+impl Theme {
+    /// A built-in theme by name
+    pub fn builtin(name: &str) -> Option<Self> {
+        let levels: &[(&str, &str)] = match name {
+            "dark" => &[
+                ("TRACE", "dim"),
+                ("DEBUG", "bright_blue"),
+                ("INFO", "green"),
+                ("WARN", "bold bright_yellow"),
+                ("ERROR", "bold bright_red"),
+            ],
+            // Yellow is hard to read on a light background
+            "light" => &[
+                ("TRACE", "dim"),
+                ("DEBUG", "blue"),
+                ("INFO", "green"),
+                ("WARN", "bold magenta"),
+                ("ERROR", "bold red"),
+            ],
+            "minimal" => &[("WARN", "bold"), ("ERROR", "bold red")],
+            _ => return None,
+        };
+        Some(Theme {
+            timestamp: (name != "minimal").then(|| Style::parse("dim").unwrap()),
+            levels: levels
+                .iter()
+                .map(|(level, style)| (level.to_string(), Style::parse(style).unwrap()))
+                .collect(),
+            targets: Vec::new(),
+            fields: Vec::new(),
+        })
+    }
+
+    /// A built-in theme by name, or one loaded from a theme file
+    pub fn select(name_or_path: &str) -> Result<Self, Box<dyn Error>> {
+        match Theme::builtin(name_or_path) {
+            Some(theme) => Ok(theme),
+            None => Theme::load(Path::new(name_or_path)),
+        }
+    }
+
+    /// Load the `[theme]` section of a file, applied over its base theme
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let content = std::fs::read_to_string(path).map_err(|err| {
+            format!(
+                "Failed to read theme {}: {}, expected a file or one of {}",
+                path.display(),
+                err,
+                BUILTIN_THEMES.join(", ")
+            )
+        })?;
+        let file: ThemeFile = toml::from_str(&content)
+            .map_err(|err| format!("Failed to parse {}: {}", path.display(), err))?;
+        let section = file.theme;
+        let invalid = |err: String| format!("{}: {}", path.display(), err);
+
+        let base = section.base.as_deref().unwrap_or("dark");
+        let mut theme = Theme::builtin(base).ok_or_else(|| {
+            invalid(format!(
+                "unknown base theme '{}', expected one of {}",
+                base,
+                BUILTIN_THEMES.join(", ")
+            ))
+        })?;
+        if let Some(style) = &section.timestamp {
+            theme.timestamp = Some(Style::parse(style).map_err(invalid)?);
+        }
+        for (level, style) in &section.levels {
+            let style = Style::parse(style).map_err(invalid)?;
+            theme.levels.insert(level.to_uppercase(), style);
+        }
+        for (target, style) in &section.targets {
+            let style = Style::parse(style).map_err(invalid)?;
+            theme.targets.push((target.clone(), style));
+        }
+        theme
+            .targets
+            .sort_by_key(|(target, _)| std::cmp::Reverse(target.len()));
+        for (pattern, style) in &section.fields {
+            let regex = Regex::new(pattern)
+                .map_err(|err| invalid(format!("invalid field pattern '{}': {}", pattern, err)))?;
+            theme
+                .fields
+                .push((regex, Style::parse(style).map_err(invalid)?));
+        }
+        Ok(theme)
+    }
+
+    pub fn timestamp(&self) -> Option<&Style> {
+        self.timestamp.as_ref()
+    }
+
+    pub fn level(&self, level: &str) -> Option<&Style> {
+        self.levels.get(&level.to_uppercase())
+    }
+
+    pub fn target(&self, target: &str) -> Option<&Style> {
+        self.targets
+            .iter()
+            .find(|(prefix, _)| target.starts_with(prefix.as_str()))
+            .map(|(_, style)| style)
+    }
+
+    pub fn field(&self, key: &str) -> Option<&Style> {
+        self.fields
+            .iter()
+            .find(|(pattern, _)| pattern.is_match(key))
+            .map(|(_, style)| style)
+    }
+}
+
+/// Write `text` in a style, or plainly without one
+pub fn write_styled(output: &mut String, text: &str, style: Option<&Style>) {
+    match style.filter(|style| !style.0.is_empty()) {
+        Some(style) => {
+            let _ = write!(output, "{}{}{}", style.0, text, RESET);
+        }
+        None => output.push_str(text),
+    }
+}
+
+/// Style the text from `start` to the end of `output`
+pub fn paint(output: &mut String, start: usize, style: &Style) {
+    if !style.0.is_empty() {
+        output.insert_str(start, &style.0);
+        output.push_str(RESET);
+    }
+}
@@ -0,0 +1,273 @@
+//! Parsing of record timestamps
+
+use serde_json::Value;
+
+/// Days from 1970-01-01 to a date in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Parse a fixed number of ASCII digits
+fn digits(text: &str, range: std::ops::Range<usize>) -> Option<u32> {
+    let part = text.get(range)?;
+    if !part.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    part.parse().ok()
+}
+
+/// Parse an ISO 8601 timestamp such as `2024-05-01T10:00:00.123456Z` into
+/// nanoseconds since the Unix epoch
+///
+/// The date and time may be separated by a space, the fraction may have
+/// up to nine digits, and the zone may be `Z`, an offset like `+02:00`, or
+/// missing, in which case UTC is assumed.
+pub fn parse_timestamp(text: &str) -> Option<i64> {
+    let text = text.trim();
+    let year = digits(text, 0..4)?;
+    let month = digits(text, 5..7)?;
+    let day = digits(text, 8..10)?;
+    let hour = digits(text, 11..13)?;
+    let minute = digits(text, 14..16)?;
+    let second = digits(text, 17..19)?;
+    if text.get(4..5)? != "-"
+        || text.get(7..8)? != "-"
+        || !matches!(text.get(10..11)?, "T" | "t" | " ")
+        || text.get(13..14)? != ":"
+        || text.get(16..17)? != ":"
+        || !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+    {
+        return None;
+    }
+
+    let mut rest = &text[19..];
+    let mut nanos = 0i64;
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let len = fraction
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(fraction.len());
+        if len == 0 {
+            return None;
+        }
+        for (i, b) in fraction[..len.min(9)].bytes().enumerate() {
+            nanos += (b - b'0') as i64 * 10i64.pow(8 - i as u32);
+        }
+        rest = &fraction[len..];
+    }
+
+    let offset_seconds = match rest {
+        "" | "Z" | "z" => 0,
+        _ => {
+            let sign = match rest.get(..1)? {
+                "+" => 1,
+                "-" => -1,
+                _ => return None,
+            };
+            let offset = rest[1..].replace(':', "");
+            let hours = digits(&offset, 0..2)? as i64;
+            let minutes = if offset.len() > 2 {
+                digits(&offset, 2..4)? as i64
+            } else {
+                0
+            };
+            sign * (hours * 3600 + minutes * 60)
+        }
+    };
+
+    let days = days_from_civil(year as i64, month, day);
+    let seconds =
+        days * 86400 + hour as i64 * 3600 + minute as i64 * 60 + second as i64 - offset_seconds;
+    Some(seconds * 1_000_000_000 + nanos)
+}
+
+/// Abbreviated month names matched by `%b`
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/// How the timestamps of records are written, when they aren't ISO 8601
+#[derive(Clone, Debug)]
+pub enum TimestampFormat {
+    /// A number of seconds, milliseconds, microseconds or nanoseconds since
+    /// the Unix epoch, given as the nanoseconds in one unit
+    Epoch(i64),
+    /// A strftime pattern such as `%d/%m/%Y %H:%M:%S`
+    Pattern(String),
+}
+
+/// Parse a `--timestamp-format`, which is `epoch-s`, `epoch-ms`,
+/// `epoch-us`, `epoch-ns` or a strftime pattern, used as a clap value
+/// parser
+pub fn parse_timestamp_format(text: &str) -> Result<TimestampFormat, String> {
+    let unit = match text {
+        "epoch-s" => 1_000_000_000,
+        "epoch-ms" => 1_000_000,
+        "epoch-us" => 1000,
+        "epoch-ns" => 1,
+        _ => {
+            let mut chars = text.chars();
+            while let Some(c) = chars.next() {
+                if c == '%'
+                    && !matches!(
+                        chars.next(),
+                        Some('Y' | 'm' | 'd' | 'H' | 'M' | 'S' | 'f' | 'z' | 'b' | 'F' | 'T' | '%')
+                    )
+                {
+                    return Err(format!(
+                        "Invalid timestamp format '{}', expected epoch-s, epoch-ms, epoch-us, epoch-ns or a pattern of %Y %m %d %b %H %M %S %f %z %F %T",
+                        text
+                    ));
+                }
+            }
+            return Ok(TimestampFormat::Pattern(text.to_string()));
+        }
+    };
+    Ok(TimestampFormat::Epoch(unit))
+}
+
+impl TimestampFormat {
+    /// Parse the timestamp of a record, a string or a number, into
+    /// nanoseconds since the Unix epoch
+    pub fn parse(&self, value: &Value) -> Option<i64> {
+        match self {
+            TimestampFormat::Epoch(unit) => match value {
+                Value::Number(number) => match number.as_i64() {
+                    Some(whole) => whole.checked_mul(*unit),
+                    None => Some((number.as_f64()? * *unit as f64) as i64),
+                },
+                Value::String(text) => {
+                    let text = text.trim();
+                    match text.parse::<i64>() {
+                        Ok(whole) => whole.checked_mul(*unit),
+                        Err(_) => Some((text.parse::<f64>().ok()? * *unit as f64) as i64),
+                    }
+                }
+                _ => None,
+            },
+            TimestampFormat::Pattern(pattern) => parse_pattern(pattern, value.as_str()?),
+        }
+    }
+}
+
+/// Take up to `max` digits from the front of the text, with their count
+fn take_digits(text: &mut &str, max: usize) -> Option<(i64, usize)> {
+    let len = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len())
+        .min(max);
+    if len == 0 {
+        return None;
+    }
+    let value = text[..len].parse().ok()?;
+    *text = &text[len..];
+    Some((value, len))
+}
+
+/// Parse text written with a strftime pattern, assuming UTC when the
+/// pattern has no `%z`
+fn parse_pattern(pattern: &str, text: &str) -> Option<i64> {
+    let pattern = pattern.replace("%F", "%Y-%m-%d").replace("%T", "%H:%M:%S");
+    let (mut year, mut month, mut day) = (1970i64, 1u32, 1u32);
+    let (mut hour, mut minute, mut second) = (0i64, 0i64, 0i64);
+    let (mut nanos, mut offset_seconds) = (0i64, 0i64);
+
+    let mut rest = text.trim();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            rest = rest.strip_prefix(c)?;
+            continue;
+        }
+        match chars.next()? {
+            'Y' => year = take_digits(&mut rest, 4)?.0,
+            'm' => month = take_digits(&mut rest, 2)?.0 as u32,
+            'd' => day = take_digits(&mut rest, 2)?.0 as u32,
+            'H' => hour = take_digits(&mut rest, 2)?.0,
+            'M' => minute = take_digits(&mut rest, 2)?.0,
+            'S' => second = take_digits(&mut rest, 2)?.0,
+            'f' => {
+                let (value, len) = take_digits(&mut rest, 9)?;
+                nanos = value * 10i64.pow(9 - len as u32);
+                // Digits past nanoseconds are dropped
+                take_digits(&mut rest, usize::MAX);
+            }
+            'b' => {
+                let name = rest.get(..3)?.to_ascii_lowercase();
+                month = MONTHS.iter().position(|m| *m == name)? as u32 + 1;
+                rest = &rest[3..];
+            }
+            'z' => {
+                if let Some(utc) = rest.strip_prefix('Z') {
+                    rest = utc;
+                    continue;
+                }
+                let sign = match rest.get(..1)? {
+                    "+" => 1,
+                    "-" => -1,
+                    _ => return None,
+                };
+                rest = &rest[1..];
+                let hours = take_digits(&mut rest, 2)?.0;
+                rest = rest.strip_prefix(':').unwrap_or(rest);
+                let minutes = take_digits(&mut rest, 2).map_or(0, |(value, _)| value);
+                offset_seconds = sign * (hours * 3600 + minutes * 60);
+            }
+            '%' => rest = rest.strip_prefix('%')?,
+            _ => return None,
+        }
+    }
+    if !rest.is_empty() || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+    let seconds = days * 86400 + hour * 3600 + minute * 60 + second - offset_seconds;
+    Some(seconds * 1_000_000_000 + nanos)
+}
+
+/// Date in the proleptic Gregorian calendar of a day counted from
+/// 1970-01-01
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Format nanoseconds since the Unix epoch as an RFC 3339 UTC timestamp
+/// with microseconds, like `2024-05-01T10:00:00.123456Z`
+pub fn format_timestamp(nanoseconds: i64) -> String {
+    let seconds = nanoseconds.div_euclid(1_000_000_000);
+    let micros = nanoseconds.rem_euclid(1_000_000_000) / 1000;
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86400));
+    let time = seconds.rem_euclid(86400);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60,
+        micros
+    )
+}
@@ -0,0 +1,152 @@
+//! Conversion of raw TSC values to time
+//!
+//! Fields holding a time stamp counter value, such as `tsc`, are annotated
+//! with the time since the counter started, or with the wall-clock time
+//! when the time of boot is known. The TSC frequency is given on the
+//! command line or found in the calibration messages logged at boot, like
+//! `tsc: Detected 2593.904 MHz processor`.
+
+use regex::Regex;
+use serde_json::Value;
+use std::error::Error;
+
+use crate::decoder::{Conditions, Decoded, Decoder, FieldContext};
+use crate::input::Records;
+use crate::numbers;
+use crate::report;
+use crate::time::format_timestamp;
+use crate::units::format_duration;
+
+/// Fields holding a raw TSC value
+const TSC_KEYS: &[&str] = &["tsc", "rdtsc", "tsc_value", "host_tsc", "guest_tsc"];
+
+/// Fields holding the TSC frequency in Hz
+const FREQUENCY_KEYS: &[&str] = &["tsc_freq", "tsc_frequency", "tsc_hz"];
+
+/// Rows searched for a calibration message
+const DETECT_ROWS: usize = 10_000;
+
+/// Where the TSC frequency comes from
+#[derive(Clone, Copy, Debug)]
+pub enum TscFrequency {
+    Hz(f64),
+    /// Found in the calibration messages of the log
+    Auto,
+}
+
+/// Parse a frequency in Hz, like `2593904000`, `2.5GHz` or `2593.9 MHz`
+fn parse_hz(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(text.len());
+    let value: f64 = text[..split].parse().ok()?;
+    let scale = match text[split..].trim().to_ascii_lowercase().as_str() {
+        "" | "hz" => 1.0,
+        "khz" => 1e3,
+        "mhz" => 1e6,
+        "ghz" => 1e9,
+        _ => return None,
+    };
+    Some(value * scale).filter(|hz| *hz > 0.0)
+}
+
+/// Parse a `--tsc-freq`, which is a frequency or `auto`, used as a clap
+/// value parser
+pub fn parse_tsc_frequency(text: &str) -> Result<TscFrequency, String> {
+    if text == "auto" {
+        return Ok(TscFrequency::Auto);
+    }
+    parse_hz(text).map(TscFrequency::Hz).ok_or_else(|| {
+        format!(
+            "Invalid TSC frequency '{}', expected one like 2.5GHz or auto",
+            text
+        )
+    })
+}
+
+/// Find the TSC frequency in Hz in the calibration messages or frequency
+/// fields of a log, preferring the refined calibration over the first
+/// estimate
+pub fn detect(records: Records) -> Result<Option<f64>, Box<dyn Error>> {
+    let calibration = Regex::new(r"(?i)\btsc\b.*?(\d+(?:\.\d+)?\s*[kmg]hz)").unwrap();
+    let mut detected = None;
+
+    for record in records.take(DETECT_ROWS) {
+        let record = record?;
+        let Some(json) = record.json() else {
+            continue;
+        };
+        let Some(fields) = json.get("fields").and_then(Value::as_object) else {
+            continue;
+        };
+        if let Some(hz) = report::field_u64(fields, FREQUENCY_KEYS) {
+            return Ok(Some(hz as f64));
+        }
+        let message = fields.get("message").and_then(Value::as_str).unwrap_or("");
+        if let Some(hz) = calibration
+            .captures(message)
+            .and_then(|caps| parse_hz(&caps[1]))
+        {
+            if message.to_ascii_lowercase().contains("refined") {
+                return Ok(Some(hz));
+            }
+            detected.get_or_insert(hz);
+        }
+    }
+    Ok(detected)
+}
+
+/// Decoder annotating TSC values with the time they stand for
+pub struct TscDecoder {
+    hz: f64,
+    /// TSC value when the counter is taken to start, like at boot
+    offset: u64,
+    /// Time of boot in nanoseconds since the Unix epoch, when known
+    boot_time: Option<i64>,
+    conditions: Conditions,
+}
+
+impl TscDecoder {
+    pub fn new(hz: f64, offset: u64, boot_time: Option<i64>) -> Self {
+        TscDecoder {
+            hz,
+            offset,
+            boot_time,
+            conditions: Conditions::any().keys(TSC_KEYS),
+        }
+    }
+}
+
+impl Decoder for TscDecoder {
+    fn name(&self) -> &str {
+        "tsc"
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "Annotates TSC values with the time they stand for, at {} MHz",
+            self.hz / 1e6
+        )
+    }
+
+    fn conditions(&self) -> &Conditions {
+        &self.conditions
+    }
+
+    fn example(&self) -> Option<(&str, Value)> {
+        Some(("tsc", Value::from(self.offset + self.hz as u64)))
+    }
+
+    fn decode(&self, _key: &str, value: &Value, _ctx: &FieldContext) -> Option<Decoded> {
+        let ticks = match value {
+            Value::String(text) => numbers::parse_integer(text)?,
+            other => other.as_u64()?,
+        };
+        let nanoseconds = (ticks as f64 - self.offset as f64) / self.hz * 1e9;
+        Some(Decoded::Annotate(match self.boot_time {
+            Some(boot) => format_timestamp(boot + nanoseconds as i64),
+            None => format!("{} since start", format_duration(nanoseconds)),
+        }))
+    }
+}
@@ -0,0 +1,422 @@
+//! Human-friendly rendering of byte counts and durations
+//!
+//! Numeric fields are recognized by name, such as `size`, `read_bytes` or
+//! `elapsed_ns`. Hyper-V reference times, counted in 100ns units since the
+//! partition was created, are recognized as `ref_time` or `reference_time`
+//! and are also shown as a wall-clock time when the time of boot is known.
+//!
+//! Further rules, tried before the built-in ones, are read from a TOML
+//! file:
+//!
+//! ```toml
+//! [[unit]]
+//! # Regex matched against the field name
+//! key = "^mmio_len$"
+//! # One of "bytes", "ns", "us", "ms", "s", "100ns", or "none" to leave
+//! # the field alone (the default)
+//! unit = "bytes"
+//! # Show the value only in the unit rather than alongside its hex
+//! # (default "annotate")
+//! display = "replace"
+//!
+//! [[unit]]
+//! key = "^load_avg_q16$"
+//! # Fixed-point value with this many fractional bits, shown as a number
+//! fraction_bits = 16
+//! # Digits after the point, and "plain", "scientific" or "engineering"
+//! # notation, also for fields holding floats
+//! precision = 3
+//! notation = "plain"
+//! ```
+//!
+//! Fields holding floats that match no rule are written with the
+//! `--float-precision` and `--float-notation` given on the command line.
+
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+use std::error::Error;
+use std::path::Path;
+
+use crate::decoder::{Conditions, Decoded, Decoder, FieldContext};
+use crate::time::format_timestamp;
+
+/// Field name patterns recognized without a units file
+const BUILTIN_UNITS: &[(&str, Unit)] = &[
+    (r"_ns$", Unit::Nanoseconds),
+    (r"_us$", Unit::Microseconds),
+    (r"_ms$", Unit::Milliseconds),
+    (r"_secs?$", Unit::Seconds),
+    (r"(^|_)duration$", Unit::Nanoseconds),
+    (r"(^|_)ref(erence)?_time$|_100ns$", Unit::ReferenceTime),
+    (r"(^|_)bytes$", Unit::Bytes),
+    (r"(^|_)(len|length|size)$", Unit::Bytes),
+];
+
+/// Unit of a numeric field
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+pub enum Unit {
+    #[serde(rename = "bytes")]
+    Bytes,
+    #[serde(rename = "ns")]
+    Nanoseconds,
+    #[serde(rename = "us")]
+    Microseconds,
+    #[serde(rename = "ms")]
+    Milliseconds,
+    #[serde(rename = "s")]
+    Seconds,
+    /// Hyper-V reference time, in 100ns units
+    #[serde(rename = "100ns")]
+    ReferenceTime,
+    #[serde(rename = "none")]
+    None,
+}
+
+/// How fractional numbers are written
+#[derive(Deserialize, Default, Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Notation {
+    /// Like 1234.5
+    #[default]
+    Plain,
+    /// Like 1.2345e3
+    Scientific,
+    /// Like 1.2345e3, with the exponent a multiple of three
+    Engineering,
+}
+
+/// Precision and notation of fractional numbers
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FloatFormat {
+    /// Digits after the point, or as many as needed when not set
+    pub precision: Option<usize>,
+    pub notation: Notation,
+}
+
+impl FloatFormat {
+    pub fn format(&self, value: f64) -> String {
+        let (mantissa, exponent) = match self.notation {
+            Notation::Plain => (value, 0),
+            _ if value == 0.0 || !value.is_finite() => (value, 0),
+            Notation::Scientific => {
+                let exponent = value.abs().log10().floor() as i32;
+                (value / 10f64.powi(exponent), exponent)
+            }
+            Notation::Engineering => {
+                let exponent = (value.abs().log10() / 3.0).floor() as i32 * 3;
+                (value / 10f64.powi(exponent), exponent)
+            }
+        };
+        let mantissa = match self.precision {
+            Some(precision) => format!("{:.*}", precision, mantissa),
+            None => mantissa.to_string(),
+        };
+        match exponent {
+            0 => mantissa,
+            exponent => format!("{}e{}", mantissa, exponent),
+        }
+    }
+}
+
+/// How a value in a unit is shown
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum Display {
+    #[default]
+    Annotate,
+    Replace,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UnitsFile {
+    #[serde(default, rename = "unit")]
+    units: Vec<UnitSpec>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UnitSpec {
+    key: String,
+    #[serde(default = "no_unit")]
+    unit: Unit,
+    #[serde(default)]
+    display: Display,
+    fraction_bits: Option<u32>,
+    precision: Option<usize>,
+    notation: Option<Notation>,
+}
+
+fn no_unit() -> Unit {
+    Unit::None
+}
+
+struct UnitRule {
+    key: Regex,
+    unit: Unit,
+    display: Display,
+    /// Fractional bits of a fixed-point value
+    fraction_bits: Option<u32>,
+    /// Format of the number when it has no unit, if set
+    float: Option<FloatFormat>,
+}
+
+/// Render a byte count with a binary prefix, like `4.0 MiB`
+fn format_bytes(bytes: f64) -> String {
+    const PREFIXES: &[&str] = &["KiB", "MiB", "GiB", "TiB", "PiB"];
+
+    if bytes.abs() < 1024.0 {
+        return format!("{} B", bytes);
+    }
+
+    let mut value = bytes / 1024.0;
+    let mut prefix = PREFIXES[0];
+    for next in &PREFIXES[1..] {
+        if value.abs() < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        prefix = next;
+    }
+    format!("{:.1} {}", value, prefix)
+}
+
+/// Render a duration in nanoseconds in the largest fitting unit, like
+/// `1.25 ms`
+pub fn format_duration(nanoseconds: f64) -> String {
+    let magnitude = nanoseconds.abs();
+    if magnitude < 1e3 {
+        format!("{} ns", nanoseconds)
+    } else if magnitude < 1e6 {
+        format!("{:.2} us", nanoseconds / 1e3)
+    } else if magnitude < 1e9 {
+        format!("{:.2} ms", nanoseconds / 1e6)
+    } else {
+        format!("{:.2} s", nanoseconds / 1e9)
+    }
+}
+
+/// Parse a quantity such as `1.5ms`, `4 KiB` or `0x1000`, returning it
+/// with its unit when one is given
+pub fn parse_quantity(text: &str) -> Option<(f64, Option<Unit>)> {
+    let text = text.trim();
+    if let Some(hex) = text.strip_prefix("0x") {
+        return Some((u64::from_str_radix(hex, 16).ok()? as f64, None));
+    }
+
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-'))
+        .unwrap_or(text.len());
+    let value: f64 = text[..split].parse().ok()?;
+    let (value, unit) = match text[split..].trim() {
+        "" => (value, None),
+        "ns" => (value, Some(Unit::Nanoseconds)),
+        "us" | "µs" => (value, Some(Unit::Microseconds)),
+        "ms" => (value, Some(Unit::Milliseconds)),
+        "s" => (value, Some(Unit::Seconds)),
+        "m" | "min" => (value * 60.0, Some(Unit::Seconds)),
+        "h" => (value * 3600.0, Some(Unit::Seconds)),
+        "B" => (value, Some(Unit::Bytes)),
+        "K" | "KiB" => (value * 1024.0, Some(Unit::Bytes)),
+        "M" | "MiB" => (value * 1024.0 * 1024.0, Some(Unit::Bytes)),
+        "G" | "GiB" => (value * 1024.0 * 1024.0 * 1024.0, Some(Unit::Bytes)),
+        _ => return None,
+    };
+    Some((value, unit))
+}
+
+/// Parse a byte count such as `4KiB` or `0x1000`, for command line options
+pub fn parse_bytes(text: &str) -> Result<u64, String> {
+    match parse_quantity(text) {
+        Some((value, None | Some(Unit::Bytes))) if value >= 0.0 => Ok(value as u64),
+        _ => Err(format!("'{}' is not a byte count", text)),
+    }
+}
+
+/// Parse a duration such as `30s` or `5min` into nanoseconds, for command
+/// line options
+pub fn parse_duration(text: &str) -> Result<u64, String> {
+    match parse_quantity(text) {
+        Some((value, Some(unit))) if unit.is_duration() && value >= 0.0 => {
+            Ok(unit.to_base(value) as u64)
+        }
+        _ => Err(format!("'{}' is not a duration like 30s or 5min", text)),
+    }
+}
+
+impl Unit {
+    /// Check whether the unit measures time
+    pub fn is_duration(self) -> bool {
+        matches!(
+            self,
+            Unit::Nanoseconds
+                | Unit::Microseconds
+                | Unit::Milliseconds
+                | Unit::Seconds
+                | Unit::ReferenceTime
+        )
+    }
+
+    /// Convert a value in this unit to bytes or nanoseconds
+    pub fn to_base(self, value: f64) -> f64 {
+        match self {
+            Unit::Microseconds => value * 1e3,
+            Unit::Milliseconds => value * 1e6,
+            Unit::Seconds => value * 1e9,
+            Unit::ReferenceTime => value * 100.0,
+            _ => value,
+        }
+    }
+
+    fn format(self, value: f64) -> Option<String> {
+        match self {
+            Unit::Bytes => Some(format_bytes(value)),
+            Unit::Nanoseconds => Some(format_duration(value)),
+            Unit::Microseconds => Some(format_duration(value * 1e3)),
+            Unit::Milliseconds => Some(format_duration(value * 1e6)),
+            Unit::Seconds => Some(format_duration(value * 1e9)),
+            Unit::ReferenceTime => Some(format_duration(value * 100.0)),
+            Unit::None => None,
+        }
+    }
+}
+
+/// Decoder showing numeric fields in their unit
+pub struct UnitsDecoder {
+    rules: Vec<UnitRule>,
+    /// Format of floats no rule matches
+    float: Option<FloatFormat>,
+    /// Time the reference time counts from, in nanoseconds since the Unix
+    /// epoch
+    reference_start: Option<i64>,
+    conditions: Conditions,
+}
+
+impl UnitsDecoder {
+    /// Create a decoder with the built-in field name patterns
+    pub fn new() -> Self {
+        let rules = BUILTIN_UNITS
+            .iter()
+            .map(|(key, unit)| UnitRule {
+                key: Regex::new(key).unwrap(),
+                unit: *unit,
+                display: Display::Annotate,
+                fraction_bits: None,
+                float: None,
+            })
+            .collect();
+
+        UnitsDecoder {
+            rules,
+            float: None,
+            reference_start: None,
+            conditions: Conditions::any(),
+        }
+    }
+
+    /// Write floats that match no rule in this format
+    pub fn set_float_format(&mut self, float: FloatFormat) {
+        self.float = Some(float);
+    }
+
+    /// Show reference times as wall-clock times too, counting from `start`
+    /// in nanoseconds since the Unix epoch
+    pub fn set_reference_start(&mut self, start: i64) {
+        self.reference_start = Some(start);
+    }
+
+    /// Add the rules in a TOML file, tried before the built-in ones
+    pub fn load(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+        let file: UnitsFile = toml::from_str(&content)
+            .map_err(|err| format!("Failed to parse {}: {}", path.display(), err))?;
+
+        let mut rules = Vec::new();
+        for (index, spec) in file.units.into_iter().enumerate() {
+            let key = Regex::new(&spec.key)
+                .map_err(|err| format!("{}: unit {}: {}", path.display(), index + 1, err))?;
+            let float =
+                (spec.precision.is_some() || spec.notation.is_some()).then(|| FloatFormat {
+                    precision: spec.precision,
+                    notation: spec.notation.unwrap_or_default(),
+                });
+            rules.push(UnitRule {
+                key,
+                unit: spec.unit,
+                display: spec.display,
+                fraction_bits: spec.fraction_bits,
+                float,
+            });
+        }
+
+        rules.append(&mut self.rules);
+        self.rules = rules;
+        Ok(())
+    }
+
+    /// Unit of the field with the given name, if it has one
+    pub fn unit_of(&self, key: &str) -> Option<Unit> {
+        self.rules
+            .iter()
+            .find(|rule| rule.key.is_match(key))
+            .map(|rule| rule.unit)
+            .filter(|unit| *unit != Unit::None)
+    }
+}
+
+impl Decoder for UnitsDecoder {
+    fn name(&self) -> &str {
+        "units"
+    }
+
+    fn description(&self) -> String {
+        "Shows numbers in the unit of their field, like _ns, _bytes or _ref_time fields and those of --units".to_string()
+    }
+
+    fn conditions(&self) -> &Conditions {
+        &self.conditions
+    }
+
+    fn example(&self) -> Option<(&str, Value)> {
+        Some(("elapsed_ns", Value::from(1_500_000u64)))
+    }
+
+    fn decode(&self, key: &str, value: &Value, _ctx: &FieldContext) -> Option<Decoded> {
+        let mut number = value.as_f64()?;
+        let Some(rule) = self.rules.iter().find(|rule| rule.key.is_match(key)) else {
+            // Integers are written as hex, not as floats
+            return match self.float {
+                Some(float) if value.is_f64() => Some(Decoded::Value(float.format(number))),
+                _ => None,
+            };
+        };
+
+        if let Some(bits) = rule.fraction_bits {
+            number /= 2f64.powi(bits as i32);
+        }
+        let text = match (rule.unit.format(number), rule.float) {
+            (Some(text), _) if rule.unit == Unit::ReferenceTime => match self.reference_start {
+                Some(start) => {
+                    let time = start + Unit::ReferenceTime.to_base(number) as i64;
+                    format!("{}, {}", text, format_timestamp(time))
+                }
+                None => text,
+            },
+            (Some(text), _) => text,
+            (None, Some(float)) => float.format(number),
+            (None, None) if rule.fraction_bits.is_some() => number.to_string(),
+            (None, None) => return None,
+        };
+
+        // A float shown without a unit takes the place of the value
+        if rule.unit == Unit::None && value.is_f64() {
+            return Some(Decoded::Value(text));
+        }
+        Some(match rule.display {
+            Display::Annotate => Decoded::Annotate(text),
+            Display::Replace => Decoded::Replace(text),
+        })
+    }
+}
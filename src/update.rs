@@ -0,0 +1,206 @@
+//! Replacing the running binary with the latest GitHub release
+//!
+//! The latest release is read from the GitHub releases API. Its asset for
+//! this platform, like `kusto-kmsg-extract-x86_64-linux`, is described by a
+//! JSON manifest in the matching `.manifest` asset:
+//!
+//! ```json
+//! {"version": "v1.2.3", "asset": "kusto-kmsg-extract-x86_64-linux", "sha256": "..."}
+//! ```
+//!
+//! The binary is only installed when the Ed25519 signature of the manifest,
+//! in the `.manifest.sig` asset, verifies with the release signing key
+//! built into the binary, given as base64 in `KKE_RELEASE_PUBLIC_KEY` when
+//! building, and the manifest names the release, this platform and the
+//! SHA-256 of the binary. Signing the version and platform along with the
+//! binary keeps an older or foreign signed binary from being installed
+//! under a newer release.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ring::signature::{UnparsedPublicKey, ED25519};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Repository the releases are published in
+const REPOSITORY: &str = "chris-oo/kusto-kmsg-extract";
+
+/// Release signing key, base64 encoded
+const PUBLIC_KEY: Option<&str> = option_env!("KKE_RELEASE_PUBLIC_KEY");
+
+/// Largest binary downloaded
+const MAX_DOWNLOAD_BYTES: u64 = 256 << 20;
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// What the signature of a release asset covers
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    /// Tag of the release the asset was built for
+    version: String,
+    /// Name of the asset
+    asset: String,
+    /// Hex encoded SHA-256 of the asset
+    sha256: String,
+}
+
+/// Name of the release asset built for this platform
+fn asset_name() -> String {
+    format!(
+        "kusto-kmsg-extract-{}-{}{}",
+        std::env::consts::ARCH,
+        std::env::consts::OS,
+        std::env::consts::EXE_SUFFIX
+    )
+}
+
+/// Parse a version like `v1.2.3` into its numbers
+fn parse_version(text: &str) -> Option<Vec<u64>> {
+    let text = text.trim_start_matches('v');
+    let text = text.split(['-', '+']).next()?;
+    text.split('.').map(|part| part.parse().ok()).collect()
+}
+
+fn download(url: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let response = ureq::get(url)
+        .call()
+        .map_err(|err| format!("Failed to download {}: {}", url, err))?;
+    let mut data = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_DOWNLOAD_BYTES)
+        .read_to_end(&mut data)
+        .map_err(|err| format!("Failed to download {}: {}", url, err))?;
+    Ok(data)
+}
+
+/// Check a manifest against its signature, given raw or as base64
+fn verify(manifest: &[u8], signature: &[u8]) -> Result<(), Box<dyn Error>> {
+    let key =
+        PUBLIC_KEY.ok_or("This build has no release signing key, so updates can't be verified")?;
+    let key = STANDARD
+        .decode(key.trim())
+        .map_err(|err| format!("Invalid release signing key: {}", err))?;
+
+    let signature = match signature.len() {
+        64 => signature.to_vec(),
+        _ => STANDARD
+            .decode(signature.trim_ascii())
+            .map_err(|err| format!("Invalid release signature: {}", err))?,
+    };
+    UnparsedPublicKey::new(&ED25519, key)
+        .verify(manifest, &signature)
+        .map_err(|_| "The release signature doesn't match the downloaded manifest")?;
+    Ok(())
+}
+
+/// Put a new binary in place of the running one
+fn replace(exe: &Path, binary: &[u8]) -> Result<(), Box<dyn Error>> {
+    let with_extension = |extension: &str| -> PathBuf {
+        let mut path = exe.as_os_str().to_owned();
+        path.push(extension);
+        PathBuf::from(path)
+    };
+    let new = with_extension(".new");
+    let old = with_extension(".old");
+
+    std::fs::write(&new, binary)
+        .map_err(|err| format!("Failed to write {}: {}", new.display(), err))?;
+    let permissions = std::fs::metadata(exe)?.permissions();
+    std::fs::set_permissions(&new, permissions)?;
+
+    // Windows can't replace a running binary but can rename it, so the old
+    // binary is moved aside and removed by the next update
+    let _ = std::fs::remove_file(&old);
+    std::fs::rename(exe, &old)
+        .map_err(|err| format!("Failed to move {} aside: {}", exe.display(), err))?;
+    if let Err(err) = std::fs::rename(&new, exe) {
+        let _ = std::fs::rename(&old, exe);
+        return Err(format!("Failed to replace {}: {}", exe.display(), err).into());
+    }
+    if cfg!(not(windows)) {
+        let _ = std::fs::remove_file(&old);
+    }
+    Ok(())
+}
+
+/// Install the latest release if it is newer than this build, or only
+/// report whether there is one with `check_only`
+pub fn self_update(check_only: bool) -> Result<(), Box<dyn Error>> {
+    let url = format!(
+        "https://api.github.com/repos/{}/releases/latest",
+        REPOSITORY
+    );
+    let response = ureq::get(&url)
+        .set("Accept", "application/vnd.github+json")
+        .set("User-Agent", "kusto-kmsg-extract")
+        .call()
+        .map_err(|err| format!("Failed to read the latest release: {}", err))?;
+    let release: Release = serde_json::from_str(&response.into_string()?)?;
+
+    let current = env!("CARGO_PKG_VERSION");
+    let latest = parse_version(&release.tag_name)
+        .ok_or_else(|| format!("Invalid release version '{}'", release.tag_name))?;
+    if latest <= parse_version(current).unwrap_or_default() {
+        println!("Already up to date at version {}", current);
+        return Ok(());
+    }
+    if check_only {
+        println!(
+            "Version {} is available, running {}",
+            release.tag_name, current
+        );
+        return Ok(());
+    }
+
+    let name = asset_name();
+    let manifest_name = format!("{}.manifest", name);
+    let signature_name = format!("{}.sig", manifest_name);
+    let find = |name: &str| {
+        release
+            .assets
+            .iter()
+            .find(|asset| asset.name == name)
+            .ok_or_else(|| format!("Release {} has no {} asset", release.tag_name, name))
+    };
+    let manifest = download(&find(&manifest_name)?.browser_download_url)?;
+    let signature = download(&find(&signature_name)?.browser_download_url)?;
+    verify(&manifest, &signature)?;
+    let manifest: Manifest = serde_json::from_slice(&manifest)
+        .map_err(|err| format!("Invalid release manifest: {}", err))?;
+    if manifest.version != release.tag_name || manifest.asset != name {
+        return Err(format!(
+            "The signed manifest is for {} of {}, not {} of {}",
+            manifest.asset, manifest.version, name, release.tag_name
+        )
+        .into());
+    }
+
+    let binary = download(&find(&name)?.browser_download_url)?;
+    let digest: String = Sha256::digest(&binary)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    if !digest.eq_ignore_ascii_case(&manifest.sha256) {
+        return Err("The downloaded binary doesn't match the signed manifest".into());
+    }
+
+    let exe = std::env::current_exe()?;
+    replace(&exe, &binary)?;
+    println!("Updated from version {} to {}", current, release.tag_name);
+    Ok(())
+}
@@ -0,0 +1,274 @@
+//! Sending of batches to log services from an async runtime, so records
+//! are decoded while earlier batches upload
+//!
+//! Batches are queued to a task on a tokio runtime, which paces and
+//! retries them with timers and posts each with the blocking client of
+//! the service on the blocking pool of the runtime. Batches are sent in
+//! order. Only a few wait to be sent, so a slow service
+//! holds back decoding instead of memory growing without bound. Batches
+//! throttled with a 429 or 503 are retried after the `Retry-After` delay
+//! of the service, or else after a delay doubling each time. Any other
+//! failure stops the uploads, and its error is returned by the next call.
+//!
+//! With `--dry-run` batches are counted instead of sent, and the first is
+//! printed as a sample of what the service would receive.
+
+use std::error::Error;
+use std::time::{Duration, Instant};
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio::task::JoinHandle;
+
+/// Batches waiting to be sent while another is sent
+const QUEUE_DEPTH: usize = 4;
+
+/// Delay before the first retry of a throttled batch without `Retry-After`
+const FIRST_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Longest delay before retrying a throttled batch
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Longest part of the first batch printed by a dry run
+const SAMPLE_CHARS: usize = 2000;
+
+/// Error of a batch, moved back from the blocking pool
+pub type UploadError = Box<dyn Error + Send + Sync>;
+
+/// Client posting batches to a log service
+pub trait Upload: Send + 'static {
+    /// Post a batch
+    fn upload(&mut self, body: &str) -> Result<(), UploadError>;
+
+    /// Start of the message of a failed batch, like `Failed to push to
+    /// Loki at URL`
+    fn failure(&self) -> String;
+
+    /// Service the batches go to, like `Loki at URL`
+    fn destination(&self) -> String;
+}
+
+/// How records are batched and paced for a log service
+#[derive(Clone, Debug, Default)]
+pub struct UploadOptions {
+    /// Most records in a batch, beyond the size limit of each service
+    pub batch_records: Option<usize>,
+    /// Longest time a record waits in a batch before it is sent, checked
+    /// as records are written
+    pub flush_interval: Option<Duration>,
+    /// Most records sent per second
+    pub rate_limit: Option<u32>,
+    /// Times a throttled batch is retried before giving up
+    pub retries: u32,
+    /// Count and sample the batches instead of sending them
+    pub dry_run: bool,
+}
+
+/// Delay a throttling service asked for, if the error is a 429 or 503
+fn throttled(err: &UploadError) -> Option<Option<Duration>> {
+    match err.downcast_ref::<ureq::Error>()? {
+        ureq::Error::Status(429 | 503, response) => Some(
+            response
+                .header("Retry-After")
+                .and_then(|value| value.trim().parse().ok())
+                .map(Duration::from_secs),
+        ),
+        _ => None,
+    }
+}
+
+/// Post a batch on the blocking pool, handing the client back with the
+/// result
+async fn post<C: Upload>(
+    mut client: C,
+    body: String,
+) -> Result<(C, String, Result<(), UploadError>), String> {
+    tokio::task::spawn_blocking(move || {
+        let result = client.upload(&body);
+        (client, body, result)
+    })
+    .await
+    .map_err(|err| format!("Upload task failed: {}", err))
+}
+
+/// Post a batch, retrying while the service throttles it
+async fn upload<C: Upload>(mut client: C, mut body: String, retries: u32) -> Result<C, String> {
+    let mut backoff = FIRST_BACKOFF;
+    let mut attempt = 0;
+    loop {
+        let result;
+        (client, body, result) = post(client, body).await?;
+        let err = match result {
+            Ok(()) => return Ok(client),
+            Err(err) => err,
+        };
+        let Some(retry_after) = throttled(&err).filter(|_| attempt < retries) else {
+            return Err(format!("{}: {}", client.failure(), err));
+        };
+
+        let delay = retry_after.unwrap_or(backoff).min(MAX_BACKOFF);
+        eprintln!(
+            "Batch throttled, retrying in {}s: {}",
+            delay.as_secs_f64(),
+            err
+        );
+        tokio::time::sleep(delay).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+        attempt += 1;
+    }
+}
+
+/// What a dry run would have sent
+struct DryRun {
+    destination: String,
+    batches: u64,
+    records: u64,
+    bytes: u64,
+}
+
+impl DryRun {
+    fn add(&mut self, body: &str, records: usize) {
+        if self.batches == 0 {
+            let sample: String = body.chars().take(SAMPLE_CHARS).collect();
+            eprintln!("First batch for {}:\n{}", self.destination, sample);
+            if sample.len() < body.len() {
+                eprintln!("... {} more bytes", body.len() - sample.len());
+            }
+        }
+        self.batches += 1;
+        self.records += records as u64;
+        self.bytes += body.len() as u64;
+    }
+}
+
+/// Send the queued batches in order, spaced so the records sent stay
+/// within `rate_limit` per second
+async fn send_batches<C: Upload>(
+    mut client: C,
+    mut receiver: Receiver<(String, usize)>,
+    rate_limit: Option<u32>,
+    retries: u32,
+) -> Result<(), String> {
+    let mut next = tokio::time::Instant::now();
+    while let Some((body, records)) = receiver.recv().await {
+        if let Some(rate) = rate_limit {
+            tokio::time::sleep_until(next).await;
+            next = next.max(tokio::time::Instant::now())
+                + Duration::from_secs_f64(records as f64 / f64::from(rate));
+        }
+        client = upload(client, body, retries).await?;
+    }
+    Ok(())
+}
+
+/// Posts batches with an [`Upload`] from a task on its own runtime
+pub struct Uploader {
+    options: UploadOptions,
+    runtime: Option<Runtime>,
+    sender: Option<Sender<(String, usize)>>,
+    worker: Option<JoinHandle<Result<(), String>>>,
+    /// Set instead of the runtime for a dry run
+    dry_run: Option<DryRun>,
+    /// Records in the batch being filled
+    pending: usize,
+    /// When the first record of the batch being filled was added
+    started: Option<Instant>,
+}
+
+impl Uploader {
+    pub fn new(client: impl Upload, options: UploadOptions) -> Result<Self, Box<dyn Error>> {
+        if options.dry_run {
+            let dry_run = DryRun {
+                destination: client.destination(),
+                batches: 0,
+                records: 0,
+                bytes: 0,
+            };
+            return Ok(Uploader {
+                options,
+                runtime: None,
+                sender: None,
+                worker: None,
+                dry_run: Some(dry_run),
+                pending: 0,
+                started: None,
+            });
+        }
+
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("upload")
+            .enable_time()
+            .build()
+            .map_err(|err| format!("Failed to start the upload runtime: {}", err))?;
+        let (sender, receiver) = mpsc::channel(QUEUE_DEPTH);
+        let worker = runtime.spawn(send_batches(
+            client,
+            receiver,
+            options.rate_limit,
+            options.retries,
+        ));
+        Ok(Uploader {
+            options,
+            runtime: Some(runtime),
+            sender: Some(sender),
+            worker: Some(worker),
+            dry_run: None,
+            pending: 0,
+            started: None,
+        })
+    }
+
+    /// Count a record added to the batch being filled, returning whether
+    /// the batch is due to be sent
+    pub fn add_record(&mut self) -> bool {
+        self.pending += 1;
+        let started = *self.started.get_or_insert_with(Instant::now);
+        self.options
+            .batch_records
+            .is_some_and(|max| self.pending >= max)
+            || self
+                .options
+                .flush_interval
+                .is_some_and(|interval| started.elapsed() >= interval)
+    }
+
+    /// Queue the batch that was filled, waiting while the queue is full
+    pub fn send(&mut self, body: String) -> Result<(), Box<dyn Error>> {
+        let records = std::mem::take(&mut self.pending);
+        self.started = None;
+        if let Some(dry_run) = &mut self.dry_run {
+            dry_run.add(&body, records);
+            return Ok(());
+        }
+        let sent = self
+            .sender
+            .as_ref()
+            .map(|sender| sender.blocking_send((body, records)));
+        match sent {
+            Some(Ok(())) => Ok(()),
+            // The task only stops early when a batch failed
+            _ => {
+                self.finish()?;
+                Err("Uploads already stopped".into())
+            }
+        }
+    }
+
+    /// Wait until every queued batch is sent
+    pub fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(dry_run) = self.dry_run.take() {
+            eprintln!(
+                "Dry run: would send {} records in {} batches ({} bytes) to {}",
+                dry_run.records, dry_run.batches, dry_run.bytes, dry_run.destination
+            );
+            return Ok(());
+        }
+        drop(self.sender.take());
+        match (&self.runtime, self.worker.take()) {
+            (Some(runtime), Some(worker)) => Ok(runtime
+                .block_on(worker)
+                .map_err(|err| format!("Upload task failed: {}", err))??),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! Checks for values that can't be right, marked `[suspect]` with
+//! `--validate`
+//!
+//! Fields whose width is fixed by the architecture, like segment selectors,
+//! privilege levels and interrupt vectors, are checked against their
+//! largest value wherever they appear: as fields of their own, or as
+//! `name: value` pairs in the dumps decoders rewrite, where numbers too
+//! large for 64 bits are flagged too. Decoders add checks of their own for
+//! the values they know. A value out of range usually means a corrupted
+//! record or a dump that was parsed wrong, which hex alone would hide.
+
+use regex::Regex;
+use serde_json::Value;
+use std::sync::OnceLock;
+
+use crate::numbers::{self, DECIMAL};
+
+/// Largest value of fields with a fixed width, also matching field names
+/// ending in `_<name>`
+const FIELD_RANGES: &[(&str, u64)] = &[
+    ("selector", 0xffff),
+    ("cpl", 3),
+    ("dpl", 3),
+    ("rpl", 3),
+    ("vector", 0xff),
+    ("vmpl", 3),
+    ("vtl", 2),
+];
+
+/// Largest value of a field, from `ranges` or else [`FIELD_RANGES`]
+fn max_value(name: &str, ranges: &[(&str, u64)]) -> Option<u64> {
+    ranges
+        .iter()
+        .chain(FIELD_RANGES)
+        .find(|(field, _)| {
+            name == *field
+                || name
+                    .strip_suffix(field)
+                    .is_some_and(|prefix| prefix.ends_with('_'))
+        })
+        .map(|(_, max)| *max)
+}
+
+/// Why a number can't be the value of a field, if it is out of range
+fn check_number(name: &str, number: u64, ranges: &[(&str, u64)]) -> Option<String> {
+    let max = max_value(name, ranges)?;
+    (number > max).then(|| format!("{} 0x{:x} above 0x{:x}", name, number, max))
+}
+
+/// Why the value of a field can't be right, for integer fields with a
+/// fixed width
+pub fn check_field(key: &str, value: &Value) -> Option<String> {
+    max_value(key, &[])?;
+    match value {
+        Value::String(text) => check_number(key, numbers::parse_integer(text)?, &[]),
+        Value::Number(number) => match (number.as_u64(), number.as_i64()) {
+            (Some(number), _) => check_number(key, number, &[]),
+            (None, Some(number)) => Some(format!("{} {} below 0", key, number)),
+            (None, None) => Some(format!("{} {} not an integer", key, number)),
+        },
+        _ => None,
+    }
+}
+
+/// Why a `name: value` pair of a dump can't be right, checking the fields
+/// of `ranges` besides those with a fixed width
+pub fn check_dump(text: &str, ranges: &[(&str, u64)]) -> Option<String> {
+    static PAIR_REGEX: OnceLock<Regex> = OnceLock::new();
+    let pair_regex = PAIR_REGEX
+        .get_or_init(|| Regex::new(&format!(r"\b(\w+): (0x[0-9a-fA-F_]+|{})\b", DECIMAL)).unwrap());
+
+    pair_regex.captures_iter(text).find_map(|caps| {
+        let name = &caps[1];
+        match numbers::parse_integer(&caps[2]) {
+            Some(number) => check_number(name, number, ranges),
+            None => Some(format!("{} {} too large for 64 bits", name, &caps[2])),
+        }
+    })
+}
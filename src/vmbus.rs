@@ -0,0 +1,141 @@
+//! Annotations for VMBus channel messages, channel IDs and ring buffers
+
+use serde_json::{Map, Value};
+
+use crate::decoder::{Conditions, Decoded, Decoder, FieldContext};
+
+/// Names of VMBus channel message types, indexed by message type
+const MESSAGE_TYPES: &[&str] = &[
+    "Invalid",
+    "OfferChannel",
+    "RescindChannelOffer",
+    "RequestOffers",
+    "AllOffersDelivered",
+    "OpenChannel",
+    "OpenChannelResult",
+    "CloseChannel",
+    "GpadlHeader",
+    "GpadlBody",
+    "GpadlCreated",
+    "GpadlTeardown",
+    "GpadlTorndown",
+    "RelIdReleased",
+    "InitiateContact",
+    "VersionResponse",
+    "Unload",
+    "UnloadResponse",
+    "Reserved18",
+    "Reserved19",
+    "Reserved20",
+    "TlConnectRequest",
+    "ModifyChannel",
+    "TlConnectResult",
+    "ModifyChannelResponse",
+];
+
+const MESSAGE_TYPE_KEYS: &[&str] = &["message_type", "msg_type", "msgtype", "message_id"];
+const CHANNEL_KEYS: &[&str] = &["channel_id", "child_relid", "relid", "channel"];
+const VERSION_KEYS: &[&str] = &["version", "vmbus_version", "requested_version"];
+const RING_SIZE_KEYS: &[&str] = &["ring_size", "data_size", "size"];
+
+/// Check whether a record comes from or talks about VMBus
+pub fn is_vmbus_record(target: &str, message: &str, fields: &Map<String, Value>) -> bool {
+    let mentions = |text: &str| text.to_ascii_lowercase().contains("vmbus");
+
+    mentions(target) || mentions(message) || fields.keys().any(|key| mentions(key))
+}
+
+/// Describe the fill level of a ring buffer from its write index
+fn annotate_ring(prefix: &str, write: u64, fields: &Map<String, Value>) -> Option<String> {
+    let read = fields
+        .get(&format!("{}read_index", prefix))
+        .and_then(Value::as_u64)?;
+    let size = RING_SIZE_KEYS
+        .iter()
+        .find_map(|key| fields.get(&format!("{}{}", prefix, key)))
+        .or_else(|| RING_SIZE_KEYS.iter().find_map(|key| fields.get(*key)))
+        .and_then(Value::as_u64)
+        .filter(|&size| size > 0);
+
+    match size {
+        Some(size) => {
+            // Reduced first, so a corrupt index can't overflow
+            let used = (write % size + size - read % size) % size;
+            Some(format!(
+                "ring used 0x{:x} of 0x{:x} bytes, free 0x{:x}",
+                used,
+                size,
+                size - used
+            ))
+        }
+        None if write == read => Some("ring empty".to_string()),
+        None => None,
+    }
+}
+
+/// Produce a readable annotation for a field of a VMBus record
+pub fn annotate_field(key: &str, value: &Value, fields: &Map<String, Value>) -> Option<String> {
+    let num = value.as_u64()?;
+
+    if MESSAGE_TYPE_KEYS.contains(&key) {
+        return Some(
+            MESSAGE_TYPES
+                .get(num as usize)
+                .map(|name| name.to_string())
+                .unwrap_or_else(|| format!("unknown message type {}", num)),
+        );
+    }
+
+    if CHANNEL_KEYS.contains(&key) {
+        return Some(format!("channel {}", num));
+    }
+
+    if key == "gpadl_id" || key == "gpadl" {
+        return Some(format!("gpadl {}", num));
+    }
+
+    if VERSION_KEYS.contains(&key) && num > 0xffff {
+        return Some(format!("protocol {}.{}", num >> 16, num & 0xffff));
+    }
+
+    // Ring buffer write indices, optionally prefixed with a direction such
+    // as `in_` or `outgoing_`, are paired with the matching read index
+    key.strip_suffix("write_index")
+        .and_then(|prefix| annotate_ring(prefix, num, fields))
+}
+
+/// Decoder for fields of VMBus related records
+pub struct VmbusDecoder {
+    conditions: Conditions,
+}
+
+impl VmbusDecoder {
+    pub fn new() -> Self {
+        VmbusDecoder {
+            conditions: Conditions::any(),
+        }
+    }
+}
+
+impl Decoder for VmbusDecoder {
+    fn name(&self) -> &str {
+        "vmbus"
+    }
+
+    fn description(&self) -> String {
+        "Annotates message types, channels, GPADLs, protocol versions and ring buffer indices in records about VMBus".to_string()
+    }
+
+    fn conditions(&self) -> &Conditions {
+        &self.conditions
+    }
+
+    fn example(&self) -> Option<(&str, Value)> {
+        Some(("vmbus_version", Value::from(0x0005_0002u64)))
+    }
+
+    fn decode(&self, key: &str, value: &Value, ctx: &FieldContext) -> Option<Decoded> {
+        let note = annotate_field(key, value, ctx.fields)?;
+        is_vmbus_record(ctx.target, ctx.message, ctx.fields).then_some(Decoded::Annotate(note))
+    }
+}
@@ -0,0 +1,75 @@
+//! Pointers from each VP entry or exit record to the next record of the
+//! opposite kind for the same VP, for jumping around the output in an
+//! editor
+//!
+//! Records are held until the end, as the line a pointer names is only
+//! known once the records after it are formatted. Line numbers count the
+//! lines written, starting at 1.
+
+use std::collections::HashMap;
+
+use crate::sink::RecordInfo;
+use crate::vp_timeline::VpState;
+
+/// Whether a VP record enters or leaves the VP
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum VpEvent {
+    Enter,
+    Exit,
+}
+
+impl VpEvent {
+    /// Classify a record by its message, like the VP timeline does
+    pub fn classify(message: &str) -> Option<VpEvent> {
+        match VpState::classify(message)? {
+            VpState::Running => Some(VpEvent::Enter),
+            VpState::InExit | VpState::InterceptPending => Some(VpEvent::Exit),
+            VpState::Halted => None,
+        }
+    }
+}
+
+/// Holds the records shown, to add pointers to them at the end
+#[derive(Default)]
+pub struct VpLinks {
+    held: Vec<(String, RecordInfo)>,
+}
+
+impl VpLinks {
+    pub fn hold(&mut self, text: &str, info: &RecordInfo) {
+        self.held.push((text.to_string(), info.clone()));
+    }
+
+    /// The records held, with a pointer added to the first line of each
+    /// entry and exit that has a next record of the opposite kind
+    pub fn finish(self) -> Vec<(String, RecordInfo)> {
+        let mut held = self.held;
+
+        let mut lines = Vec::with_capacity(held.len());
+        let mut line = 1;
+        for (text, _) in &held {
+            lines.push(line);
+            line += text.lines().count().max(1);
+        }
+
+        // Walk back from the end, remembering the next entry and exit of
+        // each VP
+        let mut next: HashMap<(u64, VpEvent), usize> = HashMap::new();
+        for ((text, info), line) in held.iter_mut().zip(lines).rev() {
+            let (Some(vp), Some(event)) = (info.vp, info.vp_event) else {
+                continue;
+            };
+            let (wanted, name) = match event {
+                VpEvent::Enter => (VpEvent::Exit, "exit"),
+                VpEvent::Exit => (VpEvent::Enter, "entry"),
+            };
+            if let Some(target) = next.get(&(vp, wanted)) {
+                let pointer = format!(" [next {} of VP {} at line {}]", name, vp, target);
+                let end = text.find('\n').unwrap_or(text.len());
+                text.insert_str(end, &pointer);
+            }
+            next.insert((vp, event), line);
+        }
+        held
+    }
+}
@@ -0,0 +1,249 @@
+//! Reconstruction of virtual processor run states from their log records
+//!
+//! Records with a VP index field are classified by their message: entering
+//! or resuming the VP means it is running, an intercept leaves it waiting
+//! for the intercept to be handled, other exits leave it in an exit, and a
+//! halt leaves it halted until the next entry.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+
+#[cfg(feature = "arrow")]
+use crate::columnar;
+use crate::input::Records;
+use crate::report::{field_u64, for_each_event, Event, VP_KEYS};
+use crate::time::parse_timestamp;
+use crate::units::format_duration;
+
+/// Run state of a VP
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum VpState {
+    Running,
+    Halted,
+    InExit,
+    InterceptPending,
+}
+
+impl VpState {
+    /// Every state, in the order of their discriminants
+    #[cfg(feature = "arrow")]
+    const ALL: [VpState; 4] = [
+        VpState::Running,
+        VpState::Halted,
+        VpState::InExit,
+        VpState::InterceptPending,
+    ];
+
+    /// Classify a record by its message, or None if it isn't a transition
+    pub fn classify(message: &str) -> Option<VpState> {
+        let message = message.to_lowercase();
+        if message.contains("intercept") {
+            Some(VpState::InterceptPending)
+        } else if message.contains("halt") {
+            Some(VpState::Halted)
+        } else if message.contains("exit") {
+            Some(VpState::InExit)
+        } else if ["enter", "resume", "run", "wake"]
+            .iter()
+            .any(|word| message.contains(word))
+        {
+            Some(VpState::Running)
+        } else {
+            None
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            VpState::Running => "running",
+            VpState::Halted => "halted",
+            VpState::InExit => "in-exit",
+            VpState::InterceptPending => "intercept-pending",
+        }
+    }
+
+    /// Character drawn for the state in a chart
+    fn symbol(self) -> char {
+        match self {
+            VpState::Running => 'R',
+            VpState::Halted => 'H',
+            VpState::InExit => 'X',
+            VpState::InterceptPending => 'I',
+        }
+    }
+}
+
+/// A change of state of a VP
+struct Transition {
+    /// Nanoseconds since the epoch
+    time: i64,
+    timestamp: String,
+    state: VpState,
+}
+
+/// The transitions of each VP, in time order
+type Timelines = BTreeMap<u64, Vec<Transition>>;
+
+/// A transition of a VP read from a record, and the time of the record
+fn transition(event: &Event) -> (Option<i64>, Option<(u64, VpState)>) {
+    let Some(time) = parse_timestamp(event.timestamp) else {
+        return (None, None);
+    };
+    let vp = field_u64(event.fields, VP_KEYS);
+    let state = vp.and_then(|_| VpState::classify(event.message));
+    (Some(time), vp.zip(state))
+}
+
+/// Collect the transitions of each VP in time order, with the time of the
+/// last record
+#[cfg(not(feature = "arrow"))]
+fn collect(records: Records) -> Result<(Timelines, i64), Box<dyn Error>> {
+    let mut vps = Timelines::new();
+    let mut end = i64::MIN;
+
+    for_each_event(records, |event| {
+        let (Some(time), change) = transition(event) else {
+            return;
+        };
+        end = end.max(time);
+
+        let Some((vp, state)) = change else {
+            return;
+        };
+        vps.entry(vp).or_default().push(Transition {
+            time,
+            timestamp: event.timestamp.to_string(),
+            state,
+        });
+    })?;
+
+    for transitions in vps.values_mut() {
+        transitions.sort_by_key(|transition| transition.time);
+        transitions.dedup_by(|next, previous| next.state == previous.state);
+    }
+    Ok((vps, end))
+}
+
+/// Collect the transitions of each VP in time order from columns of the
+/// transitions, with the time of the last record
+#[cfg(feature = "arrow")]
+fn collect(records: Records) -> Result<(Timelines, i64), Box<dyn Error>> {
+    use arrow_array::builder::{Int64Builder, StringBuilder, UInt64Builder, UInt8Builder};
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Int64Type, UInt64Type, UInt8Type};
+    use arrow_array::ArrayRef;
+    use std::sync::Arc;
+
+    let mut end = i64::MIN;
+    let mut vps = UInt64Builder::new();
+    let mut times = Int64Builder::new();
+    let mut states = UInt8Builder::new();
+    let mut timestamps = StringBuilder::new();
+    for_each_event(records, |event| {
+        let (Some(time), change) = transition(event) else {
+            return;
+        };
+        end = end.max(time);
+
+        let Some((vp, state)) = change else {
+            return;
+        };
+        vps.append_value(vp);
+        times.append_value(time);
+        states.append_value(state as u8);
+        timestamps.append_value(event.timestamp);
+    })?;
+    let vps: ArrayRef = Arc::new(vps.finish());
+    let times: ArrayRef = Arc::new(times.finish());
+    let states = states.finish();
+    let timestamps = timestamps.finish();
+
+    let mut transitions = BTreeMap::new();
+    let groups = columnar::group_by(std::slice::from_ref(&vps), std::slice::from_ref(&times))?;
+    for group in &groups.ranges {
+        let vp = vps.as_primitive::<UInt64Type>().value(groups.first(group));
+        let times = groups.take(&times, group)?;
+        let states = groups.take(&states, group)?;
+        let timestamps = groups.take(&timestamps, group)?;
+        let (times, states) = (
+            times.as_primitive::<Int64Type>(),
+            states.as_primitive::<UInt8Type>(),
+        );
+
+        let mut changes: Vec<Transition> = Vec::new();
+        for row in 0..times.len() {
+            let state = VpState::ALL[states.value(row) as usize];
+            if changes.last().is_some_and(|last| last.state == state) {
+                continue;
+            }
+            changes.push(Transition {
+                time: times.value(row),
+                timestamp: timestamps.as_string::<i32>().value(row).to_string(),
+                state,
+            });
+        }
+        transitions.insert(vp, changes);
+    }
+    Ok((transitions, end))
+}
+
+/// Print the state transitions of each VP, followed by a chart of the
+/// states over time `chart_width` characters wide when it is given
+pub fn vp_timeline(records: Records, chart_width: Option<usize>) -> Result<(), Box<dyn Error>> {
+    let (vps, end) = collect(records)?;
+
+    if vps.is_empty() {
+        eprintln!("No VP state transitions found");
+        return Ok(());
+    }
+
+    for (vp, transitions) in &vps {
+        println!("VP {}: {} transitions", vp, transitions.len());
+        for (i, transition) in transitions.iter().enumerate() {
+            let until = transitions.get(i + 1).map_or(end, |next| next.time);
+            println!(
+                "  {}  {:<17}  {}",
+                transition.timestamp,
+                transition.state.name(),
+                format_duration((until - transition.time) as f64)
+            );
+        }
+    }
+
+    if let Some(width) = chart_width {
+        print_chart(&vps, end, width.max(1));
+    }
+
+    Ok(())
+}
+
+/// Print one row per VP with a character for its state in each slice of
+/// time
+fn print_chart(vps: &Timelines, end: i64, width: usize) {
+    let start = vps
+        .values()
+        .filter_map(|transitions| transitions.first())
+        .map(|transition| transition.time)
+        .min()
+        .unwrap_or(end);
+    let slice = ((end - start) as f64 / width as f64).max(1.0);
+
+    println!();
+    for (vp, transitions) in vps {
+        let row: String = (0..width)
+            .map(|column| {
+                let time = start + (slice * column as f64) as i64;
+                let current = transitions.partition_point(|t| t.time <= time);
+                match current {
+                    0 => ' ',
+                    n => transitions[n - 1].state.symbol(),
+                }
+            })
+            .collect();
+        println!("VP {:>4} |{}|", vp, row);
+    }
+    println!(
+        "{} per column; R running, H halted, X in-exit, I intercept-pending",
+        format_duration(slice)
+    );
+}
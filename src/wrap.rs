@@ -0,0 +1,81 @@
+//! Soft wrapping of long formatted lines with `--wrap`
+//!
+//! Lines are broken at spaces, continuing with a hanging indent past the
+//! `[timestamp][level][target]` header of the record, or past the leading
+//! spaces of the expanded lines following it. Words longer than a line
+//! are left whole. Colors and hyperlinks take no columns.
+
+use regex::Regex;
+
+use crate::ansi::ESCAPE_PATTERN;
+
+/// Wraps lines to a number of columns
+pub struct Wrapper {
+    columns: usize,
+    escape: Regex,
+}
+
+impl Wrapper {
+    pub fn new(columns: usize) -> Self {
+        Wrapper {
+            columns,
+            escape: Regex::new(ESCAPE_PATTERN).unwrap(),
+        }
+    }
+
+    /// Columns taken by text when shown
+    fn width(&self, text: &str) -> usize {
+        if !text.contains('\x1b') {
+            return text.chars().count();
+        }
+        self.escape.replace_all(text, "").chars().count()
+    }
+
+    /// Columns of the hanging indent of a line
+    fn indent(&self, line: &str) -> usize {
+        let leading = line.len() - line.trim_start_matches(' ').len();
+        let indent = match line.find("] ") {
+            Some(end) if leading == 0 => self.width(&line[..end + 2]),
+            _ => leading + 2,
+        };
+        // A wide header would leave too little room
+        if indent > self.columns / 2 {
+            4
+        } else {
+            indent
+        }
+    }
+
+    /// Wrap each line of a formatted record
+    pub fn wrap(&self, text: &str) -> String {
+        let mut wrapped = String::with_capacity(text.len() + text.len() / self.columns.max(1) * 8);
+        for (index, line) in text.split('\n').enumerate() {
+            if index > 0 {
+                wrapped.push('\n');
+            }
+            if self.width(line) <= self.columns {
+                wrapped.push_str(line);
+                continue;
+            }
+
+            let indent = self.indent(line);
+            let mut column = 0;
+            for (index, word) in line.split(' ').enumerate() {
+                let width = self.width(word);
+                if index > 0 {
+                    if column + 1 + width > self.columns && column > indent && width > 0 {
+                        wrapped.push('\n');
+                        wrapped.extend(std::iter::repeat_n(' ', indent));
+                        column = indent;
+                    } else {
+                        wrapped.push(' ');
+                        column += 1;
+                    }
+                }
+                wrapped.push_str(word);
+                column += width;
+            }
+        }
+        wrapped
+    }
+}
@@ -0,0 +1,193 @@
+//! Transforms for x86 register and segment dumps
+
+use regex::Regex;
+use serde_json::Value;
+
+use crate::decoder::{Conditions, Decoded, Decoder, FieldContext};
+use crate::numbers::{self, DECIMAL};
+use crate::validate;
+
+/// Transform values inside tdx_tdg_vp_enter_exit_info to hex format
+pub fn transform_tdx_exit_info(text: &str) -> String {
+    let tdx_exit_regex =
+        Regex::new(&format!(r"(rax|rcx|rdx|rsi|rdi|r\d+): ({})", DECIMAL)).unwrap();
+
+    tdx_exit_regex
+        .replace_all(text, |caps: &regex::Captures| {
+            let reg = &caps[1];
+            format!("{}: {}", reg, numbers::decimal_to_hex(&caps[2]))
+        })
+        .to_string()
+}
+
+/// Transform TdxL2EnterGuestState contents to hex format
+pub fn transform_tdx_guest_state(text: &str) -> String {
+    let tdx_gpr_array_regex = Regex::new(r"\[([0-9,_ ]+)\]").unwrap();
+    let tdx_gpr_field_regex =
+        Regex::new(&format!(r"(rflags|rip|ssp|rvi|svi): ({})", DECIMAL)).unwrap();
+
+    // Transform the array values to hex
+    let transformed = tdx_gpr_array_regex.replace_all(text, |caps: &regex::Captures| {
+        let numbers_str = &caps[1];
+        let numbers: Vec<String> = numbers::split_list(numbers_str)
+            .into_iter()
+            .map(numbers::decimal_to_hex)
+            .collect();
+        format!("[{}]", numbers.join(", "))
+    });
+
+    // Transform individual field values to hex
+    tdx_gpr_field_regex
+        .replace_all(&transformed, |caps: &regex::Captures| {
+            let field = &caps[1];
+            format!("{}: {}", field, numbers::decimal_to_hex(&caps[2]))
+        })
+        .to_string()
+}
+
+/// Transform SegmentRegister values to hex format
+pub fn transform_segment_register(text: &str) -> String {
+    let segment_register_regex =
+        Regex::new(&format!(r"(base|limit|selector|attributes): ({})", DECIMAL)).unwrap();
+
+    segment_register_regex
+        .replace_all(text, |caps: &regex::Captures| {
+            let field = &caps[1];
+            format!("{}: {}", field, numbers::decimal_to_hex(&caps[2]))
+        })
+        .to_string()
+}
+
+/// Decoder for tdx_tdg_vp_enter_exit_info in `raw_exit` fields
+pub struct TdxExitInfoDecoder {
+    conditions: Conditions,
+}
+
+impl TdxExitInfoDecoder {
+    pub fn new() -> Self {
+        TdxExitInfoDecoder {
+            conditions: Conditions::any()
+                .keys(&["raw_exit"])
+                .contains("tdx_tdg_vp_enter_exit_info"),
+        }
+    }
+}
+
+impl Decoder for TdxExitInfoDecoder {
+    fn name(&self) -> &str {
+        "tdx-exit-info"
+    }
+
+    fn description(&self) -> String {
+        "Rewrites the decimal registers of TDX exit info dumps as hex".to_string()
+    }
+
+    fn conditions(&self) -> &Conditions {
+        &self.conditions
+    }
+
+    fn example(&self) -> Option<(&str, Value)> {
+        Some((
+            "raw_exit",
+            Value::from("tdx_tdg_vp_enter_exit_info { rax: 4096, rcx: 10, r8: 255 }"),
+        ))
+    }
+
+    fn decode(&self, _key: &str, value: &Value, _ctx: &FieldContext) -> Option<Decoded> {
+        Some(Decoded::Replace(transform_tdx_exit_info(value.as_str()?)))
+    }
+
+    fn validate(&self, _key: &str, value: &Value, _ctx: &FieldContext) -> Option<String> {
+        validate::check_dump(value.as_str()?, &[])
+    }
+}
+
+/// Decoder for TdxL2EnterGuestState in `gprs` fields
+pub struct TdxGuestStateDecoder {
+    conditions: Conditions,
+}
+
+impl TdxGuestStateDecoder {
+    pub fn new() -> Self {
+        TdxGuestStateDecoder {
+            conditions: Conditions::any()
+                .keys(&["gprs"])
+                .contains("TdxL2EnterGuestState"),
+        }
+    }
+}
+
+impl Decoder for TdxGuestStateDecoder {
+    fn name(&self) -> &str {
+        "tdx-guest-state"
+    }
+
+    fn description(&self) -> String {
+        "Rewrites the registers of TDX L2 guest state dumps as hex".to_string()
+    }
+
+    fn conditions(&self) -> &Conditions {
+        &self.conditions
+    }
+
+    fn example(&self) -> Option<(&str, Value)> {
+        Some(("gprs", Value::from("TdxL2EnterGuestState { gps: [0, 4096, 255], rflags: 2, rip: 65520, ssp: 0, rvi: 0, svi: 0 }")))
+    }
+
+    fn decode(&self, _key: &str, value: &Value, _ctx: &FieldContext) -> Option<Decoded> {
+        Some(Decoded::Replace(transform_tdx_guest_state(value.as_str()?)))
+    }
+
+    fn validate(&self, _key: &str, value: &Value, _ctx: &FieldContext) -> Option<String> {
+        validate::check_dump(value.as_str()?, &[])
+    }
+}
+
+/// Decoder for SegmentRegister dumps in any field
+pub struct SegmentRegisterDecoder {
+    conditions: Conditions,
+}
+
+impl SegmentRegisterDecoder {
+    pub fn new() -> Self {
+        SegmentRegisterDecoder {
+            conditions: Conditions::any().contains("SegmentRegister"),
+        }
+    }
+}
+
+impl Decoder for SegmentRegisterDecoder {
+    fn name(&self) -> &str {
+        "segment-register"
+    }
+
+    fn description(&self) -> String {
+        "Rewrites the base, limit, selector and attributes of segment register dumps as hex"
+            .to_string()
+    }
+
+    fn conditions(&self) -> &Conditions {
+        &self.conditions
+    }
+
+    fn example(&self) -> Option<(&str, Value)> {
+        Some((
+            "cs",
+            Value::from(
+                "SegmentRegister { base: 0, limit: 4294967295, selector: 16, attributes: 41115 }",
+            ),
+        ))
+    }
+
+    fn decode(&self, _key: &str, value: &Value, _ctx: &FieldContext) -> Option<Decoded> {
+        Some(Decoded::Replace(transform_segment_register(
+            value.as_str()?,
+        )))
+    }
+
+    fn validate(&self, _key: &str, value: &Value, _ctx: &FieldContext) -> Option<String> {
+        // Attributes and limits are 16 and 32 bits wide
+        let ranges = [("attributes", 0xffff), ("limit", 0xffff_ffff)];
+        validate::check_dump(value.as_str()?, &ranges)
+    }
+}
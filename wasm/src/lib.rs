@@ -0,0 +1,79 @@
+//! WASM build of the decoders for browsers, so an export dropped on a web
+//! page is decoded without installing anything
+//!
+//! Built with `wasm-pack build wasm --target web`, after which
+//! `www/index.html` decodes the files dropped on it:
+//!
+//! ```js
+//! import init, { Decoder } from "../pkg/kusto_kmsg_extract_wasm.js";
+//!
+//! await init();
+//! const decoder = new Decoder();
+//! const lines = decoder.decodeExport(new Uint8Array(await file.arrayBuffer()), "csv");
+//! ```
+
+use clap::ValueEnum;
+use kusto_kmsg_extract::format::{self, FormatOptions};
+use kusto_kmsg_extract::input::{self, InputFormat};
+use kusto_kmsg_extract::sink::RecordInfo;
+use std::fmt::Display;
+use wasm_bindgen::prelude::*;
+
+/// Throw an error of the tool as a JS exception
+fn error(err: impl Display) -> JsError {
+    JsError::new(&err.to_string())
+}
+
+/// Formats records like the tool given no option
+#[wasm_bindgen]
+pub struct Decoder {
+    options: FormatOptions,
+}
+
+#[wasm_bindgen]
+impl Decoder {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Result<Decoder, JsError> {
+        let decoders = format::default_decoders().map_err(error)?;
+        Ok(Decoder {
+            options: FormatOptions::new(decoders),
+        })
+    }
+
+    /// Format the message of a record, or give undefined when it shows
+    /// nothing
+    #[wasm_bindgen(js_name = decodeMessage)]
+    pub fn decode_message(&self, message: &str) -> Option<String> {
+        let mut output = String::new();
+        let mut info = RecordInfo::default();
+        format::process_message(message, None, &self.options, &mut output, &mut info);
+        (!output.is_empty()).then_some(output)
+    }
+
+    /// Format the records of an export into lines, reading it as a CSV
+    /// export unless another `--format` is named
+    #[wasm_bindgen(js_name = decodeExport)]
+    pub fn decode_export(
+        &self,
+        bytes: Vec<u8>,
+        format: Option<String>,
+    ) -> Result<Vec<String>, JsError> {
+        let format = match format.as_deref() {
+            Some(name) => InputFormat::from_str(name, true)
+                .map_err(|_| error(format!("Unknown input format '{}'", name)))?,
+            None => InputFormat::Csv,
+        };
+
+        let mut lines = Vec::new();
+        let mut output = String::new();
+        let mut info = RecordInfo::default();
+        for record in input::open_bytes(bytes, format).map_err(error)? {
+            let record = record.map_err(error)?;
+            format::format_record(record.borrowed(), &self.options, &mut output, &mut info);
+            if !output.is_empty() {
+                lines.push(output.clone());
+            }
+        }
+        Ok(lines)
+    }
+}